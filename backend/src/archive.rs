@@ -0,0 +1,149 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use axum::body::Bytes;
+use futures_core::Stream;
+use tokio::sync::mpsc;
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+use crate::errors::{ApiError, ApiResult};
+
+const MEMBER_CHUNK_BYTES: usize = 64 * 1024;
+/// Chunks buffered between the blocking reader and the response body.
+const MEMBER_CHANNEL_CHUNKS: usize = 8;
+
+/// One entry of an archive's central directory.
+#[derive(Debug, Clone)]
+pub struct ArchiveMember {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub mtime: Option<u64>,
+    pub encrypted: bool,
+}
+
+/// Only zip archives can be browsed for now; their central directory lets
+/// members be listed and read without touching the rest of the file.
+pub fn is_browsable_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|value| value.to_str())
+        .is_some_and(|value| value.eq_ignore_ascii_case("zip"))
+}
+
+/// Lists up to `limit` members of the archive at `path`, in central
+/// directory order. The flag reports whether more members were left out.
+pub async fn list_members(path: PathBuf, limit: usize) -> ApiResult<(Vec<ArchiveMember>, bool)> {
+    tokio::task::spawn_blocking(move || {
+        let mut archive = open_archive(&path)?;
+        let total = archive.len();
+        let mut members = Vec::with_capacity(total.min(limit));
+        for index in 0..total.min(limit) {
+            let entry = archive.by_index_raw(index).map_err(archive_error)?;
+            members.push(member_from(&entry));
+        }
+        Ok((members, total > limit))
+    })
+    .await
+    .map_err(|_| ApiError::internal("Archive listing failed."))?
+}
+
+/// Looks up a single member by its exact name inside the archive.
+pub async fn find_member(path: PathBuf, inner: String) -> ApiResult<ArchiveMember> {
+    tokio::task::spawn_blocking(move || {
+        let mut archive = open_archive(&path)?;
+        let index = archive
+            .index_for_name(&inner)
+            .ok_or_else(|| ApiError::not_found("Archive member not found."))?;
+        let entry = archive.by_index_raw(index).map_err(archive_error)?;
+        Ok(member_from(&entry))
+    })
+    .await
+    .map_err(|_| ApiError::internal("Archive lookup failed."))?
+}
+
+/// Decompresses `inner` on a blocking thread and yields it in chunks. The
+/// reader stops as soon as the stream is dropped.
+pub fn stream_member(path: PathBuf, inner: String) -> MemberStream {
+    let (sender, receiver) = mpsc::channel(MEMBER_CHANNEL_CHUNKS);
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = copy_member(&path, &inner, &sender) {
+            let _ = sender.blocking_send(Err(err));
+        }
+    });
+    MemberStream { receiver }
+}
+
+pub struct MemberStream {
+    receiver: mpsc::Receiver<std::io::Result<Bytes>>,
+}
+
+impl Stream for MemberStream {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+fn copy_member(
+    path: &Path,
+    inner: &str,
+    sender: &mpsc::Sender<std::io::Result<Bytes>>,
+) -> std::io::Result<()> {
+    let file = File::open(path)?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(std::io::Error::other)?;
+    let mut entry = archive.by_name(inner).map_err(std::io::Error::other)?;
+    let mut buffer = vec![0u8; MEMBER_CHUNK_BYTES];
+    loop {
+        let read = entry.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        if sender
+            .blocking_send(Ok(Bytes::copy_from_slice(&buffer[..read])))
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+}
+
+fn open_archive(path: &Path) -> ApiResult<ZipArchive<BufReader<File>>> {
+    let file = File::open(path).map_err(|err| ApiError::from_io(err, "archive"))?;
+    ZipArchive::new(BufReader::new(file)).map_err(archive_error)
+}
+
+fn archive_error(err: ZipError) -> ApiError {
+    match err {
+        ZipError::Io(err) => ApiError::from_io(err, "archive"),
+        ZipError::FileNotFound => ApiError::not_found("Archive member not found."),
+        _ => ApiError::bad_request("Archive is not a readable zip file."),
+    }
+}
+
+fn member_from(entry: &zip::read::ZipFile<'_>) -> ArchiveMember {
+    ArchiveMember {
+        name: entry.name().to_string(),
+        is_dir: entry.is_dir(),
+        size: entry.size(),
+        compressed_size: entry.compressed_size(),
+        mtime: entry.last_modified().and_then(zip_time_to_unix),
+        encrypted: entry.encrypted(),
+    }
+}
+
+/// Zip timestamps carry no zone; they are read as UTC.
+fn zip_time_to_unix(value: zip::DateTime) -> Option<u64> {
+    let month = time::Month::try_from(value.month()).ok()?;
+    let date = time::Date::from_calendar_date(i32::from(value.year()), month, value.day()).ok()?;
+    let clock = time::Time::from_hms(value.hour(), value.minute(), value.second()).ok()?;
+    let unix = time::PrimitiveDateTime::new(date, clock)
+        .assume_utc()
+        .unix_timestamp();
+    u64::try_from(unix).ok()
+}
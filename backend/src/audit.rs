@@ -0,0 +1,200 @@
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::error;
+
+use crate::session::{now_unix, unix_to_rfc3339};
+use crate::webhooks::WebhookDispatcher;
+
+/// Append-only JSON-lines audit trail for operators who want something
+/// they can `grep`/`jq` after a container restart. Lines are handed to a
+/// dedicated writer thread so request handlers never block on the disk.
+/// When no path is configured nothing is written. Events are also handed
+/// to the webhook dispatcher, when one is attached.
+#[derive(Debug, Clone, Default)]
+pub struct AuditLog {
+    sender: Option<mpsc::Sender<Message>>,
+    webhooks: Option<WebhookDispatcher>,
+}
+
+#[derive(Debug)]
+enum Message {
+    Line(String),
+    /// Acknowledged once every earlier line has been written.
+    Flush(mpsc::Sender<()>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    LoginSucceeded,
+    LoginFailed,
+    LoginBlocked,
+    Download,
+    /// First request of a burst turned away by the per-IP rate limit.
+    RateLimited,
+}
+
+impl AuditKind {
+    pub const ALL: [Self; 5] = [
+        Self::LoginSucceeded,
+        Self::LoginFailed,
+        Self::LoginBlocked,
+        Self::Download,
+        Self::RateLimited,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LoginSucceeded => "login_succeeded",
+            Self::LoginFailed => "login_failed",
+            Self::LoginBlocked => "login_blocked",
+            Self::Download => "download",
+            Self::RateLimited => "rate_limited",
+        }
+    }
+}
+
+impl FromStr for AuditKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("unknown event {value:?}"))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEvent {
+    pub event: AuditKind,
+    pub ip: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_start: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range_end: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<&'static str>,
+}
+
+impl AuditEvent {
+    pub fn login(event: AuditKind, ip: impl Into<String>, user: impl Into<String>) -> Self {
+        Self {
+            event,
+            ip: ip.into(),
+            user: Some(user.into()),
+            path: None,
+            status: None,
+            bytes: None,
+            range_start: None,
+            range_end: None,
+            transfer: None,
+        }
+    }
+
+    pub fn rate_limited(ip: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            event: AuditKind::RateLimited,
+            ip: ip.into(),
+            user: None,
+            path: Some(path.into()),
+            status: Some(429),
+            bytes: None,
+            range_start: None,
+            range_end: None,
+            transfer: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AuditLine<'a> {
+    at: String,
+    #[serde(flatten)]
+    event: &'a AuditEvent,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| format!("Failed to open audit log {}: {err}", path.display()))?;
+        let (sender, receiver) = mpsc::channel::<Message>();
+        std::thread::Builder::new()
+            .name("mlist-audit".to_string())
+            .spawn(move || {
+                for message in receiver {
+                    match message {
+                        Message::Line(line) => {
+                            if let Err(err) = writeln!(file, "{line}").and_then(|_| file.flush()) {
+                                error!("failed to write audit log: {err}");
+                            }
+                        }
+                        Message::Flush(ack) => {
+                            let _ = file.sync_data();
+                            let _ = ack.send(());
+                        }
+                    }
+                }
+            })
+            .map_err(|err| format!("Failed to start audit log writer: {err}"))?;
+        Ok(Self {
+            sender: Some(sender),
+            webhooks: None,
+        })
+    }
+
+    pub fn with_webhooks(self, webhooks: WebhookDispatcher) -> Self {
+        Self {
+            webhooks: Some(webhooks),
+            ..self
+        }
+    }
+
+    pub fn record(&self, event: &AuditEvent) {
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.notify(event);
+        }
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let line = AuditLine {
+            at: unix_to_rfc3339(now_unix()),
+            event,
+        };
+        match serde_json::to_string(&line) {
+            Ok(line) => {
+                let _ = sender.send(Message::Line(line));
+            }
+            Err(err) => error!("failed to serialize audit event: {err}"),
+        }
+    }
+
+    /// Blocks until lines recorded so far are on disk, or `timeout` passes.
+    pub fn flush(&self, timeout: Duration) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        let (ack, done) = mpsc::channel();
+        if sender.send(Message::Flush(ack)).is_ok() {
+            let _ = done.recv_timeout(timeout);
+        }
+    }
+}
@@ -1,21 +1,123 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use tokio::fs;
 
+use crate::config::AppConfig;
+use crate::db::UserRecord;
 use crate::errors::{ApiError, ApiResult};
-use crate::path_guard::{PRIVATE_MARKER_FILE, relative_string_from_root};
+use crate::path_guard::{
+    NOFEED_MARKER_FILE, PRIVATE_FILE_MARKER_PREFIX, PRIVATE_MARKER_FILE, WRITABLE_MARKER_FILE,
+    glob_matches, is_marker_name, private_file_marker_name, relative_string_from_root,
+};
 
 #[derive(Debug, Clone)]
-pub struct PrivateAnchor {
+pub struct MarkerAnchor {
     pub scope_rel: String,
     pub marker_file: &'static str,
 }
 
+/// Finds what makes `target_path` admin-only: a `.private.<name>` sidecar for
+/// a file, otherwise the nearest ancestor carrying `.private`.
 pub async fn find_private_anchor(
     root: &Path,
     target_path: &Path,
     target_is_dir: bool,
-) -> ApiResult<Option<PrivateAnchor>> {
+) -> ApiResult<Option<MarkerAnchor>> {
+    PrivateAnchors::new(root)
+        .find(target_path, target_is_dir)
+        .await
+}
+
+/// [`find_private_anchor`] for many paths at once. The anchor of every
+/// directory visited is remembered, so resolving all entries of a listing
+/// costs one marker lookup per entry plus one walk up the shared ancestors,
+/// instead of a full walk per entry.
+#[derive(Debug)]
+pub struct PrivateAnchors {
+    root: PathBuf,
+    dirs: HashMap<PathBuf, Option<MarkerAnchor>>,
+}
+
+impl PrivateAnchors {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            dirs: HashMap::new(),
+        }
+    }
+
+    pub async fn find(
+        &mut self,
+        target_path: &Path,
+        target_is_dir: bool,
+    ) -> ApiResult<Option<MarkerAnchor>> {
+        if !target_path.starts_with(&self.root) {
+            return Err(ApiError::forbidden(
+                "Path is outside configured root directory.",
+            ));
+        }
+        if target_is_dir {
+            return self.dir_anchor(target_path).await;
+        }
+        if has_private_file_marker(target_path).await? {
+            return Ok(Some(MarkerAnchor {
+                scope_rel: relative_string_from_root(&self.root, target_path)?,
+                marker_file: PRIVATE_FILE_MARKER_PREFIX,
+            }));
+        }
+        let parent = target_path.parent().unwrap_or(&self.root).to_path_buf();
+        self.dir_anchor(&parent).await
+    }
+
+    async fn dir_anchor(&mut self, dir: &Path) -> ApiResult<Option<MarkerAnchor>> {
+        let mut unresolved = Vec::new();
+        let mut current = dir.to_path_buf();
+        let anchor = loop {
+            if let Some(cached) = self.dirs.get(&current) {
+                break cached.clone();
+            }
+            if marker_exists(&current, PRIVATE_MARKER_FILE).await? {
+                let anchor = MarkerAnchor {
+                    scope_rel: relative_string_from_root(&self.root, &current)?,
+                    marker_file: PRIVATE_MARKER_FILE,
+                };
+                unresolved.push(current);
+                break Some(anchor);
+            }
+            if current == self.root {
+                unresolved.push(current);
+                break None;
+            }
+            let parent = parent_within_root(&current, &self.root)?;
+            unresolved.push(std::mem::replace(&mut current, parent));
+        };
+        for path in unresolved {
+            self.dirs.insert(path, anchor.clone());
+        }
+        Ok(anchor)
+    }
+}
+
+/// Finds the nearest ancestor of `dir` (inclusive) carrying a `.writable`
+/// marker, which opens that subtree for uploads.
+pub async fn find_writable_anchor(root: &Path, dir: &Path) -> ApiResult<Option<MarkerAnchor>> {
+    find_marker_anchor(root, dir, true, WRITABLE_MARKER_FILE).await
+}
+
+/// Finds the nearest ancestor of `dir` (inclusive) carrying a `.nofeed`
+/// marker, which keeps that subtree out of feeds.
+pub async fn find_nofeed_anchor(root: &Path, dir: &Path) -> ApiResult<Option<MarkerAnchor>> {
+    find_marker_anchor(root, dir, true, NOFEED_MARKER_FILE).await
+}
+
+async fn find_marker_anchor(
+    root: &Path,
+    target_path: &Path,
+    target_is_dir: bool,
+    marker_file: &'static str,
+) -> ApiResult<Option<MarkerAnchor>> {
     if !target_path.starts_with(root) {
         return Err(ApiError::forbidden(
             "Path is outside configured root directory.",
@@ -29,10 +131,10 @@ pub async fn find_private_anchor(
     };
 
     loop {
-        if marker_exists(&current, PRIVATE_MARKER_FILE).await? {
-            return Ok(Some(PrivateAnchor {
+        if marker_exists(&current, marker_file).await? {
+            return Ok(Some(MarkerAnchor {
                 scope_rel: relative_string_from_root(root, &current)?,
-                marker_file: PRIVATE_MARKER_FILE,
+                marker_file,
             }));
         }
 
@@ -50,6 +152,149 @@ pub async fn has_private_hide_marker(dir: &Path) -> ApiResult<bool> {
     marker_exists(dir, PRIVATE_MARKER_FILE).await
 }
 
+pub async fn has_private_file_marker(file: &Path) -> ApiResult<bool> {
+    let (Some(dir), Some(name)) = (file.parent(), file.file_name().and_then(|v| v.to_str())) else {
+        return Ok(false);
+    };
+    marker_exists(dir, &private_file_marker_name(name)).await
+}
+
+/// One `username=pattern` entry of `MLIST_PRIVATE_READ_GRANTS` or
+/// `MLIST_PRIVATE_WRITE_GRANTS`. The pattern is a glob over paths below the
+/// root, and a grant on a path covers everything beneath it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScopeGrant {
+    user: String,
+    pattern: String,
+}
+
+impl FromStr for ScopeGrant {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (user, pattern) = value
+            .split_once('=')
+            .map(|(user, pattern)| (user.trim(), pattern.trim().trim_matches('/')))
+            .filter(|(user, pattern)| !user.is_empty() && !pattern.is_empty())
+            .ok_or_else(|| format!("invalid grant {value:?}, expected username=pattern"))?;
+        Ok(Self {
+            user: user.to_string(),
+            pattern: pattern.to_string(),
+        })
+    }
+}
+
+/// What one user may open inside private scopes. Admins may read all of
+/// them; other users the paths granted to them by name.
+#[derive(Debug, Clone)]
+pub struct PrivateAccess {
+    admin: bool,
+    read: Vec<String>,
+    write: Vec<String>,
+}
+
+impl PrivateAccess {
+    pub fn for_user(config: &AppConfig, user: &UserRecord) -> Self {
+        let patterns = |grants: &[ScopeGrant]| {
+            grants
+                .iter()
+                .filter(|grant| grant.user.eq_ignore_ascii_case(&user.username))
+                .map(|grant| grant.pattern.clone())
+                .collect()
+        };
+        Self {
+            admin: user.role.is_admin(),
+            read: patterns(&config.private_read_grants),
+            write: patterns(&config.private_write_grants),
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    /// Whether `relative`, a path inside a private scope, may be listed
+    /// and read.
+    pub fn may_read(&self, relative: &str) -> bool {
+        self.admin || covers(&self.read, relative) || covers(&self.write, relative)
+    }
+
+    /// Whether a write grant opens `relative` for uploads and file
+    /// management, as a `.writable` marker would. Admins' own write access
+    /// comes from `MLIST_UPLOAD_ENABLED` and `MLIST_MANAGE_ENABLED` instead.
+    pub fn may_write(&self, relative: &str) -> bool {
+        covers(&self.write, relative)
+    }
+}
+
+fn covers(patterns: &[String], relative: &str) -> bool {
+    let mut path = relative;
+    loop {
+        if patterns.iter().any(|pattern| glob_matches(pattern, path)) {
+            return true;
+        }
+        match path.rsplit_once('/') {
+            Some((parent, _)) => path = parent,
+            None => return false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivateScope {
+    pub path: String,
+    pub is_dir: bool,
+}
+
+/// Walks the whole tree for `.private` and `.private.<name>` markers so an
+/// admin can review what is hidden. Symlinks are not followed. Stops after
+/// `max_entries` directory entries and reports whether it was cut short.
+pub fn discover_private_scopes(root: &Path, max_entries: usize) -> (Vec<PrivateScope>, bool) {
+    let mut scopes = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+    let mut scanned = 0_usize;
+    while let Some((dir, relative)) = pending.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            scanned += 1;
+            if scanned > max_entries {
+                scopes.sort_by(|a, b| a.path.cmp(&b.path));
+                return (scopes, true);
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            let join = |child: &str| {
+                if relative.is_empty() {
+                    child.to_string()
+                } else {
+                    format!("{relative}/{child}")
+                }
+            };
+            if file_type.is_file() && is_marker_name(&name) {
+                if name == PRIVATE_MARKER_FILE {
+                    scopes.push(PrivateScope {
+                        path: relative.clone(),
+                        is_dir: true,
+                    });
+                } else if let Some(target) = name.strip_prefix(PRIVATE_FILE_MARKER_PREFIX) {
+                    scopes.push(PrivateScope {
+                        path: join(target),
+                        is_dir: false,
+                    });
+                }
+            } else if file_type.is_dir() {
+                pending.push((entry.path(), join(&name)));
+            }
+        }
+    }
+    scopes.sort_by(|a, b| a.path.cmp(&b.path));
+    (scopes, false)
+}
+
 fn parent_within_root(current: &Path, root: &Path) -> ApiResult<PathBuf> {
     let parent = current
         .parent()
@@ -64,7 +309,7 @@ fn parent_within_root(current: &Path, root: &Path) -> ApiResult<PathBuf> {
     Ok(parent.to_path_buf())
 }
 
-async fn marker_exists(dir: &Path, marker_name: &'static str) -> ApiResult<bool> {
+async fn marker_exists(dir: &Path, marker_name: &str) -> ApiResult<bool> {
     let marker_path = dir.join(marker_name);
     let metadata = match fs::symlink_metadata(&marker_path).await {
         Ok(value) => value,
@@ -74,15 +319,95 @@ async fn marker_exists(dir: &Path, marker_name: &'static str) -> ApiResult<bool>
 
     if metadata.file_type().is_symlink() {
         return Err(ApiError::forbidden(
-            "Private marker file cannot be a symbolic link.",
+            "Marker file cannot be a symbolic link.",
         ));
     }
 
     if !metadata.is_file() {
-        return Err(ApiError::forbidden(
-            "Private marker file must be a regular file.",
-        ));
+        return Err(ApiError::forbidden("Marker file must be a regular file."));
     }
 
     Ok(true)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{PrivateAccess, PrivateAnchors, ScopeGrant};
+
+    #[test]
+    fn grants_open_named_scopes_and_those_below() {
+        let grants = |raw: &[&str]| {
+            raw.iter()
+                .map(|grant| grant.parse::<ScopeGrant>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let config = crate::config::AppConfig {
+            private_read_grants: grants(&["alice=films", "Alice=music/*", "bob=music/jazz"]),
+            private_write_grants: grants(&["bob=incoming"]),
+            ..crate::config::AppConfig::default()
+        };
+        let user = |name: &str| crate::db::UserRecord {
+            id: 1,
+            username: name.to_string(),
+            role: crate::db::UserRole::User,
+            totp_secret: String::new(),
+            enabled: true,
+            created_at: 0,
+            updated_at: 0,
+            last_login_at: None,
+            last_seen_at: None,
+            total_bytes_served: 0,
+        };
+
+        let alice = PrivateAccess::for_user(&config, &user("alice"));
+        assert!(alice.may_read("films"));
+        assert!(alice.may_read("films/extras"));
+        assert!(alice.may_read("music/jazz"));
+        assert!(!alice.may_read("filmsets"));
+        assert!(!alice.may_write("films"));
+
+        let bob = PrivateAccess::for_user(&config, &user("bob"));
+        assert!(bob.may_read("music/jazz"));
+        assert!(!bob.may_read("films"));
+        assert!(bob.may_read("incoming/new"));
+        assert!(bob.may_write("incoming/new"));
+
+        assert!("alice".parse::<ScopeGrant>().is_err());
+        assert!("=films".parse::<ScopeGrant>().is_err());
+    }
+
+    #[tokio::test]
+    async fn cached_anchors_match_markers() {
+        let root =
+            std::env::temp_dir().join(format!("mlist-anchors-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(root.join("open/hidden/deeper")).unwrap();
+        std::fs::write(root.join("open/hidden/.private"), b"").unwrap();
+        std::fs::write(root.join("open/hidden/deeper/a.mkv"), b"").unwrap();
+        std::fs::write(root.join("open/b.mkv"), b"").unwrap();
+        std::fs::write(root.join("open/c.mkv"), b"").unwrap();
+        std::fs::write(root.join("open/.private.c.mkv"), b"").unwrap();
+
+        let mut anchors = PrivateAnchors::new(&root);
+        let scope = |anchor: Option<super::MarkerAnchor>| anchor.map(|anchor| anchor.scope_rel);
+        // Resolving the deep file first fills the cache the others then hit.
+        let deep = anchors
+            .find(&root.join("open/hidden/deeper/a.mkv"), false)
+            .await
+            .unwrap();
+        assert_eq!(scope(deep).as_deref(), Some("open/hidden"));
+        let hidden = anchors.find(&root.join("open/hidden"), true).await.unwrap();
+        assert_eq!(scope(hidden).as_deref(), Some("open/hidden"));
+        let open = anchors.find(&root.join("open/b.mkv"), false).await.unwrap();
+        assert_eq!(scope(open), None);
+        let sidecar = anchors.find(&root.join("open/c.mkv"), false).await.unwrap();
+        assert_eq!(scope(sidecar).as_deref(), Some("open/c.mkv"));
+        assert!(
+            anchors
+                .find(std::path::Path::new("/etc"), true)
+                .await
+                .is_err()
+        );
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
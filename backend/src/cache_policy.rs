@@ -0,0 +1,149 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::path_guard::glob_matches;
+
+/// How long a downloaded file may be cached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheTtl {
+    NoStore,
+    /// Cached, but revalidated with `ETag`/`Last-Modified` before each use.
+    NoCache,
+    MaxAge(u64),
+}
+
+impl CacheTtl {
+    /// `private` responses come from a private scope and must stay out of
+    /// shared caches such as CDNs.
+    pub fn cache_control(self, private: bool) -> String {
+        let audience = if private { "private" } else { "public" };
+        match self {
+            Self::NoStore => "no-store".to_string(),
+            Self::NoCache => format!("{audience}, no-cache"),
+            Self::MaxAge(seconds) => format!("{audience}, max-age={seconds}"),
+        }
+    }
+
+    /// Lifetime for the `Expires` header sent alongside `Cache-Control`
+    /// for HTTP/1.0 caches.
+    pub fn expires_in(self) -> Option<Duration> {
+        match self {
+            Self::MaxAge(seconds) => Some(Duration::from_secs(seconds)),
+            Self::NoStore | Self::NoCache => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CachePattern {
+    /// `type/subtype`, or `type/*` for a whole class.
+    Mime(String),
+    /// Glob over the path below the root, as for hide patterns.
+    Path(String),
+}
+
+/// One `pattern=ttl` entry of `MLIST_FILE_CACHE_RULES`, such as
+/// `mime:video/*=604800`, `*.nfo=no-cache` or `incoming/*=no-store`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheRule {
+    pattern: CachePattern,
+    ttl: CacheTtl,
+}
+
+impl CacheRule {
+    fn matches(&self, path: &str, mime: &str) -> bool {
+        match &self.pattern {
+            CachePattern::Mime(pattern) => match pattern.strip_suffix("/*") {
+                Some(class) => mime
+                    .split_once('/')
+                    .is_some_and(|(top_level, _)| top_level.eq_ignore_ascii_case(class)),
+                None => mime.eq_ignore_ascii_case(pattern),
+            },
+            CachePattern::Path(pattern) => glob_matches(pattern, path),
+        }
+    }
+}
+
+impl FromStr for CacheRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid cache rule {value:?}, expected pattern=seconds");
+        let (pattern, ttl) = value.rsplit_once('=').ok_or_else(invalid)?;
+        let (pattern, ttl) = (pattern.trim(), ttl.trim());
+        let pattern = match pattern.strip_prefix("mime:") {
+            Some(mime) if mime.contains('/') => CachePattern::Mime(mime.to_string()),
+            Some(_) => return Err(invalid()),
+            None if pattern.is_empty() => return Err(invalid()),
+            None => CachePattern::Path(pattern.to_string()),
+        };
+        let ttl = match ttl {
+            "no-store" => CacheTtl::NoStore,
+            "no-cache" => CacheTtl::NoCache,
+            seconds => CacheTtl::MaxAge(seconds.parse().map_err(|_| invalid())?),
+        };
+        Ok(Self { pattern, ttl })
+    }
+}
+
+/// The first rule matching the file decides; files no rule matches get no
+/// caching headers.
+pub fn file_cache_ttl(rules: &[CacheRule], path: &str, mime: &str) -> Option<CacheTtl> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(path, mime))
+        .map(|rule| rule.ttl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CacheRule, CacheTtl, file_cache_ttl};
+
+    fn rules(raw: &[&str]) -> Vec<CacheRule> {
+        raw.iter().map(|rule| rule.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rules = rules(&[
+            "incoming/*=no-store",
+            "mime:video/*=604800",
+            "*.nfo=no-cache",
+        ]);
+        let ttl = |path, mime| file_cache_ttl(&rules, path, mime);
+        assert_eq!(
+            ttl("incoming/a.mkv", "video/x-matroska"),
+            Some(CacheTtl::NoStore)
+        );
+        assert_eq!(
+            ttl("tv/a.mkv", "video/x-matroska"),
+            Some(CacheTtl::MaxAge(604800))
+        );
+        assert_eq!(
+            ttl("tv/a.nfo", "application/octet-stream"),
+            Some(CacheTtl::NoCache)
+        );
+        assert_eq!(ttl("tv/a.txt", "text/plain"), None);
+    }
+
+    #[test]
+    fn rules_are_validated() {
+        assert!("mime:video=60".parse::<CacheRule>().is_err());
+        assert!("*.mkv".parse::<CacheRule>().is_err());
+        assert!("*.mkv=forever".parse::<CacheRule>().is_err());
+        assert!("=60".parse::<CacheRule>().is_err());
+    }
+
+    #[test]
+    fn private_files_stay_out_of_shared_caches() {
+        assert_eq!(
+            CacheTtl::MaxAge(60).cache_control(false),
+            "public, max-age=60"
+        );
+        assert_eq!(
+            CacheTtl::MaxAge(60).cache_control(true),
+            "private, max-age=60"
+        );
+        assert_eq!(CacheTtl::NoStore.cache_control(false), "no-store");
+    }
+}
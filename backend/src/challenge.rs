@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::config::AppConfig;
+
+/// An account's failures are forgotten after this long without another.
+const FAILURE_WINDOW_SECONDS: u64 = 3_600;
+const CHALLENGE_TTL_SECONDS: u64 = 300;
+const MAX_TRACKED_ACCOUNTS: usize = 100_000;
+const MAX_PENDING_CHALLENGES: usize = 10_000;
+const MAX_SOLUTION_LEN: usize = 64;
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a login must solve once its account has failed too often.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoginChallengeKind {
+    /// Only the per-IP block of `LoginRateLimiter` applies.
+    #[default]
+    Off,
+    /// A hash puzzle the login page solves in the browser.
+    ProofOfWork,
+    /// A token from an external CAPTCHA, checked against its `siteverify`
+    /// endpoint.
+    Captcha,
+}
+
+impl std::str::FromStr for LoginChallengeKind {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" | "false" => Ok(Self::Off),
+            "pow" | "proof-of-work" => Ok(Self::ProofOfWork),
+            "captcha" => Ok(Self::Captcha),
+            other => Err(format!(
+                "unknown login challenge {other:?}, expected \"off\", \"pow\" or \"captcha\""
+            )),
+        }
+    }
+}
+
+/// Sent with a rejected login; the next attempt must carry its answer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Challenge {
+    /// Find a `solution` for which SHA-256 of `nonce:solution` starts with
+    /// `difficulty` zero bits.
+    #[serde(rename = "pow")]
+    ProofOfWork { nonce: String, difficulty: u32 },
+    Captcha {
+        #[serde(rename = "siteKey")]
+        site_key: Option<String>,
+    },
+}
+
+/// The answer to a challenge, sent along with the login.
+#[derive(Debug, Default, Deserialize)]
+pub struct ChallengeAnswer {
+    pub nonce: Option<String>,
+    pub solution: Option<String>,
+    /// The token produced by the CAPTCHA widget.
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PendingChallenge {
+    username: String,
+    difficulty: u32,
+    issued_at: u64,
+}
+
+/// Counts failures per account rather than per IP, so an attacker cannot
+/// shed the challenge by rotating addresses and users behind the same NAT
+/// are not challenged for someone else's account.
+#[derive(Debug, Clone, Default)]
+pub struct LoginChallenges {
+    http: reqwest::Client,
+    failures: Arc<Mutex<HashMap<String, (u32, u64)>>>,
+    pending: Arc<Mutex<HashMap<String, PendingChallenge>>>,
+}
+
+impl LoginChallenges {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(VERIFY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the next login for `username` must answer a challenge. While
+    /// the table is full of recently failing accounts, every login must.
+    pub fn required(&self, config: &AppConfig, username: &str, now: u64) -> bool {
+        if config.login_challenge == LoginChallengeKind::Off {
+            return false;
+        }
+        let failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        if failures.len() >= MAX_TRACKED_ACCOUNTS {
+            return true;
+        }
+        failures
+            .get(&account_key(username))
+            .is_some_and(|(count, last)| {
+                *count >= config.login_challenge_after && last + FAILURE_WINDOW_SECONDS > now
+            })
+    }
+
+    pub fn record_failure(&self, username: &str, now: u64) {
+        let mut failures = self.failures.lock().unwrap_or_else(PoisonError::into_inner);
+        if failures.len() >= MAX_TRACKED_ACCOUNTS {
+            failures.retain(|_, (_, last)| *last + FAILURE_WINDOW_SECONDS > now);
+        }
+        if failures.len() >= MAX_TRACKED_ACCOUNTS {
+            return;
+        }
+        let entry = failures.entry(account_key(username)).or_insert((0, now));
+        if entry.1 + FAILURE_WINDOW_SECONDS <= now {
+            entry.0 = 0;
+        }
+        *entry = (entry.0.saturating_add(1), now);
+    }
+
+    pub fn record_success(&self, username: &str) {
+        self.failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&account_key(username));
+    }
+
+    /// Forgets every failure and outstanding challenge.
+    pub fn clear(&self) {
+        self.failures
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// A fresh challenge for `username` of the configured kind.
+    pub fn issue(&self, config: &AppConfig, username: &str, now: u64) -> Challenge {
+        if config.login_challenge == LoginChallengeKind::Captcha {
+            return Challenge::Captcha {
+                site_key: config.login_captcha_site_key.clone(),
+            };
+        }
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let difficulty = config.login_pow_difficulty;
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        pending.retain(|_, challenge| challenge.issued_at + CHALLENGE_TTL_SECONDS > now);
+        if pending.len() >= MAX_PENDING_CHALLENGES {
+            pending.clear();
+        }
+        pending.insert(
+            nonce.clone(),
+            PendingChallenge {
+                username: account_key(username),
+                difficulty,
+                issued_at: now,
+            },
+        );
+        Challenge::ProofOfWork { nonce, difficulty }
+    }
+
+    /// Checks `answer` against the configured kind. Proof-of-work nonces
+    /// are single use and bound to the account they were issued for.
+    pub async fn verify(
+        &self,
+        config: &AppConfig,
+        username: &str,
+        answer: Option<&ChallengeAnswer>,
+        client_ip: &str,
+        now: u64,
+    ) -> bool {
+        let Some(answer) = answer else {
+            return false;
+        };
+        match config.login_challenge {
+            LoginChallengeKind::Off => true,
+            LoginChallengeKind::ProofOfWork => {
+                let (Some(nonce), Some(solution)) = (&answer.nonce, &answer.solution) else {
+                    return false;
+                };
+                let Some(challenge) = self
+                    .pending
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .remove(nonce)
+                else {
+                    return false;
+                };
+                challenge.username == account_key(username)
+                    && challenge.issued_at + CHALLENGE_TTL_SECONDS > now
+                    && solves(nonce, solution, challenge.difficulty)
+            }
+            LoginChallengeKind::Captcha => match answer.token.as_deref() {
+                Some(token) if !token.is_empty() => {
+                    self.verify_captcha(config, token, client_ip).await
+                }
+                _ => false,
+            },
+        }
+    }
+
+    /// Posts the token in the form reCAPTCHA, hCaptcha and Turnstile all
+    /// accept. An unreachable verifier counts as a failed answer.
+    async fn verify_captcha(&self, config: &AppConfig, token: &str, client_ip: &str) -> bool {
+        let (Some(url), Some(secret)) = (
+            config.login_captcha_verify_url.as_deref(),
+            config.login_captcha_secret.as_deref(),
+        ) else {
+            return false;
+        };
+        let form = [
+            ("secret", secret),
+            ("response", token),
+            ("remoteip", client_ip),
+        ];
+        let Ok(body) = serde_urlencoded::to_string(form) else {
+            return false;
+        };
+        let response = self
+            .http
+            .post(url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(body)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        let bytes = match response {
+            Ok(response) => response.bytes().await,
+            Err(err) => Err(err),
+        };
+        match bytes {
+            Ok(bytes) => serde_json::from_slice::<SiteVerifyResponse>(&bytes)
+                .is_ok_and(|verdict| verdict.success),
+            Err(err) => {
+                warn!("CAPTCHA verification failed: {err}");
+                false
+            }
+        }
+    }
+}
+
+fn account_key(username: &str) -> String {
+    username.trim().to_lowercase()
+}
+
+fn solves(nonce: &str, solution: &str, difficulty: u32) -> bool {
+    solution.len() <= MAX_SOLUTION_LEN
+        && leading_zero_bits(&Sha256::digest(format!("{nonce}:{solution}"))) >= difficulty
+}
+
+fn leading_zero_bits(digest: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in digest {
+        if *byte != 0 {
+            return bits + byte.leading_zeros();
+        }
+        bits += 8;
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Challenge, ChallengeAnswer, LoginChallengeKind, LoginChallenges, leading_zero_bits, solves,
+    };
+    use crate::config::AppConfig;
+
+    #[test]
+    fn leading_zero_bits_counts_across_bytes() {
+        assert_eq!(leading_zero_bits(&[0x80]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x1f]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn solutions_must_reach_the_difficulty() {
+        let solution = (0..u32::MAX)
+            .map(|value| value.to_string())
+            .find(|candidate| solves("abc", candidate, 8))
+            .unwrap();
+        assert!(solves("abc", &solution, 8));
+        assert!(!solves("abc", &"0".repeat(65), 0));
+    }
+
+    #[tokio::test]
+    async fn proof_of_work_nonces_are_single_use_and_bound_to_the_account() {
+        let config = AppConfig {
+            login_challenge: LoginChallengeKind::ProofOfWork,
+            login_pow_difficulty: 4,
+            ..AppConfig::default()
+        };
+        let challenges = LoginChallenges::default();
+        let Challenge::ProofOfWork { nonce, .. } = challenges.issue(&config, "alice", 100) else {
+            panic!("expected a proof-of-work challenge");
+        };
+        let answer = ChallengeAnswer {
+            solution: (0..u32::MAX)
+                .map(|value| value.to_string())
+                .find(|candidate| solves(&nonce, candidate, 4)),
+            nonce: Some(nonce),
+            token: None,
+        };
+        assert!(
+            !challenges
+                .verify(&config, "bob", Some(&answer), "127.0.0.1", 101)
+                .await
+        );
+
+        let Challenge::ProofOfWork { nonce, .. } = challenges.issue(&config, "alice", 100) else {
+            panic!("expected a proof-of-work challenge");
+        };
+        let answer = ChallengeAnswer {
+            solution: (0..u32::MAX)
+                .map(|value| value.to_string())
+                .find(|candidate| solves(&nonce, candidate, 4)),
+            nonce: Some(nonce),
+            token: None,
+        };
+        assert!(
+            challenges
+                .verify(&config, "alice", Some(&answer), "127.0.0.1", 101)
+                .await
+        );
+        assert!(
+            !challenges
+                .verify(&config, "alice", Some(&answer), "127.0.0.1", 102)
+                .await
+        );
+    }
+
+    #[test]
+    fn failures_are_counted_per_account() {
+        let config = AppConfig {
+            login_challenge: LoginChallengeKind::ProofOfWork,
+            login_challenge_after: 2,
+            ..AppConfig::default()
+        };
+        let challenges = LoginChallenges::default();
+        challenges.record_failure("Alice", 100);
+        assert!(!challenges.required(&config, "alice", 100));
+        challenges.record_failure("alice ", 101);
+        assert!(challenges.required(&config, "ALICE", 102));
+        assert!(!challenges.required(&config, "bob", 102));
+        assert!(!challenges.required(&config, "alice", 101 + 3_600));
+
+        challenges.record_success("alice");
+        assert!(!challenges.required(&config, "alice", 103));
+    }
+}
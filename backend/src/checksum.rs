@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use md5::Md5;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+
+use crate::errors::{ApiError, ApiResult};
+
+/// Once the cache holds this many digests it is emptied before inserting
+/// another; rehashing is slow but always correct.
+const MAX_CACHED_DIGESTS: usize = 16_384;
+const HASH_BUFFER_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgo {
+    Sha256,
+    Md5,
+}
+
+impl ChecksumAlgo {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Md5 => "md5",
+        }
+    }
+}
+
+impl TryFrom<&str> for ChecksumAlgo {
+    type Error = ApiError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(Self::Sha256),
+            "md5" => Ok(Self::Md5),
+            _ => Err(ApiError::bad_request(
+                "Unsupported checksum algorithm; use sha256 or md5.",
+            )),
+        }
+    }
+}
+
+/// File digests keyed by resolved path and algorithm. An entry only counts
+/// while the file's size and mtime still match what was hashed, so a
+/// replaced file is rehashed on the next request.
+#[derive(Debug, Clone, Default)]
+pub struct ChecksumCache {
+    inner: Arc<RwLock<HashMap<(PathBuf, ChecksumAlgo), CachedDigest>>>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedDigest {
+    size: u64,
+    mtime: Option<SystemTime>,
+    hex: String,
+}
+
+impl ChecksumCache {
+    /// Returns the digest only if it has already been computed for this
+    /// exact version of the file.
+    pub async fn cached(
+        &self,
+        path: &Path,
+        algo: ChecksumAlgo,
+        size: u64,
+        mtime: Option<SystemTime>,
+    ) -> Option<String> {
+        let digests = self.inner.read().await;
+        digests
+            .get(&(path.to_path_buf(), algo))
+            .filter(|cached| cached.size == size && cached.mtime == mtime)
+            .map(|cached| cached.hex.clone())
+    }
+
+    /// Returns the cached digest or streams the file through the hasher on
+    /// a blocking thread.
+    pub async fn digest(
+        &self,
+        path: &Path,
+        algo: ChecksumAlgo,
+        size: u64,
+        mtime: Option<SystemTime>,
+    ) -> ApiResult<String> {
+        if let Some(hex) = self.cached(path, algo, size, mtime).await {
+            return Ok(hex);
+        }
+
+        let source = path.to_path_buf();
+        let hex = tokio::task::spawn_blocking(move || hash_file(&source, algo))
+            .await
+            .map_err(|_| ApiError::internal("Checksum computation failed."))?
+            .map_err(|err| ApiError::from_io(err, "file"))?;
+
+        let mut digests = self.inner.write().await;
+        if digests.len() >= MAX_CACHED_DIGESTS {
+            digests.clear();
+        }
+        digests.insert(
+            (path.to_path_buf(), algo),
+            CachedDigest {
+                size,
+                mtime,
+                hex: hex.clone(),
+            },
+        );
+        Ok(hex)
+    }
+}
+
+fn hash_file(path: &Path, algo: ChecksumAlgo) -> std::io::Result<String> {
+    match algo {
+        ChecksumAlgo::Sha256 => hash_with::<Sha256>(path),
+        ChecksumAlgo::Md5 => hash_with::<Md5>(path),
+    }
+}
+
+fn hash_with<D: Digest>(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = D::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_BYTES];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    let mut hex = String::new();
+    for byte in hasher.finalize() {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    Ok(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChecksumAlgo, ChecksumCache};
+
+    #[tokio::test]
+    async fn digests_are_cached_per_file_version() {
+        let dir =
+            std::env::temp_dir().join(format!("mlist-checksum-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("hello.txt");
+        std::fs::write(&path, b"hello mlist\n").unwrap();
+        let mtime = std::fs::metadata(&path).unwrap().modified().ok();
+        let cache = ChecksumCache::default();
+
+        assert_eq!(
+            cache.cached(&path, ChecksumAlgo::Sha256, 12, mtime).await,
+            None
+        );
+        let sha256 = cache
+            .digest(&path, ChecksumAlgo::Sha256, 12, mtime)
+            .await
+            .unwrap();
+        assert_eq!(
+            sha256,
+            "4c5ac73dc20b13225ea240ce7e83baf16ecd5421f724fdc789044f76cbb7eb2a"
+        );
+        assert_eq!(
+            cache.cached(&path, ChecksumAlgo::Sha256, 12, mtime).await,
+            Some(sha256)
+        );
+        assert_eq!(
+            cache.cached(&path, ChecksumAlgo::Sha256, 13, mtime).await,
+            None
+        );
+        let md5 = cache
+            .digest(&path, ChecksumAlgo::Md5, 12, mtime)
+            .await
+            .unwrap();
+        assert_eq!(md5, "fb13a8c22443381d78f0a76c00e35722");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,123 @@
+use axum::http::{Extensions, HeaderMap, StatusCode, Version, header};
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::Predicate;
+
+/// Bodies smaller than this gain nothing from compression.
+const MIN_COMPRESSED_BYTES: u64 = 256;
+/// Text files above this size are streamed as-is; compressing them would
+/// cost CPU on every request for files that are rarely that large anyway.
+const MAX_COMPRESSED_FILE_BYTES: u64 = 8 * 1024 * 1024;
+
+/// gzip/brotli for JSON responses and small text files such as subtitles,
+/// negotiated through `Accept-Encoding`. Media and other binary content is
+/// never touched.
+pub fn compression_layer() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(should_compress)
+}
+
+fn should_compress(
+    status: StatusCode,
+    _version: Version,
+    headers: &HeaderMap,
+    _extensions: &Extensions,
+) -> bool {
+    // Ranges address the identity bytes, so only full responses qualify;
+    // precompressed sidecars already carry their own encoding.
+    if status != StatusCode::OK
+        || headers.contains_key(header::CONTENT_RANGE)
+        || headers.contains_key(header::CONTENT_ENCODING)
+    {
+        return false;
+    }
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if length.is_some_and(|len| len < MIN_COMPRESSED_BYTES) {
+        return false;
+    }
+
+    if essence == "application/json" {
+        return true;
+    }
+    is_text_like(&essence) && length.is_some_and(|len| len <= MAX_COMPRESSED_FILE_BYTES)
+}
+
+fn is_text_like(essence: &str) -> bool {
+    essence.starts_with("text/")
+        || matches!(
+            essence,
+            "application/xml"
+                | "application/javascript"
+                | "application/x-subrip"
+                | "application/x-ass"
+                | "image/svg+xml"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Extensions, HeaderMap, HeaderValue, StatusCode, Version, header};
+
+    use super::should_compress;
+
+    fn headers(content_type: &str, length: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_str(content_type).unwrap(),
+        );
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(length));
+        headers
+    }
+
+    fn full(content_type: &str, length: u64) -> bool {
+        let headers = headers(content_type, length);
+        should_compress(
+            StatusCode::OK,
+            Version::HTTP_11,
+            &headers,
+            &Extensions::new(),
+        )
+    }
+
+    #[test]
+    fn compresses_json_and_small_text() {
+        assert!(full("application/json", 4096));
+        assert!(full("application/json", 64 * 1024 * 1024));
+        assert!(full("application/x-subrip", 4096));
+        assert!(full("text/plain; charset=utf-8", 4096));
+    }
+
+    #[test]
+    fn skips_media_tiny_huge_and_partial_responses() {
+        assert!(!full("video/mp4", 4096));
+        assert!(!full("text/plain", 16));
+        assert!(!full("text/plain", 64 * 1024 * 1024));
+
+        let mut partial = headers("text/plain", 4096);
+        partial.insert(
+            header::CONTENT_RANGE,
+            HeaderValue::from_static("bytes 0-4095/10000"),
+        );
+        let status = StatusCode::PARTIAL_CONTENT;
+        assert!(!should_compress(
+            status,
+            Version::HTTP_11,
+            &partial,
+            &Extensions::new()
+        ));
+    }
+}
@@ -1,54 +1,522 @@
-use std::{env, fs, path::PathBuf};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{env, fs};
+
+use arc_swap::ArcSwap;
+use mime_guess::Mime;
+
+use crate::audit::AuditKind;
+use crate::auth::ScopeGrant;
+use crate::cache_policy::CacheRule;
+use crate::challenge::LoginChallengeKind;
+use crate::hotlink::HotlinkPolicy;
+use crate::logging::LogFormat;
+use crate::natsort::NameCollator;
+use crate::net::{ForwardedHeader, IpNet};
+use crate::oidc::ClaimRule;
+use crate::path_guard::{SymlinkPolicy, normalize_relative_path};
+use crate::path_headers::HeaderRule;
+use crate::throttle::RequestRate;
+
+/// tokio reads at most this much from a file per call, so a larger buffer
+/// would only be partly used.
+const MAX_FILE_READ_BUFFER_BYTES: u64 = 2 * 1024 * 1024;
+/// Beyond this a browser needs minutes to log in.
+const MAX_LOGIN_POW_DIFFICULTY: u32 = 24;
+
+/// Command-line settings; they take precedence over everything else.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub config_file: Option<PathBuf>,
+    pub root_dir: Option<PathBuf>,
+    pub bind_addr: Option<String>,
+}
+
+/// The configuration as seen by a request. Dereferences to the snapshot
+/// taken when it was created, so one request never sees a mix of old and
+/// new settings, while every clone picks up the latest [`LiveConfig::store`]d
+/// value. Axum clones the state for each request, so handlers reading
+/// `state.config` follow reloads without holding anything themselves.
+#[derive(Debug)]
+pub struct LiveConfig {
+    current: Arc<AppConfig>,
+    shared: Arc<ArcSwap<AppConfig>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Arc<AppConfig>) -> Self {
+        Self {
+            shared: Arc::new(ArcSwap::new(Arc::clone(&config))),
+            current: config,
+        }
+    }
+
+    /// The most recently stored configuration, which may be newer than
+    /// this snapshot.
+    pub fn latest(&self) -> Arc<AppConfig> {
+        self.shared.load_full()
+    }
+
+    pub fn store(&self, config: AppConfig) {
+        self.shared.store(Arc::new(config));
+    }
+}
+
+impl Clone for LiveConfig {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.latest(),
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl Deref for LiveConfig {
+    type Target = AppConfig;
+
+    fn deref(&self) -> &AppConfig {
+        &self.current
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct AppConfig {
+    /// Where this configuration was loaded from, so a reload can repeat it.
+    pub overrides: ConfigOverrides,
     pub root_dir: PathBuf,
     pub database_path: PathBuf,
+    /// `host:port`, or `unix:/path/to.sock` for a Unix domain socket.
     pub bind_addr: String,
+    /// URL prefix the app is mounted under, e.g. `/files`; empty at the root.
+    pub base_path: String,
+    /// Permission bits of a Unix socket created for `bind_addr`.
+    pub socket_mode: u32,
+    /// PEM certificate chain and private key; when both are set the server
+    /// speaks HTTPS itself and reloads them when the files change.
+    pub tls_cert_path: Option<PathBuf>,
+    pub tls_key_path: Option<PathBuf>,
+    /// Marks the refresh cookie `Secure`. Always on when TLS is configured.
+    pub secure_cookies: bool,
+    /// Origin used for absolute links handed to external players, e.g.
+    /// `https://media.example.com`. Falls back to the request's `Host`.
+    pub public_url: Option<String>,
     pub session_ttl_seconds: u64,
     pub access_ttl_seconds: u64,
     pub refresh_ttl_seconds: u64,
     pub signed_file_link_ttl_seconds: u64,
+    pub dav_token_ttl_seconds: u64,
+    pub api_token_ttl_seconds: u64,
     pub login_max_failures: u32,
     pub login_block_seconds: u64,
+    /// Extra proof asked of logins to an account once it has failed
+    /// `login_challenge_after` times, from any address.
+    pub login_challenge: LoginChallengeKind,
+    pub login_challenge_after: u32,
+    /// Leading zero bits a proof-of-work solution must reach; each extra
+    /// bit doubles the expected work.
+    pub login_pow_difficulty: u32,
+    /// `siteverify` endpoint of the CAPTCHA provider, such as reCAPTCHA,
+    /// hCaptcha or Turnstile.
+    pub login_captcha_verify_url: Option<String>,
+    pub login_captcha_secret: Option<String>,
+    /// Public key a custom login page needs to render the CAPTCHA widget.
+    pub login_captcha_site_key: Option<String>,
+    /// Identity provider for single sign-on; OIDC login is offered when it
+    /// and `oidc_client_id` are set.
+    pub oidc_issuer: Option<String>,
+    pub oidc_client_id: Option<String>,
+    pub oidc_client_secret: Option<String>,
+    pub oidc_scopes: Vec<String>,
+    /// ID token claim holding the mlist username.
+    pub oidc_username_claim: String,
+    /// Creates an account on the first OIDC login of an unknown username.
+    pub oidc_auto_create: bool,
+    /// Accounts created through OIDC become admins, and so may open private
+    /// scopes, when any of these rules matches their claims.
+    pub oidc_admin_claims: Vec<ClaimRule>,
+    /// Paths inside private scopes that non-admin users may read, by
+    /// username and path glob.
+    pub private_read_grants: Vec<ScopeGrant>,
+    /// Like `private_read_grants`, and also open the paths for uploads and
+    /// file management.
+    pub private_write_grants: Vec<ScopeGrant>,
     pub content_security_policy: String,
+    /// `Cache-Control` for API responses that do not set their own.
+    pub api_cache_control: String,
+    /// Caching headers for downloads, by path glob or MIME class.
+    pub file_cache_rules: Vec<CacheRule>,
+    /// Extra response headers for downloads and frontend assets, by path
+    /// glob.
+    pub path_headers: Vec<HeaderRule>,
+    pub upload_enabled: bool,
+    pub upload_max_bytes: u64,
+    /// Holds resumable uploads until they complete; must share a
+    /// filesystem with `root_dir` so finished files can be renamed in.
+    pub upload_staging_dir: PathBuf,
+    /// Lets admins rename, move, delete and create entries anywhere.
+    pub manage_enabled: bool,
+    /// Deleted entries are moved here instead of being removed. Must be on
+    /// the same filesystem as `root_dir` and outside it.
+    pub trash_dir: Option<PathBuf>,
+    /// Trashed entries older than this are removed for good; 0 keeps them
+    /// until restored.
+    pub trash_retention_days: u64,
+    pub dir_size_cache_ttl_seconds: u64,
+    /// Keeps an in-memory index of every name below the root for search.
+    pub index_enabled: bool,
+    pub index_rescan_seconds: u64,
+    /// Lowercase extensions, without the dot, of the files whose contents
+    /// `/api/search?content=true` looks into.
+    pub search_content_extensions: Vec<String>,
+    /// Larger files are skipped by content search.
+    pub search_content_max_bytes: u64,
+    /// Files read by content searches at the same time, across all
+    /// requests. Only read at startup.
+    pub search_content_concurrency: u32,
+    pub thumb_cache_dir: PathBuf,
+    pub ffmpeg_path: Option<PathBuf>,
+    pub ffprobe_path: Option<PathBuf>,
+    /// Include GPS coordinates in `/api/exif`. Off by default because they
+    /// reveal where a photo was taken.
+    pub exif_gps: bool,
+    pub hls_enabled: bool,
+    pub transcode_dir: PathBuf,
+    pub max_transcodes: u32,
+    pub transcode_idle_seconds: u64,
+    pub trusted_proxies: Vec<IpNet>,
+    /// Which header `trusted_proxies` put the client address in.
+    pub forwarded_header: ForwardedHeader,
+    /// Clients allowed to reach the server at all; empty admits everyone
+    /// not in `deny_cidrs`.
+    pub allow_cidrs: Vec<IpNet>,
+    pub deny_cidrs: Vec<IpNet>,
+    /// Checked in addition to the lists above for the admin API, uploads
+    /// and file management endpoints.
+    pub admin_allow_cidrs: Vec<IpNet>,
+    pub admin_deny_cidrs: Vec<IpNet>,
+    pub hide_patterns: Vec<String>,
+    /// Short names for deep directories: a request path starting with a
+    /// key is resolved below its value instead.
+    pub aliases: BTreeMap<String, String>,
+    /// MIME types by lowercase extension, taking precedence over
+    /// `mime_guess`.
+    pub mime_overrides: HashMap<String, Mime>,
+    pub symlinks: SymlinkPolicy,
+    /// Applies to `/d/` requests whose `Origin`/`Referer` names a site other
+    /// than this one or `hotlink_allowed_hosts`.
+    pub hotlink_policy: HotlinkPolicy,
+    pub hotlink_allowed_hosts: Vec<String>,
+    pub max_bytes_per_sec: Option<u64>,
+    pub max_bytes_per_sec_per_ip: Option<u64>,
+    pub max_concurrent_downloads_per_ip: Option<u32>,
+    pub max_concurrent_downloads_per_scope: Option<u32>,
+    /// Bytes each user may download per quota window.
+    pub download_quota_per_user: Option<u64>,
+    /// Bytes that downloads from one private scope may add up to per quota
+    /// window, whoever makes them.
+    pub download_quota_per_scope: Option<u64>,
+    pub download_quota_window_seconds: u64,
+    /// Per-IP request rate for `/api/*`; the burst defaults to one second's
+    /// worth of requests.
+    pub api_rate_limit: Option<RequestRate>,
+    /// Per-IP request rate for `/d/*` downloads.
+    pub file_rate_limit: Option<RequestRate>,
+    pub max_byte_ranges: u32,
+    /// Size of each read when streaming a download. Larger reads cost fewer
+    /// syscalls and wakeups per byte on fast links.
+    pub file_read_buffer_bytes: u64,
+    pub list_readme: bool,
+    /// Orders names numerically by default (`Episode 2` before `Episode 10`).
+    pub natural_sort: bool,
+    /// BCP 47 locale whose collation orders names; needs the `collation`
+    /// feature.
+    pub sort_locale: Option<String>,
+    pub feed_enabled: bool,
+    /// Serve `/sitemap.xml` and `/api/index` without login. The listing is
+    /// built by the filesystem index, so it needs `index_enabled`.
+    pub public_index_enabled: bool,
+    pub readme_max_bytes: u64,
+    /// Bytes of a file read for `/api/preview`.
+    pub preview_max_bytes: u64,
+    pub audit_log_path: Option<PathBuf>,
+    /// Endpoints that receive audit events as JSON POSTs.
+    pub webhook_urls: Vec<String>,
+    pub webhook_events: Vec<AuditKind>,
+    /// Further attempts after a failed delivery, with exponential backoff.
+    pub webhook_retries: u64,
+    /// How long in-flight requests may finish after SIGTERM/SIGINT.
+    pub shutdown_drain_seconds: u64,
+    /// Largest request body accepted outside the upload routes.
+    pub json_body_limit_bytes: u64,
+    /// Time a client has to send a request's headers.
+    pub header_timeout_seconds: u64,
+    /// Longest pause allowed while receiving a request body.
+    pub read_timeout_seconds: u64,
+    /// Longest pause allowed while sending a response body, such as a
+    /// client that stopped reading a download.
+    pub write_timeout_seconds: u64,
+    pub log_format: LogFormat,
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            overrides: ConfigOverrides::default(),
             root_dir: PathBuf::from("/mlist-files"),
             database_path: PathBuf::from("/mlist-data/mlist.sqlite3"),
             bind_addr: "0.0.0.0:3000".to_string(),
+            base_path: String::new(),
+            socket_mode: 0o660,
+            tls_cert_path: None,
+            tls_key_path: None,
+            secure_cookies: false,
+            public_url: None,
             session_ttl_seconds: 2_592_000,
             access_ttl_seconds: 900,
             refresh_ttl_seconds: 2_592_000,
             signed_file_link_ttl_seconds: 604_800,
+            dav_token_ttl_seconds: 2_592_000,
+            api_token_ttl_seconds: 31_536_000,
             login_max_failures: 5,
             login_block_seconds: 60,
+            login_challenge: LoginChallengeKind::Off,
+            login_challenge_after: 3,
+            login_pow_difficulty: 16,
+            login_captcha_verify_url: None,
+            login_captcha_secret: None,
+            login_captcha_site_key: None,
+            oidc_issuer: None,
+            oidc_client_id: None,
+            oidc_client_secret: None,
+            oidc_scopes: vec![
+                "openid".to_string(),
+                "profile".to_string(),
+                "email".to_string(),
+            ],
+            oidc_username_claim: "preferred_username".to_string(),
+            oidc_auto_create: false,
+            oidc_admin_claims: Vec::new(),
+            private_read_grants: Vec::new(),
+            private_write_grants: Vec::new(),
             content_security_policy:
                 "default-src 'self'; img-src 'self' data: blob:; media-src 'self' blob:; object-src 'none'; frame-ancestors 'self'; script-src 'self'; style-src 'self' 'unsafe-inline';"
                     .to_string(),
+            api_cache_control: "no-store".to_string(),
+            file_cache_rules: Vec::new(),
+            path_headers: Vec::new(),
+            upload_enabled: false,
+            upload_max_bytes: 10 * 1024 * 1024 * 1024,
+            upload_staging_dir: PathBuf::from("/mlist-data/uploads"),
+            manage_enabled: false,
+            trash_dir: None,
+            trash_retention_days: 30,
+            dir_size_cache_ttl_seconds: 600,
+            index_enabled: false,
+            index_rescan_seconds: 3_600,
+            search_content_extensions: [
+                "txt", "md", "markdown", "srt", "ass", "ssa", "vtt", "sub", "lrc", "nfo", "log",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            search_content_max_bytes: 4 * 1024 * 1024,
+            search_content_concurrency: 4,
+            thumb_cache_dir: PathBuf::from("/mlist-data/thumbs"),
+            ffmpeg_path: None,
+            ffprobe_path: None,
+            exif_gps: false,
+            hls_enabled: false,
+            transcode_dir: PathBuf::from("/mlist-data/transcode"),
+            max_transcodes: 2,
+            transcode_idle_seconds: 600,
+            trusted_proxies: Vec::new(),
+            forwarded_header: ForwardedHeader::XForwardedFor,
+            allow_cidrs: Vec::new(),
+            deny_cidrs: Vec::new(),
+            admin_allow_cidrs: Vec::new(),
+            admin_deny_cidrs: Vec::new(),
+            hide_patterns: Vec::new(),
+            aliases: BTreeMap::new(),
+            mime_overrides: HashMap::new(),
+            symlinks: SymlinkPolicy::Deny,
+            hotlink_policy: HotlinkPolicy::Off,
+            hotlink_allowed_hosts: Vec::new(),
+            max_bytes_per_sec: None,
+            max_bytes_per_sec_per_ip: None,
+            max_concurrent_downloads_per_ip: None,
+            max_concurrent_downloads_per_scope: None,
+            download_quota_per_user: None,
+            download_quota_per_scope: None,
+            download_quota_window_seconds: 86_400,
+            api_rate_limit: None,
+            file_rate_limit: None,
+            max_byte_ranges: 16,
+            file_read_buffer_bytes: 256 * 1024,
+            list_readme: true,
+            natural_sort: false,
+            sort_locale: None,
+            feed_enabled: false,
+            public_index_enabled: false,
+            readme_max_bytes: 64 * 1024,
+            preview_max_bytes: 256 * 1024,
+            audit_log_path: None,
+            webhook_urls: Vec::new(),
+            webhook_events: AuditKind::ALL.to_vec(),
+            webhook_retries: 3,
+            shutdown_drain_seconds: 30,
+            json_body_limit_bytes: 1024 * 1024,
+            header_timeout_seconds: 30,
+            read_timeout_seconds: 60,
+            write_timeout_seconds: 60,
+            log_format: LogFormat::Text,
         }
     }
 }
 
 impl AppConfig {
-    pub fn load() -> Result<Self, String> {
+    /// Builds the configuration from, in increasing precedence: defaults,
+    /// the TOML file, `MLIST_*` environment variables and `overrides`.
+    pub fn load(overrides: &ConfigOverrides) -> Result<Self, String> {
+        let settings = match &overrides.config_file {
+            Some(path) => Settings::from_file(path)?,
+            None => Settings::default(),
+        };
         let mut cfg = AppConfig::default();
-        cfg.apply_env()?;
+        cfg.apply(&settings)?;
+        settings.ensure_all_used()?;
+        if let Some(root_dir) = &overrides.root_dir {
+            cfg.root_dir = root_dir.clone();
+        }
+        if let Some(bind_addr) = &overrides.bind_addr {
+            cfg.bind_addr = bind_addr.clone();
+        }
+        cfg.overrides = overrides.clone();
+        cfg.validate()
+    }
 
-        if !cfg.root_dir.is_absolute() {
+    /// Checks the invariants handlers rely on and canonicalizes `root_dir`.
+    pub fn validate(mut self) -> Result<Self, String> {
+        if !self.root_dir.is_absolute() {
             return Err("MLIST_ROOT_DIR must be an absolute path.".to_string());
         }
-        if !cfg.database_path.is_absolute() {
+        if !self.database_path.is_absolute() {
             return Err("MLIST_DATABASE_PATH must be an absolute path.".to_string());
         }
+        if !self.thumb_cache_dir.is_absolute() {
+            return Err("MLIST_THUMB_CACHE_DIR must be an absolute path.".to_string());
+        }
+        if !self.transcode_dir.is_absolute() {
+            return Err("MLIST_TRANSCODE_DIR must be an absolute path.".to_string());
+        }
+        if !self.upload_staging_dir.is_absolute() {
+            return Err("MLIST_UPLOAD_STAGING_DIR must be an absolute path.".to_string());
+        }
+        if self
+            .audit_log_path
+            .as_ref()
+            .is_some_and(|path| !path.is_absolute())
+        {
+            return Err("MLIST_AUDIT_LOG_PATH must be an absolute path.".to_string());
+        }
+        if self
+            .trash_dir
+            .as_ref()
+            .is_some_and(|path| !path.is_absolute())
+        {
+            return Err("MLIST_TRASH_DIR must be an absolute path.".to_string());
+        }
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) => {
+                if self.bind_addr.starts_with("unix:") {
+                    return Err("TLS cannot be served on a Unix socket.".to_string());
+                }
+                if !cert.is_absolute() || !key.is_absolute() {
+                    return Err(
+                        "MLIST_TLS_CERT_PATH and MLIST_TLS_KEY_PATH must be absolute paths."
+                            .to_string(),
+                    );
+                }
+                self.secure_cookies = true;
+            }
+            (None, None) => {}
+            _ => {
+                return Err(
+                    "MLIST_TLS_CERT_PATH and MLIST_TLS_KEY_PATH must be set together.".to_string(),
+                );
+            }
+        }
+        self.base_path = normalize_base_path(&self.base_path)?;
+        if self.file_read_buffer_bytes > MAX_FILE_READ_BUFFER_BYTES {
+            return Err(format!(
+                "MLIST_FILE_READ_BUFFER_BYTES must not exceed {MAX_FILE_READ_BUFFER_BYTES}."
+            ));
+        }
+        if self
+            .webhook_urls
+            .iter()
+            .any(|url| !url.starts_with("http://") && !url.starts_with("https://"))
+        {
+            return Err("MLIST_WEBHOOK_URLS must start with http:// or https://.".to_string());
+        }
+        match (&self.oidc_issuer, &self.oidc_client_id) {
+            (Some(issuer), Some(_)) => {
+                if !issuer.starts_with("https://") && !issuer.starts_with("http://") {
+                    return Err(
+                        "MLIST_OIDC_ISSUER must start with https:// or http://.".to_string()
+                    );
+                }
+                if !self.oidc_scopes.iter().any(|scope| scope == "openid") {
+                    return Err("MLIST_OIDC_SCOPES must include openid.".to_string());
+                }
+            }
+            (None, None) => {}
+            _ => {
+                return Err(
+                    "MLIST_OIDC_ISSUER and MLIST_OIDC_CLIENT_ID must be set together.".to_string(),
+                );
+            }
+        }
+        if self.login_pow_difficulty > MAX_LOGIN_POW_DIFFICULTY {
+            return Err(format!(
+                "MLIST_LOGIN_POW_DIFFICULTY must not exceed {MAX_LOGIN_POW_DIFFICULTY}."
+            ));
+        }
+        if self.login_challenge == LoginChallengeKind::Captcha {
+            match &self.login_captcha_verify_url {
+                Some(url) if url.starts_with("https://") || url.starts_with("http://") => {}
+                Some(_) => {
+                    return Err(
+                        "MLIST_LOGIN_CAPTCHA_VERIFY_URL must start with https:// or http://."
+                            .to_string(),
+                    );
+                }
+                None => {
+                    return Err(
+                        "MLIST_LOGIN_CHALLENGE=captcha needs MLIST_LOGIN_CAPTCHA_VERIFY_URL."
+                            .to_string(),
+                    );
+                }
+            }
+            if self.login_captcha_secret.is_none() {
+                return Err(
+                    "MLIST_LOGIN_CHALLENGE=captcha needs MLIST_LOGIN_CAPTCHA_SECRET.".to_string(),
+                );
+            }
+        }
+        if let Some(url) = self.public_url.take() {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err("MLIST_PUBLIC_URL must start with http:// or https://.".to_string());
+            }
+            self.public_url = Some(url.trim_end_matches('/').to_string());
+        }
+        NameCollator::new(self.natural_sort, self.sort_locale.as_deref())?;
 
-        let canonical_root = fs::canonicalize(&cfg.root_dir).map_err(|err| {
+        let canonical_root = fs::canonicalize(&self.root_dir).map_err(|err| {
             format!(
                 "Failed to canonicalize root_dir {}: {err}",
-                cfg.root_dir.display()
+                self.root_dir.display()
             )
         })?;
 
@@ -66,85 +534,747 @@ impl AppConfig {
             ));
         }
 
-        cfg.root_dir = canonical_root;
-        Ok(cfg)
+        // A trash inside the root would list deleted entries again.
+        if let Some(trash_dir) = &self.trash_dir {
+            if trash_dir.starts_with(&canonical_root) || trash_dir.starts_with(&self.root_dir) {
+                return Err("MLIST_TRASH_DIR must be outside MLIST_ROOT_DIR.".to_string());
+            }
+        }
+
+        self.root_dir = canonical_root;
+        Ok(self)
+    }
+
+    pub fn oidc_enabled(&self) -> bool {
+        self.oidc_issuer.is_some() && self.oidc_client_id.is_some()
     }
 
-    fn apply_env(&mut self) -> Result<(), String> {
-        if let Some(value) = read_env_path("MLIST_ROOT_DIR")? {
+    /// Guesses a file's MIME type from its extension, checking
+    /// `mime_overrides` before the built-in table.
+    pub fn guess_mime(&self, path: impl AsRef<Path>) -> Mime {
+        let path = path.as_ref();
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.mime_overrides.get(&ext.to_ascii_lowercase()))
+            .cloned()
+            .unwrap_or_else(|| mime_guess::from_path(path).first_or_octet_stream())
+    }
+
+    fn apply(&mut self, settings: &Settings) -> Result<(), String> {
+        if let Some(value) = settings.path("MLIST_ROOT_DIR")? {
             self.root_dir = value;
         }
-        if let Some(value) = read_env_path("MLIST_DATABASE_PATH")? {
+        if let Some(value) = settings.path("MLIST_DATABASE_PATH")? {
             self.database_path = value;
         }
-        if let Some(value) = read_env_string("MLIST_BIND_ADDR")? {
+        if let Some(value) = settings.string("MLIST_BIND_ADDR")? {
             self.bind_addr = value;
         }
-        if let Some(value) = read_env_u64("MLIST_SESSION_TTL_SECONDS")? {
+        if let Some(value) = settings.string("MLIST_BASE_PATH")? {
+            self.base_path = value;
+        }
+        if let Some(value) = settings.string("MLIST_SOCKET_MODE")? {
+            self.socket_mode = u32::from_str_radix(&value, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o777)
+                .ok_or_else(|| {
+                    "MLIST_SOCKET_MODE must be octal permission bits such as 660.".to_string()
+                })?;
+        }
+        if let Some(value) = settings.path("MLIST_TLS_CERT_PATH")? {
+            self.tls_cert_path = Some(value);
+        }
+        if let Some(value) = settings.path("MLIST_TLS_KEY_PATH")? {
+            self.tls_key_path = Some(value);
+        }
+        if let Some(value) = settings.bool("MLIST_SECURE_COOKIES")? {
+            self.secure_cookies = value;
+        }
+        if let Some(value) = settings.string("MLIST_PUBLIC_URL")? {
+            self.public_url = Some(value);
+        }
+        if let Some(value) = settings.u64("MLIST_SESSION_TTL_SECONDS")? {
             self.session_ttl_seconds = value;
             self.refresh_ttl_seconds = value;
         }
-        if let Some(value) = read_env_u64("MLIST_ACCESS_TTL_SECONDS")? {
+        if let Some(value) = settings.u64("MLIST_ACCESS_TTL_SECONDS")? {
             self.access_ttl_seconds = value;
         }
-        if let Some(value) = read_env_u64("MLIST_REFRESH_TTL_SECONDS")? {
+        if let Some(value) = settings.u64("MLIST_REFRESH_TTL_SECONDS")? {
             self.refresh_ttl_seconds = value;
         }
-        if let Some(value) = read_env_u64("MLIST_SIGNED_FILE_LINK_TTL_SECONDS")? {
+        if let Some(value) = settings.u64("MLIST_SIGNED_FILE_LINK_TTL_SECONDS")? {
             self.signed_file_link_ttl_seconds = value;
         }
-        if let Some(value) = read_env_u32("MLIST_LOGIN_MAX_FAILURES")? {
+        if let Some(value) = settings.u64("MLIST_DAV_TOKEN_TTL_SECONDS")? {
+            self.dav_token_ttl_seconds = value;
+        }
+        if let Some(value) = settings.u64("MLIST_API_TOKEN_TTL_SECONDS")? {
+            self.api_token_ttl_seconds = value;
+        }
+        if let Some(value) = settings.u32("MLIST_LOGIN_MAX_FAILURES")? {
             self.login_max_failures = value;
         }
-        if let Some(value) = read_env_u64("MLIST_LOGIN_BLOCK_SECONDS")? {
+        if let Some(value) = settings.u64("MLIST_LOGIN_BLOCK_SECONDS")? {
             self.login_block_seconds = value;
         }
-        if let Some(value) = read_env_string("MLIST_CONTENT_SECURITY_POLICY")? {
+        if let Some(value) = settings.string("MLIST_LOGIN_CHALLENGE")? {
+            self.login_challenge = value
+                .parse()
+                .map_err(|err| format!("MLIST_LOGIN_CHALLENGE: {err}"))?;
+        }
+        if let Some(value) = settings.u32("MLIST_LOGIN_CHALLENGE_AFTER")? {
+            self.login_challenge_after = value;
+        }
+        if let Some(value) = settings.u32("MLIST_LOGIN_POW_DIFFICULTY")? {
+            self.login_pow_difficulty = value;
+        }
+        if let Some(value) = settings.string("MLIST_LOGIN_CAPTCHA_VERIFY_URL")? {
+            self.login_captcha_verify_url = Some(value);
+        }
+        if let Some(value) = settings.string("MLIST_LOGIN_CAPTCHA_SECRET")? {
+            self.login_captcha_secret = Some(value);
+        }
+        if let Some(value) = settings.string("MLIST_LOGIN_CAPTCHA_SITE_KEY")? {
+            self.login_captcha_site_key = Some(value);
+        }
+        if let Some(value) = settings.string("MLIST_OIDC_ISSUER")? {
+            self.oidc_issuer = Some(value.trim_end_matches('/').to_string());
+        }
+        if let Some(value) = settings.string("MLIST_OIDC_CLIENT_ID")? {
+            self.oidc_client_id = Some(value);
+        }
+        if let Some(value) = settings.string("MLIST_OIDC_CLIENT_SECRET")? {
+            self.oidc_client_secret = Some(value);
+        }
+        if let Some(value) = settings.list("MLIST_OIDC_SCOPES")? {
+            self.oidc_scopes = value;
+        }
+        if let Some(value) = settings.string("MLIST_OIDC_USERNAME_CLAIM")? {
+            self.oidc_username_claim = value;
+        }
+        if let Some(value) = settings.bool("MLIST_OIDC_AUTO_CREATE")? {
+            self.oidc_auto_create = value;
+        }
+        if let Some(value) = settings.list("MLIST_OIDC_ADMIN_CLAIMS")? {
+            self.oidc_admin_claims = value
+                .iter()
+                .map(|rule| rule.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("MLIST_OIDC_ADMIN_CLAIMS: {err}"))?;
+        }
+        if let Some(value) = settings.list("MLIST_PRIVATE_READ_GRANTS")? {
+            self.private_read_grants = value
+                .iter()
+                .map(|grant| grant.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("MLIST_PRIVATE_READ_GRANTS: {err}"))?;
+        }
+        if let Some(value) = settings.list("MLIST_PRIVATE_WRITE_GRANTS")? {
+            self.private_write_grants = value
+                .iter()
+                .map(|grant| grant.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("MLIST_PRIVATE_WRITE_GRANTS: {err}"))?;
+        }
+        if let Some(value) = settings.string("MLIST_CONTENT_SECURITY_POLICY")? {
             self.content_security_policy = value;
         }
+        if let Some(value) = settings.string("MLIST_API_CACHE_CONTROL")? {
+            self.api_cache_control = value;
+        }
+        if let Some(value) = settings.list("MLIST_FILE_CACHE_RULES")? {
+            self.file_cache_rules = value
+                .iter()
+                .map(|rule| rule.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("MLIST_FILE_CACHE_RULES: {err}"))?;
+        }
+        if let Some(value) = settings.list("MLIST_PATH_HEADERS")? {
+            self.path_headers = value
+                .iter()
+                .map(|rule| rule.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("MLIST_PATH_HEADERS: {err}"))?;
+        }
+        if let Some(value) = settings.bool("MLIST_UPLOAD_ENABLED")? {
+            self.upload_enabled = value;
+        }
+        if let Some(value) = settings.byte_size("MLIST_UPLOAD_MAX_BYTES")? {
+            self.upload_max_bytes = value;
+        }
+        if let Some(value) = settings.path("MLIST_UPLOAD_STAGING_DIR")? {
+            self.upload_staging_dir = value;
+        }
+        if let Some(value) = settings.bool("MLIST_MANAGE_ENABLED")? {
+            self.manage_enabled = value;
+        }
+        if let Some(value) = settings.path("MLIST_TRASH_DIR")? {
+            self.trash_dir = Some(value);
+        }
+        // 0 is meaningful here, so this cannot go through `settings.u64`.
+        if let Some(value) = settings.string("MLIST_TRASH_RETENTION_DAYS")? {
+            self.trash_retention_days = value.trim().parse().map_err(|_| {
+                "MLIST_TRASH_RETENTION_DAYS must be an unsigned integer number of days.".to_string()
+            })?;
+        }
+        if let Some(value) = settings.u64("MLIST_DIR_SIZE_CACHE_TTL_SECONDS")? {
+            self.dir_size_cache_ttl_seconds = value;
+        }
+        if let Some(value) = settings.bool("MLIST_INDEX_ENABLED")? {
+            self.index_enabled = value;
+        }
+        if let Some(value) = settings.u64("MLIST_INDEX_RESCAN_SECONDS")? {
+            self.index_rescan_seconds = value;
+        }
+        if let Some(value) = settings.list("MLIST_SEARCH_CONTENT_EXTENSIONS")? {
+            self.search_content_extensions = value
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+                .collect();
+        }
+        if let Some(value) = settings.byte_size("MLIST_SEARCH_CONTENT_MAX_BYTES")? {
+            self.search_content_max_bytes = value;
+        }
+        if let Some(value) = settings.u32("MLIST_SEARCH_CONTENT_CONCURRENCY")? {
+            self.search_content_concurrency = value;
+        }
+        if let Some(value) = settings.path("MLIST_THUMB_CACHE_DIR")? {
+            self.thumb_cache_dir = value;
+        }
+        if let Some(value) = settings.path("MLIST_FFMPEG_PATH")? {
+            self.ffmpeg_path = Some(value);
+        }
+        if let Some(value) = settings.path("MLIST_FFPROBE_PATH")? {
+            self.ffprobe_path = Some(value);
+        }
+        if let Some(value) = settings.bool("MLIST_EXIF_GPS")? {
+            self.exif_gps = value;
+        }
+        if let Some(value) = settings.bool("MLIST_HLS_ENABLED")? {
+            self.hls_enabled = value;
+        }
+        if let Some(value) = settings.path("MLIST_TRANSCODE_DIR")? {
+            self.transcode_dir = value;
+        }
+        if let Some(value) = settings.u32("MLIST_MAX_TRANSCODES")? {
+            self.max_transcodes = value;
+        }
+        if let Some(value) = settings.u64("MLIST_TRANSCODE_IDLE_SECONDS")? {
+            self.transcode_idle_seconds = value;
+        }
+        if let Some(value) = settings.ip_nets("MLIST_TRUSTED_PROXIES")? {
+            self.trusted_proxies = value;
+        }
+        if let Some(value) = settings.string("MLIST_FORWARDED_HEADER")? {
+            self.forwarded_header = value
+                .parse()
+                .map_err(|err| format!("MLIST_FORWARDED_HEADER: {err}"))?;
+        }
+        if let Some(value) = settings.ip_nets("MLIST_ALLOW_CIDRS")? {
+            self.allow_cidrs = value;
+        }
+        if let Some(value) = settings.ip_nets("MLIST_DENY_CIDRS")? {
+            self.deny_cidrs = value;
+        }
+        if let Some(value) = settings.ip_nets("MLIST_ADMIN_ALLOW_CIDRS")? {
+            self.admin_allow_cidrs = value;
+        }
+        if let Some(value) = settings.ip_nets("MLIST_ADMIN_DENY_CIDRS")? {
+            self.admin_deny_cidrs = value;
+        }
+        if let Some(value) = settings.list("MLIST_HIDE_PATTERNS")? {
+            self.hide_patterns = value;
+        }
+        if let Some(value) = settings.list("MLIST_ALIASES")? {
+            self.aliases = parse_aliases(&value)?;
+        }
+        if let Some(value) = settings.list("MLIST_MIME_OVERRIDES")? {
+            self.mime_overrides = parse_mime_overrides(&value)?;
+        }
+        if let Some(value) = settings.string("MLIST_ALLOW_SYMLINKS")? {
+            self.symlinks = value
+                .parse()
+                .map_err(|err| format!("MLIST_ALLOW_SYMLINKS: {err}"))?;
+        }
+        if let Some(value) = settings.string("MLIST_HOTLINK_POLICY")? {
+            self.hotlink_policy = value
+                .parse()
+                .map_err(|err| format!("MLIST_HOTLINK_POLICY: {err}"))?;
+        }
+        if let Some(value) = settings.list("MLIST_HOTLINK_ALLOWED_HOSTS")? {
+            self.hotlink_allowed_hosts = value;
+        }
+        if let Some(value) = settings.byte_size("MLIST_MAX_BYTES_PER_SEC")? {
+            self.max_bytes_per_sec = Some(value);
+        }
+        if let Some(value) = settings.byte_size("MLIST_MAX_BYTES_PER_SEC_PER_IP")? {
+            self.max_bytes_per_sec_per_ip = Some(value);
+        }
+        if let Some(value) = settings.u32("MLIST_MAX_CONCURRENT_DOWNLOADS_PER_IP")? {
+            self.max_concurrent_downloads_per_ip = (value > 0).then_some(value);
+        }
+        if let Some(value) = settings.u32("MLIST_MAX_CONCURRENT_DOWNLOADS_PER_SCOPE")? {
+            self.max_concurrent_downloads_per_scope = (value > 0).then_some(value);
+        }
+        if let Some(value) = settings.byte_size("MLIST_DOWNLOAD_QUOTA_PER_USER")? {
+            self.download_quota_per_user = Some(value);
+        }
+        if let Some(value) = settings.byte_size("MLIST_DOWNLOAD_QUOTA_PER_SCOPE")? {
+            self.download_quota_per_scope = Some(value);
+        }
+        if let Some(value) = settings.u64("MLIST_DOWNLOAD_QUOTA_WINDOW_SECONDS")? {
+            self.download_quota_window_seconds = value;
+        }
+        if let Some(value) = settings.u32("MLIST_API_RATE_LIMIT")? {
+            self.api_rate_limit = request_rate(value);
+        }
+        if let (Some(burst), Some(rate)) = (
+            settings.u32("MLIST_API_RATE_BURST")?,
+            self.api_rate_limit.as_mut(),
+        ) {
+            rate.burst = burst;
+        }
+        if let Some(value) = settings.u32("MLIST_FILE_RATE_LIMIT")? {
+            self.file_rate_limit = request_rate(value);
+        }
+        if let (Some(burst), Some(rate)) = (
+            settings.u32("MLIST_FILE_RATE_BURST")?,
+            self.file_rate_limit.as_mut(),
+        ) {
+            rate.burst = burst;
+        }
+        if let Some(value) = settings.u32("MLIST_MAX_BYTE_RANGES")? {
+            self.max_byte_ranges = value;
+        }
+        if let Some(value) = settings.byte_size("MLIST_FILE_READ_BUFFER_BYTES")? {
+            self.file_read_buffer_bytes = value;
+        }
+        if let Some(value) = settings.bool("MLIST_LIST_README")? {
+            self.list_readme = value;
+        }
+        if let Some(value) = settings.bool("MLIST_NATURAL_SORT")? {
+            self.natural_sort = value;
+        }
+        if let Some(value) = settings.string("MLIST_SORT_LOCALE")? {
+            self.sort_locale = Some(value);
+        }
+        if let Some(value) = settings.bool("MLIST_FEED_ENABLED")? {
+            self.feed_enabled = value;
+        }
+        if let Some(value) = settings.bool("MLIST_PUBLIC_INDEX_ENABLED")? {
+            self.public_index_enabled = value;
+        }
+        if let Some(value) = settings.byte_size("MLIST_README_MAX_BYTES")? {
+            self.readme_max_bytes = value;
+        }
+        if let Some(value) = settings.byte_size("MLIST_PREVIEW_MAX_BYTES")? {
+            self.preview_max_bytes = value;
+        }
+        if let Some(value) = settings.path("MLIST_AUDIT_LOG_PATH")? {
+            self.audit_log_path = Some(value);
+        }
+        if let Some(value) = settings.list("MLIST_WEBHOOK_URLS")? {
+            self.webhook_urls = value;
+        }
+        if let Some(value) = settings.list("MLIST_WEBHOOK_EVENTS")? {
+            self.webhook_events = value
+                .iter()
+                .map(|kind| kind.parse())
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("MLIST_WEBHOOK_EVENTS: {err}"))?;
+        }
+        if let Some(value) = settings.u64("MLIST_WEBHOOK_RETRIES")? {
+            self.webhook_retries = value;
+        }
+        if let Some(value) = settings.u64("MLIST_SHUTDOWN_DRAIN_SECONDS")? {
+            self.shutdown_drain_seconds = value;
+        }
+        if let Some(value) = settings.byte_size("MLIST_JSON_BODY_LIMIT_BYTES")? {
+            self.json_body_limit_bytes = value;
+        }
+        if let Some(value) = settings.u64("MLIST_HEADER_TIMEOUT_SECONDS")? {
+            self.header_timeout_seconds = value;
+        }
+        if let Some(value) = settings.u64("MLIST_READ_TIMEOUT_SECONDS")? {
+            self.read_timeout_seconds = value;
+        }
+        if let Some(value) = settings.u64("MLIST_WRITE_TIMEOUT_SECONDS")? {
+            self.write_timeout_seconds = value;
+        }
+        if let Some(value) = settings.string("MLIST_LOG_FORMAT")? {
+            self.log_format = value
+                .parse()
+                .map_err(|err| format!("MLIST_LOG_FORMAT: {err}"))?;
+        }
         Ok(())
     }
 }
 
-fn read_env_path(name: &'static str) -> Result<Option<PathBuf>, String> {
-    Ok(read_env_string(name)?.map(PathBuf::from))
+/// Raw setting values: `MLIST_*` environment variables, falling back to
+/// the TOML file, whose keys are the variable names without the prefix in
+/// lowercase (`MLIST_HIDE_PATTERNS` is `hide_patterns`).
+#[derive(Debug, Default)]
+struct Settings {
+    file: HashMap<String, toml::Value>,
+    used: RefCell<HashSet<String>>,
 }
 
-fn read_env_string(name: &'static str) -> Result<Option<String>, String> {
-    let Ok(raw) = env::var(name) else {
-        return Ok(None);
-    };
-    let value = raw.trim();
-    if value.is_empty() {
-        return Err(format!("{name} must not be empty."));
+impl Settings {
+    fn from_file(path: &Path) -> Result<Self, String> {
+        let raw = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read config file {}: {err}", path.display()))?;
+        let table: toml::Table = raw
+            .parse()
+            .map_err(|err| format!("Invalid config file {}: {err}", path.display()))?;
+        Ok(Self {
+            file: table.into_iter().collect(),
+            used: RefCell::default(),
+        })
+    }
+
+    fn raw(&self, name: &'static str) -> Result<Option<String>, String> {
+        let key = file_key(name);
+        self.used.borrow_mut().insert(key.clone());
+        if let Ok(value) = env::var(name) {
+            return Ok(Some(value));
+        }
+        let Some(value) = self.file.get(&key) else {
+            return Ok(None);
+        };
+        let scalar = |value: &toml::Value| match value {
+            toml::Value::String(text) => Some(text.clone()),
+            toml::Value::Integer(number) => Some(number.to_string()),
+            toml::Value::Boolean(flag) => Some(flag.to_string()),
+            _ => None,
+        };
+        // Lists are accepted as arrays and read like their comma-separated
+        // environment form; maps are accepted as tables and read like a
+        // list of `key=value` pairs.
+        let text = match value {
+            toml::Value::Array(items) => items
+                .iter()
+                .map(scalar)
+                .collect::<Option<Vec<_>>>()
+                .map(|items| items.join(",")),
+            toml::Value::Table(entries) => entries
+                .iter()
+                .map(|(key, value)| scalar(value).map(|value| format!("{key}={value}")))
+                .collect::<Option<Vec<_>>>()
+                .map(|pairs| pairs.join(",")),
+            other => scalar(other),
+        };
+        text.map(Some)
+            .ok_or_else(|| format!("Config key `{key}` has an unsupported value type."))
+    }
+
+    /// Rejects keys that no setting read, which are most likely typos.
+    fn ensure_all_used(&self) -> Result<(), String> {
+        let used = self.used.borrow();
+        let mut unknown: Vec<&str> = self
+            .file
+            .keys()
+            .filter(|key| !used.contains(*key))
+            .map(String::as_str)
+            .collect();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+        unknown.sort_unstable();
+        Err(format!("Unknown config keys: {}.", unknown.join(", ")))
+    }
+
+    fn path(&self, name: &'static str) -> Result<Option<PathBuf>, String> {
+        Ok(self.string(name)?.map(PathBuf::from))
+    }
+
+    fn string(&self, name: &'static str) -> Result<Option<String>, String> {
+        let Some(raw) = self.raw(name)? else {
+            return Ok(None);
+        };
+        let value = raw.trim();
+        if value.is_empty() {
+            return Err(format!("{name} must not be empty."));
+        }
+        Ok(Some(value.to_string()))
+    }
+
+    fn u32(&self, name: &'static str) -> Result<Option<u32>, String> {
+        let Some(raw) = self.raw(name)? else {
+            return Ok(None);
+        };
+        let value = raw
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| format!("{name} must be an unsigned integer number."))?;
+        if value == 0 {
+            return Err(format!("{name} must be greater than zero."));
+        }
+        Ok(Some(value))
+    }
+
+    fn u64(&self, name: &'static str) -> Result<Option<u64>, String> {
+        let Some(raw) = self.raw(name)? else {
+            return Ok(None);
+        };
+        let value = raw
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("{name} must be an unsigned integer number of seconds."))?;
+        if value == 0 {
+            return Err(format!("{name} must be greater than zero."));
+        }
+        Ok(Some(value))
+    }
+
+    fn bool(&self, name: &'static str) -> Result<Option<bool>, String> {
+        let Some(raw) = self.raw(name)? else {
+            return Ok(None);
+        };
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Some(true)),
+            "0" | "false" | "no" | "off" => Ok(Some(false)),
+            _ => Err(format!("{name} must be a boolean (true/false).")),
+        }
+    }
+
+    fn byte_size(&self, name: &'static str) -> Result<Option<u64>, String> {
+        let Some(raw) = self.raw(name)? else {
+            return Ok(None);
+        };
+        let value = raw
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("{name} must be an unsigned integer number of bytes."))?;
+        if value == 0 {
+            return Err(format!("{name} must be greater than zero."));
+        }
+        Ok(Some(value))
+    }
+
+    fn list(&self, name: &'static str) -> Result<Option<Vec<String>>, String> {
+        Ok(self.string(name)?.map(|raw| {
+            raw.split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+                .collect()
+        }))
+    }
+
+    fn ip_nets(&self, name: &'static str) -> Result<Option<Vec<IpNet>>, String> {
+        let Some(raw) = self.string(name)? else {
+            return Ok(None);
+        };
+        raw.split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .parse::<IpNet>()
+                    .map_err(|err| format!("{name}: {err}"))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
     }
-    Ok(Some(value.to_string()))
 }
 
-fn read_env_u32(name: &'static str) -> Result<Option<u32>, String> {
-    let Ok(raw) = env::var(name) else {
-        return Ok(None);
-    };
-    let value = raw
-        .trim()
-        .parse::<u32>()
-        .map_err(|_| format!("{name} must be an unsigned integer number."))?;
-    if value == 0 {
-        return Err(format!("{name} must be greater than zero."));
+/// Accepts `/files`, `files/` and the like and returns `/files`; `/` and
+/// the empty string mean the root. Segments are limited to unreserved URL
+/// characters so the prefix can be embedded in HTML and headers as is.
+fn normalize_base_path(raw: &str) -> Result<String, String> {
+    let trimmed = raw.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    let valid = trimmed.split('/').all(|segment| {
+        !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~'))
+    });
+    if !valid {
+        return Err(
+            "MLIST_BASE_PATH must be a URL path such as /files using letters, digits, '-', '_', '.' or '~'."
+                .to_string(),
+        );
+    }
+    Ok(format!("/{trimmed}"))
+}
+
+/// Parses `name=target` pairs. A name is a single path segment; targets
+/// are normalized like request paths, surrounding slashes aside, and must
+/// not be the root.
+fn parse_aliases(pairs: &[String]) -> Result<BTreeMap<String, String>, String> {
+    let mut aliases = BTreeMap::new();
+    for pair in pairs {
+        let invalid = || format!("MLIST_ALIASES: invalid alias {pair:?}, expected name=path");
+        let (name, target) = pair.split_once('=').ok_or_else(invalid)?;
+        let name = normalize_relative_path(Some(name.trim())).map_err(|_| invalid())?;
+        let target = normalize_relative_path(Some(target.trim().trim_matches('/')))
+            .map_err(|_| invalid())?;
+        if name.is_empty() || name.contains('/') || target.is_empty() {
+            return Err(invalid());
+        }
+        aliases.insert(name, target);
+    }
+    Ok(aliases)
+}
+
+/// Parses `extension=type` pairs; a leading dot on the extension is
+/// optional.
+fn parse_mime_overrides(pairs: &[String]) -> Result<HashMap<String, Mime>, String> {
+    let mut overrides = HashMap::new();
+    for pair in pairs {
+        let invalid = || format!("MLIST_MIME_OVERRIDES: invalid entry {pair:?}, expected ext=type");
+        let (ext, mime) = pair.split_once('=').ok_or_else(invalid)?;
+        let ext = ext.trim().trim_start_matches('.').to_ascii_lowercase();
+        let mime: Mime = mime.trim().parse().map_err(|_| invalid())?;
+        if ext.is_empty() || ext.contains(['/', '.']) {
+            return Err(invalid());
+        }
+        overrides.insert(ext, mime);
     }
-    Ok(Some(value))
+    Ok(overrides)
+}
+
+fn request_rate(per_sec: u32) -> Option<RequestRate> {
+    (per_sec > 0).then_some(RequestRate {
+        per_sec,
+        burst: per_sec,
+    })
+}
+
+fn file_key(name: &str) -> String {
+    name.trim_start_matches("MLIST_").to_ascii_lowercase()
 }
 
-fn read_env_u64(name: &'static str) -> Result<Option<u64>, String> {
-    let Ok(raw) = env::var(name) else {
-        return Ok(None);
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{
+        AppConfig, ForwardedHeader, LiveConfig, LogFormat, Settings, normalize_base_path,
+        parse_aliases,
     };
-    let value = raw
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| format!("{name} must be an unsigned integer number of seconds."))?;
-    if value == 0 {
-        return Err(format!("{name} must be greater than zero."));
-    }
-    Ok(Some(value))
+
+    fn settings(toml: &str) -> Settings {
+        Settings {
+            file: toml.parse::<toml::Table>().unwrap().into_iter().collect(),
+            used: Default::default(),
+        }
+    }
+
+    #[test]
+    fn file_keys_map_to_settings() {
+        let settings = settings(
+            r#"
+            hide_patterns = ["*.nfo", "@eaDir"]
+            upload_enabled = true
+            max_transcodes = 4
+            log_format = "json"
+            "#,
+        );
+        let mut config = AppConfig::default();
+        config.apply(&settings).unwrap();
+        settings.ensure_all_used().unwrap();
+        assert_eq!(config.hide_patterns, vec!["*.nfo", "@eaDir"]);
+        assert!(config.upload_enabled);
+        assert_eq!(config.max_transcodes, 4);
+        assert_eq!(config.log_format, LogFormat::Json);
+    }
+
+    #[test]
+    fn forwarded_header_defaults_to_x_forwarded_for() {
+        let mut config = AppConfig::default();
+        assert_eq!(config.forwarded_header, ForwardedHeader::XForwardedFor);
+        config
+            .apply(&settings("forwarded_header = \"Forwarded\""))
+            .unwrap();
+        assert_eq!(config.forwarded_header, ForwardedHeader::Forwarded);
+        assert!(
+            config
+                .apply(&settings("forwarded_header = \"x-real-ip\""))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn trash_retention_accepts_zero() {
+        let mut config = AppConfig::default();
+        config.apply(&settings("trash_retention_days = 0")).unwrap();
+        assert_eq!(config.trash_retention_days, 0);
+        assert!(
+            config
+                .apply(&settings("trash_retention_days = -1"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn mime_overrides_are_read_from_a_table() {
+        let settings = settings(
+            r#"
+            [mime_overrides]
+            mkv = "video/x-matroska"
+            ".ass" = "text/x-ssa"
+            "#,
+        );
+        let mut config = AppConfig::default();
+        config.apply(&settings).unwrap();
+        assert_eq!(config.guess_mime("show/E01.MKV"), "video/x-matroska");
+        assert_eq!(config.guess_mime("E01.ass"), "text/x-ssa");
+        assert_eq!(config.guess_mime("notes.txt"), "text/plain");
+
+        let invalid = settings("mime_overrides = { cue = \"not a type\" }\n");
+        let err = AppConfig::default().apply(&invalid).unwrap_err();
+        assert!(err.contains("MLIST_MIME_OVERRIDES"), "{err}");
+    }
+
+    #[test]
+    fn unknown_file_keys_are_rejected() {
+        let settings = settings("upload_enabeld = true\n");
+        AppConfig::default().apply(&settings).unwrap();
+        let err = settings.ensure_all_used().unwrap_err();
+        assert!(err.contains("upload_enabeld"), "{err}");
+    }
+
+    #[test]
+    fn base_paths_are_normalized() {
+        assert_eq!(normalize_base_path("").unwrap(), "");
+        assert_eq!(normalize_base_path("/").unwrap(), "");
+        assert_eq!(normalize_base_path("files/").unwrap(), "/files");
+        assert_eq!(normalize_base_path("/apps/mlist").unwrap(), "/apps/mlist");
+        assert!(normalize_base_path("/a//b").is_err());
+        assert!(normalize_base_path("/../etc").is_err());
+        assert!(normalize_base_path("/a\"b").is_err());
+    }
+
+    #[test]
+    fn live_config_clones_see_stored_changes() {
+        let live = LiveConfig::new(Arc::new(AppConfig::default()));
+        live.store(AppConfig {
+            hide_patterns: vec!["*.tmp".to_string()],
+            ..AppConfig::default()
+        });
+        // The existing snapshot stays stable; new clones follow the store.
+        assert!(live.hide_patterns.is_empty());
+        assert_eq!(live.clone().hide_patterns, vec!["*.tmp"]);
+    }
+
+    #[test]
+    fn aliases_are_parsed_and_validated() {
+        let pairs = |raw: &[&str]| raw.iter().map(|pair| pair.to_string()).collect::<Vec<_>>();
+        let aliases =
+            parse_aliases(&pairs(&["iso = /distros/linux/isos/", "docs=work/docs"])).unwrap();
+        assert_eq!(aliases["iso"], "distros/linux/isos");
+        assert_eq!(aliases["docs"], "work/docs");
+
+        assert!(parse_aliases(&pairs(&["iso"])).is_err());
+        assert!(parse_aliases(&pairs(&["a/b=distros"])).is_err());
+        assert!(parse_aliases(&pairs(&["iso=../etc"])).is_err());
+        assert!(parse_aliases(&pairs(&["iso=/"])).is_err());
+    }
 }
@@ -0,0 +1,181 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::io::AsyncReadExt;
+use tokio::sync::Semaphore;
+use tracing::debug;
+
+/// Matching lines reported per file; the rest are dropped.
+pub const MAX_LINE_MATCHES: usize = 20;
+/// Longer lines are cut to this many characters in the response.
+const MAX_LINE_CHARS: usize = 240;
+/// A NUL within this many leading bytes marks a file as binary.
+const BINARY_SNIFF_BYTES: usize = 8_192;
+
+/// A line of a text file that contains the search term.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineMatch {
+    /// 1-based line number.
+    pub line: usize,
+    /// Byte offset of the start of the line within the file.
+    pub offset: u64,
+    pub text: String,
+}
+
+/// Bounds how many files `/api/search?content=true` reads at once across
+/// all requests, so content searches cannot starve downloads of disk time.
+#[derive(Debug, Clone)]
+pub struct ContentSearch {
+    slots: Arc<Semaphore>,
+}
+
+impl ContentSearch {
+    pub fn new(concurrency: u32) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(concurrency.max(1) as usize)),
+        }
+    }
+
+    /// Lines of `path` containing `needle_lower`, compared case-insensitively.
+    /// Unreadable and binary files have none.
+    pub async fn grep(&self, path: &Path, needle_lower: &str, max_bytes: u64) -> Vec<LineMatch> {
+        let Ok(_slot) = self.slots.acquire().await else {
+            return Vec::new();
+        };
+        match read_capped(path, max_bytes).await {
+            Ok(bytes) => grep_text(&bytes, needle_lower),
+            Err(err) => {
+                debug!(path = %path.display(), "skipping unreadable file: {err}");
+                Vec::new()
+            }
+        }
+    }
+}
+
+impl Default for ContentSearch {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+async fn read_capped(path: &Path, max_bytes: u64) -> std::io::Result<Vec<u8>> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut bytes = Vec::new();
+    file.take(max_bytes).read_to_end(&mut bytes).await?;
+    Ok(bytes)
+}
+
+/// Searches UTF-8 text, or UTF-16 when the file starts with its byte order
+/// mark, as subtitles exported on Windows often do. Invalid sequences are
+/// replaced rather than failing the whole file.
+pub fn grep_text(bytes: &[u8], needle_lower: &str) -> Vec<LineMatch> {
+    match bytes {
+        [0xff, 0xfe, rest @ ..] => grep_utf16(rest, needle_lower, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => grep_utf16(rest, needle_lower, u16::from_be_bytes),
+        _ => grep_utf8(bytes, needle_lower),
+    }
+}
+
+fn grep_utf8(bytes: &[u8], needle_lower: &str) -> Vec<LineMatch> {
+    if bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return Vec::new();
+    }
+    let (bom, body) = match bytes {
+        [0xef, 0xbb, 0xbf, rest @ ..] => (3, rest),
+        _ => (0, bytes),
+    };
+    let mut matches = Vec::new();
+    let mut offset = bom;
+    for (index, line) in body.split(|byte| *byte == b'\n').enumerate() {
+        let text = String::from_utf8_lossy(line);
+        if let Some(found) = line_match(index, offset, &text, needle_lower) {
+            matches.push(found);
+            if matches.len() == MAX_LINE_MATCHES {
+                break;
+            }
+        }
+        offset += line.len() as u64 + 1;
+    }
+    matches
+}
+
+fn grep_utf16(bytes: &[u8], needle_lower: &str, unit: fn([u8; 2]) -> u16) -> Vec<LineMatch> {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    let mut matches = Vec::new();
+    let mut offset = 2;
+    for (index, line) in units.split(|unit| *unit == u16::from(b'\n')).enumerate() {
+        let text = String::from_utf16_lossy(line);
+        if let Some(found) = line_match(index, offset, &text, needle_lower) {
+            matches.push(found);
+            if matches.len() == MAX_LINE_MATCHES {
+                break;
+            }
+        }
+        offset += (line.len() as u64 + 1) * 2;
+    }
+    matches
+}
+
+fn line_match(index: usize, offset: u64, text: &str, needle_lower: &str) -> Option<LineMatch> {
+    let text = text.trim_end_matches('\r');
+    if !text.to_lowercase().contains(needle_lower) {
+        return None;
+    }
+    Some(LineMatch {
+        line: index + 1,
+        offset,
+        text: text.trim().chars().take(MAX_LINE_CHARS).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineMatch, MAX_LINE_MATCHES, grep_text};
+
+    #[test]
+    fn matches_report_line_numbers_and_byte_offsets() {
+        let text = "1\r\n00:00:01,000 --> 00:00:02,000\r\nWinter is Coming\r\n\r\nwinter again";
+        assert_eq!(
+            grep_text(text.as_bytes(), "winter"),
+            vec![
+                LineMatch {
+                    line: 3,
+                    offset: 34,
+                    text: "Winter is Coming".to_string(),
+                },
+                LineMatch {
+                    line: 5,
+                    offset: 54,
+                    text: "winter again".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_and_bom_prefixed_files_are_decoded() {
+        let mut utf16 = vec![0xff, 0xfe];
+        for unit in "abc\n你好世界".encode_utf16() {
+            utf16.extend_from_slice(&unit.to_le_bytes());
+        }
+        let found = grep_text(&utf16, "好世");
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].line, found[0].offset), (2, 10));
+        assert_eq!(found[0].text, "你好世界");
+
+        let found = grep_text("\u{feff}héllo".as_bytes(), "héllo");
+        assert_eq!((found[0].line, found[0].offset), (1, 3));
+    }
+
+    #[test]
+    fn binary_files_never_match_and_matches_are_capped() {
+        assert!(grep_text(b"needle\0needle", "needle").is_empty());
+        let many = "needle\n".repeat(MAX_LINE_MATCHES + 5);
+        assert_eq!(grep_text(many.as_bytes(), "needle").len(), MAX_LINE_MATCHES);
+    }
+}
@@ -4,7 +4,7 @@ use crate::errors::{ApiError, ApiResult};
 use crate::session::now_unix;
 
 use super::helpers::{db_error, fetch_user_by_id_from};
-use super::types::{UserRecord, UserView};
+use super::types::{SessionView, UserRecord, UserView};
 use super::AuthDb;
 
 impl AuthDb {
@@ -51,6 +51,16 @@ impl AuthDb {
                 .execute(&mut *tx)
                 .await
                 .map_err(db_error)?;
+            sqlx::query("DELETE FROM dav_tokens WHERE user_id = ?1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
+            sqlx::query("DELETE FROM api_tokens WHERE user_id = ?1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(db_error)?;
         }
 
         let updated = fetch_user_by_id_from(&mut tx, user_id).await?;
@@ -81,9 +91,12 @@ impl AuthDb {
             "sessions",
             "access_tokens",
             "signed_file_tokens",
+            "dav_tokens",
+            "api_tokens",
             "resource_access_events",
             "user_resource_usage",
             "user_file_states",
+            "user_identities",
         ] {
             let sql = format!("DELETE FROM {table} WHERE user_id = ?1");
             sqlx::query(&sql)
@@ -128,8 +141,77 @@ impl AuthDb {
             .execute(&mut *tx)
             .await
             .map_err(db_error)?;
+        sqlx::query("DELETE FROM dav_tokens WHERE user_id = ?1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        sqlx::query("DELETE FROM api_tokens WHERE user_id = ?1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
         let user = fetch_user_by_id_from(&mut tx, user_id).await?;
         tx.commit().await.map_err(db_error)?;
         Ok(user)
     }
+
+    pub async fn list_active_sessions(&self) -> ApiResult<Vec<SessionView>> {
+        let now = now_unix() as i64;
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                substr(s.token_hash, 1, 16) AS id, s.user_id, u.username, s.created_at,
+                s.last_active_at, s.expires_at
+            FROM sessions s
+            JOIN users u ON u.id = s.user_id
+            WHERE s.expires_at > ?1
+            ORDER BY s.last_active_at DESC
+            "#,
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| SessionView {
+                id: row.get("id"),
+                user_id: row.get("user_id"),
+                username: row.get("username"),
+                created_at: row.get("created_at"),
+                last_active_at: row.get("last_active_at"),
+                expires_at: row.get("expires_at"),
+            })
+            .collect())
+    }
+
+    /// Drops the refresh session listed under `session_id`. Access tokens
+    /// already minted from it are not linked back and lapse on their own
+    /// within the access TTL; use [`AuthDb::revoke_user_sessions`] to cut
+    /// them off immediately.
+    pub async fn revoke_session(&self, session_id: &str) -> ApiResult<()> {
+        let valid = session_id.len() == 16 && session_id.bytes().all(|b| b.is_ascii_hexdigit());
+        if !valid {
+            return Err(ApiError::not_found("Session not found."));
+        }
+        let result = sqlx::query("DELETE FROM sessions WHERE substr(token_hash, 1, 16) = ?1")
+            .bind(session_id.to_ascii_lowercase())
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        if result.rows_affected() == 0 {
+            return Err(ApiError::not_found("Session not found."));
+        }
+        Ok(())
+    }
+
+    /// Signs `user_id` out everywhere without disabling the account.
+    pub async fn revoke_user_sessions(&self, user_id: i64) -> ApiResult<()> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        fetch_user_by_id_from(&mut tx, user_id).await?;
+        tx.commit().await.map_err(db_error)?;
+        self.remove_sessions_for_user(user_id).await
+    }
 }
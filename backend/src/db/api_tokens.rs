@@ -0,0 +1,181 @@
+use sqlx::Row;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::session::now_unix;
+
+use super::helpers::{db_error, fetch_user_by_id_from, hash_token, user_from_row};
+use super::types::{ApiTokenScope, ApiTokenView, AuthSession};
+use super::AuthDb;
+
+impl AuthDb {
+    pub async fn create_api_token(
+        &self,
+        user_id: i64,
+        name: &str,
+        scopes: &[ApiTokenScope],
+        token: &str,
+        ttl_seconds: u64,
+    ) -> ApiResult<ApiTokenView> {
+        let now = now_unix() as i64;
+        let expires_at = now.saturating_add(ttl_seconds as i64);
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        let user = fetch_user_by_id_from(&mut tx, user_id).await?;
+        if !user.enabled {
+            return Err(ApiError::bad_request("User is disabled."));
+        }
+        if scopes.contains(&ApiTokenScope::Admin) && !user.role.is_admin() {
+            return Err(ApiError::bad_request(
+                "Only administrators can hold admin-scoped tokens.",
+            ));
+        }
+
+        let id = sqlx::query(
+            r#"
+            INSERT INTO api_tokens (token_hash, user_id, name, scopes, expires_at, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(hash_token(token))
+        .bind(user_id)
+        .bind(name)
+        .bind(join_scopes(scopes))
+        .bind(expires_at)
+        .bind(now)
+        .execute(&mut *tx)
+        .await
+        .map_err(db_error)?
+        .last_insert_rowid();
+        tx.commit().await.map_err(db_error)?;
+
+        Ok(ApiTokenView {
+            id,
+            user_id,
+            username: user.username,
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            created_at: now,
+            expires_at,
+            last_used_at: None,
+        })
+    }
+
+    pub async fn list_api_tokens(&self) -> ApiResult<Vec<ApiTokenView>> {
+        let now = now_unix() as i64;
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                t.id, t.user_id, u.username, t.name, t.scopes, t.created_at,
+                t.expires_at, t.last_used_at
+            FROM api_tokens t
+            JOIN users u ON u.id = t.user_id
+            WHERE t.expires_at > ?1
+            ORDER BY t.created_at DESC, t.id DESC
+            "#,
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        rows.iter().map(api_token_from_row).collect()
+    }
+
+    pub async fn revoke_api_token(&self, token_id: i64) -> ApiResult<()> {
+        let result = sqlx::query("DELETE FROM api_tokens WHERE id = ?1")
+            .bind(token_id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        if result.rows_affected() == 0 {
+            return Err(ApiError::not_found("Token not found."));
+        }
+        Ok(())
+    }
+
+    /// Resolves a bearer API token to a session restricted to the token's
+    /// scopes.
+    pub async fn api_token_session(&self, token: &str) -> ApiResult<Option<AuthSession>> {
+        let now = now_unix() as i64;
+        sqlx::query("DELETE FROM api_tokens WHERE expires_at <= ?1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+
+        let token_hash = hash_token(token);
+        let Some(row) = sqlx::query(
+            r#"
+            SELECT
+                t.expires_at, t.scopes,
+                u.id, u.username, u.role, u.totp_secret, u.enabled,
+                u.created_at, u.updated_at, u.last_login_at, u.last_seen_at,
+                COALESCE(SUM(uru.total_bytes_served), 0) AS total_bytes_served
+            FROM api_tokens t
+            JOIN users u ON u.id = t.user_id
+            LEFT JOIN user_resource_usage uru ON uru.user_id = u.id
+            WHERE t.token_hash = ?1 AND t.expires_at > ?2
+            GROUP BY t.token_hash
+            "#,
+        )
+        .bind(&token_hash)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?
+        else {
+            return Ok(None);
+        };
+
+        let user = user_from_row(&row)?;
+        if !user.enabled {
+            sqlx::query("DELETE FROM api_tokens WHERE user_id = ?1")
+                .bind(user.id)
+                .execute(&self.pool)
+                .await
+                .map_err(db_error)?;
+            return Ok(None);
+        }
+        let scopes = split_scopes(&row.get::<String, _>("scopes"))?;
+
+        sqlx::query("UPDATE api_tokens SET last_used_at = ?1 WHERE token_hash = ?2")
+            .bind(now)
+            .bind(&token_hash)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+
+        Ok(Some(AuthSession {
+            user,
+            expires_at: row.get("expires_at"),
+            scopes: Some(scopes),
+        }))
+    }
+}
+
+fn join_scopes(scopes: &[ApiTokenScope]) -> String {
+    scopes
+        .iter()
+        .map(ApiTokenScope::as_str)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn split_scopes(raw: &str) -> ApiResult<Vec<ApiTokenScope>> {
+    raw.split(',')
+        .filter(|scope| !scope.is_empty())
+        .map(ApiTokenScope::try_from)
+        .collect()
+}
+
+fn api_token_from_row(row: &sqlx::sqlite::SqliteRow) -> ApiResult<ApiTokenView> {
+    Ok(ApiTokenView {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        username: row.get("username"),
+        name: row.get("name"),
+        scopes: split_scopes(&row.get::<String, _>("scopes"))?,
+        created_at: row.get("created_at"),
+        expires_at: row.get("expires_at"),
+        last_used_at: row.get("last_used_at"),
+    })
+}
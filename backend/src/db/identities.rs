@@ -0,0 +1,79 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::session::now_unix;
+
+use super::AuthDb;
+use super::helpers::{db_error, fetch_user_by_id_from, is_unique_violation, user_from_row};
+use super::types::UserRecord;
+
+impl AuthDb {
+    /// The account an OIDC login with this issuer and subject signs into.
+    pub async fn user_by_identity(
+        &self,
+        issuer: &str,
+        subject: &str,
+    ) -> ApiResult<Option<UserRecord>> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                u.id, u.username, u.role, u.totp_secret, u.enabled,
+                u.created_at, u.updated_at, u.last_login_at, u.last_seen_at,
+                COALESCE(SUM(uru.total_bytes_served), 0) AS total_bytes_served
+            FROM user_identities i
+            JOIN users u ON u.id = i.user_id
+            LEFT JOIN user_resource_usage uru ON uru.user_id = u.id
+            WHERE i.issuer = ?1 AND i.subject = ?2
+            GROUP BY u.id
+            "#,
+        )
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        row.as_ref().map(user_from_row).transpose()
+    }
+
+    /// Lets OIDC logins with this issuer and subject sign into `user_id`,
+    /// replacing any earlier link of that account to the same issuer.
+    pub async fn link_identity(&self, user_id: i64, issuer: &str, subject: &str) -> ApiResult<()> {
+        let mut tx = self.pool.begin().await.map_err(db_error)?;
+        fetch_user_by_id_from(&mut tx, user_id).await?;
+        sqlx::query("DELETE FROM user_identities WHERE user_id = ?1 AND issuer = ?2")
+            .bind(user_id)
+            .bind(issuer)
+            .execute(&mut *tx)
+            .await
+            .map_err(db_error)?;
+        sqlx::query(
+            r#"
+            INSERT INTO user_identities (issuer, subject, user_id, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            "#,
+        )
+        .bind(issuer)
+        .bind(subject)
+        .bind(user_id)
+        .bind(now_unix() as i64)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| {
+            if is_unique_violation(&err) {
+                ApiError::conflict("This identity is already linked to another account.")
+            } else {
+                db_error(err)
+            }
+        })?;
+        tx.commit().await.map_err(db_error)?;
+        Ok(())
+    }
+
+    pub async fn unlink_identities(&self, user_id: i64) -> ApiResult<()> {
+        sqlx::query("DELETE FROM user_identities WHERE user_id = ?1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+}
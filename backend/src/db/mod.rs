@@ -6,11 +6,14 @@ use sqlx::{Row, SqlitePool};
 use crate::session::now_unix;
 
 mod admin;
+mod api_tokens;
 mod file_states;
 mod helpers;
+mod identities;
 mod resource_access;
 #[cfg(test)]
 mod tests;
+mod trash;
 mod types;
 mod users;
 
@@ -140,6 +143,46 @@ impl AuthDb {
         )
         .execute(&self.pool)
         .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS dav_tokens (
+                token_hash TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                expires_at INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_used_at INTEGER
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS dav_tokens_user_id_idx ON dav_tokens(user_id)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS dav_tokens_expires_at_idx ON dav_tokens(expires_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS api_tokens (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                token_hash TEXT NOT NULL UNIQUE,
+                user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                name TEXT NOT NULL,
+                scopes TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                last_used_at INTEGER
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS api_tokens_user_id_idx ON api_tokens(user_id)")
+            .execute(&self.pool)
+            .await?;
         sqlx::query(
             r#"
             CREATE TABLE IF NOT EXISTS resource_access_events (
@@ -252,6 +295,34 @@ impl AuthDb {
         )
         .execute(&self.pool)
         .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS user_identities (
+                issuer TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (issuer, subject)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS trash_entries (
+                id TEXT PRIMARY KEY,
+                path TEXT NOT NULL,
+                is_dir INTEGER NOT NULL,
+                size INTEGER,
+                marker TEXT,
+                deleted_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+                deleted_at INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
@@ -273,6 +344,12 @@ impl AuthDb {
         Ok(())
     }
 
+    /// Waits for queries in flight and closes the pool. Transfers still
+    /// marked active at this point are marked stale on the next start.
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
     async fn mark_active_resource_accesses_stale(&self) -> sqlx::Result<()> {
         let now = now_unix() as i64;
         sqlx::query(
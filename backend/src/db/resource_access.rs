@@ -5,8 +5,8 @@ use crate::session::now_unix;
 
 use super::helpers::{db_error, event_from_row, upsert_resource_usage_delta, usage_from_row};
 use super::types::{
-    RecordResourceAccess, ResourceAccessEventView, ResourceKind, ResourceTransferState,
-    ResourceUsageView,
+    RecordResourceAccess, ResourceAccessEventView, ResourceKind, ResourceStatsView,
+    ResourceTotalsView, ResourceTransferState, ResourceUsageView,
 };
 use super::AuthDb;
 
@@ -380,4 +380,76 @@ impl AuthDb {
 
         Ok(rows.into_iter().map(usage_from_row).collect())
     }
+
+    /// Sums download counters for `path` and everything below it; an empty
+    /// path covers the whole library.
+    pub async fn resource_stats(&self, path: &str) -> ApiResult<ResourceStatsView> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                COUNT(DISTINCT path) AS files,
+                COALESCE(SUM(access_count), 0) AS access_count,
+                COALESCE(SUM(total_bytes_served), 0) AS total_bytes_served,
+                COUNT(DISTINCT user_id) AS unique_users,
+                MAX(last_access_at) AS last_access_at
+            FROM user_resource_usage
+            WHERE ?1 = '' OR path = ?1 OR substr(path, 1, length(?1) + 1) = ?1 || '/'
+            "#,
+        )
+        .bind(path)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        Ok(ResourceStatsView {
+            path: path.to_string(),
+            files: row.get("files"),
+            access_count: row.get("access_count"),
+            total_bytes_served: row.get("total_bytes_served"),
+            unique_users: row.get("unique_users"),
+            last_access_at: row.get("last_access_at"),
+        })
+    }
+
+    /// Most requested files below `path`, ranked by request count and then
+    /// bytes served.
+    pub async fn top_resources(
+        &self,
+        path: &str,
+        limit: i64,
+    ) -> ApiResult<Vec<ResourceTotalsView>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                path,
+                MAX(file_size) AS file_size,
+                SUM(access_count) AS access_count,
+                SUM(total_bytes_served) AS total_bytes_served,
+                COUNT(DISTINCT user_id) AS unique_users,
+                MAX(last_access_at) AS last_access_at
+            FROM user_resource_usage
+            WHERE ?1 = '' OR path = ?1 OR substr(path, 1, length(?1) + 1) = ?1 || '/'
+            GROUP BY path
+            ORDER BY access_count DESC, total_bytes_served DESC, path ASC
+            LIMIT ?2
+            "#,
+        )
+        .bind(path)
+        .bind(limit.clamp(1, 500))
+        .fetch_all(&self.pool)
+        .await
+        .map_err(db_error)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ResourceTotalsView {
+                path: row.get("path"),
+                file_size: row.get("file_size"),
+                access_count: row.get("access_count"),
+                total_bytes_served: row.get("total_bytes_served"),
+                unique_users: row.get("unique_users"),
+                last_access_at: row.get("last_access_at"),
+            })
+            .collect())
+    }
 }
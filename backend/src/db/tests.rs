@@ -2,7 +2,9 @@ use std::path::PathBuf;
 
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
 
-use super::{AuthDb, RecordResourceAccess, ResourceKind, ResourceTransferState, UserRole};
+use super::{
+    ApiTokenScope, AuthDb, RecordResourceAccess, ResourceKind, ResourceTransferState, UserRole,
+};
 
 fn test_db_path(name: &str) -> PathBuf {
     std::env::temp_dir().join(format!(
@@ -102,6 +104,64 @@ async fn resource_access_updates_seen_and_totals() {
     let _ = std::fs::remove_file(path);
 }
 
+#[tokio::test]
+async fn resource_stats_aggregate_across_users_and_subtrees() {
+    let path = test_db_path("stats");
+    let db = AuthDb::connect(&path).await.unwrap();
+    let alice = db
+        .create_user("alice", UserRole::User, "SECRET")
+        .await
+        .unwrap();
+    let bob = db
+        .create_user("bob", UserRole::User, "SECRET")
+        .await
+        .unwrap();
+
+    let download = |user_id: i64, file: &str, bytes: i64| RecordResourceAccess {
+        user_id,
+        kind: ResourceKind::File,
+        path: file.to_string(),
+        route: "/d",
+        status: 200,
+        bytes_served: bytes,
+        file_size: Some(bytes),
+        range_start: None,
+        range_end: None,
+    };
+    for access in [
+        download(alice.id, "releases/v1.zip", 100),
+        download(bob.id, "releases/v1.zip", 100),
+        download(bob.id, "releases/v2.zip", 300),
+        download(alice.id, "releases-old/v0.zip", 50),
+    ] {
+        db.record_resource_access(access).await.unwrap();
+    }
+
+    let stats = db.resource_stats("releases").await.unwrap();
+    assert_eq!(stats.files, 2);
+    assert_eq!(stats.access_count, 3);
+    assert_eq!(stats.total_bytes_served, 500);
+    assert_eq!(stats.unique_users, 2);
+    assert!(stats.last_access_at.is_some());
+
+    let single = db.resource_stats("releases/v1.zip").await.unwrap();
+    assert_eq!(single.access_count, 2);
+    let empty = db.resource_stats("nothing").await.unwrap();
+    assert_eq!(empty.access_count, 0);
+    assert!(empty.last_access_at.is_none());
+
+    let top = db.top_resources("", 2).await.unwrap();
+    assert_eq!(
+        top.iter()
+            .map(|item| item.path.as_str())
+            .collect::<Vec<_>>(),
+        vec!["releases/v1.zip", "releases/v2.zip"]
+    );
+    assert_eq!(top[0].unique_users, 2);
+
+    let _ = std::fs::remove_file(path);
+}
+
 #[tokio::test]
 async fn resource_access_migrates_legacy_events_to_completed() {
     let path = test_db_path("audit-migration");
@@ -351,6 +411,32 @@ async fn signed_file_tokens_are_path_bound_and_revoked_with_user() {
     let _ = std::fs::remove_file(path);
 }
 
+#[tokio::test]
+async fn dav_tokens_are_user_bound_and_revoked_with_totp_reset() {
+    let path = test_db_path("dav-token");
+    let db = AuthDb::connect(&path).await.unwrap();
+    let user = db
+        .create_user("alice", UserRole::User, "SECRET")
+        .await
+        .unwrap();
+
+    db.create_dav_token(user.id, "dav-token", 60).await.unwrap();
+
+    let session = db.dav_session("Alice", "dav-token").await.unwrap().unwrap();
+    assert_eq!(session.user.id, user.id);
+    assert!(db.dav_session("bob", "dav-token").await.unwrap().is_none());
+
+    db.reset_totp(user.id, "NEWSECRET").await.unwrap();
+    assert!(
+        db.dav_session("alice", "dav-token")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    let _ = std::fs::remove_file(path);
+}
+
 #[tokio::test]
 async fn remove_access_token_revokes_bearer_session() {
     let path = test_db_path("remove-access-token");
@@ -458,3 +544,87 @@ async fn delete_user_removes_user_but_keeps_last_admin() {
 
     let _ = std::fs::remove_file(path);
 }
+
+#[tokio::test]
+async fn api_tokens_carry_scopes_and_die_with_the_account() {
+    let path = test_db_path("api-tokens");
+    let db = AuthDb::connect(&path).await.unwrap();
+    let user = db
+        .create_user("alice", UserRole::User, "SECRET")
+        .await
+        .unwrap();
+
+    assert!(
+        db.create_api_token(user.id, "cron", &[ApiTokenScope::Admin], "mlt_a", 60)
+            .await
+            .is_err()
+    );
+    let issued = db
+        .create_api_token(user.id, "cron", &[ApiTokenScope::Read], "mlt_b", 60)
+        .await
+        .unwrap();
+    assert_eq!(db.list_api_tokens().await.unwrap().len(), 1);
+
+    let session = db.api_token_session("mlt_b").await.unwrap().unwrap();
+    assert_eq!(session.user.id, user.id);
+    assert!(session.allows(ApiTokenScope::Read));
+    assert!(!session.allows(ApiTokenScope::Write));
+    assert!(db.api_token_session("mlt_c").await.unwrap().is_none());
+
+    db.set_user_enabled(user.id, false).await.unwrap();
+    assert!(db.api_token_session("mlt_b").await.unwrap().is_none());
+    assert!(db.revoke_api_token(issued.id).await.is_err());
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn identities_link_one_subject_to_one_account() {
+    let path = test_db_path("identities");
+    let db = AuthDb::connect(&path).await.unwrap();
+    let alice = db
+        .create_user("alice", UserRole::User, "SECRET")
+        .await
+        .unwrap();
+    let bob = db
+        .create_user("bob", UserRole::User, "SECRET")
+        .await
+        .unwrap();
+    let issuer = "https://id.example.com";
+
+    assert!(
+        db.user_by_identity(issuer, "sub-1")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    db.link_identity(alice.id, issuer, "sub-1").await.unwrap();
+    let found = db.user_by_identity(issuer, "sub-1").await.unwrap().unwrap();
+    assert_eq!(found.id, alice.id);
+    assert!(
+        db.user_by_identity("https://other.example.com", "sub-1")
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(db.link_identity(bob.id, issuer, "sub-1").await.is_err());
+
+    // Relinking replaces the account's earlier subject for that issuer.
+    db.link_identity(alice.id, issuer, "sub-2").await.unwrap();
+    assert!(
+        db.user_by_identity(issuer, "sub-1")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    db.unlink_identities(alice.id).await.unwrap();
+    assert!(
+        db.user_by_identity(issuer, "sub-2")
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,94 @@
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::errors::ApiResult;
+
+use super::AuthDb;
+use super::helpers::db_error;
+use super::types::TrashEntry;
+
+const TRASH_COLUMNS: &str = r#"
+    t.id, t.path, t.is_dir, t.size, t.marker, t.deleted_by, t.deleted_at,
+    u.username AS deleted_by_name
+    FROM trash_entries t
+    LEFT JOIN users u ON u.id = t.deleted_by
+"#;
+
+impl AuthDb {
+    pub async fn record_trash_entry(&self, entry: &TrashEntry) -> ApiResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO trash_entries (id, path, is_dir, size, marker, deleted_by, deleted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            "#,
+        )
+        .bind(&entry.id)
+        .bind(&entry.path)
+        .bind(entry.is_dir)
+        .bind(entry.size)
+        .bind(&entry.marker)
+        .bind(entry.deleted_by_id)
+        .bind(entry.deleted_at)
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(())
+    }
+
+    /// Newest first; only those deleted by `deleted_by` when it is set.
+    pub async fn list_trash_entries(&self, deleted_by: Option<i64>) -> ApiResult<Vec<TrashEntry>> {
+        let sql = format!(
+            "SELECT {TRASH_COLUMNS} WHERE ?1 IS NULL OR t.deleted_by = ?1 \
+             ORDER BY t.deleted_at DESC, t.id"
+        );
+        let rows = sqlx::query(&sql)
+            .bind(deleted_by)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(rows.iter().map(trash_entry_from_row).collect())
+    }
+
+    pub async fn trash_entry(&self, id: &str) -> ApiResult<Option<TrashEntry>> {
+        let sql = format!("SELECT {TRASH_COLUMNS} WHERE t.id = ?1");
+        let row = sqlx::query(&sql)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(row.as_ref().map(trash_entry_from_row))
+    }
+
+    /// Entries deleted before `cutoff`, oldest first.
+    pub async fn expired_trash_entries(&self, cutoff: i64) -> ApiResult<Vec<TrashEntry>> {
+        let sql = format!("SELECT {TRASH_COLUMNS} WHERE t.deleted_at < ?1 ORDER BY t.deleted_at");
+        let rows = sqlx::query(&sql)
+            .bind(cutoff)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(rows.iter().map(trash_entry_from_row).collect())
+    }
+
+    pub async fn remove_trash_entry(&self, id: &str) -> ApiResult<()> {
+        sqlx::query("DELETE FROM trash_entries WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+        Ok(())
+    }
+}
+
+fn trash_entry_from_row(row: &SqliteRow) -> TrashEntry {
+    TrashEntry {
+        id: row.get("id"),
+        path: row.get("path"),
+        is_dir: row.get::<i64, _>("is_dir") != 0,
+        size: row.get("size"),
+        marker: row.get("marker"),
+        deleted_by_id: row.get("deleted_by"),
+        deleted_by: row.get("deleted_by_name"),
+        deleted_at: row.get("deleted_at"),
+    }
+}
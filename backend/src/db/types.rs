@@ -64,6 +64,19 @@ pub struct UserView {
     pub total_bytes_served: i64,
 }
 
+/// A live refresh session. `id` is a prefix of the stored token hash, enough
+/// to address the session without exposing anything usable as a credential.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionView {
+    pub id: String,
+    pub user_id: i64,
+    pub username: String,
+    pub created_at: i64,
+    pub last_active_at: i64,
+    pub expires_at: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct UserRecord {
     pub id: i64,
@@ -175,6 +188,29 @@ pub struct ResourceUsageView {
     pub last_access_at: i64,
 }
 
+/// Download totals summed over every user for a file or a subtree.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceStatsView {
+    pub path: String,
+    pub files: i64,
+    pub access_count: i64,
+    pub total_bytes_served: i64,
+    pub unique_users: i64,
+    pub last_access_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceTotalsView {
+    pub path: String,
+    pub file_size: Option<i64>,
+    pub access_count: i64,
+    pub total_bytes_served: i64,
+    pub unique_users: i64,
+    pub last_access_at: i64,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UserFileStateView {
@@ -190,8 +226,90 @@ pub struct UserFavoriteView {
     pub created_at: i64,
 }
 
+/// An entry moved into `MLIST_TRASH_DIR`; `id` is its name there.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashEntry {
+    pub id: String,
+    /// Where the entry was before it was deleted.
+    pub path: String,
+    pub is_dir: bool,
+    pub size: Option<i64>,
+    /// Name in the trash of the file's private marker, moved along with it.
+    #[serde(skip)]
+    pub marker: Option<String>,
+    #[serde(skip)]
+    pub deleted_by_id: Option<i64>,
+    pub deleted_by: Option<String>,
+    pub deleted_at: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct AuthSession {
     pub user: UserRecord,
     pub expires_at: i64,
+    /// Set when the caller authenticated with an API token; interactive
+    /// logins may do everything the user's role allows.
+    pub scopes: Option<Vec<ApiTokenScope>>,
+}
+
+impl AuthSession {
+    pub fn allows(&self, scope: ApiTokenScope) -> bool {
+        self.scopes
+            .as_ref()
+            .is_none_or(|scopes| scopes.contains(&scope))
+    }
+}
+
+/// What an API token may be used for. Tokens never exceed the role of the
+/// user they were issued to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiTokenScope {
+    /// Listing, search, thumbnails and downloads.
+    Read,
+    /// Uploads, file management and per-user file state such as favorites.
+    Write,
+    /// The `/api/admin` endpoints.
+    Admin,
+}
+
+impl ApiTokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl TryFrom<&str> for ApiTokenScope {
+    type Error = ApiError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "admin" => Ok(Self::Admin),
+            _ => Err(ApiError::internal(
+                "Invalid token scope stored in database.",
+            )),
+        }
+    }
+}
+
+/// An issued API token. The secret itself is only returned once, at
+/// creation; `id` addresses the token for revocation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenView {
+    pub id: i64,
+    pub user_id: i64,
+    pub username: String,
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub created_at: i64,
+    pub expires_at: i64,
+    pub last_used_at: Option<i64>,
 }
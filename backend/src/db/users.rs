@@ -244,6 +244,7 @@ impl AuthDb {
             AuthSession {
                 user,
                 expires_at: row.get("expires_at"),
+                scopes: None,
             },
             next_expires_at,
         )))
@@ -297,6 +298,7 @@ impl AuthDb {
         Ok(Some(AuthSession {
             user,
             expires_at: row.get("expires_at"),
+            scopes: None,
         }))
     }
 
@@ -330,6 +332,25 @@ impl AuthDb {
         &self,
         token: &str,
         path: &str,
+    ) -> ApiResult<Option<AuthSession>> {
+        self.signed_token_session(token, path, false).await
+    }
+
+    /// Like `signed_file_session`, but the token also admits every path
+    /// below the one it was issued for.
+    pub async fn signed_scope_session(
+        &self,
+        token: &str,
+        path: &str,
+    ) -> ApiResult<Option<AuthSession>> {
+        self.signed_token_session(token, path, true).await
+    }
+
+    async fn signed_token_session(
+        &self,
+        token: &str,
+        path: &str,
+        include_descendants: bool,
     ) -> ApiResult<Option<AuthSession>> {
         let now = now_unix() as i64;
         sqlx::query("DELETE FROM signed_file_tokens WHERE expires_at <= ?1")
@@ -349,13 +370,16 @@ impl AuthDb {
             FROM signed_file_tokens t
             JOIN users u ON u.id = t.user_id
             LEFT JOIN user_resource_usage uru ON uru.user_id = u.id
-            WHERE t.token_hash = ?1 AND t.path = ?2 AND t.expires_at > ?3
+            WHERE t.token_hash = ?1
+              AND (t.path = ?2 OR (?4 AND substr(?2, 1, length(t.path) + 1) = t.path || '/'))
+              AND t.expires_at > ?3
             GROUP BY t.token_hash
             "#,
         )
         .bind(&token_hash)
         .bind(path)
         .bind(now)
+        .bind(include_descendants)
         .fetch_optional(&self.pool)
         .await
         .map_err(db_error)?
@@ -383,6 +407,90 @@ impl AuthDb {
         Ok(Some(AuthSession {
             user,
             expires_at: row.get("expires_at"),
+            scopes: None,
+        }))
+    }
+
+    pub async fn create_dav_token(
+        &self,
+        user_id: i64,
+        token: &str,
+        ttl_seconds: u64,
+    ) -> ApiResult<i64> {
+        let now = now_unix() as i64;
+        let expires_at = now.saturating_add(ttl_seconds as i64);
+        sqlx::query(
+            r#"
+            INSERT INTO dav_tokens (token_hash, user_id, expires_at, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            "#,
+        )
+        .bind(hash_token(token))
+        .bind(user_id)
+        .bind(expires_at)
+        .bind(now)
+        .execute(&self.pool)
+        .await
+        .map_err(db_error)?;
+        Ok(expires_at)
+    }
+
+    /// Resolves HTTP Basic credentials presented to `/dav`: the username must
+    /// match the user the token was issued to.
+    pub async fn dav_session(&self, username: &str, token: &str) -> ApiResult<Option<AuthSession>> {
+        let now = now_unix() as i64;
+        sqlx::query("DELETE FROM dav_tokens WHERE expires_at <= ?1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+
+        let token_hash = hash_token(token);
+        let Some(row) = sqlx::query(
+            r#"
+            SELECT
+                t.expires_at,
+                u.id, u.username, u.role, u.totp_secret, u.enabled,
+                u.created_at, u.updated_at, u.last_login_at, u.last_seen_at,
+                COALESCE(SUM(uru.total_bytes_served), 0) AS total_bytes_served
+            FROM dav_tokens t
+            JOIN users u ON u.id = t.user_id
+            LEFT JOIN user_resource_usage uru ON uru.user_id = u.id
+            WHERE t.token_hash = ?1 AND u.username = ?2 AND t.expires_at > ?3
+            GROUP BY t.token_hash
+            "#,
+        )
+        .bind(&token_hash)
+        .bind(username)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(db_error)?
+        else {
+            return Ok(None);
+        };
+
+        let user = user_from_row(&row)?;
+        if !user.enabled {
+            sqlx::query("DELETE FROM dav_tokens WHERE user_id = ?1")
+                .bind(user.id)
+                .execute(&self.pool)
+                .await
+                .map_err(db_error)?;
+            return Ok(None);
+        }
+
+        sqlx::query("UPDATE dav_tokens SET last_used_at = ?1 WHERE token_hash = ?2")
+            .bind(now)
+            .bind(&token_hash)
+            .execute(&self.pool)
+            .await
+            .map_err(db_error)?;
+
+        Ok(Some(AuthSession {
+            user,
+            expires_at: row.get("expires_at"),
+            scopes: None,
         }))
     }
 
@@ -413,7 +521,7 @@ impl AuthDb {
         Ok(())
     }
 
-    async fn remove_sessions_for_user(&self, user_id: i64) -> ApiResult<()> {
+    pub(super) async fn remove_sessions_for_user(&self, user_id: i64) -> ApiResult<()> {
         let mut tx = self.pool.begin().await.map_err(db_error)?;
         sqlx::query("DELETE FROM sessions WHERE user_id = ?1")
             .bind(user_id)
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::path_guard::{DIR_CONFIG_FILE, is_hidden_name};
+
+/// Once the cache holds this many directories it is cleared before
+/// inserting new ones; entries are cheap to rebuild.
+const MAX_CACHED_DIRS: usize = 4096;
+
+/// Per-directory settings read from a `.mlist.toml` inside it. They apply
+/// to that directory only, not to its subdirectories. Unknown keys make
+/// the file invalid, and an invalid file is logged and ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirConfig {
+    /// `false` keeps the directory's entries from non-admins everywhere
+    /// they could be enumerated: listings, WebDAV, tree, search, playlists,
+    /// feeds, folder downloads and the public index. Files in it stay
+    /// reachable through links.
+    pub listing: Option<bool>,
+    /// Default `sort` (`name`, `natural`, `size` or `mtime`) and `order`
+    /// (`asc` or `desc`) when the request does not choose one.
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    /// Entry names listed first, in this sequence, ahead of whatever sort
+    /// applies to the rest.
+    pub pin: Vec<String>,
+    /// Glob patterns for entries left out of every enumeration of the
+    /// directory, in addition to `MLIST_HIDE_PATTERNS`. Unlike those, the
+    /// entries stay reachable by path; use `.private` to restrict access.
+    pub hide: Vec<String>,
+    pub download: Option<DownloadMode>,
+    /// Short text shown with the listing.
+    pub description: Option<String>,
+}
+
+impl DirConfig {
+    /// Whether the directory's entries may be enumerated for a user.
+    pub fn lists_for(&self, admin: bool) -> bool {
+        admin || self.listing != Some(false)
+    }
+
+    /// Whether `name` is left out by the directory's `hide` patterns.
+    pub fn hides(&self, name: &str) -> bool {
+        is_hidden_name(name, &self.hide)
+    }
+}
+
+/// How files in the directory are served by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadMode {
+    /// Opened in the browser when it can display them.
+    Inline,
+    /// Always saved, as with `?download=1`.
+    Attachment,
+}
+
+/// Parsed `.mlist.toml` files keyed by directory and revalidated against
+/// the file's mtime on every lookup, so edits apply immediately.
+#[derive(Debug, Clone, Default)]
+pub struct DirConfigCache {
+    inner: Arc<RwLock<HashMap<PathBuf, CachedDirConfig>>>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedDirConfig {
+    mtime: Option<SystemTime>,
+    config: Arc<DirConfig>,
+}
+
+impl DirConfigCache {
+    /// Settings for the resolved directory `dir`; the defaults when it has
+    /// no readable, valid `.mlist.toml`. Like the `.private` markers, the
+    /// file only counts when it is a regular file, not a symbolic link.
+    pub async fn load(&self, dir: &Path) -> Arc<DirConfig> {
+        let file = dir.join(DIR_CONFIG_FILE);
+        let mtime = match tokio::fs::symlink_metadata(&file).await {
+            Ok(metadata) if metadata.is_file() => metadata.modified().ok(),
+            _ => return Arc::default(),
+        };
+        if let Some(cached) = self.inner.read().await.get(dir) {
+            if cached.mtime == mtime {
+                return Arc::clone(&cached.config);
+            }
+        }
+
+        let config = Arc::new(read_dir_config(&file).await);
+        let mut cached = self.inner.write().await;
+        if cached.len() >= MAX_CACHED_DIRS {
+            cached.clear();
+        }
+        cached.insert(
+            dir.to_path_buf(),
+            CachedDirConfig {
+                mtime,
+                config: Arc::clone(&config),
+            },
+        );
+        config
+    }
+}
+
+/// Settings for `dir` read straight from disk, for crawls that run on a
+/// blocking thread outside the cache.
+pub fn load_blocking(dir: &Path) -> DirConfig {
+    let file = dir.join(DIR_CONFIG_FILE);
+    if !std::fs::symlink_metadata(&file).is_ok_and(|metadata| metadata.is_file()) {
+        return DirConfig::default();
+    }
+    let parsed = match std::fs::read_to_string(&file) {
+        Ok(raw) => parse(&raw),
+        Err(err) => Err(err.to_string()),
+    };
+    parsed.unwrap_or_else(|err| {
+        warn!("ignoring invalid {}: {err}", file.display());
+        DirConfig::default()
+    })
+}
+
+async fn read_dir_config(file: &Path) -> DirConfig {
+    let parsed = match tokio::fs::read_to_string(file).await {
+        Ok(raw) => parse(&raw),
+        Err(err) => Err(err.to_string()),
+    };
+    parsed.unwrap_or_else(|err| {
+        warn!("ignoring invalid {}: {err}", file.display());
+        DirConfig::default()
+    })
+}
+
+pub(crate) fn parse(raw: &str) -> Result<DirConfig, String> {
+    let config: DirConfig = toml::from_str(raw).map_err(|err| err.to_string())?;
+    if let Some(sort) = config.sort.as_deref() {
+        if !matches!(sort, "name" | "natural" | "size" | "mtime") {
+            return Err(format!("unknown sort {sort:?}"));
+        }
+    }
+    if let Some(order) = config.order.as_deref() {
+        if !matches!(order, "asc" | "desc") {
+            return Err(format!("unknown order {order:?}"));
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirConfig, DirConfigCache, DownloadMode, load_blocking, parse};
+
+    #[test]
+    fn settings_are_parsed_and_validated() {
+        let config = parse(
+            r#"
+            listing = false
+            sort = "mtime"
+            order = "desc"
+            hide = ["*.srt"]
+            pin = ["README.txt", "SHA256SUMS"]
+            download = "attachment"
+            description = "Nightly builds"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.listing, Some(false));
+        assert_eq!(config.sort.as_deref(), Some("mtime"));
+        assert_eq!(config.hide, vec!["*.srt"]);
+        assert_eq!(config.pin, vec!["README.txt", "SHA256SUMS"]);
+        assert_eq!(config.download, Some(DownloadMode::Attachment));
+        assert_eq!(config.description.as_deref(), Some("Nightly builds"));
+
+        assert_eq!(parse("").unwrap(), DirConfig::default());
+        assert!(parse("sort = \"random\"").is_err());
+        assert!(parse("colour = \"blue\"").is_err());
+    }
+
+    #[tokio::test]
+    async fn edits_are_picked_up_and_bad_files_ignored() {
+        let dir = std::env::temp_dir().join(format!(
+            "mlist-dir-config-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache = DirConfigCache::default();
+        assert_eq!(*cache.load(&dir).await, DirConfig::default());
+
+        let file = dir.join(".mlist.toml");
+        std::fs::write(&file, "description = \"one\"").unwrap();
+        assert_eq!(cache.load(&dir).await.description.as_deref(), Some("one"));
+
+        std::fs::write(&file, "description = [").unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(*cache.load(&dir).await, DirConfig::default());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinked_files_are_not_config() {
+        let dir = std::env::temp_dir().join(format!(
+            "mlist-dir-config-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("elsewhere.toml");
+        std::fs::write(&target, "listing = false").unwrap();
+        std::os::unix::fs::symlink(&target, dir.join(".mlist.toml")).unwrap();
+
+        let cache = DirConfigCache::default();
+        assert_eq!(*cache.load(&dir).await, DirConfig::default());
+        assert_eq!(load_blocking(&dir), DirConfig::default());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::config::AppConfig;
+use crate::dir_config;
+use crate::path_guard::{DIR_CONFIG_FILE, PRIVATE_FILE_MARKER_PREFIX, is_marker_name};
+
+/// How serious a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Requests will fail or a feature cannot work.
+    Error,
+    /// Works, but most likely not as intended.
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    pub severity: Severity,
+    /// Relative to the root for problems in the tree; absent for settings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match &self.path {
+            Some(path) if path.is_empty() => write!(f, "{severity}: /: {}", self.message),
+            Some(path) => write!(f, "{severity}: {path}: {}", self.message),
+            None => write!(f, "{severity}: {}", self.message),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// Set when the tree scan stopped at its entry limit.
+    pub truncated: bool,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+    }
+
+    fn push(&mut self, severity: Severity, path: Option<&str>, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            path: path.map(str::to_string),
+            message: message.into(),
+        });
+    }
+}
+
+/// Looks for problems that otherwise only show up as failed requests:
+/// data directories that cannot be written, missing tools, and marker or
+/// `.mlist.toml` files in the tree that are broken or unreadable. The
+/// configuration itself is already valid by the time it gets here.
+///
+/// Blocking. The scan does not follow symlinks and stops after
+/// `max_entries` directory entries.
+pub fn diagnose(config: &AppConfig, max_entries: usize) -> Report {
+    let mut report = Report::default();
+    check_settings(config, &mut report);
+    scan_tree(&config.root_dir, max_entries, &mut report);
+    report
+}
+
+fn check_settings(config: &AppConfig, report: &mut Report) {
+    let mut writable = vec![("MLIST_THUMB_CACHE_DIR", config.thumb_cache_dir.as_path())];
+    if let Some(parent) = config.database_path.parent() {
+        writable.push(("MLIST_DATABASE_PATH", parent));
+    }
+    if config.upload_enabled {
+        writable.push((
+            "MLIST_UPLOAD_STAGING_DIR",
+            config.upload_staging_dir.as_path(),
+        ));
+        if same_filesystem(&config.upload_staging_dir, &config.root_dir) == Some(false) {
+            report.push(
+                Severity::Error,
+                None,
+                "MLIST_UPLOAD_STAGING_DIR is on a different filesystem than the root, so \
+                 finished resumable uploads cannot be moved into place.",
+            );
+        }
+    }
+    if config.hls_enabled {
+        writable.push(("MLIST_TRANSCODE_DIR", config.transcode_dir.as_path()));
+        if config.ffmpeg_path.is_none() {
+            report.push(
+                Severity::Error,
+                None,
+                "MLIST_HLS_ENABLED is set but MLIST_FFMPEG_PATH is not.",
+            );
+        }
+    }
+    if config.public_index_enabled && !config.index_enabled {
+        report.push(
+            Severity::Error,
+            None,
+            "MLIST_PUBLIC_INDEX_ENABLED is set but MLIST_INDEX_ENABLED is not.",
+        );
+    }
+    if let Some(trash_dir) = &config.trash_dir {
+        writable.push(("MLIST_TRASH_DIR", trash_dir.as_path()));
+    }
+    if let Some(parent) = config.audit_log_path.as_deref().and_then(Path::parent) {
+        writable.push(("MLIST_AUDIT_LOG_PATH", parent));
+    }
+    for (name, dir) in writable {
+        if let Err(err) = probe_writable(dir) {
+            report.push(Severity::Error, None, format!("{name}: {err}"));
+        }
+    }
+
+    let tools = [
+        ("MLIST_FFMPEG_PATH", &config.ffmpeg_path),
+        ("MLIST_FFPROBE_PATH", &config.ffprobe_path),
+    ];
+    for (name, path) in tools {
+        let Some(path) = path else {
+            continue;
+        };
+        if let Err(err) = check_executable(path) {
+            report.push(Severity::Error, None, format!("{name}: {err}"));
+        }
+    }
+}
+
+/// Data directories are created on first use, so the nearest one that
+/// already exists is tested by creating and removing a file in it.
+fn probe_writable(dir: &Path) -> Result<(), String> {
+    let existing = dir
+        .ancestors()
+        .find(|path| path.exists())
+        .ok_or_else(|| format!("no part of {} exists", dir.display()))?;
+    if !existing.is_dir() {
+        return Err(format!("{} is not a directory", existing.display()));
+    }
+    let probe = existing.join(format!(".mlist-doctor-{}", uuid::Uuid::new_v4().simple()));
+    std::fs::File::create_new(&probe)
+        .map_err(|err| format!("{} is not writable: {err}", existing.display()))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+fn check_executable(path: &Path) -> Result<(), String> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|err| format!("{} cannot be read: {err}", path.display()))?;
+    if !metadata.is_file() {
+        return Err(format!("{} is not a file", path.display()));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(format!("{} is not executable", path.display()));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn same_filesystem(left: &Path, right: &Path) -> Option<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let device = |path: &Path| {
+        path.ancestors()
+            .find_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.dev())
+    };
+    Some(device(left)? == device(right)?)
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(_left: &Path, _right: &Path) -> Option<bool> {
+    None
+}
+
+fn scan_tree(root: &Path, max_entries: usize, report: &mut Report) {
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+    let mut scanned = 0_usize;
+    while let Some((dir, relative)) = pending.pop() {
+        let read_dir = match std::fs::read_dir(&dir) {
+            Ok(read_dir) => read_dir,
+            Err(err) => {
+                report.push(
+                    Severity::Warning,
+                    Some(&relative),
+                    format!("directory cannot be read, so it lists as empty: {err}"),
+                );
+                continue;
+            }
+        };
+        let mut names = HashSet::new();
+        let mut file_markers = Vec::new();
+        for entry in read_dir.flatten() {
+            scanned += 1;
+            if scanned > max_entries {
+                report.truncated = true;
+                return;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name().to_string_lossy().to_string();
+            let path = if relative.is_empty() {
+                name.clone()
+            } else {
+                format!("{relative}/{name}")
+            };
+
+            if name == DIR_CONFIG_FILE {
+                if let Err(err) = std::fs::read_to_string(entry.path())
+                    .map_err(|err| err.to_string())
+                    .and_then(|raw| dir_config::parse(&raw))
+                {
+                    report.push(
+                        Severity::Warning,
+                        Some(&path),
+                        format!("ignored because it is invalid: {err}"),
+                    );
+                }
+                continue;
+            }
+            if is_marker_name(&name) {
+                // Access checks refuse every request below a marker they
+                // cannot trust.
+                if file_type.is_symlink() {
+                    report.push(
+                        Severity::Error,
+                        Some(&path),
+                        "marker is a symbolic link, so requests here are refused",
+                    );
+                } else if !file_type.is_file() {
+                    report.push(
+                        Severity::Error,
+                        Some(&path),
+                        "marker is not a regular file, so requests here are refused",
+                    );
+                } else if let Some(target) = name.strip_prefix(PRIVATE_FILE_MARKER_PREFIX) {
+                    file_markers.push((path, target.to_string()));
+                }
+                continue;
+            }
+
+            if file_type.is_dir() {
+                pending.push((entry.path(), path));
+            }
+            names.insert(name);
+        }
+        for (path, target) in file_markers {
+            if !names.contains(&target) {
+                report.push(
+                    Severity::Warning,
+                    Some(&path),
+                    format!("marks {target} as private, but there is no such entry"),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Severity, diagnose};
+    use crate::config::AppConfig;
+
+    #[test]
+    fn broken_markers_and_settings_are_reported() {
+        let base =
+            std::env::temp_dir().join(format!("mlist-doctor-{}", uuid::Uuid::new_v4().simple()));
+        let root = base.join("files");
+        std::fs::create_dir_all(root.join("films/.private")).unwrap();
+        std::fs::create_dir_all(root.join("music")).unwrap();
+        std::fs::write(root.join("music/.mlist.toml"), "sort = \"size\"\n").unwrap();
+        std::fs::write(root.join("music/a.flac"), b"").unwrap();
+        std::fs::write(root.join("music/.private.a.flac"), b"").unwrap();
+        std::fs::write(root.join("music/.private.gone.flac"), b"").unwrap();
+        std::fs::create_dir_all(root.join("notes")).unwrap();
+        std::fs::write(root.join("notes/.mlist.toml"), "sort = \"colour\"\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("/dev/null", root.join("notes/.writable")).unwrap();
+
+        let config = AppConfig {
+            root_dir: root.clone(),
+            database_path: base.join("mlist.sqlite3"),
+            thumb_cache_dir: base.join("thumbs"),
+            hls_enabled: true,
+            ..AppConfig::default()
+        };
+        let report = diagnose(&config, usize::MAX);
+        let found = |path: &str| {
+            report
+                .findings
+                .iter()
+                .find(|finding| finding.path.as_deref() == Some(path))
+                .map(|finding| finding.severity)
+        };
+        assert_eq!(found("films/.private"), Some(Severity::Error));
+        assert_eq!(found("music/.mlist.toml"), None);
+        assert_eq!(found("music/.private.a.flac"), None);
+        assert_eq!(found("music/.private.gone.flac"), Some(Severity::Warning));
+        assert_eq!(found("notes/.mlist.toml"), Some(Severity::Warning));
+        #[cfg(unix)]
+        assert_eq!(found("notes/.writable"), Some(Severity::Error));
+        assert!(
+            report
+                .findings
+                .iter()
+                .any(|finding| finding.message.contains("MLIST_FFMPEG_PATH"))
+        );
+        assert!(report.has_errors());
+        assert!(!report.truncated);
+
+        let limited = diagnose(&config, 2);
+        assert!(limited.truncated);
+        let _ = std::fs::remove_dir_all(base);
+    }
+}
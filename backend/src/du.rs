@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use crate::path_guard::{PRIVATE_MARKER_FILE, private_file_marker_name};
+use crate::session::now_unix;
+
+/// Once the cache holds this many directories, expired entries are dropped
+/// before inserting new ones.
+const MAX_CACHED_DIRS: usize = 4096;
+
+/// Recursive directory sizes computed off the request path. A lookup that
+/// misses schedules a background walk and returns `None`; the next listing
+/// picks up the result. Entries are keyed by directory and whether private
+/// subtrees are counted, and are invalidated when the directory's own mtime
+/// changes or the TTL elapses (nested changes do not touch the parent mtime).
+#[derive(Debug, Clone)]
+pub struct DirSizeCache {
+    inner: Arc<RwLock<HashMap<(PathBuf, bool), CacheSlot>>>,
+    ttl_seconds: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CacheSlot {
+    Pending,
+    Ready {
+        mtime: Option<SystemTime>,
+        size: u64,
+        computed_at: u64,
+    },
+}
+
+impl DirSizeCache {
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(HashMap::new())),
+            ttl_seconds,
+        }
+    }
+
+    pub async fn lookup(
+        &self,
+        dir: &Path,
+        mtime: Option<SystemTime>,
+        include_private: bool,
+    ) -> Option<u64> {
+        let key = (dir.to_path_buf(), include_private);
+        let now = now_unix();
+        {
+            let slots = self.inner.read().await;
+            match slots.get(&key) {
+                Some(CacheSlot::Pending) => return None,
+                Some(CacheSlot::Ready {
+                    mtime: cached_mtime,
+                    size,
+                    computed_at,
+                }) if *cached_mtime == mtime && !self.expired(*computed_at, now) => {
+                    return Some(*size);
+                }
+                _ => {}
+            }
+        }
+
+        let mut slots = self.inner.write().await;
+        if matches!(slots.get(&key), Some(CacheSlot::Pending)) {
+            return None;
+        }
+        if slots.len() >= MAX_CACHED_DIRS {
+            slots.retain(|_, slot| match slot {
+                CacheSlot::Pending => true,
+                CacheSlot::Ready { computed_at, .. } => !self.expired(*computed_at, now),
+            });
+        }
+        slots.insert(key.clone(), CacheSlot::Pending);
+        drop(slots);
+
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let (path, include_private) = key.clone();
+            let walked =
+                tokio::task::spawn_blocking(move || directory_size(&path, include_private)).await;
+            let mut slots = cache.inner.write().await;
+            match walked {
+                Ok(size) => {
+                    slots.insert(
+                        key,
+                        CacheSlot::Ready {
+                            mtime,
+                            size,
+                            computed_at: now_unix(),
+                        },
+                    );
+                }
+                Err(err) => {
+                    debug!("directory size walk failed: {err}");
+                    slots.remove(&key);
+                }
+            }
+        });
+
+        None
+    }
+
+    fn expired(&self, computed_at: u64, now: u64) -> bool {
+        now.saturating_sub(computed_at) >= self.ttl_seconds
+    }
+}
+
+/// Sums regular file sizes below `dir`. Symlinks are never followed and
+/// unreadable entries are skipped rather than failing the whole walk.
+fn directory_size(dir: &Path, include_private: bool) -> u64 {
+    let mut total = 0u64;
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&current) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_symlink() {
+                continue;
+            }
+            if file_type.is_dir() {
+                let path = entry.path();
+                if !include_private && path.join(PRIVATE_MARKER_FILE).exists() {
+                    continue;
+                }
+                pending.push(path);
+            } else if file_type.is_file() {
+                let hidden = entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| current.join(private_file_marker_name(name)).exists());
+                if !include_private && hidden {
+                    continue;
+                }
+                let len = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                total = total.saturating_add(len);
+            }
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirSizeCache, directory_size};
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("mlist-du-{name}-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(dir.join("season/extras")).unwrap();
+        std::fs::create_dir_all(dir.join("season/hidden")).unwrap();
+        std::fs::write(dir.join("season/e01.mkv"), vec![0u8; 100]).unwrap();
+        std::fs::write(dir.join("season/extras/bonus.mkv"), vec![0u8; 20]).unwrap();
+        std::fs::write(dir.join("season/hidden/.private"), b"").unwrap();
+        std::fs::write(dir.join("season/hidden/secret.mkv"), vec![0u8; 5]).unwrap();
+        std::fs::write(dir.join("season/e02.mkv"), vec![0u8; 7]).unwrap();
+        std::fs::write(dir.join("season/.private.e02.mkv"), b"").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("/etc", dir.join("season/escape")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn directory_size_skips_symlinks_and_private_scopes() {
+        let dir = fixture("walk");
+        assert_eq!(directory_size(&dir.join("season"), false), 120);
+        assert_eq!(directory_size(&dir.join("season"), true), 132);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn lookup_fills_cache_in_background() {
+        let dir = fixture("cache");
+        let season = dir.join("season");
+        let mtime = std::fs::metadata(&season).unwrap().modified().ok();
+        let cache = DirSizeCache::new(60);
+
+        assert_eq!(cache.lookup(&season, mtime, false).await, None);
+        let mut size = None;
+        for _ in 0..100 {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            size = cache.lookup(&season, mtime, false).await;
+            if size.is_some() {
+                break;
+            }
+        }
+        assert_eq!(size, Some(120));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -2,16 +2,28 @@ use axum::Json;
 use axum::extract::{Path as AxumPath, Query, State};
 use axum::http::HeaderMap;
 
+use crate::auth::discover_private_scopes;
 use crate::db::UserRole;
-use crate::db::UserView;
-use crate::errors::ApiResult;
+use crate::db::{ResourceStatsView, UserView};
+use crate::doctor::{Report, diagnose};
+use crate::errors::{ApiError, ApiResult};
+use crate::session::{now_unix, unix_to_rfc3339};
 
-use super::helpers::{binding_response, generate_totp_secret, require_admin, validate_login_name};
+use super::helpers::{
+    API_TOKEN_PREFIX, binding_response, generate_totp_secret, normalize_request_path,
+    require_admin, validate_login_name,
+};
 use super::types::{
-    AppState, AuditEventsResponse, AuditQuery, AuditResourcesResponse, CreateUserRequest,
-    GenericOkResponse, TotpBindingResponse, UsersResponse,
+    ApiTokenCreatedResponse, ApiTokensResponse, AppState, AuditEventsResponse, AuditQuery,
+    AuditResourcesResponse, ClearLoginBlocksResponse, CreateApiTokenRequest, CreateUserRequest,
+    EntryKind, GenericOkResponse, LoginBlockView, LoginBlocksResponse, ScopeView, ScopesResponse,
+    SessionsResponse, StatsQuery, TopResourcesResponse, TotpBindingResponse, UsersResponse,
 };
 
+/// Upper bound on directory entries inspected when listing private scopes
+/// or running diagnostics.
+const MAX_SCOPE_SCAN_ENTRIES: usize = 200_000;
+
 pub async fn admin_users_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -63,6 +75,29 @@ pub async fn admin_audit_resources_handler(
     }))
 }
 
+/// Download totals for a file, or for everything below a directory.
+pub async fn admin_stats_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<StatsQuery>,
+) -> ApiResult<Json<ResourceStatsView>> {
+    require_admin(&state, &headers).await?;
+    let path = normalize_request_path(&state, query.path.as_deref())?;
+    Ok(Json(state.db.resource_stats(&path).await?))
+}
+
+pub async fn admin_stats_top_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<StatsQuery>,
+) -> ApiResult<Json<TopResourcesResponse>> {
+    require_admin(&state, &headers).await?;
+    let path = normalize_request_path(&state, query.path.as_deref())?;
+    let limit = query.limit.unwrap_or(20).clamp(1, 500);
+    let resources = state.db.top_resources(&path, limit).await?;
+    Ok(Json(TopResourcesResponse { path, resources }))
+}
+
 pub async fn admin_create_user_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -117,3 +152,156 @@ pub async fn admin_reset_totp_handler(
     let user = state.db.reset_totp(user_id, &secret).await?;
     Ok(Json(binding_response(user.view(), &secret)?))
 }
+
+pub async fn admin_sessions_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<SessionsResponse>> {
+    require_admin(&state, &headers).await?;
+    Ok(Json(SessionsResponse {
+        sessions: state.db.list_active_sessions().await?,
+    }))
+}
+
+pub async fn admin_revoke_session_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(session_id): AxumPath<String>,
+) -> ApiResult<Json<GenericOkResponse>> {
+    require_admin(&state, &headers).await?;
+    state.db.revoke_session(&session_id).await?;
+    Ok(Json(GenericOkResponse { ok: true }))
+}
+
+pub async fn admin_revoke_user_sessions_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(user_id): AxumPath<i64>,
+) -> ApiResult<Json<GenericOkResponse>> {
+    require_admin(&state, &headers).await?;
+    state.db.revoke_user_sessions(user_id).await?;
+    Ok(Json(GenericOkResponse { ok: true }))
+}
+
+pub async fn admin_api_tokens_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ApiTokensResponse>> {
+    require_admin(&state, &headers).await?;
+    Ok(Json(ApiTokensResponse {
+        tokens: state.db.list_api_tokens().await?,
+    }))
+}
+
+/// Issues a bearer token acting as `user_id` for scripted clients that
+/// cannot go through the TOTP login.
+pub async fn admin_create_api_token_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(user_id): AxumPath<i64>,
+    Json(payload): Json<CreateApiTokenRequest>,
+) -> ApiResult<Json<ApiTokenCreatedResponse>> {
+    require_admin(&state, &headers).await?;
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > 64 {
+        return Err(ApiError::bad_request(
+            "Token name must be between 1 and 64 characters.",
+        ));
+    }
+    let mut scopes = payload.scopes;
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(ApiError::bad_request("At least one scope is required."));
+    }
+    let ttl_seconds = payload
+        .ttl_seconds
+        .unwrap_or(state.config.api_token_ttl_seconds);
+    if ttl_seconds == 0 {
+        return Err(ApiError::bad_request("ttlSeconds must be positive."));
+    }
+
+    let token = format!("{API_TOKEN_PREFIX}{}", uuid::Uuid::new_v4().simple());
+    let info = state
+        .db
+        .create_api_token(user_id, name, &scopes, &token, ttl_seconds)
+        .await?;
+    Ok(Json(ApiTokenCreatedResponse { token, info }))
+}
+
+pub async fn admin_revoke_api_token_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(token_id): AxumPath<i64>,
+) -> ApiResult<Json<GenericOkResponse>> {
+    require_admin(&state, &headers).await?;
+    state.db.revoke_api_token(token_id).await?;
+    Ok(Json(GenericOkResponse { ok: true }))
+}
+
+pub async fn admin_login_blocks_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<LoginBlocksResponse>> {
+    require_admin(&state, &headers).await?;
+    let blocks = state
+        .login_limiter
+        .blocked(now_unix())
+        .await
+        .into_iter()
+        .map(|(key, until)| LoginBlockView {
+            key,
+            blocked_until: unix_to_rfc3339(until),
+        })
+        .collect();
+    Ok(Json(LoginBlocksResponse { blocks }))
+}
+
+pub async fn admin_clear_login_blocks_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ClearLoginBlocksResponse>> {
+    require_admin(&state, &headers).await?;
+    let cleared = state.login_limiter.clear(now_unix()).await;
+    state.login_challenges.clear();
+    Ok(Json(ClearLoginBlocksResponse { ok: true, cleared }))
+}
+
+/// Same checks as `doctor`, against the running configuration.
+pub async fn admin_diagnostics_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<Report>> {
+    require_admin(&state, &headers).await?;
+    let config = state.config.latest();
+    let report = tokio::task::spawn_blocking(move || diagnose(&config, MAX_SCOPE_SCAN_ENTRIES))
+        .await
+        .map_err(|_| ApiError::internal("Diagnostics failed."))?;
+    Ok(Json(report))
+}
+
+pub async fn admin_scopes_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ScopesResponse>> {
+    require_admin(&state, &headers).await?;
+    let root = state.config.root_dir.clone();
+    let (scopes, truncated) =
+        tokio::task::spawn_blocking(move || discover_private_scopes(&root, MAX_SCOPE_SCAN_ENTRIES))
+            .await
+            .map_err(|_| ApiError::internal("Scope scan failed."))?;
+    Ok(Json(ScopesResponse {
+        scopes: scopes
+            .into_iter()
+            .map(|scope| ScopeView {
+                path: scope.path,
+                kind: if scope.is_dir {
+                    EntryKind::Dir
+                } else {
+                    EntryKind::File
+                },
+            })
+            .collect(),
+        truncated,
+    }))
+}
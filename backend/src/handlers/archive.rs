@@ -0,0 +1,163 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::Response;
+
+use crate::archive::{find_member, is_browsable_archive, list_members, stream_member};
+use crate::audit::{AuditEvent, AuditKind};
+use crate::db::{RecordResourceAccess, ResourceKind};
+use crate::errors::{ApiError, ApiResult};
+use crate::session::now_unix;
+
+use super::files::{
+    AccessibleFile, CountingFileStream, FileAccessRecorder, ensure_file_accessible,
+};
+use super::helpers::{
+    client_ip_for_request, file_session_for_request, normalize_request_path, require_session,
+};
+use super::http_util::{build_quota_exceeded, content_disposition};
+use super::types::{AppState, ArchiveEntry, ArchiveListResponse, ArchiveQuery, EntryKind};
+
+/// Upper bound on members returned by one listing.
+const MAX_ARCHIVE_ENTRIES: usize = 10_000;
+
+pub async fn archive_list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ArchiveQuery>,
+) -> ApiResult<Json<ArchiveListResponse>> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+    ensure_browsable(&accessible)?;
+
+    let (members, truncated) = list_members(accessible.resolved, MAX_ARCHIVE_ENTRIES).await?;
+    let entries = members
+        .into_iter()
+        .map(|member| ArchiveEntry {
+            name: member.name,
+            kind: if member.is_dir {
+                EntryKind::Dir
+            } else {
+                EntryKind::File
+            },
+            size: member.size,
+            compressed_size: member.compressed_size,
+            mtime: member.mtime,
+            encrypted: member.encrypted,
+        })
+        .collect();
+    Ok(Json(ArchiveListResponse {
+        path: relative_path,
+        entries,
+        truncated,
+    }))
+}
+
+/// Streams one member of an archive, decompressing it on the fly. Like
+/// `/api/thumb`, a signed token for the archive is accepted in place of the
+/// bearer header so members can be opened straight from the browser.
+pub async fn archive_file_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<ArchiveQuery>,
+) -> ApiResult<Response> {
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let session = match query.token.as_deref() {
+        Some(token) => file_session_for_request(&state, &relative_path, Some(token)).await?,
+        None => require_session(&state, &headers).await?,
+    };
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+    ensure_browsable(&accessible)?;
+    let inner = query
+        .inner
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| ApiError::bad_request("Archive member is required."))?;
+
+    let member = find_member(accessible.resolved.clone(), inner.clone()).await?;
+    if member.is_dir {
+        return Err(ApiError::bad_request("Archive member is a directory."));
+    }
+    if member.encrypted {
+        return Err(ApiError::bad_request(
+            "Encrypted archive members cannot be read.",
+        ));
+    }
+
+    let client_ip = client_ip_for_request(
+        &headers,
+        peer.ip(),
+        &state.config.trusted_proxies,
+        state.config.forwarded_header,
+    );
+    let meter = match state.download_quotas.check(
+        session.user.id,
+        accessible.private_scope.as_deref(),
+        now_unix(),
+    ) {
+        Ok(meter) => meter,
+        Err(resets_at) => return Ok(build_quota_exceeded(resets_at)),
+    };
+    let permit = state
+        .download_slots
+        .try_acquire(client_ip, accessible.private_scope.as_deref())
+        .ok_or_else(|| ApiError::rate_limited("Too many concurrent downloads."))?;
+
+    let route = "/api/archive/file";
+    let event_id = state
+        .db
+        .start_resource_stream_access(RecordResourceAccess {
+            user_id: session.user.id,
+            kind: ResourceKind::File,
+            path: relative_path.clone(),
+            route,
+            status: StatusCode::OK.as_u16(),
+            bytes_served: 0,
+            file_size: i64::try_from(accessible.metadata.len()).ok(),
+            range_start: None,
+            range_end: None,
+        })
+        .await?;
+    let recorder = FileAccessRecorder::with_audit(
+        state.db.clone(),
+        event_id,
+        state.audit.clone(),
+        AuditEvent {
+            event: AuditKind::Download,
+            ip: client_ip.to_string(),
+            user: Some(session.user.username.clone()),
+            path: Some(format!("{relative_path}#{inner}")),
+            status: Some(StatusCode::OK.as_u16()),
+            bytes: None,
+            range_start: None,
+            range_end: None,
+            transfer: None,
+        },
+    );
+
+    let mime = state.config.guess_mime(&inner);
+    let disposition = content_disposition(Path::new(&inner), false);
+    let counted =
+        CountingFileStream::from_stream(stream_member(accessible.resolved, inner), recorder);
+    Response::builder()
+        .header(header::CONTENT_TYPE, mime.essence_str())
+        .header(header::CONTENT_DISPOSITION, disposition)
+        .header(header::CONTENT_LENGTH, member.size.to_string())
+        .body(Body::from_stream(permit.attach(
+            meter.attach(state.bandwidth.throttle(client_ip, counted)),
+        )))
+        .map_err(|_| ApiError::internal("Failed to build archive response."))
+}
+
+fn ensure_browsable(accessible: &AccessibleFile) -> ApiResult<()> {
+    if is_browsable_archive(&accessible.resolved) {
+        Ok(())
+    } else {
+        Err(ApiError::bad_request("Only zip archives can be browsed."))
+    }
+}
@@ -2,21 +2,23 @@ use std::net::SocketAddr;
 
 use axum::Json;
 use axum::extract::{ConnectInfo, State};
-use axum::http::HeaderMap;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum_extra::extract::CookieJar;
-use axum_extra::extract::cookie::{Cookie, SameSite};
 use tracing::info;
 
+use crate::audit::{AuditEvent, AuditKind};
 use crate::errors::{ApiError, ApiResult};
 use crate::session::{REFRESH_COOKIE_NAME, now_unix, unix_to_rfc3339};
 
 use super::helpers::{
-    bearer_token, build_refresh_cookie, build_totp_binding, client_ip_for_request,
-    current_session, generate_totp_secret, validate_login_name, verify_totp,
+    bearer_token, build_refresh_cookie, build_totp_binding, client_ip_for_request, current_session,
+    generate_totp_secret, validate_login_name, verify_totp,
 };
 use super::types::{
     AppState, BootstrapFinishRequest, BootstrapStartRequest, BootstrapStartResponse,
-    GenericOkResponse, LoginRequest, LoginResponse, MeResponse, RefreshResponse,
+    GenericOkResponse, LoginChallengeResponse, LoginRequest, LoginResponse, MeResponse,
+    RefreshResponse,
 };
 
 pub async fn login_handler(
@@ -25,19 +27,57 @@ pub async fn login_handler(
     ConnectInfo(connect_info): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
-) -> ApiResult<(CookieJar, Json<LoginResponse>)> {
+) -> ApiResult<Response> {
     let now = now_unix();
-    let client_ip = client_ip_for_request(&headers, connect_info.ip()).to_string();
+    let client_ip = client_ip_for_request(
+        &headers,
+        connect_info.ip(),
+        &state.config.trusted_proxies,
+        state.config.forwarded_header,
+    )
+    .to_string();
     let username = payload.username.trim();
     let limiter_key = format!("{client_ip}:{}", username.to_lowercase());
 
     if let Some(until) = state.login_limiter.blocked_until(&limiter_key, now).await {
+        state.audit.record(&AuditEvent::login(
+            AuditKind::LoginBlocked,
+            &client_ip,
+            username,
+        ));
         let remaining = until.saturating_sub(now);
         return Err(ApiError::rate_limited(format!(
             "Too many login failures. Retry in {remaining} seconds."
         )));
     }
 
+    // 账户失败过多时先校验挑战，未通过则不再核对动态码
+    let challenges = &state.login_challenges;
+    if challenges.required(&state.config, username, now)
+        && !challenges
+            .verify(
+                &state.config,
+                username,
+                payload.challenge.as_ref(),
+                &client_ip,
+                now,
+            )
+            .await
+    {
+        state.audit.record(&AuditEvent::login(
+            AuditKind::LoginFailed,
+            &client_ip,
+            username,
+        ));
+        return Ok(challenge_response(
+            &state,
+            username,
+            now,
+            "CHALLENGE_REQUIRED",
+            "Complete the login challenge to continue.",
+        ));
+    }
+
     let user = state
         .db
         .user_by_username(username)
@@ -50,17 +90,33 @@ pub async fn login_handler(
     };
 
     if !valid {
+        state.audit.record(&AuditEvent::login(
+            AuditKind::LoginFailed,
+            &client_ip,
+            username,
+        ));
+        challenges.record_failure(username, now);
         if let Some(until) = state.login_limiter.record_failure(&limiter_key, now).await {
             let remaining = until.saturating_sub(now);
             return Err(ApiError::rate_limited(format!(
                 "Too many login failures. Retry in {remaining} seconds."
             )));
         }
+        if challenges.required(&state.config, username, now) {
+            return Ok(challenge_response(
+                &state,
+                username,
+                now,
+                "UNAUTHORIZED",
+                "Invalid username or code.",
+            ));
+        }
         return Err(ApiError::unauthorized("Invalid username or code."));
     }
 
     let user = user.ok_or_else(|| ApiError::unauthorized("Invalid username or code."))?;
     state.login_limiter.record_success(&limiter_key).await;
+    challenges.record_success(username);
 
     let refresh_token = uuid::Uuid::new_v4().simple().to_string();
     let refresh_expires_at = state
@@ -75,8 +131,17 @@ pub async fn login_handler(
     state.db.record_login(user.id).await?;
 
     info!(ip = client_ip, user = user.username, "login succeeded");
+    state.audit.record(&AuditEvent::login(
+        AuditKind::LoginSucceeded,
+        &client_ip,
+        &user.username,
+    ));
 
-    let cookie = build_refresh_cookie(&refresh_token, state.config.refresh_ttl_seconds);
+    let cookie = build_refresh_cookie(
+        &state.config,
+        &refresh_token,
+        state.config.refresh_ttl_seconds,
+    );
     let updated_jar = jar.add(cookie);
 
     Ok((
@@ -88,7 +153,25 @@ pub async fn login_handler(
             refresh_expires_at: unix_to_rfc3339(refresh_expires_at as u64),
             user: user.view(),
         }),
-    ))
+    )
+        .into_response())
+}
+
+/// A 401 carrying a fresh challenge, which the next attempt must answer.
+fn challenge_response(
+    state: &AppState,
+    username: &str,
+    now: u64,
+    code: &'static str,
+    message: &'static str,
+) -> Response {
+    let challenge = state.login_challenges.issue(&state.config, username, now);
+    let body = LoginChallengeResponse {
+        code,
+        message,
+        challenge,
+    };
+    (StatusCode::UNAUTHORIZED, Json(body)).into_response()
 }
 
 pub async fn bootstrap_start_handler(
@@ -140,7 +223,11 @@ pub async fn bootstrap_finish_handler(
     state.db.record_login(user.id).await?;
     info!(user = user.username, "bootstrap admin created");
 
-    let cookie = build_refresh_cookie(&refresh_token, state.config.refresh_ttl_seconds);
+    let cookie = build_refresh_cookie(
+        &state.config,
+        &refresh_token,
+        state.config.refresh_ttl_seconds,
+    );
     let updated_jar = jar.add(cookie);
 
     Ok((
@@ -186,6 +273,7 @@ pub async fn refresh_handler(
         )
         .await?;
     let updated_jar = jar.add(build_refresh_cookie(
+        &state.config,
         &next_refresh_token,
         state.config.refresh_ttl_seconds,
     ));
@@ -214,12 +302,7 @@ pub async fn logout_handler(
         state.db.remove_access_token(token).await?;
     }
 
-    let removal = Cookie::build((REFRESH_COOKIE_NAME, ""))
-        .path("/api/auth")
-        .http_only(true)
-        .same_site(SameSite::Lax)
-        .max_age(time::Duration::seconds(0))
-        .build();
+    let removal = build_refresh_cookie(&state.config, "", 0);
 
     Ok((jar.remove(removal), Json(GenericOkResponse { ok: true })))
 }
@@ -235,6 +318,7 @@ pub async fn me_handler(
             user: None,
             access_expires_at: None,
             needs_bootstrap,
+            oidc_enabled: state.config.oidc_enabled(),
         }));
     };
 
@@ -243,5 +327,6 @@ pub async fn me_handler(
         user: Some(session.user.view()),
         access_expires_at: Some(unix_to_rfc3339(session.expires_at as u64)),
         needs_bootstrap,
+        oidc_enabled: state.config.oidc_enabled(),
     }))
 }
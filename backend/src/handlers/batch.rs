@@ -0,0 +1,330 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::Response;
+use tokio::fs;
+
+use crate::audit::{AuditEvent, AuditKind};
+use crate::auth::{PrivateAccess, PrivateAnchors};
+use crate::db::{RecordResourceAccess, ResourceKind};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, is_marker_name,
+    resolve_existing_path, resolve_symlink_entry,
+};
+use crate::session::now_unix;
+use crate::tarball::{TarMember, stream_tar, tar_len};
+
+use super::files::{CountingFileStream, FileAccessRecorder, ensure_file_accessible};
+use super::helpers::{
+    client_ip_for_request, join_relative, normalize_request_path, require_session,
+};
+use super::http_util::{build_quota_exceeded, content_disposition};
+use super::types::{AppState, BatchDownloadRequest};
+
+/// Upper bound on paths named in one request.
+const MAX_BATCH_PATHS: usize = 1_000;
+/// Upper bound on archive members once directories are expanded.
+const MAX_BATCH_MEMBERS: usize = 20_000;
+
+/// Streams the selected files and directories as one uncompressed tar.
+/// Member names are relative to the deepest directory containing the whole
+/// selection, and paths already covered by a selected directory are only
+/// included once. Directory contents follow the listing's rules: hidden,
+/// marker and unreadable private entries are left out.
+pub async fn batch_download_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<BatchDownloadRequest>,
+) -> ApiResult<Response> {
+    let session = require_session(&state, &headers).await?;
+    if payload.paths.is_empty() {
+        return Err(ApiError::bad_request("No paths were selected."));
+    }
+    if payload.paths.len() > MAX_BATCH_PATHS {
+        return Err(ApiError::bad_request("Too many paths were selected."));
+    }
+    let mut paths = payload
+        .paths
+        .iter()
+        .map(|raw| normalize_request_path(&state, Some(raw)))
+        .collect::<ApiResult<Vec<_>>>()?;
+    if paths.iter().any(String::is_empty) {
+        return Err(ApiError::bad_request(
+            "The root directory cannot be downloaded.",
+        ));
+    }
+    paths.sort();
+    paths.dedup();
+    // 排序后祖先目录总在其子路径之前，已选目录下的路径无需重复打包
+    let mut selected: Vec<String> = Vec::new();
+    for path in paths {
+        if !selected
+            .iter()
+            .any(|dir| path.starts_with(&format!("{dir}/")))
+        {
+            selected.push(path);
+        }
+    }
+    let base = common_parent(&selected);
+
+    let root = &state.config.root_dir;
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    let mut anchors = PrivateAnchors::new(root);
+    let mut members = Vec::new();
+    // 涉及的每个私有范围都要计入配额和并发下载数
+    let mut scopes = BTreeSet::new();
+    for path in &selected {
+        ensure_not_marker_path(path)?;
+        ensure_not_hidden_path(path, &state.config.hide_patterns)?;
+        let resolved = resolve_existing_path(root, path, state.config.symlinks).await?;
+        let metadata = fs::metadata(&resolved)
+            .await
+            .map_err(|err| ApiError::from_io(err, "path"))?;
+        if !metadata.is_dir() {
+            let accessible = ensure_file_accessible(&state, &session, path).await?;
+            members.push(TarMember {
+                name: member_name(&base, path),
+                source: Some(accessible.resolved),
+                size: accessible.metadata.len(),
+                mtime: mtime_of(&accessible.metadata),
+            });
+            scopes.extend(accessible.private_scope);
+            continue;
+        }
+        if let Some(anchor) = anchors.find(&resolved, true).await? {
+            if !access.may_read(path) {
+                return Err(ApiError::not_found("Path not found."));
+            }
+            scopes.insert(anchor.scope_rel);
+        }
+        members.push(TarMember {
+            name: member_name(&base, path),
+            source: None,
+            size: 0,
+            mtime: mtime_of(&metadata),
+        });
+        let dir_scopes = collect_dir(
+            &state,
+            &access,
+            &mut anchors,
+            &base,
+            resolved,
+            path,
+            &mut members,
+        )
+        .await?;
+        scopes.extend(dir_scopes);
+    }
+
+    let client_ip = client_ip_for_request(
+        &headers,
+        peer.ip(),
+        &state.config.trusted_proxies,
+        state.config.forwarded_header,
+    );
+    let scopes = scopes.iter().map(String::as_str).collect::<Vec<_>>();
+    let meter = match state
+        .download_quotas
+        .check_all(session.user.id, &scopes, now_unix())
+    {
+        Ok(meter) => meter,
+        Err(resets_at) => return Ok(build_quota_exceeded(resets_at)),
+    };
+    let permit = state
+        .download_slots
+        .try_acquire_all(client_ip, &scopes)
+        .ok_or_else(|| ApiError::rate_limited("Too many concurrent downloads."))?;
+
+    let total = tar_len(&members);
+    let archive_path = format!("{base}/");
+    let event_id = state
+        .db
+        .start_resource_stream_access(RecordResourceAccess {
+            user_id: session.user.id,
+            kind: ResourceKind::File,
+            path: archive_path.clone(),
+            route: "/api/batch-download",
+            status: StatusCode::OK.as_u16(),
+            bytes_served: 0,
+            file_size: i64::try_from(total).ok(),
+            range_start: None,
+            range_end: None,
+        })
+        .await?;
+    let recorder = FileAccessRecorder::with_audit(
+        state.db.clone(),
+        event_id,
+        state.audit.clone(),
+        AuditEvent {
+            event: AuditKind::Download,
+            ip: client_ip.to_string(),
+            user: Some(session.user.username.clone()),
+            path: Some(archive_path),
+            status: Some(StatusCode::OK.as_u16()),
+            bytes: None,
+            range_start: None,
+            range_end: None,
+            transfer: None,
+        },
+    );
+    tracing::info!(
+        user = session.user.username,
+        base,
+        members = members.len(),
+        bytes = total,
+        "batch download started"
+    );
+
+    let archive_name = match base.rsplit('/').next() {
+        Some(name) if !name.is_empty() => format!("{name}.tar"),
+        _ => "mlist.tar".to_string(),
+    };
+    let counted = CountingFileStream::from_stream(stream_tar(members), recorder);
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-tar")
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(Path::new(&archive_name), true),
+        )
+        .header(header::CONTENT_LENGTH, total.to_string())
+        .body(Body::from_stream(permit.attach(
+            meter.attach(state.bandwidth.throttle(client_ip, counted)),
+        )))
+        .map_err(|_| ApiError::internal("Failed to build archive response."))
+}
+
+/// Adds everything below a selected directory, breadth first. Followed
+/// symlinks are entered at most once so a loop cannot grow the archive.
+/// Returns the private scopes the added files belong to.
+pub(super) async fn collect_dir(
+    state: &AppState,
+    access: &PrivateAccess,
+    anchors: &mut PrivateAnchors,
+    base: &str,
+    resolved: PathBuf,
+    relative: &str,
+    members: &mut Vec<TarMember>,
+) -> ApiResult<BTreeSet<String>> {
+    let root = &state.config.root_dir;
+    let mut scopes = BTreeSet::new();
+    let mut visited = HashSet::from([resolved.clone()]);
+    let mut pending = VecDeque::from([(resolved, relative.to_string())]);
+    while let Some((dir, relative)) = pending.pop_front() {
+        // 禁止列出的目录只打包目录本身，不泄露其中的条目
+        let dir_config = state.dir_configs.load(&dir).await;
+        if !dir_config.lists_for(access.is_admin()) {
+            continue;
+        }
+        let mut read_dir = fs::read_dir(&dir)
+            .await
+            .map_err(|err| ApiError::from_io(err, "directory"))?;
+        let mut children = Vec::new();
+        while let Some(entry) = read_dir
+            .next_entry()
+            .await
+            .map_err(|err| ApiError::from_io(err, "directory entry"))?
+        {
+            children.push(entry);
+        }
+        children.sort_by_key(|entry| entry.file_name());
+
+        for entry in children {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_marker_name(&name)
+                || is_hidden_name(&name, &state.config.hide_patterns)
+                || dir_config.hides(&name)
+            {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+            let entry_resolved = if file_type.is_symlink() {
+                match resolve_symlink_entry(root, &entry.path(), state.config.symlinks).await {
+                    Some(target) => target,
+                    None => continue,
+                }
+            } else {
+                entry.path()
+            };
+            let Ok(metadata) = fs::metadata(&entry_resolved).await else {
+                continue;
+            };
+            let is_dir = metadata.is_dir();
+            if !is_dir && !metadata.is_file() {
+                continue;
+            }
+            let entry_path = join_relative(&relative, &name);
+            // 标记文件读取失败时按私有处理，与列表保持一致
+            let anchor = anchors.find(&entry_resolved, is_dir).await;
+            let private = !matches!(anchor, Ok(None));
+            if private && !access.may_read(&entry_path) {
+                continue;
+            }
+
+            if members.len() >= MAX_BATCH_MEMBERS {
+                return Err(ApiError::bad_request(
+                    "The selection has too many entries to download at once.",
+                ));
+            }
+            members.push(TarMember {
+                name: member_name(base, &entry_path),
+                source: (!is_dir).then(|| entry_resolved.clone()),
+                size: if is_dir { 0 } else { metadata.len() },
+                mtime: mtime_of(&metadata),
+            });
+            if let (false, Ok(Some(anchor))) = (is_dir, anchor) {
+                scopes.insert(anchor.scope_rel);
+            }
+            if is_dir && visited.insert(entry_resolved.clone()) {
+                pending.push_back((entry_resolved, entry_path));
+            }
+        }
+    }
+    Ok(scopes)
+}
+
+/// The deepest directory that contains every selected path.
+pub(super) fn common_parent(paths: &[String]) -> String {
+    let mut common: Option<Vec<&str>> = None;
+    for path in paths {
+        let mut parts = path.split('/').collect::<Vec<_>>();
+        parts.pop();
+        common = Some(match common {
+            None => parts,
+            Some(current) => current
+                .into_iter()
+                .zip(parts)
+                .take_while(|(left, right)| left == right)
+                .map(|(left, _)| left)
+                .collect(),
+        });
+    }
+    common.unwrap_or_default().join("/")
+}
+
+pub(super) fn member_name(base: &str, path: &str) -> String {
+    if base.is_empty() {
+        return path.to_string();
+    }
+    path.strip_prefix(base)
+        .and_then(|rest| rest.strip_prefix('/'))
+        .unwrap_or(path)
+        .to_string()
+}
+
+pub(super) fn mtime_of(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,45 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+
+use crate::checksum::ChecksumAlgo;
+use crate::errors::ApiResult;
+
+use super::files::ensure_file_accessible;
+use super::helpers::{normalize_request_path, require_session};
+use super::types::{AppState, ChecksumQuery, ChecksumResponse};
+
+/// Hashes a file so clients can verify a download without fetching it
+/// twice. The first request for a file version reads it in full; later ones
+/// are answered from the cache until the size or mtime changes.
+pub async fn checksum_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ChecksumQuery>,
+) -> ApiResult<Json<ChecksumResponse>> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+
+    let algo = match query.algo.as_deref() {
+        Some(raw) => ChecksumAlgo::try_from(raw)?,
+        None => ChecksumAlgo::Sha256,
+    };
+    let size = accessible.metadata.len();
+    let digest = state
+        .checksums
+        .digest(
+            &accessible.resolved,
+            algo,
+            size,
+            accessible.metadata.modified().ok(),
+        )
+        .await?;
+
+    Ok(Json(ChecksumResponse {
+        path: relative_path,
+        algo,
+        digest,
+        size,
+    }))
+}
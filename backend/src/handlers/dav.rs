@@ -0,0 +1,353 @@
+use std::fmt::Write as _;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path as AxumPath, State};
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::response::Response;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use tokio::fs;
+
+use crate::auth::{
+    PrivateAccess, find_private_anchor, has_private_file_marker, has_private_hide_marker,
+};
+use crate::config::AppConfig;
+use crate::db::{ApiTokenScope, AuthSession, RecordResourceAccess, ResourceKind};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, is_marker_name,
+    normalize_relative_path, resolve_existing_path, resolve_symlink_entry,
+};
+use crate::session::unix_to_rfc3339;
+
+use super::files::{ServeOptions, serve_file_for_session};
+use super::helpers::{bearer_token, client_ip_for_request, current_session, require_session};
+use super::http_util::{format_http_date, make_etag, url_path_segment_encode, xml_escape};
+use super::types::{AppState, DavTokenResponse};
+
+const DAV_ROUTE: &str = "/dav";
+const DAV_ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND";
+const DAV_REALM: &str = "Basic realm=\"mlist\", charset=\"UTF-8\"";
+
+/// Issues a long-lived credential for mounting `/dav`. WebDAV clients only
+/// speak HTTP Basic, so the token doubles as the password for the caller's
+/// username; it is revoked together with the user's other sessions.
+pub async fn create_dav_token_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<DavTokenResponse>> {
+    let session = require_session(&state, &headers).await?;
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let expires_at = state
+        .db
+        .create_dav_token(session.user.id, &token, state.config.dav_token_ttl_seconds)
+        .await?;
+
+    Ok(Json(DavTokenResponse {
+        url: format!("{}{DAV_ROUTE}/", state.config.base_path),
+        username: session.user.username,
+        token,
+        expires_at: unix_to_rfc3339(expires_at as u64),
+    }))
+}
+
+pub async fn dav_root_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    method: Method,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    dav_request(&state, peer.ip(), method, &headers, "").await
+}
+
+pub async fn dav_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    method: Method,
+    AxumPath(raw_path): AxumPath<String>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    dav_request(&state, peer.ip(), method, &headers, &raw_path).await
+}
+
+async fn dav_request(
+    state: &AppState,
+    peer_ip: IpAddr,
+    method: Method,
+    headers: &HeaderMap,
+    raw_path: &str,
+) -> ApiResult<Response> {
+    if method == Method::OPTIONS {
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("dav", "1")
+            .header(header::ALLOW, DAV_ALLOW)
+            .header(header::CONTENT_LENGTH, "0")
+            .body(Body::empty())
+            .map_err(|_| ApiError::internal("Failed to build DAV response."));
+    }
+
+    let Some(session) = dav_session(state, headers).await? else {
+        return basic_challenge();
+    };
+
+    // Collections are addressed with a trailing slash by most clients.
+    let relative_path = normalize_relative_path(Some(raw_path.trim_end_matches('/')))?;
+
+    match method.as_str() {
+        "GET" | "HEAD" => {
+            let client_ip = client_ip_for_request(
+                headers,
+                peer_ip,
+                &state.config.trusted_proxies,
+                state.config.forwarded_header,
+            );
+            let options = ServeOptions {
+                route: DAV_ROUTE,
+                head_only: method == Method::HEAD,
+                attachment: false,
+                strip_metadata: false,
+            };
+            serve_file_for_session(state, headers, &session, client_ip, relative_path, options)
+                .await
+        }
+        "PROPFIND" => propfind(state, headers, &session, relative_path).await,
+        _ => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, DAV_ALLOW)
+            .body(Body::empty())
+            .map_err(|_| ApiError::internal("Failed to build DAV response.")),
+    }
+}
+
+async fn dav_session(state: &AppState, headers: &HeaderMap) -> ApiResult<Option<AuthSession>> {
+    if bearer_token(headers).is_some() {
+        let session = current_session(state, headers).await?;
+        return Ok(session.filter(|session| session.allows(ApiTokenScope::Read)));
+    }
+    let Some((username, token)) = basic_credentials(headers) else {
+        return Ok(None);
+    };
+    state.db.dav_session(&username, &token).await
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, encoded) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = BASE64.decode(encoded.trim()).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (username, token) = decoded.split_once(':')?;
+    if username.is_empty() || token.is_empty() {
+        return None;
+    }
+    Some((username.to_string(), token.to_string()))
+}
+
+fn basic_challenge() -> ApiResult<Response> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(header::WWW_AUTHENTICATE, DAV_REALM)
+        .header(header::CONTENT_LENGTH, "0")
+        .body(Body::empty())
+        .map_err(|_| ApiError::internal("Failed to build DAV response."))
+}
+
+async fn propfind(
+    state: &AppState,
+    headers: &HeaderMap,
+    session: &AuthSession,
+    relative_path: String,
+) -> ApiResult<Response> {
+    // Only the target and its direct children are ever reported; walking a
+    // whole media library for `Depth: infinity` is refused up front.
+    let include_children = match headers.get("depth").and_then(|v| v.to_str().ok()) {
+        Some("0") => false,
+        Some(raw) if raw.eq_ignore_ascii_case("infinity") => {
+            return Err(ApiError::forbidden("Depth: infinity is not supported."));
+        }
+        _ => true,
+    };
+
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "path"))?;
+
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    if find_private_anchor(root, &resolved, metadata.is_dir())
+        .await?
+        .is_some()
+        && !access.may_read(&relative_path)
+    {
+        return Err(ApiError::not_found("Path not found."));
+    }
+
+    let mut body = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+    );
+    write_prop_response(&mut body, &state.config, &relative_path, &metadata);
+
+    if metadata.is_dir() && include_children {
+        let dir_config = state.dir_configs.load(&resolved).await;
+        if !dir_config.lists_for(session.user.role.is_admin()) {
+            return Err(ApiError::forbidden(
+                "Listing is disabled for this directory.",
+            ));
+        }
+        let mut read_dir = fs::read_dir(&resolved)
+            .await
+            .map_err(|err| ApiError::from_io(err, "directory"))?;
+        while let Some(entry) = read_dir
+            .next_entry()
+            .await
+            .map_err(|err| ApiError::from_io(err, "directory entry"))?
+        {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_marker_name(&name)
+                || is_hidden_name(&name, &state.config.hide_patterns)
+                || dir_config.hides(&name)
+            {
+                continue;
+            }
+            let file_type = entry
+                .file_type()
+                .await
+                .map_err(|err| ApiError::from_io(err, "directory entry"))?;
+            let (entry_meta, private) = if file_type.is_symlink() {
+                let Some(target) =
+                    resolve_symlink_entry(root, &entry.path(), state.config.symlinks).await
+                else {
+                    continue;
+                };
+                let entry_meta = fs::metadata(&target)
+                    .await
+                    .map_err(|err| ApiError::from_io(err, "directory entry"))?;
+                let private = find_private_anchor(root, &target, entry_meta.is_dir())
+                    .await?
+                    .is_some();
+                (entry_meta, private)
+            } else {
+                let entry_meta = entry
+                    .metadata()
+                    .await
+                    .map_err(|err| ApiError::from_io(err, "directory entry"))?;
+                let private = if entry_meta.is_dir() {
+                    has_private_hide_marker(&entry.path()).await?
+                } else {
+                    has_private_file_marker(&entry.path()).await?
+                };
+                (entry_meta, private)
+            };
+            if !(entry_meta.is_dir() || entry_meta.is_file()) {
+                continue;
+            }
+
+            let entry_path = if relative_path.is_empty() {
+                name
+            } else {
+                format!("{relative_path}/{name}")
+            };
+            if private && !access.may_read(&entry_path) {
+                continue;
+            }
+            write_prop_response(&mut body, &state.config, &entry_path, &entry_meta);
+        }
+    }
+    body.push_str("</D:multistatus>\n");
+
+    if metadata.is_dir() {
+        state
+            .db
+            .record_resource_access(RecordResourceAccess {
+                user_id: session.user.id,
+                kind: ResourceKind::Directory,
+                path: relative_path.clone(),
+                route: DAV_ROUTE,
+                status: StatusCode::MULTI_STATUS.as_u16(),
+                bytes_served: 0,
+                file_size: None,
+                range_start: None,
+                range_end: None,
+            })
+            .await?;
+    }
+
+    Response::builder()
+        .status(StatusCode::MULTI_STATUS)
+        .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+        .body(Body::from(body))
+        .map_err(|_| ApiError::internal("Failed to build DAV response."))
+}
+
+fn write_prop_response(
+    out: &mut String,
+    config: &AppConfig,
+    relative_path: &str,
+    metadata: &std::fs::Metadata,
+) {
+    let is_dir = metadata.is_dir();
+    let mut href = format!("{}{DAV_ROUTE}/", config.base_path);
+    if !relative_path.is_empty() {
+        let encoded = relative_path
+            .split('/')
+            .map(url_path_segment_encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        href.push_str(&encoded);
+        if is_dir {
+            href.push('/');
+        }
+    }
+    let display_name = Path::new(relative_path)
+        .file_name()
+        .map(|value| value.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    out.push_str("<D:response>");
+    let _ = write!(out, "<D:href>{}</D:href>", xml_escape(&href));
+    out.push_str("<D:propstat><D:prop>");
+    let _ = write!(
+        out,
+        "<D:displayname>{}</D:displayname>",
+        xml_escape(&display_name)
+    );
+    if is_dir {
+        out.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+    } else {
+        let mime = config.guess_mime(relative_path);
+        out.push_str("<D:resourcetype/>");
+        let _ = write!(
+            out,
+            "<D:getcontentlength>{}</D:getcontentlength>",
+            metadata.len()
+        );
+        let _ = write!(
+            out,
+            "<D:getcontenttype>{}</D:getcontenttype>",
+            xml_escape(mime.essence_str())
+        );
+        if let Ok(modified) = metadata.modified() {
+            let _ = write!(
+                out,
+                "<D:getetag>{}</D:getetag>",
+                xml_escape(&make_etag(metadata.len(), modified))
+            );
+        }
+    }
+    if let Some(last_modified) = metadata.modified().ok().and_then(format_http_date) {
+        let _ = write!(
+            out,
+            "<D:getlastmodified>{last_modified}</D:getlastmodified>"
+        );
+    }
+    out.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n");
+}
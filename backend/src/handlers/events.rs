@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_core::Stream;
+use serde::Serialize;
+use tokio::fs;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use crate::auth::{
+    PrivateAccess, find_private_anchor, has_private_file_marker, has_private_hide_marker,
+};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, is_marker_name,
+    resolve_existing_path,
+};
+use crate::watch::{ChangeKind, DirChange, DirSubscription};
+
+use super::helpers::{join_relative, normalize_request_path, require_session};
+use super::types::{AppState, EventsQuery};
+
+#[derive(Serialize)]
+struct ChangePayload {
+    kind: ChangeKind,
+    path: String,
+}
+
+struct EventContext {
+    subscription: DirSubscription,
+    shutdown: CancellationToken,
+    resolved: PathBuf,
+    relative: String,
+    /// `MLIST_HIDE_PATTERNS` plus the directory's `.mlist.toml` `hide`.
+    hide_patterns: Vec<String>,
+    access: PrivateAccess,
+    /// Names a non-admin subscriber has been able to see. Deletions, and
+    /// changes to entries that can no longer be inspected, are only
+    /// reported for these, so private names never reach the stream.
+    known: HashSet<String>,
+}
+
+/// Streams `created`/`modified`/`deleted` events for the direct children of
+/// a directory over SSE, so open listings can refresh themselves. Entries
+/// hidden from the subscriber are never named, not even once deleted. A
+/// `resync` event means changes were dropped and the listing should be
+/// reloaded. Browsers' `EventSource` cannot send a bearer header; the
+/// frontend reads the stream with `fetch` instead.
+pub async fn events_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    if find_private_anchor(root, &resolved, true).await?.is_some()
+        && !access.may_read(&relative_path)
+    {
+        return Err(ApiError::not_found("Path not found."));
+    }
+    let dir_config = state.dir_configs.load(&resolved).await;
+    if !dir_config.lists_for(access.is_admin()) {
+        return Err(ApiError::forbidden(
+            "Listing is disabled for this directory.",
+        ));
+    }
+
+    let mut context = EventContext {
+        subscription: state.watcher.subscribe(&resolved)?,
+        shutdown: state.shutdown.clone(),
+        resolved,
+        relative: relative_path,
+        hide_patterns: [state.config.hide_patterns.as_slice(), &dir_config.hide].concat(),
+        access,
+        known: HashSet::new(),
+    };
+    // 订阅之后再读取目录，期间发生的变更仍会从订阅中收到
+    if !context.access.is_admin() {
+        context.known = visible_names(&context).await?;
+    }
+    let stream = futures_util::stream::unfold(context, |mut context| async move {
+        loop {
+            let received = tokio::select! {
+                received = context.subscription.receiver.recv() => received,
+                () = context.shutdown.cancelled() => return None,
+            };
+            let change = match received {
+                Ok(change) => change,
+                Err(RecvError::Lagged(_)) => {
+                    return Some((Ok(Event::default().event("resync").data("{}")), context));
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            if !visible(&mut context, &change).await {
+                continue;
+            }
+            let payload = ChangePayload {
+                kind: change.kind,
+                path: join_relative(&context.relative, &change.name),
+            };
+            let event = Event::default()
+                .event(change.kind.as_str())
+                .json_data(&payload)
+                .unwrap_or_else(|_| Event::default().event("resync").data("{}"));
+            return Some((Ok(event), context));
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Applies the listing's visibility rules to a change. Deleted entries can
+/// no longer be checked for private markers, so for non-admins they are
+/// only reported when their name was visible before.
+async fn visible(context: &mut EventContext, change: &DirChange) -> bool {
+    if is_marker_name(&change.name) || is_hidden_name(&change.name, &context.hide_patterns) {
+        return false;
+    }
+    if context.access.is_admin() {
+        return true;
+    }
+    if change.kind == ChangeKind::Deleted {
+        return context.known.remove(&change.name);
+    }
+    match entry_visible(context, &change.name).await {
+        Some(true) => {
+            context.known.insert(change.name.clone());
+            true
+        }
+        Some(false) => false,
+        None => context.known.contains(&change.name),
+    }
+}
+
+/// Whether a non-admin may see the entry `name`; `None` when it cannot be
+/// inspected, typically because it is already gone again.
+async fn entry_visible(context: &EventContext, name: &str) -> Option<bool> {
+    let entry = context.resolved.join(name);
+    let metadata = fs::symlink_metadata(&entry).await.ok()?;
+    Some(may_see(context, &entry, metadata.is_dir(), name).await)
+}
+
+async fn may_see(context: &EventContext, entry: &Path, is_dir: bool, name: &str) -> bool {
+    // 标记文件读取失败时按私有处理，与列表保持一致
+    let private = if is_dir {
+        has_private_hide_marker(entry).await
+    } else {
+        has_private_file_marker(entry).await
+    };
+    !private.unwrap_or(true)
+        || context
+            .access
+            .may_read(&join_relative(&context.relative, name))
+}
+
+/// Entries of the subscribed directory a non-admin can currently see.
+async fn visible_names(context: &EventContext) -> ApiResult<HashSet<String>> {
+    let mut read_dir = fs::read_dir(&context.resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    let mut names = HashSet::new();
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if is_marker_name(&name) || is_hidden_name(&name, &context.hide_patterns) {
+            continue;
+        }
+        let Ok(file_type) = entry.file_type().await else {
+            continue;
+        };
+        if may_see(context, &entry.path(), file_type.is_dir(), &name).await {
+            names.insert(name);
+        }
+    }
+    Ok(names)
+}
@@ -0,0 +1,41 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::photo::read_photo_info;
+
+use super::files::ensure_file_accessible;
+use super::helpers::{normalize_request_path, require_session};
+use super::types::{AppState, ExifQuery, ExifResponse};
+
+/// Camera, exposure and capture time of a photo for the gallery view. GPS
+/// coordinates are only included when `MLIST_EXIF_GPS` is set. Images
+/// without EXIF data get a response with every field empty.
+pub async fn exif_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ExifQuery>,
+) -> ApiResult<Json<ExifResponse>> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+
+    let mime = state.config.guess_mime(&relative_path);
+    if !mime.essence_str().starts_with("image/") {
+        return Err(ApiError::unsupported_media_type(
+            "Only images carry EXIF metadata.",
+        ));
+    }
+    let include_gps = state.config.exif_gps;
+    let info =
+        tokio::task::spawn_blocking(move || read_photo_info(&accessible.resolved, include_gps))
+            .await
+            .map_err(|_| ApiError::internal("Failed to read image metadata."))?
+            .map_err(|err| ApiError::from_io(err, "file"))?;
+
+    Ok(Json(ExifResponse {
+        path: relative_path,
+        info: info.unwrap_or_default(),
+    }))
+}
@@ -4,14 +4,14 @@ use axum::http::HeaderMap;
 use tokio::fs;
 use tracing::info;
 
-use crate::auth::{find_private_anchor, has_private_hide_marker};
-use crate::db::{AuthSession, UserFavoriteView, UserFileStateView};
+use crate::auth::{PrivateAccess, find_private_anchor};
+use crate::db::{ApiTokenScope, AuthSession, UserFavoriteView, UserFileStateView};
 use crate::errors::{ApiError, ApiResult};
-use crate::path_guard::{ensure_not_marker_path, normalize_relative_path, resolve_existing_path};
+use crate::path_guard::{ensure_not_hidden_path, ensure_not_marker_path, resolve_existing_path};
 use crate::session::now_unix;
 
 use super::files::ensure_file_accessible;
-use super::helpers::{file_name_is_marker, require_session};
+use super::helpers::{file_name_is_marker, normalize_request_path, require_scope, require_session};
 use super::types::{
     AppState, FavoriteRequest, FavoritesResponse, FileStateRequest, FileStatesResponse,
 };
@@ -31,8 +31,8 @@ pub async fn set_file_state_handler(
     headers: HeaderMap,
     Json(payload): Json<FileStateRequest>,
 ) -> ApiResult<Json<UserFileStateView>> {
-    let session = require_session(&state, &headers).await?;
-    let path = normalize_relative_path(Some(&payload.path))?;
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let path = normalize_request_path(&state, Some(&payload.path))?;
     ensure_file_accessible(&state, &session, &path).await?;
 
     Ok(Json(
@@ -67,8 +67,8 @@ pub async fn set_favorite_handler(
     headers: HeaderMap,
     Json(payload): Json<FavoriteRequest>,
 ) -> ApiResult<Json<UserFavoriteView>> {
-    let session = require_session(&state, &headers).await?;
-    let path = normalize_relative_path(Some(&payload.path))?;
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let path = normalize_request_path(&state, Some(&payload.path))?;
     ensure_path_favorite_accessible(&state, &session, &path).await?;
 
     let now = now_unix() as i64;
@@ -86,12 +86,13 @@ async fn ensure_path_favorite_accessible(
     relative_path: &str,
 ) -> ApiResult<()> {
     ensure_not_marker_path(relative_path)?;
+    ensure_not_hidden_path(relative_path, &state.config.hide_patterns)?;
     if relative_path.is_empty() {
         return Err(ApiError::bad_request("Path must reference a file or directory."));
     }
 
     let root = &state.config.root_dir;
-    let resolved = resolve_existing_path(root, relative_path).await?;
+    let resolved = resolve_existing_path(root, relative_path, state.config.symlinks).await?;
     let metadata = fs::metadata(&resolved)
         .await
         .map_err(|err| ApiError::from_io(err, "path"))?;
@@ -103,20 +104,13 @@ async fn ensure_path_favorite_accessible(
         return Err(ApiError::not_found("Path not found."));
     }
 
-    if metadata.is_dir()
-        && has_private_hide_marker(&resolved).await?
-        && !session.user.role.is_admin()
-    {
-        return Err(ApiError::not_found("Path not found."));
-    }
-
     if let Some(anchor) = find_private_anchor(root, &resolved, metadata.is_dir()).await? {
-        if !session.user.role.is_admin() {
+        if !PrivateAccess::for_user(&state.config, &session.user).may_read(relative_path) {
             info!(
                 user = session.user.username,
                 scope = anchor.scope_rel,
                 marker = anchor.marker_file,
-                "non-admin favorite path access denied"
+                "favorite path access denied"
             );
             return Err(ApiError::not_found("Path not found."));
         }
@@ -130,11 +124,14 @@ async fn favorite_path_valid(
     session: &AuthSession,
     relative_path: &str,
 ) -> bool {
-    if ensure_not_marker_path(relative_path).is_err() || relative_path.is_empty() {
+    if ensure_not_marker_path(relative_path).is_err()
+        || ensure_not_hidden_path(relative_path, &state.config.hide_patterns).is_err()
+        || relative_path.is_empty()
+    {
         return false;
     }
     let root = &state.config.root_dir;
-    let resolved = match resolve_existing_path(root, relative_path).await {
+    let resolved = match resolve_existing_path(root, relative_path, state.config.symlinks).await {
         Ok(p) => p,
         Err(_) => return false,
     };
@@ -148,15 +145,10 @@ async fn favorite_path_valid(
     if file_name_is_marker(&resolved) {
         return false;
     }
-    if metadata.is_dir() {
-        match has_private_hide_marker(&resolved).await {
-            Ok(true) if !session.user.role.is_admin() => return false,
-            Ok(_) => {}
-            Err(_) => return false,
-        }
-    }
     match find_private_anchor(root, &resolved, metadata.is_dir()).await {
-        Ok(Some(_)) => session.user.role.is_admin(),
+        Ok(Some(_)) => {
+            PrivateAccess::for_user(&state.config, &session.user).may_read(relative_path)
+        }
         Ok(None) => true,
         Err(_) => false,
     }
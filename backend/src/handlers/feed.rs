@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, header};
+use axum::response::Response;
+use tokio::fs;
+
+use crate::auth::{find_nofeed_anchor, find_private_anchor};
+use crate::dir_config;
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    NOFEED_MARKER_FILE, PRIVATE_MARKER_FILE, ensure_not_hidden_path, ensure_not_marker_path,
+    is_hidden_name, is_marker_name, private_file_marker_name, resolve_existing_path,
+};
+use crate::session::{now_unix, unix_to_rfc3339};
+
+use super::helpers::{join_relative, normalize_request_path, request_origin};
+use super::http_util::{url_path_segment_encode, xml_escape};
+use super::types::{AppState, FeedQuery};
+
+const MAX_FEED_ENTRIES: usize = 50;
+const MAX_FEED_DEPTH: usize = 16;
+/// Upper bound on directory entries inspected per request.
+const MAX_SCANNED_ENTRIES: usize = 100_000;
+
+struct FeedItem {
+    path: String,
+    size: u64,
+    mtime: SystemTime,
+}
+
+/// Atom feed of the newest files under a public directory, readable without
+/// logging in so feed readers can follow a folder. Private scopes and
+/// directories carrying `.nofeed` are left out. Entries link to the web UI,
+/// so downloading still requires an account.
+pub async fn feed_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<FeedQuery>,
+) -> ApiResult<Response> {
+    if !state.config.feed_enabled {
+        return Err(ApiError::not_found("Feeds are not enabled."));
+    }
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+    let origin = request_origin(
+        &headers,
+        peer.ip(),
+        state.config.public_url.as_deref(),
+        &state.config.base_path,
+        &state.config.trusted_proxies,
+    )
+    .ok_or_else(|| ApiError::bad_request("Cannot determine the public URL for links."))?;
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+    if find_private_anchor(root, &resolved, true).await?.is_some()
+        || find_nofeed_anchor(root, &resolved).await?.is_some()
+    {
+        return Err(ApiError::not_found("Path not found."));
+    }
+
+    let hide_patterns = state.config.hide_patterns.clone();
+    let start = relative_path.clone();
+    let items =
+        tokio::task::spawn_blocking(move || collect_recent(resolved, start, &hide_patterns))
+            .await
+            .map_err(|_| ApiError::internal("Failed to scan directory."))?;
+
+    let self_url = format!(
+        "{origin}/api/feed.xml?path={}",
+        url_path_segment_encode(&relative_path)
+    );
+    let updated = items
+        .first()
+        .map(|item| unix_seconds(item.mtime))
+        .unwrap_or_else(now_unix);
+    let title = if relative_path.is_empty() {
+        "mlist".to_string()
+    } else {
+        format!("mlist: {relative_path}")
+    };
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(out, "<id>{}</id>", xml_escape(&self_url));
+    let _ = writeln!(out, "<title>{}</title>", xml_escape(&title));
+    let _ = writeln!(out, "<updated>{}</updated>", unix_to_rfc3339(updated));
+    out.push_str("<author><name>mlist</name></author>\n");
+    let _ = writeln!(
+        out,
+        "<link rel=\"self\" href=\"{}\"/>",
+        xml_escape(&self_url)
+    );
+    let _ = writeln!(
+        out,
+        "<link href=\"{}\"/>",
+        xml_escape(&ui_url(&origin, &relative_path))
+    );
+    for item in &items {
+        let link = ui_url(&origin, &item.path);
+        let mtime = unix_seconds(item.mtime);
+        let name = item.path.rsplit('/').next().unwrap_or(&item.path);
+        out.push_str("<entry>\n");
+        let _ = writeln!(out, "<id>{}#{mtime}</id>", xml_escape(&link));
+        let _ = writeln!(out, "<title>{}</title>", xml_escape(name));
+        let _ = writeln!(out, "<updated>{}</updated>", unix_to_rfc3339(mtime));
+        let _ = writeln!(out, "<link href=\"{}\"/>", xml_escape(&link));
+        let _ = writeln!(
+            out,
+            "<summary>{} ({} bytes)</summary>",
+            xml_escape(&item.path),
+            item.size
+        );
+        out.push_str("</entry>\n");
+    }
+    out.push_str("</feed>\n");
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")
+        .header(header::CACHE_CONTROL, "public, max-age=300")
+        .body(Body::from(out))
+        .map_err(|_| ApiError::internal("Failed to build feed response."))
+}
+
+/// Walks `dir` for regular files, newest first. A directory whose listing
+/// contains `.private` or `.nofeed`, or whose `.mlist.toml` disables
+/// listing, is skipped with everything below it; symlinks are never
+/// followed.
+fn collect_recent(dir: PathBuf, relative: String, hide_patterns: &[String]) -> Vec<FeedItem> {
+    let mut items = Vec::new();
+    let mut scanned = 0_usize;
+    let mut pending = vec![(dir, relative, 1)];
+    'walk: while let Some((dir, relative, depth)) = pending.pop() {
+        let dir_config = dir_config::load_blocking(&dir);
+        if !dir_config.lists_for(false) {
+            continue;
+        }
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut names = HashSet::new();
+        let mut files = Vec::new();
+        let mut subdirs = Vec::new();
+        for entry in read_dir.flatten() {
+            scanned += 1;
+            if scanned > MAX_SCANNED_ENTRIES {
+                break 'walk;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            names.insert(name.clone());
+            if is_marker_name(&name)
+                || is_hidden_name(&name, hide_patterns)
+                || dir_config.hides(&name)
+            {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                subdirs.push((entry.path(), join_relative(&relative, &name)));
+            } else if file_type.is_file() {
+                files.push((entry, name));
+            }
+        }
+        // 子目录自带 .private/.nofeed 时整棵子树都不进入订阅
+        if depth > 1 && (names.contains(PRIVATE_MARKER_FILE) || names.contains(NOFEED_MARKER_FILE))
+        {
+            continue;
+        }
+        if depth < MAX_FEED_DEPTH {
+            pending.extend(
+                subdirs
+                    .into_iter()
+                    .map(|(path, relative)| (path, relative, depth + 1)),
+            );
+        }
+        for (entry, name) in files {
+            if names.contains(&private_file_marker_name(&name)) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            items.push(FeedItem {
+                path: join_relative(&relative, &name),
+                size: metadata.len(),
+                mtime: metadata.modified().unwrap_or(UNIX_EPOCH),
+            });
+        }
+    }
+    items.sort_by(|a, b| b.mtime.cmp(&a.mtime).then_with(|| a.path.cmp(&b.path)));
+    items.truncate(MAX_FEED_ENTRIES);
+    items
+}
+
+fn ui_url(origin: &str, path: &str) -> String {
+    let encoded = path
+        .split('/')
+        .map(url_path_segment_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("{origin}/{encoded}")
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
@@ -1,52 +1,76 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use std::task::{Context, Poll};
-use std::time::UNIX_EPOCH;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use axum::Json;
 use axum::body::{Body, Bytes};
-use axum::extract::{Path as AxumPath, Query, State};
-use axum::http::{HeaderMap, StatusCode, header};
-use axum::response::Response;
+use axum::extract::{ConnectInfo, Path as AxumPath, Query, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use axum::response::{IntoResponse, Redirect, Response};
 use futures_core::Stream;
+use sha2::{Digest, Sha256};
 use tokio::fs;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, SeekFrom};
 use tokio_util::io::ReaderStream;
 use tracing::error;
 
-use crate::auth::{find_private_anchor, has_private_hide_marker};
+use crate::audit::{AuditEvent, AuditKind, AuditLog};
+use crate::auth::{PrivateAccess, PrivateAnchors, find_private_anchor};
+use crate::cache_policy::{CacheTtl, file_cache_ttl};
+use crate::checksum::ChecksumAlgo;
+use crate::config::AppConfig;
 use crate::db::{AuthDb, AuthSession, RecordResourceAccess, ResourceKind, ResourceTransferState};
+use crate::dir_config::DownloadMode;
 use crate::errors::{ApiError, ApiResult};
+use crate::hotlink::{HotlinkPolicy, is_foreign_embed, url_host};
+use crate::media::MediaKind;
+use crate::natsort::NameCollator;
 use crate::path_guard::{
-    ensure_not_marker_path, is_private_marker_name, normalize_relative_path, resolve_existing_path,
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, is_marker_name,
+    resolve_existing_path, resolve_symlink_entry,
 };
+use crate::path_headers::{HeaderTarget, apply_path_headers};
+use crate::photo::jpeg_metadata_segments;
 use crate::session::now_unix;
 
-use super::helpers::{file_name_is_marker, file_session_for_request, require_session};
+use super::helpers::{
+    client_ip_for_request, file_name_is_marker, file_session_for_request, normalize_request_path,
+    request_origin, require_session,
+};
 use super::http_util::{
-    ByteRange, build_not_modified, build_range_not_satisfiable, content_disposition_inline,
-    format_http_date, if_none_match_matches, if_range_matches, make_etag, parse_range_header,
-    signed_direct_file_url,
+    ByteRange, MultipartRanges, accepts_encoding, build_not_modified, build_quota_exceeded,
+    build_range_not_satisfiable, content_disposition, format_http_date, if_none_match_matches,
+    if_range_matches, make_etag, parse_range_header, signed_direct_file_url,
+    url_path_segment_encode,
 };
 use super::types::{
-    AppState, DirectFileQuery, ListEntry, ListResponse, PathQuery, SignedFileLinkRequest,
-    SignedFileLinkResponse,
+    AppState, DirReadme, DirectFileQuery, ListEntry, ListResponse, PathQuery,
+    SignedFileLinkRequest, SignedFileLinkResponse,
 };
 use crate::session::unix_to_rfc3339;
 
+/// Carries the file's SHA-256 once `/api/checksum` has computed it.
+const CHECKSUM_SHA256_HEADER: &str = "x-checksum-sha256";
+/// Content type of streamed directory listings.
+const NDJSON: &str = "application/x-ndjson";
+
 pub async fn list_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
     Query(query): Query<PathQuery>,
-) -> ApiResult<Json<ListResponse>> {
+) -> ApiResult<Response> {
     let session = require_session(&state, &headers).await?;
-    let relative_path = normalize_relative_path(query.path.as_deref())?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
     ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
 
     let root = &state.config.root_dir;
-    let resolved = resolve_existing_path(root, &relative_path).await?;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
     let metadata = fs::metadata(&resolved)
         .await
         .map_err(|err| ApiError::from_io(err, "directory"))?;
@@ -55,41 +79,209 @@ pub async fn list_handler(
         return Err(ApiError::bad_request("Path is not a directory."));
     }
 
-    let anchor = find_private_anchor(root, &resolved, true).await?;
+    // 同一目录下的条目共享祖先链，逐条判定时复用已查过的标记
+    let mut anchors = PrivateAnchors::new(root);
+    let anchor = anchors.find(&resolved, true).await?;
+    let access = PrivateAccess::for_user(&state.config, &session.user);
     if let Some(private_anchor) = &anchor {
-        if !session.user.role.is_admin() {
+        if !access.may_read(&relative_path) {
             tracing::info!(
                 user = session.user.username,
                 scope = private_anchor.scope_rel,
                 marker = private_anchor.marker_file,
-                "non-admin private directory access denied"
+                "private directory access denied"
             );
             return Err(ApiError::not_found("Path not found."));
         }
     }
 
-    let favorites_only = query.favorites_only.unwrap_or(false);
-    let search = query.search.as_deref().map(str::trim).filter(|value| !value.is_empty());
-    let search_lower = search.map(|value| value.to_lowercase());
-    let fav_set = state.db.list_favorite_paths(session.user.id).await?;
+    let dir_config = state.dir_configs.load(&resolved).await;
+    if dir_config.listing == Some(false) && !session.user.role.is_admin() {
+        return Err(ApiError::forbidden(
+            "Listing is disabled for this directory.",
+        ));
+    }
+
+    let mut lister = EntryLister {
+        state: state.clone(),
+        access,
+        relative_path: relative_path.clone(),
+        anchors,
+        dir_hide_patterns: dir_config.hide.clone(),
+        pins: dir_config.pin.clone(),
+        fav_set: state.db.list_favorite_paths(session.user.id).await?,
+        search_lower: query
+            .search
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_lowercase),
+        favorites_only: query.favorites_only.unwrap_or(false),
+        with_dir_sizes: query.with_dir_sizes.unwrap_or(false),
+    };
 
-    let mut entries = Vec::new();
     let mut read_dir = fs::read_dir(&resolved)
         .await
         .map_err(|err| ApiError::from_io(err, "directory"))?;
 
+    if query.stream.unwrap_or(false) || accepts_ndjson(&headers) {
+        record_directory_access(&state, &session, &relative_path).await?;
+        return stream_list_response(read_dir, lister);
+    }
+
+    let mut entries = Vec::new();
     while let Some(entry) = read_dir
         .next_entry()
         .await
         .map_err(|err| ApiError::from_io(err, "directory entry"))?
     {
+        if let Some(listed) = lister.entry(entry).await? {
+            entries.push(listed);
+        }
+    }
+
+    // sort=natural 显式要求按数字感知排序；其余按名称比较时沿用配置的默认规则。
+    // 请求未指定时采用目录 .mlist.toml 中的排序
+    let sort = query.sort.as_deref().or(dir_config.sort.as_deref());
+    let order = query.order.as_deref().or(dir_config.order.as_deref());
+    let sort_field = sort.unwrap_or("name");
+    let order_desc = matches!(order, Some("desc"));
+    let explicit_sort = sort.is_some() || order.is_some();
+    let names = NameCollator::new(
+        sort_field == "natural" || state.config.natural_sort,
+        state.config.sort_locale.as_deref(),
+    )
+    .map_err(|_| ApiError::internal("Failed to prepare name ordering."))?;
+
+    // 置顶条目按 pin 中的顺序排在最前，不受排序方向影响
+    let pin_rank = |entry: &ListEntry| dir_config.pin.iter().position(|name| *name == entry.name);
+    entries.sort_by(|a, b| {
+        match (pin_rank(a), pin_rank(b)) {
+            (Some(a), Some(b)) => return a.cmp(&b),
+            (Some(_), None) => return std::cmp::Ordering::Less,
+            (None, Some(_)) => return std::cmp::Ordering::Greater,
+            (None, None) => {}
+        }
+        if !explicit_sort {
+            let type_order = match (&a.kind, &b.kind) {
+                (super::types::EntryKind::Dir, super::types::EntryKind::File) => std::cmp::Ordering::Less,
+                (super::types::EntryKind::File, super::types::EntryKind::Dir) => std::cmp::Ordering::Greater,
+                _ => std::cmp::Ordering::Equal,
+            };
+            if type_order != std::cmp::Ordering::Equal {
+                return type_order;
+            }
+            return names.compare(&a.name, &b.name);
+        }
+
+        let ordering = match sort_field {
+            "size" => {
+                let (av, bv) = (a.size.unwrap_or(0), b.size.unwrap_or(0));
+                av.cmp(&bv).then_with(|| names.compare(&a.name, &b.name))
+            }
+            "mtime" => {
+                let (av, bv) = (a.mtime.unwrap_or(0), b.mtime.unwrap_or(0));
+                av.cmp(&bv).then_with(|| names.compare(&a.name, &b.name))
+            }
+            _ => names.compare(&a.name, &b.name),
+        };
+        if order_desc { ordering.reverse() } else { ordering }
+    });
+
+    let total = entries.len();
+    let limit = query.limit.unwrap_or(50).clamp(1, 200) as usize;
+    let offset = query.offset.unwrap_or(0).max(0) as usize;
+    let has_more = offset.saturating_add(limit) < total;
+    let offset = offset.min(total);
+    let end = offset.saturating_add(limit).min(total);
+    let mut entries = entries[offset..end].to_vec();
+    if query.with_meta.unwrap_or(false) {
+        attach_media_meta(&state, &mut entries).await;
+    }
+
+    let readme = if state.config.list_readme {
+        load_dir_readme(&state, &session, &relative_path).await
+    } else {
+        None
+    };
+
+    let body = serde_json::to_vec(&ListResponse {
+        path: relative_path.clone(),
+        entries,
+        requires_auth: anchor.is_some(),
+        authorized: true,
+        total,
+        has_more,
+        readme,
+        description: dir_config.description.clone(),
+    })
+    .map_err(|_| ApiError::internal("Failed to encode the listing."))?;
+    let etag = listing_etag(metadata.modified().ok(), total, &body);
+    let inm_header = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok());
+    if inm_header.is_some_and(|raw| if_none_match_matches(raw, &etag)) {
+        return build_not_modified(Some(&etag), None);
+    }
+    // 304 未传输列表内容，只记录实际返回了列表的请求
+    record_directory_access(&state, &session, &relative_path).await?;
+    let mut response = Response::new(Body::from(body));
+    let response_headers = response.headers_mut();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&etag) {
+        response_headers.insert(header::ETAG, value);
+    }
+    Ok(response)
+}
+
+/// The directory's mtime and child count keep the tag readable, but they
+/// miss files edited in place, favorites and `.mlist.toml` changes, and
+/// differ per user and page anyway; the digest of the body decides.
+fn listing_etag(modified: Option<SystemTime>, total: usize, body: &[u8]) -> String {
+    let mtime = modified
+        .and_then(|value| value.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |value| value.as_secs());
+    let digest = Sha256::digest(body)[..12]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("W/\"list-{mtime:x}-{total:x}-{digest}\"")
+}
+
+/// Applies the listing's visibility rules and query filters to raw
+/// directory entries. Shared by the buffered and the streamed response.
+struct EntryLister {
+    state: AppState,
+    access: PrivateAccess,
+    relative_path: String,
+    anchors: PrivateAnchors,
+    /// `hide` from the directory's `.mlist.toml`.
+    dir_hide_patterns: Vec<String>,
+    /// `pin` from the directory's `.mlist.toml`.
+    pins: Vec<String>,
+    fav_set: HashSet<String>,
+    search_lower: Option<String>,
+    favorites_only: bool,
+    with_dir_sizes: bool,
+}
+
+impl EntryLister {
+    async fn entry(&mut self, entry: fs::DirEntry) -> ApiResult<Option<ListEntry>> {
+        let state = &self.state;
+        let root = &state.config.root_dir;
         let name = entry.file_name().to_string_lossy().to_string();
-        if is_private_marker_name(&name) {
-            continue;
+        if is_marker_name(&name)
+            || is_hidden_name(&name, &state.config.hide_patterns)
+            || is_hidden_name(&name, &self.dir_hide_patterns)
+        {
+            return Ok(None);
         }
-        if let Some(search) = &search_lower {
+        if let Some(search) = &self.search_lower {
             if !name.to_lowercase().contains(search) {
-                continue;
+                return Ok(None);
             }
         }
 
@@ -98,125 +290,126 @@ pub async fn list_handler(
             .await
             .map_err(|err| ApiError::from_io(err, "directory entry"))?;
 
-        if file_type.is_symlink() {
-            continue;
-        }
-
-        if !file_type.is_dir() && !file_type.is_file() {
-            continue;
+        if !file_type.is_symlink() && !file_type.is_dir() && !file_type.is_file() {
+            return Ok(None);
         }
 
-        let entry_path = if relative_path.is_empty() {
+        let entry_path = if self.relative_path.is_empty() {
             name.clone()
         } else {
-            format!("{relative_path}/{name}")
+            format!("{}/{name}", self.relative_path)
         };
 
-        if favorites_only {
-            if !visible_in_favorites_view(&entry_path, file_type.is_dir(), &fav_set) {
-                continue;
+        let resolved_entry = if file_type.is_symlink() {
+            match resolve_symlink_entry(root, &entry.path(), state.config.symlinks).await {
+                Some(target) => target,
+                None => return Ok(None),
             }
-        }
-
-        let resolved_entry = resolve_existing_path(root, &entry_path).await?;
+        } else {
+            resolve_existing_path(root, &entry_path, state.config.symlinks).await?
+        };
         let entry_meta = fs::metadata(&resolved_entry)
             .await
             .map_err(|err| ApiError::from_io(err, "directory entry"))?;
+        let is_dir = entry_meta.is_dir();
+        if !is_dir && !entry_meta.is_file() {
+            return Ok(None);
+        }
 
-        if file_type.is_dir()
-            && has_private_hide_marker(&resolved_entry).await?
-            && !session.user.role.is_admin()
-        {
-            continue;
+        if self.favorites_only && !visible_in_favorites_view(&entry_path, is_dir, &self.fav_set) {
+            return Ok(None);
         }
 
-        let entry_anchor = find_private_anchor(root, &resolved_entry, file_type.is_dir()).await?;
+        let entry_anchor = self.anchors.find(&resolved_entry, is_dir).await?;
         let requires_auth = entry_anchor.is_some();
-        let authorized = entry_anchor
-            .as_ref()
-            .map(|_| session.user.role.is_admin())
-            .unwrap_or(true);
-        if requires_auth && !authorized {
-            continue;
+        if requires_auth && !self.access.may_read(&entry_path) {
+            return Ok(None);
         }
 
-        let mime = if file_type.is_file() {
-            Some(
-                mime_guess::from_path(&name)
-                    .first_or_octet_stream()
-                    .essence_str()
-                    .to_string(),
-            )
-        } else {
-            None
-        };
-
-        entries.push(ListEntry {
+        let favorite = self.fav_set.contains(&entry_path);
+        let mut listed = list_entry(
+            &state.config,
             name,
-            path: entry_path.clone(),
-            kind: if file_type.is_dir() {
-                super::types::EntryKind::Dir
-            } else {
-                super::types::EntryKind::File
-            },
-            size: file_type.is_file().then_some(entry_meta.len()),
-            mtime: entry_meta
-                .modified()
-                .ok()
-                .and_then(|value| value.duration_since(UNIX_EPOCH).ok())
-                .map(|value| value.as_secs()),
-            mime,
+            entry_path,
+            is_dir,
+            &entry_meta,
             requires_auth,
-            authorized,
-            favorite: fav_set.contains(&entry_path),
-        });
+            favorite,
+        );
+        listed.pinned = self.pins.contains(&listed.name);
+        if self.with_dir_sizes && is_dir {
+            listed.size = state
+                .dir_sizes
+                .lookup(
+                    &resolved_entry,
+                    entry_meta.modified().ok(),
+                    self.access.is_admin(),
+                )
+                .await;
+        }
+        Ok(Some(listed))
     }
+}
 
-    let sort_field = query.sort.as_deref().unwrap_or("name");
-    let order_desc = matches!(query.order.as_deref(), Some("desc"));
-    let explicit_sort = query.sort.is_some() || query.order.is_some();
+fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|item| item.split(';').next().unwrap_or_default().trim() == NDJSON)
+        })
+}
 
-    entries.sort_by(|a, b| {
-        if !explicit_sort {
-            let type_order = match (&a.kind, &b.kind) {
-                (super::types::EntryKind::Dir, super::types::EntryKind::File) => std::cmp::Ordering::Less,
-                (super::types::EntryKind::File, super::types::EntryKind::Dir) => std::cmp::Ordering::Greater,
-                _ => std::cmp::Ordering::Equal,
+/// Streams entries as newline-delimited JSON in directory order while they
+/// are read, so huge directories need neither a full buffer nor a wait for
+/// the last entry. Sorting, pagination, `withMeta` and the README are not
+/// available in this mode. An error after the first byte aborts the body.
+fn stream_list_response(read_dir: fs::ReadDir, lister: EntryLister) -> ApiResult<Response> {
+    let lines = futures_util::stream::unfold(Some((read_dir, lister)), |pending| async move {
+        let (mut read_dir, mut lister) = pending?;
+        loop {
+            let entry = match read_dir.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => return None,
+                Err(err) => return Some((Err(err), None)),
             };
-            if type_order != std::cmp::Ordering::Equal {
-                return type_order;
+            match lister.entry(entry).await {
+                Ok(Some(listed)) => {
+                    let mut line = match serde_json::to_vec(&listed) {
+                        Ok(line) => line,
+                        Err(err) => return Some((Err(std::io::Error::other(err)), None)),
+                    };
+                    line.push(b'\n');
+                    return Some((Ok(Bytes::from(line)), Some((read_dir, lister))));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    error!("directory listing stream failed: {err:?}");
+                    return Some((Err(std::io::Error::other("listing failed")), None));
+                }
             }
-            return a.name.to_lowercase().cmp(&b.name.to_lowercase());
         }
-
-        let ordering = match sort_field {
-            "size" => {
-                let (av, bv) = (a.size.unwrap_or(0), b.size.unwrap_or(0));
-                av.cmp(&bv).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
-            }
-            "mtime" => {
-                let (av, bv) = (a.mtime.unwrap_or(0), b.mtime.unwrap_or(0));
-                av.cmp(&bv).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
-            }
-            _ => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
-        };
-        if order_desc { ordering.reverse() } else { ordering }
     });
+    Response::builder()
+        .header(header::CONTENT_TYPE, NDJSON)
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from_stream(lines))
+        .map_err(|_| ApiError::internal("Failed to build listing response."))
+}
 
-    let total = entries.len();
-    let limit = query.limit.unwrap_or(50).clamp(1, 200) as usize;
-    let offset = query.offset.unwrap_or(0).max(0) as usize;
-    let has_more = offset.saturating_add(limit) < total;
-    let offset = offset.min(total);
-    let end = offset.saturating_add(limit).min(total);
-    let entries = entries[offset..end].to_vec();
-
+async fn record_directory_access(
+    state: &AppState,
+    session: &AuthSession,
+    path: &str,
+) -> ApiResult<()> {
     state
         .db
         .record_resource_access(RecordResourceAccess {
             user_id: session.user.id,
             kind: ResourceKind::Directory,
-            path: relative_path.clone(),
+            path: path.to_string(),
             route: "/api/list",
             status: StatusCode::OK.as_u16(),
             bytes_served: 0,
@@ -224,22 +417,122 @@ pub async fn list_handler(
             range_start: None,
             range_end: None,
         })
-        .await?;
+        .await
+}
 
-    Ok(Json(ListResponse {
-        path: relative_path,
-        entries,
-        requires_auth: anchor.is_some(),
+/// Fills in `extra` for the media files on the current page. Probes run
+/// concurrently; a file that cannot be read simply keeps `extra` empty.
+async fn attach_media_meta(state: &AppState, entries: &mut [ListEntry]) {
+    let mut probes = tokio::task::JoinSet::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let Some(kind) = entry.mime.as_deref().and_then(MediaKind::for_mime) else {
+            continue;
+        };
+        let state = state.clone();
+        let path = entry.path.clone();
+        probes.spawn(async move {
+            let root = &state.config.root_dir;
+            let resolved = resolve_existing_path(root, &path, state.config.symlinks)
+                .await
+                .ok()?;
+            let metadata = fs::metadata(&resolved).await.ok()?;
+            let meta = state
+                .media_meta
+                .lookup(
+                    &resolved,
+                    kind,
+                    &metadata,
+                    state.config.ffprobe_path.as_deref(),
+                )
+                .await?;
+            Some((index, meta))
+        });
+    }
+    while let Some(probed) = probes.join_next().await {
+        if let Ok(Some((index, meta))) = probed {
+            entries[index].extra = Some(meta);
+        }
+    }
+}
+
+/// Names checked, in order, for a directory's description.
+const README_NAMES: [&str; 4] = ["README.md", "Readme.md", "readme.md", "index.md"];
+
+/// Reads the first README candidate in `dir` the session may see. Hidden,
+/// private or unreadable candidates are skipped rather than failing the
+/// listing.
+async fn load_dir_readme(state: &AppState, session: &AuthSession, dir: &str) -> Option<DirReadme> {
+    for name in README_NAMES {
+        let path = if dir.is_empty() {
+            name.to_string()
+        } else {
+            format!("{dir}/{name}")
+        };
+        let Ok(accessible) = ensure_file_accessible(state, session, &path).await else {
+            continue;
+        };
+        let max_bytes = state.config.readme_max_bytes;
+        let Ok(file) = fs::File::open(&accessible.resolved).await else {
+            continue;
+        };
+        let mut content = Vec::new();
+        if file
+            .take(max_bytes)
+            .read_to_end(&mut content)
+            .await
+            .is_err()
+        {
+            continue;
+        }
+        return Some(DirReadme {
+            name: name.to_string(),
+            content: String::from_utf8_lossy(&content).into_owned(),
+            truncated: accessible.metadata.len() > max_bytes,
+        });
+    }
+    None
+}
+
+/// Builds the listing row for an entry the caller has already decided to
+/// show; hidden or unauthorized entries never reach this point.
+pub(super) fn list_entry(
+    config: &AppConfig,
+    name: String,
+    path: String,
+    is_dir: bool,
+    metadata: &std::fs::Metadata,
+    requires_auth: bool,
+    favorite: bool,
+) -> ListEntry {
+    let mime = (!is_dir).then(|| config.guess_mime(&name).essence_str().to_string());
+
+    ListEntry {
+        name,
+        path,
+        kind: if is_dir {
+            super::types::EntryKind::Dir
+        } else {
+            super::types::EntryKind::File
+        },
+        size: (!is_dir).then_some(metadata.len()),
+        mtime: metadata
+            .modified()
+            .ok()
+            .and_then(|value| value.duration_since(UNIX_EPOCH).ok())
+            .map(|value| value.as_secs()),
+        mime,
+        requires_auth,
         authorized: true,
-        total,
-        has_more,
-    }))
+        favorite,
+        pinned: false,
+        extra: None,
+    }
 }
 
 pub(super) fn visible_in_favorites_view(
     entry_path: &str,
     is_dir: bool,
-    fav_set: &std::collections::HashSet<String>,
+    fav_set: &HashSet<String>,
 ) -> bool {
     if fav_set.contains(entry_path) {
         return true;
@@ -265,28 +558,79 @@ fn path_is_descendant_of(path: &str, parent: &str) -> bool {
 
 pub async fn direct_file_handler(
     State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    method: Method,
     AxumPath(raw_path): AxumPath<String>,
     Query(query): Query<DirectFileQuery>,
     headers: HeaderMap,
 ) -> ApiResult<Response> {
-    let relative_path = normalize_relative_path(Some(&raw_path))?;
+    let relative_path = normalize_request_path(&state, Some(&raw_path))?;
+    if state.config.hotlink_policy != HotlinkPolicy::Off {
+        let origin = request_origin(
+            &headers,
+            peer.ip(),
+            state.config.public_url.as_deref(),
+            "",
+            &state.config.trusted_proxies,
+        );
+        let own_host = origin.as_deref().and_then(url_host);
+        if is_foreign_embed(
+            &headers,
+            own_host.as_deref(),
+            &state.config.hotlink_allowed_hosts,
+        ) {
+            return Ok(hotlink_response(&state, &relative_path));
+        }
+    }
+    let client_ip = client_ip_for_request(
+        &headers,
+        peer.ip(),
+        &state.config.trusted_proxies,
+        state.config.forwarded_header,
+    );
+    let options = ServeOptions {
+        route: "/d",
+        head_only: method == Method::HEAD,
+        attachment: query.wants_attachment(),
+        strip_metadata: query.wants_sanitized(),
+    };
     serve_file_response(
         &state,
         &headers,
+        client_ip,
         relative_path,
-        "/d",
         query.token.as_deref(),
+        options,
     )
     .await
 }
 
+/// Answers a foreign embed with 403, or with a redirect to the listing of
+/// the directory holding the file.
+fn hotlink_response(state: &AppState, relative_path: &str) -> Response {
+    if state.config.hotlink_policy != HotlinkPolicy::Redirect {
+        return ApiError::forbidden("Embedding this file on other sites is not allowed.")
+            .into_response();
+    }
+    let parent = relative_path
+        .rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or_default();
+    let listing = parent
+        .split('/')
+        .map(url_path_segment_encode)
+        .collect::<Vec<_>>()
+        .join("/");
+    Redirect::to(&format!("{}/{listing}", state.config.base_path)).into_response()
+}
+
 pub async fn create_file_link_handler(
     State(state): State<AppState>,
     headers: HeaderMap,
     Json(payload): Json<SignedFileLinkRequest>,
 ) -> ApiResult<Json<SignedFileLinkResponse>> {
     let session = require_session(&state, &headers).await?;
-    let path = normalize_relative_path(Some(&payload.path))?;
+    let path = normalize_request_path(&state, Some(&payload.path))?;
     ensure_file_accessible(&state, &session, &path).await?;
 
     let token = uuid::Uuid::new_v4().simple().to_string();
@@ -301,33 +645,129 @@ pub async fn create_file_link_handler(
         .await?;
 
     Ok(Json(SignedFileLinkResponse {
-        url: signed_direct_file_url(&path, &token),
+        url: format!(
+            "{}{}",
+            state.config.base_path,
+            signed_direct_file_url(&path, &token)
+        ),
         expires_at: unix_to_rfc3339(expires_at as u64),
     }))
 }
 
+/// How a file response is framed, as chosen by the route serving it.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct ServeOptions {
+    pub(super) route: &'static str,
+    pub(super) head_only: bool,
+    /// Send `Content-Disposition: attachment` so browsers save the file
+    /// instead of opening it.
+    pub(super) attachment: bool,
+    /// Leave out a JPEG's EXIF and XMP segments, which can hold the camera
+    /// owner and where the photo was taken.
+    pub(super) strip_metadata: bool,
+}
+
+/// Whether the `.mlist.toml` of the file's directory asks for downloads.
+async fn forced_attachment(state: &AppState, resolved: &Path) -> bool {
+    let Some(dir) = resolved.parent() else {
+        return false;
+    };
+    state.dir_configs.load(dir).await.download == Some(DownloadMode::Attachment)
+}
+
 async fn serve_file_response(
     state: &AppState,
     headers: &HeaderMap,
+    client_ip: IpAddr,
     relative_path: String,
-    route: &'static str,
     signed_token: Option<&str>,
+    options: ServeOptions,
 ) -> ApiResult<Response> {
     let session = file_session_for_request(state, &relative_path, signed_token).await?;
-    let accessible = ensure_file_accessible(state, &session, &relative_path).await?;
+    serve_file_for_session(state, headers, &session, client_ip, relative_path, options).await
+}
+
+/// Streams `relative_path` to an already authenticated `session`, honouring
+/// conditional and range headers, the bandwidth limits for `client_ip`, and
+/// recording the transfer.
+pub(super) async fn serve_file_for_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    session: &AuthSession,
+    client_ip: IpAddr,
+    relative_path: String,
+    options: ServeOptions,
+) -> ApiResult<Response> {
+    let ServeOptions {
+        route,
+        head_only,
+        attachment,
+        strip_metadata,
+    } = options;
+    let accessible = ensure_file_accessible(state, session, &relative_path).await?;
+    let private = accessible.private_scope.is_some();
     let resolved = accessible.resolved;
     let metadata = accessible.metadata;
+    let attachment = attachment || forced_attachment(state, &resolved).await;
 
     let file_size = metadata.len();
-    let mime = mime_guess::from_path(&resolved)
-        .first_or_octet_stream()
-        .essence_str()
-        .to_string();
-    let content_disposition = content_disposition_inline(&resolved);
+    let mime = state.config.guess_mime(&resolved).essence_str().to_string();
+    let content_disposition = content_disposition(&resolved, attachment);
+    let cache_ttl = file_cache_ttl(&state.config.file_cache_rules, &relative_path, &mime);
+
+    // 去除元数据时按保留的字节区间拼接输出，不支持 Range 与预压缩副本
+    let stripped = if strip_metadata {
+        Some(metadata_free_ranges(&resolved, &mime, file_size).await?)
+    } else {
+        None
+    };
 
     let modified = metadata.modified().ok();
-    let etag = modified.map(|m| make_etag(file_size, m));
+    // 无 Range 时优先返回同目录下预压缩的 .br/.gz 副本；Range 始终针对原始字节。
+    // 所选表示决定 ETag，因此须在处理条件请求之前确定。
+    let range_header = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let accept_encoding = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| range_header.is_none());
+    let sidecars = match (&stripped, modified) {
+        (None, Some(modified)) => find_precompressed(&resolved, modified, accept_encoding).await,
+        _ => Sidecars::default(),
+    };
+    let precompressed = sidecars.chosen;
+    let vary = sidecars.exist.then_some("accept-encoding");
+
+    let etag = modified.map(|m| {
+        let tag = make_etag(file_size, m);
+        let suffix = match (&stripped, &precompressed) {
+            (Some(_), _) => Some("-nometa"),
+            (None, Some(sidecar)) => Some(sidecar.etag_suffix),
+            (None, None) => None,
+        };
+        match suffix {
+            Some(suffix) => format!("{}{suffix}\"", tag.trim_end_matches('"')),
+            None => tag,
+        }
+    });
     let last_modified = modified.and_then(format_http_date);
+    let not_modified = || -> ApiResult<Response> {
+        let mut response = build_not_modified(etag.as_deref(), last_modified.as_deref())?;
+        if let Some(vary) = vary {
+            response
+                .headers_mut()
+                .insert(header::VARY, HeaderValue::from_static(vary));
+        }
+        set_cache_headers(response.headers_mut(), cache_ttl, private);
+        apply_path_headers(
+            &state.config.path_headers,
+            HeaderTarget::File,
+            &relative_path,
+            response.headers_mut(),
+        );
+        Ok(response)
+    };
 
     // RFC 7232: If-None-Match 优先，命中则 304；仅在 If-None-Match 缺失时才退到 If-Modified-Since。
     let inm_header = headers
@@ -337,7 +777,7 @@ async fn serve_file_response(
         if if_none_match_matches(raw, tag) {
             record_file_access(
                 state,
-                &session,
+                session,
                 &relative_path,
                 route,
                 StatusCode::NOT_MODIFIED,
@@ -346,7 +786,7 @@ async fn serve_file_response(
                 None,
             )
             .await?;
-            return build_not_modified(etag.as_deref(), last_modified.as_deref());
+            return not_modified();
         }
     } else if inm_header.is_none() {
         let ims_header = headers
@@ -356,7 +796,7 @@ async fn serve_file_response(
             if raw.trim() == lm {
                 record_file_access(
                     state,
-                    &session,
+                    session,
                     &relative_path,
                     route,
                     StatusCode::NOT_MODIFIED,
@@ -365,7 +805,7 @@ async fn serve_file_response(
                     None,
                 )
                 .await?;
-                return build_not_modified(etag.as_deref(), last_modified.as_deref());
+                return not_modified();
             }
         }
     }
@@ -376,47 +816,149 @@ async fn serve_file_response(
         None => true,
     };
 
-    let range_header = headers
-        .get(header::RANGE)
-        .and_then(|value| value.to_str().ok());
-    let range = if if_range_ok {
-        match range_header.map(|value| parse_range_header(value, file_size)) {
-            Some(Ok(value)) => Some(value),
+    let ranges = if if_range_ok && stripped.is_none() {
+        let max_ranges = state.config.max_byte_ranges as usize;
+        match range_header.map(|value| parse_range_header(value, file_size, max_ranges)) {
+            Some(Ok(value)) => value,
             Some(Err(_)) => {
-                return build_range_not_satisfiable(
+                let mut response = build_range_not_satisfiable(
                     file_size,
                     etag.as_deref(),
                     last_modified.as_deref(),
-                );
+                )?;
+                if let Some(vary) = vary {
+                    response
+                        .headers_mut()
+                        .insert(header::VARY, HeaderValue::from_static(vary));
+                }
+                return Ok(response);
             }
-            None => None,
+            None => Vec::new(),
         }
     } else {
-        None
+        Vec::new()
+    };
+    // 多区间响应以 multipart/byteranges 分段返回；访问记录取覆盖所有区间的跨度。
+    let multipart = (ranges.len() > 1).then(|| {
+        let boundary = uuid::Uuid::new_v4().simple().to_string();
+        MultipartRanges::new(boundary, &mime, file_size, &ranges)
+    });
+    let range = match ranges.as_slice() {
+        [] => None,
+        [single] => Some(*single),
+        many => Some(ByteRange {
+            start: many.iter().map(|value| value.start).min().unwrap_or(0),
+            end: many.iter().map(|value| value.end).max().unwrap_or(0),
+        }),
+    };
+    let body_size = match (&stripped, &precompressed) {
+        (Some(kept), _) => kept.content_length(),
+        (None, Some(sidecar)) => sidecar.size,
+        (None, None) => file_size,
     };
 
-    let mut file = fs::File::open(&resolved)
-        .await
-        .map_err(|err| ApiError::from_io(err, "file"))?;
+    let (status, content_length, content_range_header) = match (&multipart, range) {
+        (Some(parts), _) => (StatusCode::PARTIAL_CONTENT, parts.content_length(), None),
+        (None, Some(value)) => (
+            StatusCode::PARTIAL_CONTENT,
+            value.len(),
+            Some(format!("bytes {}-{}/{}", value.start, value.end, file_size)),
+        ),
+        (None, None) => (StatusCode::OK, body_size, None),
+    };
+    let content_type = match &multipart {
+        Some(parts) => parts.content_type(),
+        None => mime,
+    };
 
-    let (status, content_length, content_range_header) = match range {
-        Some(value) => {
-            file.seek(SeekFrom::Start(value.start))
-                .await
-                .map_err(|err| ApiError::from_io(err, "file"))?;
-            (
-                StatusCode::PARTIAL_CONTENT,
-                value.len(),
-                Some(format!("bytes {}-{}/{}", value.start, value.end, file_size)),
-            )
+    let mut builder = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_DISPOSITION, content_disposition)
+        .header(
+            header::ACCEPT_RANGES,
+            if stripped.is_some() { "none" } else { "bytes" },
+        )
+        .header(header::CONTENT_LENGTH, content_length.to_string());
+
+    if let Some(content_range) = content_range_header {
+        builder = builder.header(header::CONTENT_RANGE, content_range);
+    }
+    if let Some(sidecar) = &precompressed {
+        builder = builder.header(header::CONTENT_ENCODING, sidecar.encoding);
+    }
+    if let Some(vary) = vary {
+        builder = builder.header(header::VARY, vary);
+    }
+    // 仅在摘要已由 /api/checksum 算出时附带，不为此在下载路径上读整个文件。
+    if precompressed.is_none() && stripped.is_none() {
+        let cached = state
+            .checksums
+            .cached(&resolved, ChecksumAlgo::Sha256, file_size, modified)
+            .await;
+        if let Some(digest) = cached {
+            builder = builder.header(CHECKSUM_SHA256_HEADER, digest);
         }
-        None => (StatusCode::OK, file_size, None),
-    };
+    }
+    if let Some(ref tag) = etag {
+        builder = builder.header(header::ETAG, tag);
+    }
+    if let Some(ref lm) = last_modified {
+        builder = builder.header(header::LAST_MODIFIED, lm);
+    }
+    if let Some(headers) = builder.headers_mut() {
+        set_cache_headers(headers, cache_ttl, private);
+        apply_path_headers(
+            &state.config.path_headers,
+            HeaderTarget::File,
+            &relative_path,
+            headers,
+        );
+    }
 
-    let reader = match range {
-        Some(value) => file.take(value.len()),
-        None => file.take(file_size),
+    // HEAD 探测只返回响应头：不打开文件，也不产生流式传输记录
+    if head_only {
+        record_file_access(
+            state,
+            session,
+            &relative_path,
+            route,
+            status,
+            0,
+            file_size,
+            range,
+        )
+        .await?;
+        return builder
+            .body(Body::empty())
+            .map_err(|_| ApiError::internal("Failed to build file response."));
+    }
+
+    let meter = match state.download_quotas.check(
+        session.user.id,
+        accessible.private_scope.as_deref(),
+        now_unix(),
+    ) {
+        Ok(meter) => meter,
+        Err(resets_at) => return Ok(build_quota_exceeded(resets_at)),
     };
+    let permit = state
+        .download_slots
+        .try_acquire(client_ip, accessible.private_scope.as_deref())
+        .ok_or_else(|| ApiError::rate_limited("Too many concurrent downloads."))?;
+
+    let body_path = precompressed
+        .as_ref()
+        .map_or(resolved.as_path(), |sidecar| sidecar.path.as_path());
+    let mut file = fs::File::open(body_path)
+        .await
+        .map_err(|err| ApiError::from_io(err, "file"))?;
+    if let (None, Some(value)) = (&multipart, range) {
+        file.seek(SeekFrom::Start(value.start))
+            .await
+            .map_err(|err| ApiError::from_io(err, "file"))?;
+    }
+
     let event_id = state
         .db
         .start_resource_stream_access(RecordResourceAccess {
@@ -431,30 +973,155 @@ async fn serve_file_response(
             range_end: range.map(|value| u64_to_i64(value.end)),
         })
         .await?;
-    let recorder = FileAccessRecorder::new(state.db.clone(), event_id);
-    let stream = CountingFileStream::new(reader, recorder);
-    let body = Body::from_stream(stream);
+    let recorder = FileAccessRecorder::with_audit(
+        state.db.clone(),
+        event_id,
+        state.audit.clone(),
+        AuditEvent {
+            event: AuditKind::Download,
+            ip: client_ip.to_string(),
+            user: Some(session.user.username.clone()),
+            path: Some(relative_path),
+            status: Some(status.as_u16()),
+            bytes: None,
+            range_start: range.map(|value| value.start),
+            range_end: range.map(|value| value.end),
+            transfer: None,
+        },
+    );
+    let read_buffer = usize::try_from(state.config.file_read_buffer_bytes).unwrap_or(usize::MAX);
+    let body = match multipart.or(stripped) {
+        Some(parts) => {
+            let parts = ByteRangesStream::new(file, resolved, parts, read_buffer);
+            let counted = CountingFileStream::from_stream(parts, recorder);
+            let throttled = state.bandwidth.throttle(client_ip, counted);
+            Body::from_stream(permit.attach(meter.attach(throttled)))
+        }
+        None => {
+            let reader = file.take(range.map_or(body_size, ByteRange::len));
+            let counted = CountingFileStream::new(reader, read_buffer, recorder);
+            let throttled = state.bandwidth.throttle(client_ip, counted);
+            Body::from_stream(permit.attach(meter.attach(throttled)))
+        }
+    };
 
-    let mut builder = Response::builder()
-        .status(status)
-        .header(header::CONTENT_TYPE, mime)
-        .header(header::CONTENT_DISPOSITION, content_disposition)
-        .header(header::ACCEPT_RANGES, "bytes")
-        .header(header::CONTENT_LENGTH, content_length.to_string());
+    builder
+        .body(body)
+        .map_err(|_| ApiError::internal("Failed to build file response."))
+}
 
-    if let Some(content_range) = content_range_header {
-        builder = builder.header(header::CONTENT_RANGE, content_range);
+/// The byte ranges of a JPEG left once its EXIF and XMP segments are cut
+/// out. Other formats are refused rather than served with their metadata.
+async fn metadata_free_ranges(
+    resolved: &Path,
+    mime: &str,
+    file_size: u64,
+) -> ApiResult<MultipartRanges> {
+    if mime != "image/jpeg" {
+        return Err(ApiError::unsupported_media_type(
+            "Metadata can only be stripped from JPEG images.",
+        ));
     }
-    if let Some(ref tag) = etag {
-        builder = builder.header(header::ETAG, tag);
+    let path = resolved.to_path_buf();
+    let segments = tokio::task::spawn_blocking(move || jpeg_metadata_segments(&path))
+        .await
+        .map_err(|_| ApiError::internal("Failed to read image metadata."))?
+        .map_err(|err| ApiError::from_io(err, "file"))?
+        .ok_or_else(|| ApiError::unsupported_media_type("The file is not a readable JPEG."))?;
+    let mut kept = Vec::new();
+    let mut start = 0;
+    for segment in segments {
+        if segment.start > start {
+            kept.push(ByteRange {
+                start,
+                end: segment.start - 1,
+            });
+        }
+        start = segment.end;
     }
-    if let Some(ref lm) = last_modified {
-        builder = builder.header(header::LAST_MODIFIED, lm);
+    if start < file_size {
+        kept.push(ByteRange {
+            start,
+            end: file_size - 1,
+        });
     }
+    Ok(MultipartRanges::unframed(kept))
+}
 
-    builder
-        .body(body)
-        .map_err(|_| ApiError::internal("Failed to build file response."))
+/// Adds `Cache-Control` and `Expires` when a `MLIST_FILE_CACHE_RULES` entry
+/// matched the file.
+fn set_cache_headers(headers: &mut HeaderMap, ttl: Option<CacheTtl>, private: bool) {
+    let Some(ttl) = ttl else {
+        return;
+    };
+    if let Ok(value) = HeaderValue::from_str(&ttl.cache_control(private)) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    let expires = ttl
+        .expires_in()
+        .and_then(|lifetime| format_http_date(SystemTime::now() + lifetime))
+        .and_then(|date| HeaderValue::from_str(&date).ok());
+    if let Some(value) = expires {
+        headers.insert(header::EXPIRES, value);
+    }
+}
+
+/// Pre-compressed copies that may sit next to a file, in order of
+/// preference, with the suffix that keeps their ETags apart from the
+/// original's.
+const PRECOMPRESSED_SIDECARS: [(&str, &str, &str); 2] =
+    [("br", ".br", "-br"), ("gzip", ".gz", "-gz")];
+
+struct Precompressed {
+    path: PathBuf,
+    encoding: &'static str,
+    etag_suffix: &'static str,
+    size: u64,
+}
+
+#[derive(Default)]
+struct Sidecars {
+    /// Whether any usable sidecar exists, so the response varies by
+    /// `Accept-Encoding` whichever representation is sent.
+    exist: bool,
+    /// The preferred sidecar the client accepts.
+    chosen: Option<Precompressed>,
+}
+
+/// Looks for `<file>.br` and `<file>.gz` sidecars, choosing the first one
+/// `accept_encoding` allows. Sidecars share the directory, and therefore
+/// the access rules, of the original; symlinked sidecars and ones older
+/// than the original, left behind when it was replaced, are ignored.
+async fn find_precompressed(
+    resolved: &Path,
+    modified: SystemTime,
+    accept_encoding: Option<&str>,
+) -> Sidecars {
+    let mut found = Sidecars::default();
+    for (encoding, extension, etag_suffix) in PRECOMPRESSED_SIDECARS {
+        let mut name = resolved.as_os_str().to_os_string();
+        name.push(extension);
+        let path = PathBuf::from(name);
+        let Ok(metadata) = fs::symlink_metadata(&path).await else {
+            continue;
+        };
+        let fresh = metadata.modified().is_ok_and(|sidecar| sidecar >= modified);
+        if !metadata.is_file() || !fresh {
+            continue;
+        }
+        found.exist = true;
+        if found.chosen.is_none()
+            && accept_encoding.is_some_and(|accept| accepts_encoding(accept, encoding))
+        {
+            found.chosen = Some(Precompressed {
+                path,
+                encoding,
+                etag_suffix,
+                size: metadata.len(),
+            });
+        }
+    }
+    found
 }
 
 pub(super) struct FileAccessRecorder {
@@ -463,18 +1130,34 @@ pub(super) struct FileAccessRecorder {
     bytes_served: AtomicU64,
     last_progress_at: AtomicI64,
     finalized: AtomicBool,
+    /// Download line written to the audit log once the transfer ends.
+    audit: Option<(AuditLog, AuditEvent)>,
 }
 
 const STREAM_PROGRESS_FLUSH_INTERVAL_SECONDS: i64 = 5;
 
 impl FileAccessRecorder {
     pub(super) fn new(db: AuthDb, event_id: i64) -> Arc<Self> {
+        Self::build(db, event_id, None)
+    }
+
+    pub(super) fn with_audit(
+        db: AuthDb,
+        event_id: i64,
+        audit: AuditLog,
+        event: AuditEvent,
+    ) -> Arc<Self> {
+        Self::build(db, event_id, Some((audit, event)))
+    }
+
+    fn build(db: AuthDb, event_id: i64, audit: Option<(AuditLog, AuditEvent)>) -> Arc<Self> {
         Arc::new(Self {
             db,
             event_id,
             bytes_served: AtomicU64::new(0),
             last_progress_at: AtomicI64::new(now_unix() as i64),
             finalized: AtomicBool::new(false),
+            audit,
         })
     }
 
@@ -524,6 +1207,13 @@ impl FileAccessRecorder {
             return;
         }
 
+        if let Some((audit, event)) = &self.audit {
+            let mut event = event.clone();
+            event.bytes = Some(self.bytes_served.load(Ordering::Acquire));
+            event.transfer = Some(transfer_state.as_str());
+            audit.record(&event);
+        }
+
         tokio::spawn(async move {
             let bytes_served = u64_to_i64(self.bytes_served.load(Ordering::Acquire));
             if let Err(err) = self
@@ -537,31 +1227,35 @@ impl FileAccessRecorder {
     }
 }
 
-pub(super) struct CountingFileStream<R> {
-    inner: Pin<Box<ReaderStream<R>>>,
+pub(super) struct CountingFileStream<S> {
+    inner: S,
     recorder: Arc<FileAccessRecorder>,
 }
 
-impl<R> CountingFileStream<R>
+impl<R> CountingFileStream<ReaderStream<R>>
 where
     R: AsyncRead + Unpin,
 {
-    pub(super) fn new(reader: R, recorder: Arc<FileAccessRecorder>) -> Self {
-        Self {
-            inner: Box::pin(ReaderStream::new(reader)),
-            recorder,
-        }
+    /// Reads `reader` in chunks of up to `capacity` bytes.
+    pub(super) fn new(reader: R, capacity: usize, recorder: Arc<FileAccessRecorder>) -> Self {
+        Self::from_stream(ReaderStream::with_capacity(reader, capacity), recorder)
     }
 }
 
-impl<R> Stream for CountingFileStream<R>
+impl<S> CountingFileStream<S> {
+    pub(super) fn from_stream(inner: S, recorder: Arc<FileAccessRecorder>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl<S> Stream for CountingFileStream<S>
 where
-    R: AsyncRead + Unpin,
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
 {
     type Item = std::io::Result<Bytes>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.inner.as_mut().poll_next(cx) {
+        match Pin::new(&mut self.inner).poll_next(cx) {
             Poll::Ready(Some(Ok(bytes))) => {
                 self.recorder.add_bytes(bytes.len());
                 Poll::Ready(Some(Ok(bytes)))
@@ -583,7 +1277,7 @@ where
     }
 }
 
-impl<R> Drop for CountingFileStream<R> {
+impl<S> Drop for CountingFileStream<S> {
     fn drop(&mut self) {
         self.recorder
             .clone()
@@ -591,9 +1285,102 @@ impl<R> Drop for CountingFileStream<R> {
     }
 }
 
+type SeekFuture = Pin<Box<dyn Future<Output = std::io::Result<fs::File>> + Send>>;
+
+/// Body of a `multipart/byteranges` response: each part header followed by
+/// that slice of the file, then the closing delimiter. The first part reuses
+/// the handle opened by the caller; later parts reopen the file.
+pub(super) struct ByteRangesStream {
+    path: PathBuf,
+    file: Option<fs::File>,
+    capacity: usize,
+    parts: std::vec::IntoIter<(Bytes, ByteRange)>,
+    trailer: Option<Bytes>,
+    state: RangePartState,
+}
+
+enum RangePartState {
+    Idle,
+    Seeking(u64, SeekFuture),
+    Streaming(ReaderStream<tokio::io::Take<fs::File>>),
+}
+
+impl ByteRangesStream {
+    pub(super) fn new(
+        file: fs::File,
+        path: PathBuf,
+        ranges: MultipartRanges,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            path,
+            file: Some(file),
+            capacity,
+            parts: ranges.parts.into_iter(),
+            trailer: Some(ranges.trailer),
+            state: RangePartState::Idle,
+        }
+    }
+
+    fn seek_to(&mut self, start: u64) -> SeekFuture {
+        let path = self.path.clone();
+        let file = self.file.take();
+        Box::pin(async move {
+            let mut file = match file {
+                Some(file) => file,
+                None => fs::File::open(&path).await?,
+            };
+            file.seek(SeekFrom::Start(start)).await?;
+            Ok(file)
+        })
+    }
+}
+
+impl Stream for ByteRangesStream {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match &mut this.state {
+                RangePartState::Idle => {
+                    let Some((head, range)) = this.parts.next() else {
+                        return Poll::Ready(this.trailer.take().map(Ok));
+                    };
+                    let seek = this.seek_to(range.start);
+                    this.state = RangePartState::Seeking(range.len(), seek);
+                    return Poll::Ready(Some(Ok(head)));
+                }
+                RangePartState::Seeking(len, seek) => {
+                    let len = *len;
+                    match seek.as_mut().poll(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Ok(file)) => {
+                            let reader = ReaderStream::with_capacity(file.take(len), this.capacity);
+                            this.state = RangePartState::Streaming(reader);
+                        }
+                        Poll::Ready(Err(err)) => {
+                            this.state = RangePartState::Idle;
+                            this.parts = Vec::new().into_iter();
+                            this.trailer = None;
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                    }
+                }
+                RangePartState::Streaming(reader) => match Pin::new(reader).poll_next(cx) {
+                    Poll::Ready(None) => this.state = RangePartState::Idle,
+                    other => return other,
+                },
+            }
+        }
+    }
+}
+
 pub(super) struct AccessibleFile {
-    resolved: PathBuf,
-    metadata: std::fs::Metadata,
+    pub(super) resolved: PathBuf,
+    pub(super) metadata: std::fs::Metadata,
+    /// Scope of the nearest `.private` marker, when the file sits in one.
+    pub(super) private_scope: Option<String>,
 }
 
 pub(super) async fn ensure_file_accessible(
@@ -602,12 +1389,13 @@ pub(super) async fn ensure_file_accessible(
     relative_path: &str,
 ) -> ApiResult<AccessibleFile> {
     ensure_not_marker_path(relative_path)?;
+    ensure_not_hidden_path(relative_path, &state.config.hide_patterns)?;
     if relative_path.is_empty() {
         return Err(ApiError::bad_request("Path must reference a file."));
     }
 
     let root = &state.config.root_dir;
-    let resolved = resolve_existing_path(root, relative_path).await?;
+    let resolved = resolve_existing_path(root, relative_path, state.config.symlinks).await?;
     let metadata = fs::metadata(&resolved)
         .await
         .map_err(|err| ApiError::from_io(err, "file"))?;
@@ -619,19 +1407,24 @@ pub(super) async fn ensure_file_accessible(
         return Err(ApiError::not_found("File not found."));
     }
 
-    if let Some(anchor) = find_private_anchor(root, &resolved, false).await? {
-        if !session.user.role.is_admin() {
+    let anchor = find_private_anchor(root, &resolved, false).await?;
+    if let Some(anchor) = &anchor {
+        if !PrivateAccess::for_user(&state.config, &session.user).may_read(relative_path) {
             tracing::info!(
                 user = session.user.username,
                 scope = anchor.scope_rel,
                 marker = anchor.marker_file,
-                "non-admin private file access denied"
+                "private file access denied"
             );
             return Err(ApiError::not_found("File not found."));
         }
     }
 
-    Ok(AccessibleFile { resolved, metadata })
+    Ok(AccessibleFile {
+        resolved,
+        metadata,
+        private_scope: anchor.map(|anchor| anchor.scope_rel),
+    })
 }
 
 async fn record_file_access(
@@ -5,24 +5,117 @@ use axum::http::{HeaderMap, header};
 use axum_extra::extract::cookie::{Cookie, SameSite};
 use totp_rs::{Algorithm, Secret, TOTP};
 
-use crate::db::{AuthSession, UserView};
+use crate::config::AppConfig;
+use crate::db::{ApiTokenScope, AuthSession, UserView};
 use crate::errors::{ApiError, ApiResult};
-use crate::path_guard::is_private_marker_name;
+use crate::net::{ForwardedHeader, IpNet};
+use crate::path_guard::{expand_alias, is_marker_name, normalize_relative_path};
 use crate::session::REFRESH_COOKIE_NAME;
 
 use super::types::AppState;
 
-pub(super) fn client_ip_for_request(headers: &HeaderMap, peer_ip: IpAddr) -> IpAddr {
-    parse_x_forwarded_for(headers)
-        .or_else(|| parse_x_real_ip(headers))
-        .unwrap_or(peer_ip)
+/// Normalizes a client-supplied path and resolves a leading alias from
+/// `MLIST_ALIASES`, so handlers only ever see real paths below the root.
+pub(super) fn normalize_request_path(state: &AppState, raw: Option<&str>) -> ApiResult<String> {
+    normalize_relative_path(raw).map(|path| expand_alias(path, &state.config.aliases))
 }
 
-pub(super) fn parse_x_forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
-    let raw = headers.get("x-forwarded-for")?.to_str().ok()?;
-    raw.split(',')
-        .map(str::trim)
-        .find_map(parse_forwarded_ip_token)
+/// The relative path of `name` inside the relative directory `parent`.
+pub(super) fn join_relative(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_string()
+    } else {
+        format!("{parent}/{name}")
+    }
+}
+
+/// Applies the `.mlist.toml` `listing` and `hide` settings of every
+/// directory between `base` and `path`, for paths found without walking
+/// those directories, such as from the filesystem index.
+pub(super) async fn listed_below(state: &AppState, base: &str, path: &str, admin: bool) -> bool {
+    let rest = path.strip_prefix(base).unwrap_or(path);
+    let mut dir = state.config.root_dir.join(base);
+    for segment in rest.split('/').filter(|segment| !segment.is_empty()) {
+        let config = state.dir_configs.load(&dir).await;
+        if !config.lists_for(admin) || config.hides(segment) {
+            return false;
+        }
+        dir.push(segment);
+    }
+    true
+}
+
+/// Resolves the client address for rate limiting and logging. Forwarding
+/// headers are only believed when the socket peer is a configured trusted
+/// proxy, and only the one header that proxy is configured to set; the
+/// chain is then walked from the nearest hop outwards and the first
+/// untrusted address wins, so a client cannot spoof its way past the proxy
+/// by sending its own `X-Forwarded-For` or `Forwarded`.
+pub(crate) fn client_ip_for_request(
+    headers: &HeaderMap,
+    peer_ip: IpAddr,
+    trusted_proxies: &[IpNet],
+    forwarded_header: ForwardedHeader,
+) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !is_trusted(peer_ip) {
+        return peer_ip;
+    }
+
+    let chain = match forwarded_header {
+        ForwardedHeader::XForwardedFor => parse_x_forwarded_for_chain(headers),
+        ForwardedHeader::Forwarded => parse_forwarded_chain(headers),
+    };
+    if let Some(client) = chain.iter().rev().copied().find(|ip| !is_trusted(*ip)) {
+        return client;
+    }
+    if !chain.is_empty() {
+        // Every hop is one of ours; the originating entry is the client.
+        return chain[0];
+    }
+
+    match forwarded_header {
+        ForwardedHeader::XForwardedFor => parse_x_real_ip(headers).unwrap_or(peer_ip),
+        ForwardedHeader::Forwarded => peer_ip,
+    }
+}
+
+/// Collects hop addresses from RFC 7239 `Forwarded`. Unparseable hops
+/// (`unknown`, obfuscated identifiers) are dropped.
+fn parse_forwarded_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+    headers
+        .get_all(header::FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| parse_forwarded_node(value))
+                    .flatten()
+            })
+        })
+        .collect()
+}
+
+pub(super) fn parse_x_forwarded_for_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|token| parse_forwarded_ip_token(token.trim()))
+        .collect()
+}
+
+fn parse_forwarded_node(raw: &str) -> Option<IpAddr> {
+    let node = raw.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    parse_forwarded_ip_token(node)
 }
 
 pub(super) fn parse_x_real_ip(headers: &HeaderMap) -> Option<IpAddr> {
@@ -40,15 +133,59 @@ fn parse_forwarded_ip_token(raw: &str) -> Option<IpAddr> {
         .or_else(|| raw.parse::<SocketAddr>().ok().map(|value| value.ip()))
 }
 
-pub(super) fn build_refresh_cookie(refresh_token: &str, ttl_seconds: u64) -> Cookie<'static> {
+/// Scheme, host and base path for absolute URLs handed to clients outside
+/// the browser. A configured public URL wins over the request. Otherwise
+/// `Host` is used, and `X-Forwarded-Proto`/`X-Forwarded-Host` are believed
+/// only from a trusted proxy, as in [`client_ip_for_request`].
+pub(super) fn request_origin(
+    headers: &HeaderMap,
+    peer_ip: IpAddr,
+    public_url: Option<&str>,
+    base_path: &str,
+    trusted_proxies: &[IpNet],
+) -> Option<String> {
+    if let Some(url) = public_url {
+        return Some(format!("{url}{base_path}"));
+    }
+    let forwarded = |name: &str| {
+        trusted_proxies
+            .iter()
+            .any(|net| net.contains(peer_ip))
+            .then(|| headers.get(name)?.to_str().ok())
+            .flatten()
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+    let scheme = match forwarded("x-forwarded-proto") {
+        Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+        _ => "http",
+    };
+    let host = forwarded("x-forwarded-host")
+        .or_else(|| headers.get(header::HOST)?.to_str().ok())
+        .filter(|host| !host.is_empty() && !host.contains(['/', '\\', '?', '#', '@']))?;
+    Some(format!("{scheme}://{host}{base_path}"))
+}
+
+/// The refresh cookie; an empty token with no lifetime removes it.
+pub(super) fn build_refresh_cookie(
+    config: &AppConfig,
+    refresh_token: &str,
+    ttl_seconds: u64,
+) -> Cookie<'static> {
     Cookie::build((REFRESH_COOKIE_NAME, refresh_token.to_string()))
-        .path("/api/auth")
+        .path(format!("{}/api/auth", config.base_path))
         .http_only(true)
+        .secure(config.secure_cookies)
         .same_site(SameSite::Lax)
         .max_age(time::Duration::seconds(ttl_seconds as i64))
         .build()
 }
 
+/// API tokens carry a recognizable prefix so `current_session` knows which
+/// table to look them up in.
+pub(super) const API_TOKEN_PREFIX: &str = "mlt_";
+
 pub(super) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
     let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
     value
@@ -64,6 +201,9 @@ pub(super) async fn current_session(
     let Some(token) = bearer_token(headers) else {
         return Ok(None);
     };
+    if token.starts_with(API_TOKEN_PREFIX) {
+        return state.db.api_token_session(token).await;
+    }
     state.db.access_session_by_token(token).await
 }
 
@@ -87,13 +227,29 @@ pub(super) async fn file_session_for_request(
 }
 
 pub(super) async fn require_session(state: &AppState, headers: &HeaderMap) -> ApiResult<AuthSession> {
-    current_session(state, headers)
+    require_scope(state, headers, ApiTokenScope::Read).await
+}
+
+/// Interactive sessions pass any scope check; API tokens must carry `scope`.
+pub(super) async fn require_scope(
+    state: &AppState,
+    headers: &HeaderMap,
+    scope: ApiTokenScope,
+) -> ApiResult<AuthSession> {
+    let session = current_session(state, headers)
         .await?
-        .ok_or_else(ApiError::auth_required)
+        .ok_or_else(ApiError::auth_required)?;
+    if !session.allows(scope) {
+        return Err(ApiError::forbidden(format!(
+            "Token is missing the {} scope.",
+            scope.as_str()
+        )));
+    }
+    Ok(session)
 }
 
 pub(super) async fn require_admin(state: &AppState, headers: &HeaderMap) -> ApiResult<AuthSession> {
-    let session = require_session(state, headers).await?;
+    let session = require_scope(state, headers, ApiTokenScope::Admin).await?;
     if !session.user.role.is_admin() {
         return Err(ApiError::forbidden("Administrator privileges required."));
     }
@@ -176,5 +332,20 @@ pub(super) fn verify_totp(username: &str, secret: &str, code: &str) -> ApiResult
 pub(super) fn file_name_is_marker(path: &Path) -> bool {
     path.file_name()
         .and_then(|value| value.to_str())
-        .is_some_and(is_private_marker_name)
+        .is_some_and(is_marker_name)
+}
+
+/// Checks a single path segment supplied by a client, such as an upload
+/// file name or a rename target, and returns it trimmed.
+pub(super) fn validate_entry_name(raw: &str) -> ApiResult<String> {
+    let name = raw.trim();
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains(['/', '\\', '\0'])
+        || is_marker_name(name)
+    {
+        return Err(ApiError::bad_request("Invalid file name."));
+    }
+    Ok(name.to_string())
 }
@@ -0,0 +1,152 @@
+use std::net::SocketAddr;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path as AxumPath, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::Response;
+use tokio::fs;
+use tokio_util::io::ReaderStream;
+
+use crate::audit::{AuditEvent, AuditKind};
+use crate::db::{RecordResourceAccess, ResourceKind};
+use crate::errors::{ApiError, ApiResult};
+use crate::session::now_unix;
+use crate::transcode::with_token;
+
+use super::files::{CountingFileStream, FileAccessRecorder, ensure_file_accessible};
+use super::helpers::{
+    client_ip_for_request, file_session_for_request, normalize_request_path, require_session,
+};
+use super::http_util::build_quota_exceeded;
+use super::types::{AppState, HlsQuery};
+
+const MASTER_PLAYLIST: &str = "master.m3u8";
+
+/// Serves `/api/hls/{path}/master.m3u8` and the segments it lists, starting
+/// an ffmpeg transcode on the first playlist request. Segment URIs in the
+/// playlist are relative, so they resolve back to this route; when the
+/// playlist was fetched with a signed token the token is appended to each
+/// of them. Segments count against download quotas and concurrency caps
+/// like any other download, and the playlist is refused up front when
+/// either is already exhausted.
+pub async fn hls_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    AxumPath(raw_path): AxumPath<String>,
+    Query(query): Query<HlsQuery>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let ffmpeg = match state.config.ffmpeg_path.as_deref() {
+        Some(path) if state.config.hls_enabled => path,
+        _ => return Err(ApiError::not_found("HLS streaming is not enabled.")),
+    };
+    let (file_path, asset) = raw_path
+        .rsplit_once('/')
+        .ok_or_else(|| ApiError::not_found("HLS asset not found."))?;
+    let relative_path = normalize_request_path(&state, Some(file_path))?;
+    let session = match query.token.as_deref() {
+        Some(token) => file_session_for_request(&state, &relative_path, Some(token)).await?,
+        None => require_session(&state, &headers).await?,
+    };
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+
+    let mime = state.config.guess_mime(&accessible.resolved);
+    if !mime.essence_str().starts_with("video/") {
+        return Err(ApiError::bad_request("Only videos can be streamed as HLS."));
+    }
+
+    let client_ip = client_ip_for_request(
+        &headers,
+        peer.ip(),
+        &state.config.trusted_proxies,
+        state.config.forwarded_header,
+    );
+    let scope = accessible.private_scope.as_deref();
+    let meter = match state
+        .download_quotas
+        .check(session.user.id, scope, now_unix())
+    {
+        Ok(meter) => meter,
+        Err(resets_at) => return Ok(build_quota_exceeded(resets_at)),
+    };
+    let permit = state
+        .download_slots
+        .try_acquire(client_ip, scope)
+        .ok_or_else(|| ApiError::rate_limited("Too many concurrent downloads."))?;
+
+    if asset == MASTER_PLAYLIST {
+        // 播放列表本身不计流量，只在转码开始前确认配额和并发数仍有余量
+        drop(permit);
+        let playlist = state
+            .transcodes
+            .playlist(
+                ffmpeg,
+                &accessible.resolved,
+                &accessible.metadata,
+                session.user.id,
+            )
+            .await?;
+        return Response::builder()
+            .header(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::from(with_token(&playlist, query.token.as_deref())))
+            .map_err(|_| ApiError::internal("Failed to build playlist response."));
+    }
+
+    let segment = state
+        .transcodes
+        .segment(
+            &accessible.resolved,
+            &accessible.metadata,
+            asset,
+            session.user.id,
+        )
+        .await?;
+    let file = fs::File::open(&segment)
+        .await
+        .map_err(|err| ApiError::from_io(err, "segment"))?;
+    let len = file
+        .metadata()
+        .await
+        .map_err(|err| ApiError::from_io(err, "segment"))?
+        .len();
+    let event_id = state
+        .db
+        .start_resource_stream_access(RecordResourceAccess {
+            user_id: session.user.id,
+            kind: ResourceKind::File,
+            path: relative_path.clone(),
+            route: "/api/hls",
+            status: StatusCode::OK.as_u16(),
+            bytes_served: 0,
+            file_size: i64::try_from(accessible.metadata.len()).ok(),
+            range_start: None,
+            range_end: None,
+        })
+        .await?;
+    let recorder = FileAccessRecorder::with_audit(
+        state.db.clone(),
+        event_id,
+        state.audit.clone(),
+        AuditEvent {
+            event: AuditKind::Download,
+            ip: client_ip.to_string(),
+            user: Some(session.user.username.clone()),
+            path: Some(format!("{relative_path}#{asset}")),
+            status: Some(StatusCode::OK.as_u16()),
+            bytes: None,
+            range_start: None,
+            range_end: None,
+            transfer: None,
+        },
+    );
+    let counted = CountingFileStream::from_stream(ReaderStream::new(file), recorder);
+    Response::builder()
+        .header(header::CONTENT_TYPE, "video/mp2t")
+        .header(header::CONTENT_LENGTH, len.to_string())
+        .header(header::CACHE_CONTROL, "private, max-age=3600")
+        .body(Body::from_stream(permit.attach(
+            meter.attach(state.bandwidth.throttle(client_ip, counted)),
+        )))
+        .map_err(|_| ApiError::internal("Failed to build segment response."))
+}
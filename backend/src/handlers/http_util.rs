@@ -1,10 +1,13 @@
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use axum::http::{StatusCode, header};
+use axum::body::Bytes;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::IntoResponse;
 use time::{Month, OffsetDateTime, UtcOffset, Weekday};
 
 use crate::errors::{ApiError, ApiResult};
+use crate::session::{now_unix, unix_to_rfc3339};
 
 #[derive(Debug, Clone, Copy)]
 pub(super) struct ByteRange {
@@ -19,15 +22,19 @@ impl ByteRange {
 }
 
 pub(super) fn signed_direct_file_url(path: &str, token: &str) -> String {
+    format!("{}?token={token}", direct_file_url(path))
+}
+
+pub(super) fn direct_file_url(path: &str) -> String {
     let encoded_path = path
         .split('/')
         .map(url_path_segment_encode)
         .collect::<Vec<_>>()
         .join("/");
-    format!("/d/{encoded_path}?token={token}")
+    format!("/d/{encoded_path}")
 }
 
-fn url_path_segment_encode(value: &str) -> String {
+pub(super) fn url_path_segment_encode(value: &str) -> String {
     let mut encoded = String::with_capacity(value.len());
     for byte in value.as_bytes() {
         if is_url_unreserved(*byte) {
@@ -45,7 +52,7 @@ fn is_url_unreserved(byte: u8) -> bool {
     byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
 }
 
-pub(super) fn content_disposition_inline(path: &Path) -> String {
+pub(super) fn content_disposition(path: &Path, attachment: bool) -> String {
     let raw_name = path
         .file_name()
         .map(|value| value.to_string_lossy().to_string())
@@ -53,7 +60,8 @@ pub(super) fn content_disposition_inline(path: &Path) -> String {
     let fallback = ascii_filename_fallback(&raw_name);
     let escaped_fallback = escape_quoted_string(&fallback);
     let encoded = rfc5987_encode(&raw_name);
-    format!("inline; filename=\"{escaped_fallback}\"; filename*=UTF-8''{encoded}")
+    let kind = if attachment { "attachment" } else { "inline" };
+    format!("{kind}; filename=\"{escaped_fallback}\"; filename*=UTF-8''{encoded}")
 }
 
 fn ascii_filename_fallback(raw_name: &str) -> String {
@@ -75,6 +83,21 @@ fn ascii_filename_fallback(raw_name: &str) -> String {
     }
 }
 
+pub(super) fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
 fn escape_quoted_string(value: &str) -> String {
     value.replace('\\', "\\\\").replace('"', "\\\"")
 }
@@ -163,6 +186,25 @@ fn strip_weak_prefix(s: &str) -> &str {
     s.strip_prefix("W/").unwrap_or(s)
 }
 
+/// Whether an `Accept-Encoding` header admits `coding`. Explicit `q=0`
+/// entries refuse it; wildcards are not treated as acceptance.
+pub(super) fn accepts_encoding(raw: &str, coding: &str) -> bool {
+    raw.split(',').any(|entry| {
+        let mut params = entry.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        if !name.eq_ignore_ascii_case(coding) {
+            return false;
+        }
+        !params.any(|param| {
+            param
+                .trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        })
+    })
+}
+
 pub(super) fn if_range_matches(raw: &str, etag: Option<&str>, last_modified: Option<&str>) -> bool {
     let raw = raw.trim();
     if raw.starts_with('"') || raw.starts_with("W/") {
@@ -172,7 +214,14 @@ pub(super) fn if_range_matches(raw: &str, etag: Option<&str>, last_modified: Opt
     last_modified.is_some_and(|lm| raw == lm)
 }
 
-pub(super) fn parse_range_header(raw_header: &str, file_size: u64) -> ApiResult<ByteRange> {
+/// Parses a `Range` header into the satisfiable ranges it names, in request
+/// order. Parts starting past the end of the file are dropped; the request
+/// fails when none remain or more than `max_ranges` are asked for.
+pub(super) fn parse_range_header(
+    raw_header: &str,
+    file_size: u64,
+    max_ranges: usize,
+) -> ApiResult<Vec<ByteRange>> {
     if file_size == 0 {
         return Err(ApiError::invalid_range(
             "Range request cannot be satisfied for an empty file.",
@@ -180,17 +229,40 @@ pub(super) fn parse_range_header(raw_header: &str, file_size: u64) -> ApiResult<
     }
 
     let raw = raw_header.trim();
-    let Some(raw_range) = raw.strip_prefix("bytes=") else {
+    let Some(raw_ranges) = raw.strip_prefix("bytes=") else {
         return Err(ApiError::invalid_range("Only bytes ranges are supported."));
     };
 
-    if raw_range.contains(',') {
+    let specs = raw_ranges
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .collect::<Vec<_>>();
+    if specs.is_empty() {
+        return Err(ApiError::invalid_range("Malformed Range header."));
+    }
+    if specs.len() > max_ranges.max(1) {
+        return Err(ApiError::invalid_range("Too many ranges requested."));
+    }
+
+    let mut ranges = Vec::with_capacity(specs.len());
+    for spec in specs {
+        if let Some(range) = parse_range_spec(spec, file_size)? {
+            ranges.push(range);
+        }
+    }
+    if ranges.is_empty() {
         return Err(ApiError::invalid_range(
-            "Multiple ranges are not supported.",
+            "Range start is beyond end of file.",
         ));
     }
+    Ok(ranges)
+}
 
-    let (start_part, end_part) = raw_range
+/// Parses one `first-last` or `-suffix` spec. `None` means the spec is well
+/// formed but starts beyond the end of the file.
+fn parse_range_spec(spec: &str, file_size: u64) -> ApiResult<Option<ByteRange>> {
+    let (start_part, end_part) = spec
         .split_once('-')
         .ok_or_else(|| ApiError::invalid_range("Malformed Range header."))?;
 
@@ -206,36 +278,91 @@ pub(super) fn parse_range_header(raw_header: &str, file_size: u64) -> ApiResult<
         let read_len = suffix_len.min(file_size);
         let start = file_size - read_len;
         let end = file_size - 1;
-        return Ok(ByteRange { start, end });
+        return Ok(Some(ByteRange { start, end }));
     }
 
     let start = start_part
         .parse::<u64>()
         .map_err(|_| ApiError::invalid_range("Malformed start byte range."))?;
-    if start >= file_size {
-        return Err(ApiError::invalid_range(
-            "Range start is beyond end of file.",
-        ));
-    }
 
     let mut end = if end_part.is_empty() {
-        file_size - 1
+        u64::MAX
     } else {
         end_part
             .parse::<u64>()
             .map_err(|_| ApiError::invalid_range("Malformed end byte range."))?
     };
-
-    if end >= file_size {
-        end = file_size - 1;
-    }
     if end < start {
         return Err(ApiError::invalid_range(
             "Range end cannot be smaller than range start.",
         ));
     }
+    if start >= file_size {
+        return Ok(None);
+    }
+    if end >= file_size {
+        end = file_size - 1;
+    }
+
+    Ok(Some(ByteRange { start, end }))
+}
+
+/// Framing for a `multipart/byteranges` response (RFC 7233 §4.1). Part
+/// headers and the closing delimiter are built up front so the body length
+/// is known before anything is read from disk.
+pub(super) struct MultipartRanges {
+    pub(super) boundary: String,
+    pub(super) parts: Vec<(Bytes, ByteRange)>,
+    pub(super) trailer: Bytes,
+}
+
+impl MultipartRanges {
+    pub(super) fn new(boundary: String, mime: &str, file_size: u64, ranges: &[ByteRange]) -> Self {
+        let parts = ranges
+            .iter()
+            .enumerate()
+            .map(|(index, range)| {
+                let lead = if index == 0 { "" } else { "\r\n" };
+                let head = format!(
+                    "{lead}--{boundary}\r\nContent-Type: {mime}\r\nContent-Range: bytes {}-{}/{file_size}\r\n\r\n",
+                    range.start, range.end
+                );
+                (Bytes::from(head), *range)
+            })
+            .collect();
+        let trailer = Bytes::from(format!("\r\n--{boundary}--\r\n"));
+        Self {
+            boundary,
+            parts,
+            trailer,
+        }
+    }
+
+    /// The ranges back to back, without part headers or a closing
+    /// delimiter, for a body that is just a selection of the file's bytes.
+    pub(super) fn unframed(ranges: Vec<ByteRange>) -> Self {
+        Self {
+            boundary: String::new(),
+            parts: ranges
+                .into_iter()
+                .map(|range| (Bytes::new(), range))
+                .collect(),
+            trailer: Bytes::new(),
+        }
+    }
 
-    Ok(ByteRange { start, end })
+    pub(super) fn content_type(&self) -> String {
+        format!("multipart/byteranges; boundary={}", self.boundary)
+    }
+
+    pub(super) fn content_length(&self) -> u64 {
+        let parts = self
+            .parts
+            .iter()
+            .map(|(head, range)| head.len() as u64 + range.len())
+            .sum::<u64>();
+        parts + self.trailer.len() as u64
+    }
 }
 
 pub(super) fn build_not_modified(etag: Option<&str>, last_modified: Option<&str>) -> ApiResult<axum::response::Response> {
@@ -270,3 +397,20 @@ pub(super) fn build_range_not_satisfiable(
         .body(axum::body::Body::empty())
         .map_err(|_| ApiError::internal("Failed to build 416 response."))
 }
+
+/// 429 for a used-up download quota. `resets_at` is sent as Unix time in
+/// `X-RateLimit-Reset` and as a delay in `Retry-After`.
+pub(super) fn build_quota_exceeded(resets_at: u64) -> axum::response::Response {
+    let mut response = ApiError::rate_limited(format!(
+        "Download quota exceeded. It resets at {}.",
+        unix_to_rfc3339(resets_at)
+    ))
+    .into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        header::RETRY_AFTER,
+        HeaderValue::from(resets_at.saturating_sub(now_unix())),
+    );
+    headers.insert("x-ratelimit-reset", HeaderValue::from(resets_at));
+    response
+}
@@ -0,0 +1,333 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::auth::{PrivateAccess, find_private_anchor, find_writable_anchor};
+use crate::db::{ApiTokenScope, AuthSession, TrashEntry};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, private_file_marker_name,
+    resolve_existing_path,
+};
+use crate::session::now_unix;
+
+use super::files::list_entry;
+use super::helpers::{join_relative, normalize_request_path, require_scope, validate_entry_name};
+use super::types::{
+    AppState, FsMoveRequest, FsPathRequest, FsRenameRequest, GenericOkResponse, ListEntry,
+};
+
+/// An existing entry addressed by its parent directory, so symlinks are
+/// renamed or deleted themselves rather than the files they point to.
+struct Entry {
+    parent: PathBuf,
+    parent_relative: String,
+    name: String,
+    is_dir: bool,
+}
+
+impl Entry {
+    fn path(&self) -> PathBuf {
+        self.parent.join(&self.name)
+    }
+}
+
+/// Renames an entry within its directory.
+pub async fn fs_rename_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<FsRenameRequest>,
+) -> ApiResult<Json<ListEntry>> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let entry = locate_entry(&state, &session, &payload.path).await?;
+    let name = validate_new_name(&state, &payload.name)?;
+    let parent = entry.parent.clone();
+    let parent_relative = entry.parent_relative.clone();
+    relocate(&state, &session, entry, &parent, &parent_relative, name).await
+}
+
+/// Moves an entry into another directory, keeping its name.
+pub async fn fs_move_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<FsMoveRequest>,
+) -> ApiResult<Json<ListEntry>> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let entry = locate_entry(&state, &session, &payload.path).await?;
+    let (to, to_relative) = locate_dir(&state, &session, &payload.to).await?;
+    if entry.is_dir && to.starts_with(entry.path()) {
+        return Err(ApiError::bad_request(
+            "A directory cannot be moved into itself.",
+        ));
+    }
+    let name = entry.name.clone();
+    relocate(&state, &session, entry, &to, &to_relative, name).await
+}
+
+/// Deletes an entry, or moves it into `trash_dir` when one is configured.
+pub async fn fs_delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<FsPathRequest>,
+) -> ApiResult<Json<GenericOkResponse>> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let entry = locate_entry(&state, &session, &payload.path).await?;
+    let source = entry.path();
+    let path = join_relative(&entry.parent_relative, &entry.name);
+    let marker = entry.parent.join(private_file_marker_name(&entry.name));
+
+    if let Some(trash_dir) = &state.config.trash_dir {
+        fs::create_dir_all(trash_dir)
+            .await
+            .map_err(|err| ApiError::from_io(err, "trash directory"))?;
+        // 时间戳加随机前缀，避免同名条目在回收站中互相覆盖
+        let deleted_at = now_unix();
+        let prefix = format!("{deleted_at}-{}", &Uuid::new_v4().simple().to_string()[..8]);
+        let id = format!("{prefix}-{}", entry.name);
+        let size = if entry.is_dir {
+            None
+        } else {
+            fs::symlink_metadata(&source)
+                .await
+                .ok()
+                .map(|metadata| metadata.len() as i64)
+        };
+        fs::rename(&source, trash_dir.join(&id))
+            .await
+            .map_err(rename_error)?;
+        // 私有标记一并移入回收站，恢复后文件仍然私有
+        let mut trashed_marker = None;
+        if !entry.is_dir && fs::symlink_metadata(&marker).await.is_ok() {
+            let name = format!("{prefix}-{}", private_file_marker_name(&entry.name));
+            if fs::rename(&marker, trash_dir.join(&name)).await.is_ok() {
+                trashed_marker = Some(name);
+            }
+        }
+        state
+            .db
+            .record_trash_entry(&TrashEntry {
+                id,
+                path: path.clone(),
+                is_dir: entry.is_dir,
+                size,
+                marker: trashed_marker,
+                deleted_by_id: Some(session.user.id),
+                deleted_by: None,
+                deleted_at: deleted_at as i64,
+            })
+            .await?;
+    } else if entry.is_dir {
+        fs::remove_dir_all(&source)
+            .await
+            .map_err(|err| ApiError::from_io(err, "directory"))?;
+    } else {
+        fs::remove_file(&source)
+            .await
+            .map_err(|err| ApiError::from_io(err, "file"))?;
+    }
+    if !entry.is_dir {
+        let _ = fs::remove_file(&marker).await;
+    }
+
+    tracing::info!(
+        user = session.user.username,
+        path,
+        trashed = state.config.trash_dir.is_some(),
+        "entry deleted"
+    );
+    Ok(Json(GenericOkResponse { ok: true }))
+}
+
+/// Creates a directory; its parent must already exist.
+pub async fn fs_mkdir_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<FsPathRequest>,
+) -> ApiResult<Json<ListEntry>> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let relative_path = normalize_request_path(&state, Some(&payload.path))?;
+    let (parent_relative, raw_name) = split_relative(&relative_path)?;
+    let name = validate_new_name(&state, raw_name)?;
+    let (parent, parent_relative) = locate_dir(&state, &session, parent_relative).await?;
+
+    let target = parent.join(&name);
+    fs::create_dir(&target)
+        .await
+        .map_err(|err| match err.kind() {
+            ErrorKind::AlreadyExists => {
+                ApiError::conflict("An entry with this name already exists.")
+            }
+            _ => ApiError::from_io(err, "directory"),
+        })?;
+    let path = join_relative(&parent_relative, &name);
+    tracing::info!(user = session.user.username, path, "directory created");
+    created_entry(&state, &target, name, path, true).await
+}
+
+async fn relocate(
+    state: &AppState,
+    session: &AuthSession,
+    entry: Entry,
+    to: &Path,
+    to_relative: &str,
+    name: String,
+) -> ApiResult<Json<ListEntry>> {
+    let source = entry.path();
+    let target = to.join(&name);
+    if target == source {
+        return Err(ApiError::bad_request(
+            "Source and destination are the same.",
+        ));
+    }
+    // rename 会静默覆盖已存在的文件，先拒绝同名目标
+    if fs::symlink_metadata(&target).await.is_ok() {
+        return Err(ApiError::conflict(
+            "An entry with this name already exists.",
+        ));
+    }
+    fs::rename(&source, &target).await.map_err(rename_error)?;
+    if !entry.is_dir {
+        // 文件级私有标记跟随文件移动，否则改名后会变成公开文件
+        let marker = entry.parent.join(private_file_marker_name(&entry.name));
+        if fs::symlink_metadata(&marker).await.is_ok() {
+            fs::rename(&marker, to.join(private_file_marker_name(&name)))
+                .await
+                .map_err(rename_error)?;
+        }
+    }
+
+    let from_path = join_relative(&entry.parent_relative, &entry.name);
+    let path = join_relative(to_relative, &name);
+    tracing::info!(
+        user = session.user.username,
+        from = from_path,
+        to = path,
+        "entry moved"
+    );
+    created_entry(state, &target, name, path, entry.is_dir).await
+}
+
+pub(super) async fn created_entry(
+    state: &AppState,
+    target: &Path,
+    name: String,
+    path: String,
+    is_dir: bool,
+) -> ApiResult<Json<ListEntry>> {
+    let metadata = fs::metadata(target)
+        .await
+        .map_err(|err| ApiError::from_io(err, "entry"))?;
+    let requires_auth = find_private_anchor(&state.config.root_dir, target, is_dir)
+        .await?
+        .is_some();
+    Ok(Json(list_entry(
+        &state.config,
+        name,
+        path,
+        is_dir,
+        &metadata,
+        requires_auth,
+        false,
+    )))
+}
+
+/// Resolves the entry named by `raw` and checks the caller may change it.
+/// The permission check runs against the parent directory, so a non-admin
+/// can manage what lies inside a `.writable` scope but not the scope root.
+async fn locate_entry(state: &AppState, session: &AuthSession, raw: &str) -> ApiResult<Entry> {
+    let relative_path = normalize_request_path(state, Some(raw))?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+    let (parent_relative, name) = split_relative(&relative_path)?;
+    let name = name.to_string();
+    let (parent, parent_relative) = locate_dir(state, session, parent_relative).await?;
+
+    let metadata = fs::symlink_metadata(parent.join(&name))
+        .await
+        .map_err(|err| ApiError::from_io(err, "entry"))?;
+    let is_dir = metadata.is_dir();
+    if find_private_anchor(&state.config.root_dir, &parent.join(&name), is_dir)
+        .await?
+        .is_some()
+        && !PrivateAccess::for_user(&state.config, &session.user)
+            .may_read(&join_relative(&parent_relative, &name))
+    {
+        return Err(ApiError::not_found("Path not found."));
+    }
+    Ok(Entry {
+        parent,
+        parent_relative,
+        name,
+        is_dir,
+    })
+}
+
+/// Resolves an existing directory that entries are created in or moved
+/// into, and checks the caller may write there.
+pub(super) async fn locate_dir(
+    state: &AppState,
+    session: &AuthSession,
+    raw: &str,
+) -> ApiResult<(PathBuf, String)> {
+    let relative_path = normalize_request_path(state, Some(raw))?;
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    let private = find_private_anchor(root, &resolved, true).await?.is_some();
+    if private && !access.may_read(&relative_path) {
+        return Err(ApiError::not_found("Path not found."));
+    }
+    let allowed = (state.config.manage_enabled && access.is_admin())
+        || (private && access.may_write(&relative_path))
+        || find_writable_anchor(root, &resolved).await?.is_some();
+    if !allowed {
+        return Err(ApiError::forbidden(
+            "File management is not allowed in this directory.",
+        ));
+    }
+    Ok((resolved, relative_path))
+}
+
+fn split_relative(relative_path: &str) -> ApiResult<(&str, &str)> {
+    if relative_path.is_empty() {
+        return Err(ApiError::bad_request(
+            "The root directory cannot be changed.",
+        ));
+    }
+    ensure_not_marker_path(relative_path)?;
+    Ok(relative_path
+        .rsplit_once('/')
+        .unwrap_or(("", relative_path)))
+}
+
+fn validate_new_name(state: &AppState, raw: &str) -> ApiResult<String> {
+    let name = validate_entry_name(raw)?;
+    if is_hidden_name(&name, &state.config.hide_patterns) {
+        return Err(ApiError::bad_request(
+            "File name is hidden by configuration.",
+        ));
+    }
+    Ok(name)
+}
+
+pub(super) fn rename_error(err: std::io::Error) -> ApiError {
+    if err.kind() == ErrorKind::CrossesDevices {
+        tracing::warn!("rename across filesystems failed: {err}");
+        return ApiError::internal("Destination is on a different filesystem than the source.");
+    }
+    ApiError::from_io(err, "entry")
+}
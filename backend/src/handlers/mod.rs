@@ -1,22 +1,73 @@
 mod admin;
+mod archive;
 mod auth;
+mod batch;
+mod checksum;
+mod dav;
+mod events;
+mod exif;
 mod favorites;
+mod feed;
 mod files;
 mod helpers;
+mod hls;
 mod http_util;
+mod manage;
+mod oidc;
+mod playlist;
+mod preview;
+mod public_index;
+mod resumable;
+mod search;
+mod tail;
 #[cfg(test)]
 mod tests;
+mod thumbs;
+mod torrent;
+mod trash;
+mod tree;
 mod types;
+mod upload;
 
 pub use admin::{
-    admin_audit_events_handler, admin_audit_resources_handler, admin_create_user_handler,
-    admin_delete_user_handler, admin_disable_user_handler, admin_enable_user_handler,
-    admin_reset_totp_handler, admin_users_handler,
+    admin_api_tokens_handler, admin_audit_events_handler, admin_audit_resources_handler,
+    admin_clear_login_blocks_handler, admin_create_api_token_handler, admin_create_user_handler,
+    admin_delete_user_handler, admin_diagnostics_handler, admin_disable_user_handler,
+    admin_enable_user_handler, admin_login_blocks_handler, admin_reset_totp_handler,
+    admin_revoke_api_token_handler, admin_revoke_session_handler,
+    admin_revoke_user_sessions_handler, admin_scopes_handler, admin_sessions_handler,
+    admin_stats_handler, admin_stats_top_handler, admin_users_handler,
 };
+pub use archive::{archive_file_handler, archive_list_handler};
 pub use auth::{
     bootstrap_finish_handler, bootstrap_start_handler, login_handler, logout_handler, me_handler,
     refresh_handler,
 };
+pub use batch::batch_download_handler;
+pub use checksum::checksum_handler;
+pub use dav::{create_dav_token_handler, dav_handler, dav_root_handler};
+pub use events::events_handler;
+pub use exif::exif_handler;
 pub use favorites::{favorites_handler, file_states_handler, set_favorite_handler, set_file_state_handler};
+pub use feed::feed_handler;
 pub use files::{create_file_link_handler, direct_file_handler, list_handler};
+pub(crate) use helpers::client_ip_for_request;
+pub use hls::hls_handler;
+pub use manage::{fs_delete_handler, fs_mkdir_handler, fs_move_handler, fs_rename_handler};
+pub use oidc::{
+    admin_link_oidc_handler, admin_unlink_oidc_handler, oidc_callback_handler, oidc_login_handler,
+};
+pub use playlist::playlist_handler;
+pub use preview::preview_handler;
+pub use public_index::{public_index_handler, sitemap_handler};
+pub use resumable::{
+    cancel_upload_handler, create_upload_handler, upload_chunk_handler, upload_status_handler,
+};
+pub use search::search_handler;
+pub use tail::tail_handler;
+pub use thumbs::{image_handler, thumb_handler};
+pub use torrent::{seed_handler, torrent_handler};
+pub use trash::{trash_list_handler, trash_restore_handler};
+pub use tree::tree_handler;
 pub use types::AppState;
+pub use upload::upload_handler;
@@ -0,0 +1,242 @@
+use std::net::SocketAddr;
+
+use axum::Json;
+use axum::extract::{ConnectInfo, Path as AxumPath, Query, State};
+use axum::http::HeaderMap;
+use axum::response::Redirect;
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use tracing::{info, warn};
+
+use crate::audit::{AuditEvent, AuditKind};
+use crate::config::AppConfig;
+use crate::db::UserRole;
+use crate::errors::{ApiError, ApiResult};
+use crate::oidc::{PENDING_TTL_SECONDS, STATE_COOKIE_NAME, claimed_subject, claimed_username};
+use crate::session::now_unix;
+
+use super::helpers::{
+    build_refresh_cookie, client_ip_for_request, generate_totp_secret, request_origin,
+    require_admin,
+};
+use super::types::{AppState, GenericOkResponse, OidcCallbackQuery, OidcLinkRequest};
+
+/// Sends the browser to the identity provider, which returns it to
+/// `/api/auth/oidc/callback`.
+pub async fn oidc_login_handler(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> ApiResult<(CookieJar, Redirect)> {
+    if !state.config.oidc_enabled() {
+        return Err(ApiError::not_found("OIDC login is not configured."));
+    }
+    let origin = request_origin(
+        &headers,
+        peer.ip(),
+        state.config.public_url.as_deref(),
+        &state.config.base_path,
+        &state.config.trusted_proxies,
+    )
+    .ok_or_else(|| ApiError::bad_request("Cannot determine the public URL for the callback."))?;
+    let redirect_uri = format!("{origin}/api/auth/oidc/callback");
+    let (url, login_state) = state
+        .oidc
+        .authorization_url(&state.config, redirect_uri, now_unix())
+        .await
+        .map_err(|err| {
+            warn!("OIDC login could not be started: {err}");
+            ApiError::internal("Failed to contact the identity provider.")
+        })?;
+    let cookie = state_cookie(&state.config, login_state, PENDING_TTL_SECONDS);
+    Ok((jar.add(cookie), Redirect::to(&url)))
+}
+
+/// Scoped to the callback and sent on the provider's top-level redirect
+/// back, which `SameSite=Lax` allows.
+fn state_cookie(config: &AppConfig, value: String, ttl_seconds: u64) -> Cookie<'static> {
+    Cookie::build((STATE_COOKIE_NAME, value))
+        .path(format!("{}/api/auth/oidc", config.base_path))
+        .http_only(true)
+        .secure(config.secure_cookies)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(ttl_seconds as i64))
+        .build()
+}
+
+/// Completes an OIDC login: the ID token's issuer and subject pick the
+/// account linked to them. Without a link, an account named by the
+/// username claim is created when `MLIST_OIDC_AUTO_CREATE` is on, but an
+/// existing local account of that name is never taken over; an admin has
+/// to link it. Like a TOTP login it starts a refresh session; the browser
+/// is sent back to the app, which picks the session up from the cookie.
+pub async fn oidc_callback_handler(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallbackQuery>,
+) -> ApiResult<(CookieJar, Redirect)> {
+    if !state.config.oidc_enabled() {
+        return Err(ApiError::not_found("OIDC login is not configured."));
+    }
+    let client_ip = client_ip_for_request(
+        &headers,
+        peer.ip(),
+        &state.config.trusted_proxies,
+        state.config.forwarded_header,
+    )
+    .to_string();
+    if let Some(error) = query.error.as_deref() {
+        return Err(ApiError::unauthorized(format!(
+            "The identity provider refused the login: {error}."
+        )));
+    }
+    let (Some(code), Some(login_state)) = (query.code.as_deref(), query.state.as_deref()) else {
+        return Err(ApiError::bad_request("Missing code or state."));
+    };
+    // state 必须与发起登录的浏览器中的 Cookie 一致，防止登录 CSRF
+    if jar.get(STATE_COOKIE_NAME).map(Cookie::value) != Some(login_state) {
+        warn!(
+            ip = client_ip,
+            "OIDC callback without a matching state cookie"
+        );
+        return Err(ApiError::unauthorized("OIDC login failed."));
+    }
+    let jar = jar.remove(state_cookie(&state.config, String::new(), 0));
+
+    let claims = state
+        .oidc
+        .finish(&state.config, login_state, code, now_unix())
+        .await
+        .map_err(|err| {
+            warn!(ip = client_ip, "OIDC login failed: {err}");
+            ApiError::unauthorized("OIDC login failed.")
+        })?;
+    let issuer = state
+        .config
+        .oidc_issuer
+        .clone()
+        .ok_or_else(|| ApiError::not_found("OIDC login is not configured."))?;
+    let subject = claimed_subject(&claims)
+        .ok_or_else(|| ApiError::unauthorized("The ID token names no user."))?;
+    let username = claimed_username(&state.config, &claims).unwrap_or(subject);
+
+    let user = match state.db.user_by_identity(&issuer, subject).await? {
+        Some(user) => Some(user),
+        None if state.config.oidc_auto_create => {
+            if state.db.user_by_username(username).await?.is_some() {
+                warn!(
+                    ip = client_ip,
+                    user = username,
+                    subject,
+                    "OIDC identity matches the name of an unlinked account"
+                );
+                state.audit.record(&AuditEvent::login(
+                    AuditKind::LoginFailed,
+                    &client_ip,
+                    username,
+                ));
+                return Err(ApiError::conflict(
+                    "An account with this name exists but is not linked to this identity.",
+                ));
+            }
+            // 角色只在创建账号时由声明决定，之后以管理后台为准
+            let admin = state
+                .config
+                .oidc_admin_claims
+                .iter()
+                .any(|rule| rule.matches(&claims));
+            let role = if admin {
+                UserRole::Admin
+            } else {
+                UserRole::User
+            };
+            info!(
+                user = username,
+                role = role.as_str(),
+                "creating account for OIDC login"
+            );
+            let user = state
+                .db
+                .create_user(username, role, &generate_totp_secret())
+                .await?;
+            state.db.link_identity(user.id, &issuer, subject).await?;
+            Some(user)
+        }
+        None => {
+            // 记录 subject，管理员可据此把已有账号关联到该身份
+            warn!(
+                ip = client_ip,
+                user = username,
+                subject,
+                "OIDC identity is not linked to any account"
+            );
+            None
+        }
+    };
+    let Some(user) = user.filter(|value| value.enabled) else {
+        state.audit.record(&AuditEvent::login(
+            AuditKind::LoginFailed,
+            &client_ip,
+            username,
+        ));
+        return Err(ApiError::unauthorized("No enabled account for this user."));
+    };
+
+    let refresh_token = uuid::Uuid::new_v4().simple().to_string();
+    state
+        .db
+        .create_refresh_session(user.id, &refresh_token, state.config.refresh_ttl_seconds)
+        .await?;
+    state.db.record_login(user.id).await?;
+
+    info!(ip = client_ip, user = user.username, "OIDC login succeeded");
+    state.audit.record(&AuditEvent::login(
+        AuditKind::LoginSucceeded,
+        &client_ip,
+        &user.username,
+    ));
+
+    let cookie = build_refresh_cookie(
+        &state.config,
+        &refresh_token,
+        state.config.refresh_ttl_seconds,
+    );
+    Ok((
+        jar.add(cookie),
+        Redirect::to(&format!("{}/", state.config.base_path)),
+    ))
+}
+
+/// Lets OIDC logins whose ID token carries `subject` sign into the user's
+/// existing account, which is how accounts created before OIDC was set up
+/// are taken over by single sign-on.
+pub async fn admin_link_oidc_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(user_id): AxumPath<i64>,
+    Json(payload): Json<OidcLinkRequest>,
+) -> ApiResult<Json<GenericOkResponse>> {
+    require_admin(&state, &headers).await?;
+    let Some(issuer) = state.config.oidc_issuer.clone() else {
+        return Err(ApiError::not_found("OIDC login is not configured."));
+    };
+    let subject = payload.subject.trim();
+    if subject.is_empty() {
+        return Err(ApiError::bad_request("Subject must not be empty."));
+    }
+    state.db.link_identity(user_id, &issuer, subject).await?;
+    Ok(Json(GenericOkResponse { ok: true }))
+}
+
+pub async fn admin_unlink_oidc_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(user_id): AxumPath<i64>,
+) -> ApiResult<Json<GenericOkResponse>> {
+    require_admin(&state, &headers).await?;
+    state.db.unlink_identities(user_id).await?;
+    Ok(Json(GenericOkResponse { ok: true }))
+}
@@ -0,0 +1,197 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, header};
+use axum::response::Response;
+use tokio::fs;
+use tracing::debug;
+
+use crate::auth::{PrivateAccess, find_private_anchor, has_private_hide_marker};
+use crate::config::AppConfig;
+use crate::errors::{ApiError, ApiResult};
+use crate::media::MediaKind;
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, is_marker_name,
+    resolve_existing_path,
+};
+
+use super::files::ensure_file_accessible;
+use super::helpers::{join_relative, normalize_request_path, request_origin, require_session};
+use super::http_util::{content_disposition, signed_direct_file_url};
+use super::types::{AppState, PlaylistQuery};
+
+/// Upper bound on tracks in one playlist; each one mints a signed token.
+const MAX_PLAYLIST_ENTRIES: usize = 1_000;
+const MAX_PLAYLIST_DEPTH: usize = 16;
+
+/// Emits an extended M3U playlist of the audio and video files under
+/// `path` for external players. Every entry is an absolute `/d/` URL with
+/// its own signed token, so the playlist keeps working without a session
+/// until the links expire. Directories are walked depth-first in name
+/// order; symlinked directories are not followed.
+pub async fn playlist_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<PlaylistQuery>,
+) -> ApiResult<Response> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+    let origin = request_origin(
+        &headers,
+        peer.ip(),
+        state.config.public_url.as_deref(),
+        &state.config.base_path,
+        &state.config.trusted_proxies,
+    )
+    .ok_or_else(|| ApiError::bad_request("Cannot determine the public URL for links."))?;
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    if find_private_anchor(root, &resolved, true).await?.is_some()
+        && !access.may_read(&relative_path)
+    {
+        return Err(ApiError::not_found("Path not found."));
+    }
+
+    let max_depth = if query.recursive.unwrap_or(false) {
+        MAX_PLAYLIST_DEPTH
+    } else {
+        1
+    };
+    let mut tracks = Vec::new();
+    let mut pending = vec![(resolved, relative_path.clone(), 1)];
+    'walk: while let Some((dir, relative, depth)) = pending.pop() {
+        let dir_config = state.dir_configs.load(&dir).await;
+        if !dir_config.lists_for(access.is_admin()) {
+            if depth == 1 {
+                return Err(ApiError::forbidden(
+                    "Listing is disabled for this directory.",
+                ));
+            }
+            continue;
+        }
+        let mut files = Vec::new();
+        let mut subdirs = Vec::new();
+        let mut read_dir = match fs::read_dir(&dir).await {
+            Ok(value) => value,
+            Err(err) if depth > 1 => {
+                debug!(path = relative, "skipping unreadable directory: {err}");
+                continue;
+            }
+            Err(err) => return Err(ApiError::from_io(err, "directory")),
+        };
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_marker_name(&name)
+                || is_hidden_name(&name, &state.config.hide_patterns)
+                || dir_config.hides(&name)
+            {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if depth < max_depth {
+                    subdirs.push(name);
+                }
+            } else if is_media_file(&state.config, &name) {
+                files.push(name);
+            }
+        }
+        files.sort_by_key(|name| name.to_lowercase());
+        subdirs.sort_by_key(|name| name.to_lowercase());
+
+        for name in files {
+            let path = join_relative(&relative, &name);
+            // 逐个复核，符号链接与单文件私有标记都在这里拦下
+            if ensure_file_accessible(&state, &session, &path)
+                .await
+                .is_err()
+            {
+                continue;
+            }
+            if tracks.len() == MAX_PLAYLIST_ENTRIES {
+                break 'walk;
+            }
+            tracks.push(path);
+        }
+        for name in subdirs.into_iter().rev() {
+            let child = dir.join(&name);
+            let child_relative = join_relative(&relative, &name);
+            if !access.is_admin()
+                && has_private_hide_marker(&child).await.unwrap_or(true)
+                && !access.may_read(&child_relative)
+            {
+                continue;
+            }
+            pending.push((child, child_relative, depth + 1));
+        }
+    }
+
+    let mut body = String::from("#EXTM3U\n");
+    for path in &tracks {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        state
+            .db
+            .create_signed_file_token(
+                session.user.id,
+                path,
+                &token,
+                state.config.signed_file_link_ttl_seconds,
+            )
+            .await?;
+        body.push_str(&format!(
+            "#EXTINF:-1,{}\n{origin}{}\n",
+            track_title(path),
+            signed_direct_file_url(path, &token)
+        ));
+    }
+
+    let file_name = match relative_path.rsplit('/').next() {
+        Some(name) if !name.is_empty() => format!("{name}.m3u8"),
+        _ => "mlist.m3u8".to_string(),
+    };
+    Response::builder()
+        .header(header::CONTENT_TYPE, "audio/x-mpegurl; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(Path::new(&file_name), true),
+        )
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(body))
+        .map_err(|_| ApiError::internal("Failed to build playlist response."))
+}
+
+fn is_media_file(config: &AppConfig, name: &str) -> bool {
+    let mime = config.guess_mime(name);
+    matches!(
+        MediaKind::for_mime(mime.essence_str()),
+        Some(MediaKind::Audio | MediaKind::Video)
+    )
+}
+
+/// File name without extension, with control characters that would break
+/// the line-based format replaced.
+fn track_title(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    Path::new(name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| name.to_string())
+        .chars()
+        .map(|ch| if ch.is_control() { ' ' } else { ch })
+        .collect()
+}
@@ -0,0 +1,58 @@
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::preview::{decode_preview, is_text_like};
+
+use super::files::ensure_file_accessible;
+use super::helpers::{normalize_request_path, require_session};
+use super::types::{AppState, PreviewQuery, PreviewResponse};
+
+const DEFAULT_PREVIEW_LINES: usize = 200;
+const MAX_PREVIEW_LINES: usize = 10_000;
+
+/// Returns the head of a text file as UTF-8 so NFOs, logs and subtitles can
+/// be shown without downloading them. At most `MLIST_PREVIEW_MAX_BYTES` are
+/// read; binary files are refused.
+pub async fn preview_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PreviewQuery>,
+) -> ApiResult<Json<PreviewResponse>> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+    let max_lines = query
+        .lines
+        .unwrap_or(DEFAULT_PREVIEW_LINES)
+        .clamp(1, MAX_PREVIEW_LINES);
+
+    let max_bytes = state.config.preview_max_bytes;
+    let file = fs::File::open(&accessible.resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "file"))?;
+    let mut head = Vec::new();
+    file.take(max_bytes)
+        .read_to_end(&mut head)
+        .await
+        .map_err(|err| ApiError::from_io(err, "file"))?;
+
+    let mime = state.config.guess_mime(&relative_path);
+    if !is_text_like(mime.essence_str(), &head) {
+        return Err(ApiError::unsupported_media_type(
+            "Only text files can be previewed.",
+        ));
+    }
+    let preview = decode_preview(&head, max_lines, accessible.metadata.len() > max_bytes);
+
+    Ok(Json(PreviewResponse {
+        path: relative_path,
+        encoding: preview.encoding,
+        content: preview.content,
+        lines: preview.lines,
+        truncated: preview.truncated,
+    }))
+}
@@ -0,0 +1,199 @@
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::response::Response;
+
+use crate::auth::PrivateAnchors;
+use crate::errors::{ApiError, ApiResult};
+use crate::index::{PublicFile, PublicListing};
+use crate::path_guard::ensure_not_hidden_path;
+use crate::session::unix_to_rfc3339;
+
+use super::helpers::{listed_below, request_origin};
+use super::http_util::{
+    build_not_modified, direct_file_url, format_http_date, if_none_match_matches, xml_escape,
+};
+use super::types::{AppState, PublicIndexEntry, PublicIndexQuery, PublicIndexResponse};
+
+const DEFAULT_INDEX_LIMIT: usize = 500;
+const MAX_INDEX_LIMIT: usize = 5_000;
+/// The sitemap protocol's limit on URLs in one file.
+const MAX_SITEMAP_URLS: usize = 50_000;
+const PUBLIC_CACHE_CONTROL: &str = "public, max-age=300";
+
+/// Pages through the files a visitor without an account may know about:
+/// everything outside private scopes, hide patterns and directories whose
+/// `.mlist.toml` disables listing. Readable without logging in so mirror
+/// scripts can enumerate the tree; `/d/` links still need a signed token.
+/// `offset` counts entries of the crawled listing, so a page can come back
+/// short when files turned private since the crawl.
+pub async fn public_index_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PublicIndexQuery>,
+) -> ApiResult<Response> {
+    let listing = public_listing(&state)?;
+    let (etag, last_modified) = validators(&listing);
+    if let Some(response) = not_modified(&headers, &etag, last_modified.as_deref()) {
+        return response;
+    }
+
+    let total = listing.files.len();
+    let offset = query.offset.unwrap_or(0).min(total);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_INDEX_LIMIT)
+        .clamp(1, MAX_INDEX_LIMIT);
+    let end = offset.saturating_add(limit).min(total);
+    let mut anchors = PrivateAnchors::new(&state.config.root_dir);
+    let mut files = Vec::new();
+    for file in &listing.files[offset..end] {
+        if still_public(&state, &mut anchors, file).await {
+            files.push(PublicIndexEntry {
+                path: file.path.clone(),
+                url: format!("{}{}", state.config.base_path, direct_file_url(&file.path)),
+                size: file.size,
+                modified: unix_to_rfc3339(file.modified),
+            });
+        }
+    }
+
+    let body = serde_json::to_vec(&PublicIndexResponse {
+        files,
+        total,
+        offset,
+        next_offset: (end < total).then_some(end),
+        generated_at: unix_to_rfc3339(listing.built_at),
+    })
+    .map_err(|_| ApiError::internal("Failed to encode the public index."))?;
+    cacheable_response("application/json", &etag, last_modified.as_deref(), body)
+}
+
+/// `sitemap.xml` listing the `/d/` URL and modification time of every
+/// public file, up to the protocol's 50,000 URLs.
+pub async fn sitemap_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let listing = public_listing(&state)?;
+    let (etag, last_modified) = validators(&listing);
+    if let Some(response) = not_modified(&headers, &etag, last_modified.as_deref()) {
+        return response;
+    }
+    let origin = request_origin(
+        &headers,
+        peer.ip(),
+        state.config.public_url.as_deref(),
+        &state.config.base_path,
+        &state.config.trusted_proxies,
+    )
+    .ok_or_else(|| ApiError::bad_request("Cannot determine the public URL for links."))?;
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    let mut anchors = PrivateAnchors::new(&state.config.root_dir);
+    let mut written = 0_usize;
+    for file in &listing.files {
+        if written == MAX_SITEMAP_URLS {
+            break;
+        }
+        if !still_public(&state, &mut anchors, file).await {
+            continue;
+        }
+        let url = format!("{origin}{}", direct_file_url(&file.path));
+        let _ = writeln!(
+            out,
+            "<url><loc>{}</loc><lastmod>{}</lastmod></url>",
+            xml_escape(&url),
+            unix_to_rfc3339(file.modified)
+        );
+        written += 1;
+    }
+    out.push_str("</urlset>\n");
+    cacheable_response(
+        "application/xml; charset=utf-8",
+        &etag,
+        last_modified.as_deref(),
+        out.into_bytes(),
+    )
+}
+
+fn public_listing(state: &AppState) -> ApiResult<Arc<PublicListing>> {
+    if !state.config.public_index_enabled || !state.config.index_enabled {
+        return Err(ApiError::not_found("The public index is not enabled."));
+    }
+    state
+        .index
+        .public_listing()
+        .ok_or_else(|| ApiError::unavailable("The public index is still being built."))
+}
+
+/// Both change only when the indexer rebuilds the listing.
+fn validators(listing: &PublicListing) -> (String, Option<String>) {
+    let etag = format!(
+        "W/\"public-{:x}-{:x}\"",
+        listing.built_at,
+        listing.files.len()
+    );
+    let last_modified = format_http_date(UNIX_EPOCH + Duration::from_secs(listing.built_at));
+    (etag, last_modified)
+}
+
+fn not_modified(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<&str>,
+) -> Option<ApiResult<Response>> {
+    let raw = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())?;
+    if !if_none_match_matches(raw, etag) {
+        return None;
+    }
+    Some(
+        build_not_modified(Some(etag), last_modified).map(|mut response| {
+            response.headers_mut().insert(
+                header::CACHE_CONTROL,
+                HeaderValue::from_static(PUBLIC_CACHE_CONTROL),
+            );
+            response
+        }),
+    )
+}
+
+fn cacheable_response(
+    content_type: &str,
+    etag: &str,
+    last_modified: Option<&str>,
+    body: Vec<u8>,
+) -> ApiResult<Response> {
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, PUBLIC_CACHE_CONTROL)
+        .header(header::ETAG, etag);
+    if let Some(value) = last_modified {
+        builder = builder.header(header::LAST_MODIFIED, value);
+    }
+    builder
+        .body(Body::from(body))
+        .map_err(|_| ApiError::internal("Failed to build public index response."))
+}
+
+/// The listing is only as fresh as the last crawl, so a file that has been
+/// marked private or hidden since is dropped here. Marker lookups that fail
+/// count as private.
+async fn still_public(state: &AppState, anchors: &mut PrivateAnchors, file: &PublicFile) -> bool {
+    if ensure_not_hidden_path(&file.path, &state.config.hide_patterns).is_err()
+        || !listed_below(state, "", &file.path, false).await
+    {
+        return false;
+    }
+    let resolved = state.config.root_dir.join(&file.path);
+    matches!(anchors.find(&resolved, false).await, Ok(None))
+}
@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path as AxumPath, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::Response;
+use futures_util::StreamExt;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::db::{ApiTokenScope, AuthSession};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, resolve_existing_path,
+};
+use crate::session::now_unix;
+use crate::uploads::StagedUpload;
+
+use super::helpers::{join_relative, normalize_request_path, require_scope, validate_entry_name};
+use super::types::{AppState, CreateUploadRequest, UploadCreatedResponse, UploadQuery};
+use super::upload::ensure_upload_allowed;
+
+const UPLOAD_OFFSET_HEADER: &str = "upload-offset";
+const UPLOAD_LENGTH_HEADER: &str = "upload-length";
+
+/// Starts a resumable upload of `name` (`size` bytes) into the directory
+/// named by `?path=`. The bytes follow in `PATCH /api/uploads/{id}`
+/// requests, each carrying the `Upload-Offset` it continues from; `HEAD`
+/// reports how much has arrived after a dropped connection. Permissions
+/// and limits are those of `/api/upload`.
+pub async fn create_upload_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UploadQuery>,
+    Json(payload): Json<CreateUploadRequest>,
+) -> ApiResult<Response> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let name = validate_entry_name(&payload.name)?;
+    if is_hidden_name(&name, &state.config.hide_patterns) {
+        return Err(ApiError::bad_request(
+            "Upload file name is hidden by configuration.",
+        ));
+    }
+    if payload.size > state.config.upload_max_bytes {
+        return Err(ApiError::payload_too_large(
+            "Uploaded file exceeds the configured size limit.",
+        ));
+    }
+    let target = upload_target(&state, &session, &relative_path, &name).await?;
+
+    let upload = StagedUpload {
+        user_id: session.user.id,
+        dir: relative_path,
+        name,
+        size: payload.size,
+        created_at: now_unix(),
+    };
+    let id = state.uploads.create(&upload).await?;
+    if upload.size == 0 {
+        state.uploads.finish(&id, &target).await?;
+    }
+
+    let body = serde_json::to_vec(&UploadCreatedResponse {
+        id: id.clone(),
+        offset: 0,
+    })
+    .map_err(|_| ApiError::internal("Failed to build upload response."))?;
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header(
+            header::LOCATION,
+            format!("{}/api/uploads/{id}", state.config.base_path),
+        )
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(UPLOAD_OFFSET_HEADER, "0")
+        .body(Body::from(body))
+        .map_err(|_| ApiError::internal("Failed to build upload response."))
+}
+
+/// Appends the request body at `Upload-Offset`. Bytes received before a
+/// dropped connection are kept, so the client can ask `HEAD` for the new
+/// offset and continue from there; a body overrunning the declared size is
+/// discarded whole. The file is moved into place once the declared size
+/// has arrived.
+pub async fn upload_chunk_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+    body: Body,
+) -> ApiResult<Response> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    // 先占用再读取偏移量，避免并发请求基于同一个旧偏移量追加
+    let _lock = state.uploads.lock(&id)?;
+    let (upload, offset) = state.uploads.get(&id, session.user.id).await?;
+    let claimed = headers
+        .get(UPLOAD_OFFSET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .ok_or_else(|| ApiError::bad_request("Missing or invalid Upload-Offset header."))?;
+    if claimed != offset {
+        return Err(ApiError::conflict(format!(
+            "Upload-Offset {claimed} does not match the received {offset} bytes."
+        )));
+    }
+
+    let part = state.uploads.part_path(&id);
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(&part)
+        .await
+        .map_err(|err| ApiError::from_io(err, "upload staging file"))?;
+    let mut received = offset;
+    let mut stream = body.into_data_stream();
+    let outcome = loop {
+        let chunk = match stream.next().await {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(_)) => break Err(ApiError::bad_request("Upload body was interrupted.")),
+            None => break Ok(()),
+        };
+        if received.saturating_add(chunk.len() as u64) > upload.size {
+            // 超出声明大小的请求整体作废，回到本次请求开始时的偏移量
+            if let Err(err) = file.set_len(offset).await {
+                break Err(ApiError::from_io(err, "upload staging file"));
+            }
+            break Err(ApiError::payload_too_large(
+                "Upload exceeds its declared size.",
+            ));
+        }
+        if let Err(err) = file.write_all(&chunk).await {
+            break Err(ApiError::from_io(err, "upload staging file"));
+        }
+        received += chunk.len() as u64;
+    };
+    // 中断时也保留已写入的部分，客户端可通过 HEAD 取得新的偏移量后续传
+    file.flush()
+        .await
+        .map_err(|err| ApiError::from_io(err, "upload staging file"))?;
+    drop(file);
+    outcome?;
+
+    if received == upload.size {
+        let target = upload_target(&state, &session, &upload.dir, &upload.name).await?;
+        state.uploads.finish(&id, &target).await?;
+        tracing::info!(
+            user = session.user.username,
+            path = join_relative(&upload.dir, &upload.name),
+            bytes = upload.size,
+            "resumable upload finished"
+        );
+    }
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(UPLOAD_OFFSET_HEADER, received.to_string())
+        .body(Body::empty())
+        .map_err(|_| ApiError::internal("Failed to build upload response."))
+}
+
+/// Reports how many bytes of an upload have been received.
+pub async fn upload_status_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult<Response> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let (upload, offset) = state.uploads.get(&id, session.user.id).await?;
+    Response::builder()
+        .header(UPLOAD_OFFSET_HEADER, offset.to_string())
+        .header(UPLOAD_LENGTH_HEADER, upload.size.to_string())
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::empty())
+        .map_err(|_| ApiError::internal("Failed to build upload response."))
+}
+
+/// Abandons an upload and discards the bytes received so far.
+pub async fn cancel_upload_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    AxumPath(id): AxumPath<String>,
+) -> ApiResult<StatusCode> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let _lock = state.uploads.lock(&id)?;
+    state.uploads.get(&id, session.user.id).await?;
+    state.uploads.remove(&id).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Resolves where a finished upload goes and checks the caller may still
+/// write there; it runs again on completion, since the directory or its
+/// markers may have changed while the bytes were arriving.
+async fn upload_target(
+    state: &AppState,
+    session: &AuthSession,
+    relative_path: &str,
+    name: &str,
+) -> ApiResult<PathBuf> {
+    ensure_not_marker_path(relative_path)?;
+    ensure_not_hidden_path(relative_path, &state.config.hide_patterns)?;
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+    ensure_upload_allowed(state, session, &resolved, relative_path).await?;
+
+    let target = resolved.join(name);
+    if fs::symlink_metadata(&target).await.is_ok() {
+        return Err(ApiError::conflict("File already exists."));
+    }
+    Ok(target)
+}
@@ -0,0 +1,374 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use tokio::fs;
+use tracing::debug;
+
+use crate::auth::{
+    PrivateAccess, PrivateAnchors, find_private_anchor, has_private_file_marker,
+    has_private_hide_marker,
+};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, is_marker_name,
+    resolve_existing_path, resolve_symlink_entry,
+};
+
+use super::files::list_entry;
+use super::helpers::{join_relative, listed_below, normalize_request_path, require_session};
+use super::types::{AppState, ListEntry, SearchQuery, SearchResponse};
+
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+const MAX_SEARCH_LIMIT: usize = 500;
+const DEFAULT_SEARCH_DEPTH: usize = 8;
+const MAX_SEARCH_DEPTH: usize = 32;
+/// Upper bound on directory entries inspected per request, so a broad query
+/// against a huge library cannot keep a worker busy indefinitely.
+const MAX_SCANNED_ENTRIES: usize = 200_000;
+/// Upper bound on index hits checked against the filesystem per request.
+const MAX_INDEX_CANDIDATES: usize = 20_000;
+/// Upper bound on files a content search reads per request, by count and
+/// by total size.
+const MAX_CONTENT_FILES: usize = 5_000;
+const MAX_CONTENT_BYTES: u64 = 512 * 1024 * 1024;
+
+struct PendingDir {
+    resolved: PathBuf,
+    relative: String,
+    depth: usize,
+    private: bool,
+}
+
+pub async fn search_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SearchQuery>,
+) -> ApiResult<Json<SearchResponse>> {
+    let session = require_session(&state, &headers).await?;
+    let needle = query
+        .q
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| ApiError::bad_request("Search query must not be empty."))?
+        .to_string();
+    let needle_lower = needle.to_lowercase();
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_SEARCH_LIMIT);
+    let max_depth = query
+        .depth
+        .unwrap_or(DEFAULT_SEARCH_DEPTH)
+        .clamp(1, MAX_SEARCH_DEPTH);
+    let content = query.content.unwrap_or(false);
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    let anchor = find_private_anchor(root, &resolved, true).await?;
+    if anchor.is_some() && !access.may_read(&relative_path) {
+        return Err(ApiError::not_found("Path not found."));
+    }
+
+    let admin = access.is_admin();
+    if !state.dir_configs.load(&resolved).await.lists_for(admin) {
+        return Err(ApiError::forbidden(
+            "Listing is disabled for this directory.",
+        ));
+    }
+
+    let fav_set = state.db.list_favorite_paths(session.user.id).await?;
+    // 起点经过符号链接时索引中没有对应路径，只能遍历；索引也不含文件内容
+    if !content && resolved == root.join(&relative_path) {
+        if let Some(candidates) = state.index.search(
+            &relative_path,
+            &needle_lower,
+            max_depth,
+            MAX_INDEX_CANDIDATES,
+        ) {
+            let visibility = Visibility {
+                access: &access,
+                base: &relative_path,
+            };
+            let (entries, truncated) =
+                check_candidates(&state, &visibility, &fav_set, candidates, limit).await;
+            return Ok(Json(SearchResponse {
+                path: relative_path,
+                query: needle,
+                entries,
+                truncated,
+                matches: None,
+            }));
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut files = Vec::new();
+    let mut truncated = false;
+    let mut scanned = 0_usize;
+    let mut visited = HashSet::from([resolved.clone()]);
+    let mut pending = VecDeque::from([PendingDir {
+        resolved,
+        relative: relative_path.clone(),
+        depth: 1,
+        private: anchor.is_some(),
+    }]);
+
+    'walk: while let Some(dir) = pending.pop_front() {
+        // 禁止列出的目录本身仍可被搜到，但不搜索其内容
+        let dir_config = state.dir_configs.load(&dir.resolved).await;
+        if !dir_config.lists_for(admin) {
+            continue;
+        }
+        let children = match read_children(&dir.resolved).await {
+            Ok(value) => value,
+            Err(err) if dir.depth > 1 => {
+                debug!(path = dir.relative, "skipping unreadable directory: {err}");
+                continue;
+            }
+            Err(err) => return Err(ApiError::from_io(err, "directory")),
+        };
+
+        for entry in children {
+            scanned += 1;
+            if scanned > MAX_SCANNED_ENTRIES {
+                truncated = true;
+                break 'walk;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_marker_name(&name)
+                || is_hidden_name(&name, &state.config.hide_patterns)
+                || dir_config.hides(&name)
+            {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+            let entry_path = join_relative(&dir.relative, &name);
+            let (entry_resolved, is_dir, private) = if file_type.is_symlink() {
+                let Some(target) =
+                    resolve_symlink_entry(root, &entry.path(), state.config.symlinks).await
+                else {
+                    continue;
+                };
+                let Ok(target_meta) = fs::metadata(&target).await else {
+                    continue;
+                };
+                if !target_meta.is_dir() && !target_meta.is_file() {
+                    continue;
+                }
+                let is_dir = target_meta.is_dir();
+                // 链接目标可能位于别处的私有目录中，按目标的祖先重新判定
+                let private = dir.private
+                    || find_private_anchor(root, &target, is_dir)
+                        .await
+                        .map(|anchor| anchor.is_some())
+                        .unwrap_or(true);
+                (target, is_dir, private)
+            } else {
+                if !file_type.is_dir() && !file_type.is_file() {
+                    continue;
+                }
+                let is_dir = file_type.is_dir();
+                let entry_resolved = dir.resolved.join(&name);
+                // 标记文件异常（如符号链接）时按私有处理，避免搜索绕过目录隐藏
+                let marked = if is_dir {
+                    has_private_hide_marker(&entry_resolved).await
+                } else {
+                    has_private_file_marker(&entry_resolved).await
+                };
+                let private = dir.private || marked.unwrap_or(true);
+                (entry_resolved, is_dir, private)
+            };
+            if private && !access.may_read(&entry_path) {
+                continue;
+            }
+
+            let matched = if content {
+                !is_dir && is_content_searchable(&state.config.search_content_extensions, &name)
+            } else {
+                name.to_lowercase().contains(&needle_lower)
+            };
+            if matched {
+                let full = if content {
+                    files.len() == MAX_CONTENT_FILES
+                } else {
+                    entries.len() == limit
+                };
+                if full {
+                    truncated = true;
+                    break 'walk;
+                }
+                if let Ok(entry_meta) = fs::metadata(&entry_resolved).await {
+                    let favorite = fav_set.contains(&entry_path);
+                    let entry = list_entry(
+                        &state.config,
+                        name,
+                        entry_path.clone(),
+                        is_dir,
+                        &entry_meta,
+                        private,
+                        favorite,
+                    );
+                    if !content {
+                        entries.push(entry);
+                    } else if entry_meta.len() <= state.config.search_content_max_bytes {
+                        files.push((entry, entry_resolved.clone()));
+                    }
+                }
+            }
+
+            // Followed symlinks can point back at an ancestor; walk each
+            // directory at most once.
+            if is_dir && dir.depth < max_depth && visited.insert(entry_resolved.clone()) {
+                pending.push_back(PendingDir {
+                    resolved: entry_resolved,
+                    relative: entry_path,
+                    depth: dir.depth + 1,
+                    private,
+                });
+            }
+        }
+    }
+
+    let matches = if content {
+        let mut matches = BTreeMap::new();
+        let mut read = 0_u64;
+        for (entry, entry_resolved) in files {
+            let size = entry.size.unwrap_or_default();
+            if entries.len() == limit || read + size > MAX_CONTENT_BYTES {
+                truncated = true;
+                break;
+            }
+            read += size;
+            let lines = state
+                .content_search
+                .grep(
+                    &entry_resolved,
+                    &needle_lower,
+                    state.config.search_content_max_bytes,
+                )
+                .await;
+            if !lines.is_empty() {
+                matches.insert(entry.path.clone(), lines);
+                entries.push(entry);
+            }
+        }
+        Some(matches)
+    } else {
+        None
+    };
+
+    Ok(Json(SearchResponse {
+        path: relative_path,
+        query: needle,
+        entries,
+        truncated,
+        matches,
+    }))
+}
+
+fn is_content_searchable(extensions: &[String], name: &str) -> bool {
+    name.rsplit_once('.').is_some_and(|(_, extension)| {
+        extensions
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(extension))
+    })
+}
+
+/// Who is searching, and from where, for rechecking index candidates.
+struct Visibility<'a> {
+    access: &'a PrivateAccess,
+    base: &'a str,
+}
+
+/// Applies the walk's visibility rules to paths found in the filesystem
+/// index, which may be stale.
+async fn check_candidates(
+    state: &AppState,
+    visibility: &Visibility<'_>,
+    fav_set: &HashSet<String>,
+    candidates: Vec<String>,
+    limit: usize,
+) -> (Vec<ListEntry>, bool) {
+    let root = &state.config.root_dir;
+    let more_candidates = candidates.len() == MAX_INDEX_CANDIDATES;
+    let mut anchors = PrivateAnchors::new(root);
+    let mut entries = Vec::new();
+    for path in candidates {
+        if entries.len() == limit {
+            return (entries, true);
+        }
+        if ensure_not_hidden_path(&path, &state.config.hide_patterns).is_err()
+            || !listed_below(state, visibility.base, &path, visibility.access.is_admin()).await
+        {
+            continue;
+        }
+        let Ok(entry_resolved) = resolve_existing_path(root, &path, state.config.symlinks).await
+        else {
+            continue;
+        };
+        let Ok(entry_meta) = fs::metadata(&entry_resolved).await else {
+            continue;
+        };
+        let is_dir = entry_meta.is_dir();
+        if !is_dir && !entry_meta.is_file() {
+            continue;
+        }
+        // 链接所在目录或链接目标任一处于私有范围即按私有处理，与遍历一致
+        let link = root.join(&path);
+        let mut private = anchors
+            .find(&entry_resolved, is_dir)
+            .await
+            .map(|anchor| anchor.is_some())
+            .unwrap_or(true);
+        if !private && entry_resolved != link {
+            private = anchors
+                .find(link.parent().unwrap_or(root), true)
+                .await
+                .map(|anchor| anchor.is_some())
+                .unwrap_or(true);
+        }
+        if private && !visibility.access.may_read(&path) {
+            continue;
+        }
+        let name = path.rsplit('/').next().unwrap_or_default().to_string();
+        let favorite = fav_set.contains(&path);
+        entries.push(list_entry(
+            &state.config,
+            name,
+            path,
+            is_dir,
+            &entry_meta,
+            private,
+            favorite,
+        ));
+    }
+    (entries, more_candidates)
+}
+
+async fn read_children(dir: &Path) -> std::io::Result<Vec<fs::DirEntry>> {
+    let mut read_dir = fs::read_dir(dir).await?;
+    let mut children = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+        children.push(entry);
+    }
+    children.sort_by_key(|entry| entry.file_name());
+    Ok(children)
+}
@@ -0,0 +1,177 @@
+use std::convert::Infallible;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use futures_core::Stream;
+use serde::Serialize;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_util::sync::CancellationToken;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::watch::DirSubscription;
+
+use super::files::ensure_file_accessible;
+use super::helpers::{normalize_request_path, require_session};
+use super::types::{AppState, TailQuery};
+
+const TAIL_EXTENSIONS: [&str; 2] = ["log", "txt"];
+const DEFAULT_BACKLOG_LINES: usize = 20;
+const MAX_BACKLOG_LINES: usize = 1_000;
+/// Window searched for the backlog at the end of the file.
+const BACKLOG_BYTES: u64 = 64 * 1024;
+/// Appended data is read and sent in pieces of at most this size.
+const READ_CHUNK_BYTES: u64 = 256 * 1024;
+/// A line growing past this without a newline is sent as is.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+/// Fallback for filesystems whose changes the watcher never sees.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+struct AppendPayload {
+    lines: Vec<String>,
+}
+
+struct TailContext {
+    subscription: DirSubscription,
+    shutdown: CancellationToken,
+    resolved: PathBuf,
+    name: String,
+    offset: u64,
+    /// Bytes of the current, unterminated line.
+    pending: Vec<u8>,
+    /// Lines kept from the first read, which starts at the backlog window
+    /// rather than the end of the file.
+    backlog: Option<usize>,
+}
+
+/// Follows a `.log` or `.txt` file like `tail -f`: the last `lines` lines
+/// (20 by default) arrive first, then every appended line as `append`
+/// events. A `reset` event means the file shrank, as after truncation or
+/// rotation, and is being followed from its start again.
+pub async fn tail_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<TailQuery>,
+) -> ApiResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+    let tailable = Path::new(&relative_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| TAIL_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+    if !tailable {
+        return Err(ApiError::unsupported_media_type(
+            "Only .log and .txt files can be followed.",
+        ));
+    }
+
+    let Some((dir, name)) = accessible
+        .resolved
+        .parent()
+        .zip(accessible.resolved.file_name())
+    else {
+        return Err(ApiError::bad_request("Path is not a file."));
+    };
+    let backlog = query
+        .lines
+        .unwrap_or(DEFAULT_BACKLOG_LINES)
+        .min(MAX_BACKLOG_LINES);
+    let len = accessible.metadata.len();
+    let context = TailContext {
+        subscription: state.watcher.subscribe(dir)?,
+        shutdown: state.shutdown.clone(),
+        name: name.to_string_lossy().into_owned(),
+        resolved: accessible.resolved,
+        offset: if backlog > 0 {
+            len.saturating_sub(BACKLOG_BYTES)
+        } else {
+            len
+        },
+        pending: Vec::new(),
+        backlog: (backlog > 0).then_some(backlog),
+    };
+    let stream = futures_util::stream::unfold(context, |mut context| async move {
+        loop {
+            if let Some(event) = context.read_appended().await {
+                return Some((Ok(event), context));
+            }
+            tokio::select! {
+                received = context.subscription.receiver.recv() => match received {
+                    Ok(change) if change.name != context.name => continue,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return None,
+                },
+                () = tokio::time::sleep(POLL_INTERVAL) => {}
+                () = context.shutdown.cancelled() => return None,
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+impl TailContext {
+    /// Reads whatever was appended since the last call and turns the
+    /// complete lines into an event. Missing files are waited for, since
+    /// log rotation briefly leaves none.
+    async fn read_appended(&mut self) -> Option<Event> {
+        let mut backlog = self.backlog.take();
+        let mut file = fs::File::open(&self.resolved).await.ok()?;
+        loop {
+            let len = file.metadata().await.ok()?.len();
+            if len < self.offset {
+                self.offset = 0;
+                self.pending.clear();
+                return Some(Event::default().event("reset").data("{}"));
+            }
+            if len == self.offset {
+                return None;
+            }
+            file.seek(SeekFrom::Start(self.offset)).await.ok()?;
+            let mut chunk = Vec::new();
+            (&mut file)
+                .take(READ_CHUNK_BYTES)
+                .read_to_end(&mut chunk)
+                .await
+                .ok()?;
+            let starts_mid_file = self.offset > 0;
+            self.offset += chunk.len() as u64;
+            self.pending.extend_from_slice(&chunk);
+            let mut lines = self.take_lines();
+            if let Some(keep) = backlog.take() {
+                // 窗口起点多半落在行中间，丢弃这半行
+                let partial = usize::from(starts_mid_file).min(lines.len());
+                lines = lines.split_off(partial.max(lines.len().saturating_sub(keep)));
+            }
+            if !lines.is_empty() {
+                return Event::default()
+                    .event("append")
+                    .json_data(AppendPayload { lines })
+                    .ok();
+            }
+        }
+    }
+
+    fn take_lines(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(end) = self.pending[start..].iter().position(|&b| b == b'\n') {
+            let line = &self.pending[start..start + end];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            lines.push(String::from_utf8_lossy(line).into_owned());
+            start += end + 1;
+        }
+        self.pending.drain(..start);
+        if self.pending.len() > MAX_LINE_BYTES {
+            lines.push(String::from_utf8_lossy(&self.pending).into_owned());
+            self.pending.clear();
+        }
+        lines
+    }
+}
@@ -11,12 +11,14 @@ use futures_util::StreamExt;
 use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
 
 use crate::db::{AuthDb, RecordResourceAccess, ResourceKind, UserRole};
+use crate::net::ForwardedHeader;
 
+use super::batch::{common_parent, member_name};
 use super::files::{CountingFileStream, FileAccessRecorder, visible_in_favorites_view};
-use super::helpers::parse_x_forwarded_for;
+use super::helpers::{client_ip_for_request, parse_x_forwarded_for_chain, request_origin};
 use super::http_util::{
-    content_disposition_inline, format_http_date, if_none_match_matches, if_range_matches,
-    make_etag, parse_range_header, signed_direct_file_url,
+    ByteRange, MultipartRanges, accepts_encoding, content_disposition, format_http_date,
+    if_none_match_matches, if_range_matches, make_etag, parse_range_header, signed_direct_file_url,
 };
 
 fn test_path(name: &str, extension: &str) -> PathBuf {
@@ -76,7 +78,7 @@ fn favorites_view_matches_only_path_boundaries() {
 
 #[test]
 fn range_parses_open_ended() {
-    let range = parse_range_header("bytes=10-", 100).unwrap();
+    let range = parse_range_header("bytes=10-", 100, 1).unwrap()[0];
     assert_eq!(range.start, 10);
     assert_eq!(range.end, 99);
     assert_eq!(range.len(), 90);
@@ -84,31 +86,68 @@ fn range_parses_open_ended() {
 
 #[test]
 fn range_parses_suffix() {
-    let range = parse_range_header("bytes=-20", 100).unwrap();
+    let range = parse_range_header("bytes=-20", 100, 1).unwrap()[0];
     assert_eq!(range.start, 80);
     assert_eq!(range.end, 99);
 }
 
 #[test]
 fn range_rejects_out_of_bounds_start() {
-    assert!(parse_range_header("bytes=100-120", 100).is_err());
+    assert!(parse_range_header("bytes=100-120", 100, 1).is_err());
 }
 
 #[test]
-fn range_rejects_multi_ranges() {
-    assert!(parse_range_header("bytes=0-10,20-30", 100).is_err());
+fn range_parses_multi_ranges_up_to_cap() {
+    let ranges = parse_range_header("bytes=0-10, 20-30, 200-", 100, 3).unwrap();
+    assert_eq!(ranges.len(), 2);
+    assert_eq!((ranges[1].start, ranges[1].end), (20, 30));
+
+    assert!(parse_range_header("bytes=0-10,20-30", 100, 1).is_err());
+    assert!(parse_range_header("bytes=0-10,x-30", 100, 2).is_err());
+}
+
+#[test]
+fn multipart_ranges_length_matches_framing() {
+    let ranges = [
+        ByteRange { start: 0, end: 4 },
+        ByteRange { start: 6, end: 10 },
+    ];
+    let parts = MultipartRanges::new("b".to_string(), "text/plain", 12, &ranges);
+    let framing = parts
+        .parts
+        .iter()
+        .map(|(head, _)| head.len() as u64)
+        .sum::<u64>()
+        + parts.trailer.len() as u64;
+    assert_eq!(parts.content_length(), framing + 10);
+    assert_eq!(parts.content_type(), "multipart/byteranges; boundary=b");
+    assert!(parts.parts[1].0.starts_with(b"\r\n--b\r\n"));
+}
+
+#[test]
+fn accept_encoding_honours_zero_quality() {
+    assert!(accepts_encoding("gzip, deflate, br", "br"));
+    assert!(accepts_encoding("GZIP;q=0.5", "gzip"));
+    assert!(!accepts_encoding("br;q=0, gzip", "br"));
+    assert!(!accepts_encoding("*", "gzip"));
 }
 
 #[test]
 fn content_disposition_contains_ascii_filename() {
-    let disposition = content_disposition_inline(Path::new("/tmp/video.mkv"));
+    let disposition = content_disposition(Path::new("/tmp/video.mkv"), false);
     assert!(disposition.contains("filename=\"video.mkv\""));
     assert!(disposition.contains("filename*=UTF-8''video.mkv"));
 }
 
+#[test]
+fn content_disposition_can_force_download() {
+    let disposition = content_disposition(Path::new("/tmp/video.mkv"), true);
+    assert!(disposition.starts_with("attachment; filename=\"video.mkv\""));
+}
+
 #[test]
 fn content_disposition_encodes_utf8_filename() {
-    let disposition = content_disposition_inline(Path::new("/tmp/你好 字幕.ass"));
+    let disposition = content_disposition(Path::new("/tmp/你好 字幕.ass"), false);
     assert!(disposition.contains("filename=\"__ __.ass\""));
     assert!(
         disposition.contains("filename*=UTF-8''%E4%BD%A0%E5%A5%BD%20%E5%AD%97%E5%B9%95.ass")
@@ -151,7 +190,7 @@ async fn counting_stream_records_only_consumed_bytes_on_drop() {
         .await
         .unwrap();
     let recorder = FileAccessRecorder::new(db.clone(), event_id);
-    let mut stream = CountingFileStream::new(file.take(data.len() as u64), recorder);
+    let mut stream = CountingFileStream::new(file.take(data.len() as u64), 4096, recorder);
     let first_chunk_len = stream.next().await.unwrap().unwrap().len();
     assert!(first_chunk_len < data.len());
     drop(stream);
@@ -204,7 +243,7 @@ async fn counting_stream_marks_completed_after_full_consumption() {
         .await
         .unwrap();
     let recorder = FileAccessRecorder::new(db.clone(), event_id);
-    let mut stream = CountingFileStream::new(file.take(data.len() as u64), recorder);
+    let mut stream = CountingFileStream::new(file.take(data.len() as u64), 4096, recorder);
     let mut total = 0;
     while let Some(chunk) = stream.next().await {
         total += chunk.unwrap().len();
@@ -248,7 +287,7 @@ async fn counting_stream_marks_failed_on_read_error() {
         .await
         .unwrap();
     let recorder = FileAccessRecorder::new(db.clone(), event_id);
-    let mut stream = CountingFileStream::new(FailingReader, recorder);
+    let mut stream = CountingFileStream::new(FailingReader, 4096, recorder);
     assert!(stream.next().await.unwrap().is_err());
     drop(stream);
 
@@ -265,12 +304,12 @@ async fn counting_stream_marks_failed_on_read_error() {
 }
 
 #[test]
-fn x_forwarded_for_uses_first_valid_ip() {
+fn x_forwarded_for_skips_invalid_hops() {
     let mut headers = HeaderMap::new();
     headers.insert("x-forwarded-for", "unknown, 203.0.113.8".parse().unwrap());
 
-    let ip = parse_x_forwarded_for(&headers).unwrap();
-    assert_eq!(ip, "203.0.113.8".parse::<IpAddr>().unwrap());
+    let chain = parse_x_forwarded_for_chain(&headers);
+    assert_eq!(chain, vec!["203.0.113.8".parse::<IpAddr>().unwrap()]);
 }
 
 #[test]
@@ -278,15 +317,119 @@ fn x_forwarded_for_accepts_socket_address_tokens() {
     let mut headers = HeaderMap::new();
     headers.insert("x-forwarded-for", "198.51.100.1:45321".parse().unwrap());
 
-    let ip = parse_x_forwarded_for(&headers).unwrap();
-    assert_eq!(ip, "198.51.100.1".parse::<IpAddr>().unwrap());
+    let chain = parse_x_forwarded_for_chain(&headers);
+    assert_eq!(chain, vec!["198.51.100.1".parse::<IpAddr>().unwrap()]);
 }
 
 #[test]
 fn x_forwarded_for_returns_none_for_invalid_values() {
     let mut headers = HeaderMap::new();
     headers.insert("x-forwarded-for", "unknown, garbage".parse().unwrap());
-    assert!(parse_x_forwarded_for(&headers).is_none());
+    assert!(parse_x_forwarded_for_chain(&headers).is_empty());
+}
+
+fn trusted(nets: &[&str]) -> Vec<crate::net::IpNet> {
+    nets.iter().map(|net| net.parse().unwrap()).collect()
+}
+
+#[test]
+fn client_ip_ignores_forwarding_headers_from_untrusted_peers() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "203.0.113.8".parse().unwrap());
+    let peer = "198.51.100.7".parse::<IpAddr>().unwrap();
+
+    assert_eq!(
+        client_ip_for_request(&headers, peer, &[], ForwardedHeader::XForwardedFor),
+        peer
+    );
+    assert_eq!(
+        client_ip_for_request(
+            &headers,
+            peer,
+            &trusted(&["10.0.0.0/8"]),
+            ForwardedHeader::XForwardedFor
+        ),
+        peer
+    );
+}
+
+#[test]
+fn request_origin_trusts_forwarded_scheme_only_from_proxies() {
+    let mut headers = HeaderMap::new();
+    headers.insert("host", "10.0.0.5:3000".parse().unwrap());
+    headers.insert("x-forwarded-proto", "https".parse().unwrap());
+    headers.insert("x-forwarded-host", "media.example.com".parse().unwrap());
+    let proxy = "10.0.0.1".parse::<IpAddr>().unwrap();
+    let outsider = "198.51.100.7".parse::<IpAddr>().unwrap();
+    let nets = trusted(&["10.0.0.0/8"]);
+
+    assert_eq!(
+        request_origin(&headers, proxy, None, "", &nets).as_deref(),
+        Some("https://media.example.com")
+    );
+    assert_eq!(
+        request_origin(&headers, outsider, None, "", &nets).as_deref(),
+        Some("http://10.0.0.5:3000")
+    );
+    assert_eq!(
+        request_origin(&headers, outsider, Some("https://files.example"), "", &nets).as_deref(),
+        Some("https://files.example")
+    );
+    assert_eq!(
+        request_origin(&HeaderMap::new(), outsider, None, "", &nets),
+        None
+    );
+    assert_eq!(
+        request_origin(&headers, proxy, None, "/files", &nets).as_deref(),
+        Some("https://media.example.com/files")
+    );
+}
+
+#[test]
+fn client_ip_walks_chain_from_trusted_proxy() {
+    let mut headers = HeaderMap::new();
+    // The client prepended a spoofed hop; nginx appended the real address.
+    headers.insert(
+        "x-forwarded-for",
+        "1.2.3.4, 203.0.113.8, 10.0.0.2".parse().unwrap(),
+    );
+    let peer = "10.0.0.1".parse::<IpAddr>().unwrap();
+
+    let ip = client_ip_for_request(
+        &headers,
+        peer,
+        &trusted(&["10.0.0.0/8"]),
+        ForwardedHeader::XForwardedFor,
+    );
+    assert_eq!(ip, "203.0.113.8".parse::<IpAddr>().unwrap());
+}
+
+#[test]
+fn client_ip_reads_only_the_configured_forwarding_header() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "forwarded",
+        "for=\"[2001:db8::17]:4711\";proto=https, for=127.0.0.1"
+            .parse()
+            .unwrap(),
+    );
+    headers.insert("x-forwarded-for", "203.0.113.8".parse().unwrap());
+    let peer = "127.0.0.1".parse::<IpAddr>().unwrap();
+    let proxies = trusted(&["127.0.0.1"]);
+
+    let ip = client_ip_for_request(&headers, peer, &proxies, ForwardedHeader::Forwarded);
+    assert_eq!(ip, "2001:db8::17".parse::<IpAddr>().unwrap());
+    let ip = client_ip_for_request(&headers, peer, &proxies, ForwardedHeader::XForwardedFor);
+    assert_eq!(ip, "203.0.113.8".parse::<IpAddr>().unwrap());
+
+    // A client-supplied `Forwarded` must not displace the proxy's XFF.
+    let mut headers = HeaderMap::new();
+    headers.insert("forwarded", "for=1.2.3.4".parse().unwrap());
+    headers.insert("x-real-ip", "203.0.113.9".parse().unwrap());
+    let ip = client_ip_for_request(&headers, peer, &proxies, ForwardedHeader::XForwardedFor);
+    assert_eq!(ip, "203.0.113.9".parse::<IpAddr>().unwrap());
+    let ip = client_ip_for_request(&headers, peer, &proxies, ForwardedHeader::Forwarded);
+    assert_eq!(ip, "1.2.3.4".parse::<IpAddr>().unwrap());
 }
 
 #[test]
@@ -341,3 +484,14 @@ fn if_range_accepts_matching_date() {
         Some(lm),
     ));
 }
+
+#[test]
+fn batch_members_are_named_below_the_common_parent() {
+    let paths = ["films/2020/a.mkv", "films/2020/extras", "films/2021/b.mkv"]
+        .map(String::from)
+        .to_vec();
+    assert_eq!(common_parent(&paths), "films");
+    assert_eq!(member_name("films", "films/2020/a.mkv"), "2020/a.mkv");
+    assert_eq!(common_parent(&["films".to_string()]), "");
+    assert_eq!(common_parent(&["a/b.txt".to_string(), "c".to_string()]), "");
+}
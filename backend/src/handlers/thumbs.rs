@@ -0,0 +1,116 @@
+use axum::body::Body;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, header};
+use axum::response::Response;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::thumbs::{
+    DEFAULT_THUMB_EDGE, MAX_RESIZE_EDGE, MAX_THUMB_EDGE, MIN_THUMB_EDGE, ResizeFormat,
+    ResizeRequest, ThumbRequest, ThumbSource, load_or_generate, load_or_resize,
+};
+
+use super::files::ensure_file_accessible;
+use super::helpers::{file_session_for_request, normalize_request_path, require_session};
+use super::types::{AppState, ImageQuery, ThumbQuery};
+
+/// Serves a cached JPEG preview. Gallery `<img>` tags cannot attach a bearer
+/// header, so the signed token minted for the file by `/api/file-link` is
+/// accepted as well.
+pub async fn thumb_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ThumbQuery>,
+) -> ApiResult<Response> {
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let session = match query.token.as_deref() {
+        Some(token) => file_session_for_request(&state, &relative_path, Some(token)).await?,
+        None => require_session(&state, &headers).await?,
+    };
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+
+    let mime = state.config.guess_mime(&accessible.resolved);
+    let kind = ThumbSource::for_mime(mime.essence_str()).ok_or_else(|| {
+        ApiError::bad_request("Thumbnails are only available for images and videos.")
+    })?;
+    let width = query
+        .w
+        .unwrap_or(DEFAULT_THUMB_EDGE)
+        .clamp(MIN_THUMB_EDGE, MAX_THUMB_EDGE);
+    let height = query
+        .h
+        .unwrap_or(width)
+        .clamp(MIN_THUMB_EDGE, MAX_THUMB_EDGE);
+
+    let bytes = load_or_generate(
+        &state.config.thumb_cache_dir,
+        state.config.ffmpeg_path.as_deref(),
+        ThumbRequest {
+            source: &accessible.resolved,
+            kind,
+            metadata: &accessible.metadata,
+            width,
+            height,
+        },
+    )
+    .await?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "image/jpeg")
+        .header(header::CONTENT_LENGTH, bytes.len().to_string())
+        .header(header::CACHE_CONTROL, "private, max-age=86400")
+        .body(Body::from(bytes))
+        .map_err(|_| ApiError::internal("Failed to build thumbnail response."))
+}
+
+/// Serves an image scaled down to fit `w`x`h` and re-encoded, for viewing
+/// large photos over slow links. Missing edges default to the maximum, so
+/// `?format=webp` alone only converts. Accepts the same signed token as
+/// `/api/thumb`.
+pub async fn image_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ImageQuery>,
+) -> ApiResult<Response> {
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let session = match query.token.as_deref() {
+        Some(token) => file_session_for_request(&state, &relative_path, Some(token)).await?,
+        None => require_session(&state, &headers).await?,
+    };
+    let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+
+    let mime = state.config.guess_mime(&accessible.resolved);
+    if ThumbSource::for_mime(mime.essence_str()) != Some(ThumbSource::Image) {
+        return Err(ApiError::bad_request("Only images can be resized."));
+    }
+    let format = match query.format.as_deref() {
+        Some(raw) => ResizeFormat::try_from(raw)?,
+        None => ResizeFormat::Jpeg,
+    };
+    let width = query
+        .w
+        .unwrap_or(MAX_RESIZE_EDGE)
+        .clamp(MIN_THUMB_EDGE, MAX_RESIZE_EDGE);
+    let height = query
+        .h
+        .unwrap_or(MAX_RESIZE_EDGE)
+        .clamp(MIN_THUMB_EDGE, MAX_RESIZE_EDGE);
+
+    let bytes = load_or_resize(
+        &state.config.thumb_cache_dir,
+        ResizeRequest {
+            source: &accessible.resolved,
+            metadata: &accessible.metadata,
+            width,
+            height,
+            format,
+        },
+    )
+    .await?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, format.mime())
+        .header(header::CONTENT_LENGTH, bytes.len().to_string())
+        .header(header::CACHE_CONTROL, "private, max-age=86400")
+        .body(Body::from(bytes))
+        .map_err(|_| ApiError::internal("Failed to build image response."))
+}
@@ -0,0 +1,217 @@
+use std::net::SocketAddr;
+use std::path::Path;
+
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Path as AxumPath, Query, State};
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use tokio::fs;
+
+use crate::auth::{PrivateAccess, PrivateAnchors};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{ensure_not_hidden_path, ensure_not_marker_path, resolve_existing_path};
+use crate::session::now_unix;
+use crate::torrent::{TorrentFile, TorrentSource, TorrentStatus, encode_torrent};
+
+use super::batch::{collect_dir, mtime_of};
+use super::files::{ServeOptions, ensure_file_accessible, serve_file_for_session};
+use super::helpers::{
+    client_ip_for_request, normalize_request_path, request_origin, require_session,
+};
+use super::http_util::{content_disposition, url_path_segment_encode};
+use super::types::{AppState, TorrentProgressResponse, TorrentQuery};
+
+/// Builds a `.torrent` for a file or directory whose web seed is this
+/// server. Hashing runs in the background: until it finishes the response
+/// is 202 with the progress, and clients poll until they get the torrent.
+/// Each torrent carries a fresh signed token scoped to its file or
+/// directory, so it stays downloadable until the link expires.
+pub async fn torrent_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<TorrentQuery>,
+) -> ApiResult<Response> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    let Some(name) = relative_path
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+    else {
+        return Err(ApiError::bad_request(
+            "The root directory cannot be shared as a torrent.",
+        ));
+    };
+    let name = name.to_string();
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+    let origin = request_origin(
+        &headers,
+        peer.ip(),
+        state.config.public_url.as_deref(),
+        &state.config.base_path,
+        &state.config.trusted_proxies,
+    )
+    .ok_or_else(|| ApiError::bad_request("Cannot determine the public URL for links."))?;
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "path"))?;
+    let source = if metadata.is_dir() {
+        let access = PrivateAccess::for_user(&state.config, &session.user);
+        let mut anchors = PrivateAnchors::new(root);
+        if anchors.find(&resolved, true).await?.is_some() && !access.may_read(&relative_path) {
+            return Err(ApiError::not_found("Path not found."));
+        }
+        let mut members = Vec::new();
+        collect_dir(
+            &state,
+            &access,
+            &mut anchors,
+            &relative_path,
+            resolved.clone(),
+            &relative_path,
+            &mut members,
+        )
+        .await?;
+        let files = members
+            .into_iter()
+            .filter_map(|member| {
+                Some(TorrentFile {
+                    path: member.name.split('/').map(str::to_string).collect(),
+                    source: member.source?,
+                    size: member.size,
+                    mtime: member.mtime,
+                })
+            })
+            .collect();
+        TorrentSource {
+            name,
+            files,
+            is_dir: true,
+        }
+    } else {
+        let accessible = ensure_file_accessible(&state, &session, &relative_path).await?;
+        TorrentSource {
+            files: vec![TorrentFile {
+                path: vec![name.clone()],
+                source: accessible.resolved,
+                size: accessible.metadata.len(),
+                mtime: mtime_of(&accessible.metadata),
+            }],
+            name,
+            is_dir: false,
+        }
+    };
+    if source.total_size() == 0 {
+        return Err(ApiError::bad_request("There is nothing to share."));
+    }
+
+    let info = match state.torrents.request(resolved, source) {
+        TorrentStatus::Ready(info) => info,
+        TorrentStatus::Hashing { hashed, total } => {
+            let progress = TorrentProgressResponse {
+                path: relative_path,
+                status: "hashing",
+                hashed_bytes: hashed,
+                total_bytes: total,
+            };
+            return Ok((StatusCode::ACCEPTED, Json(progress)).into_response());
+        }
+        TorrentStatus::Failed(message) => {
+            tracing::warn!(
+                path = relative_path,
+                "torrent could not be built: {message}"
+            );
+            return Err(ApiError::internal(
+                "Failed to hash the files for the torrent.",
+            ));
+        }
+    };
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    state
+        .db
+        .create_signed_file_token(
+            session.user.id,
+            &relative_path,
+            &token,
+            state.config.signed_file_link_ttl_seconds,
+        )
+        .await?;
+    // 多文件种子的 web seed 以 `/` 结尾，客户端会在其后拼接 `名称/子路径`
+    let seed_path = if metadata.is_dir() {
+        relative_path
+            .rsplit_once('/')
+            .map(|(parent, _)| format!("{}/", encode_path(parent)))
+            .unwrap_or_default()
+    } else {
+        encode_path(&relative_path)
+    };
+    let web_seed = format!("{origin}/seed/{token}/{seed_path}");
+    let body = encode_torrent(&info, &web_seed, now_unix());
+
+    let file_name = format!(
+        "{}.torrent",
+        relative_path.rsplit('/').next().unwrap_or("mlist")
+    );
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-bittorrent")
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(Path::new(&file_name), true),
+        )
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(body))
+        .map_err(|_| ApiError::internal("Failed to build torrent response."))
+}
+
+/// Web seed downloads. Unlike `/d/`, whose tokens name one exact file,
+/// the token here was issued for a torrent's directory and admits every
+/// file below it, since clients build the URLs themselves.
+pub async fn seed_handler(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    method: Method,
+    AxumPath((token, raw_path)): AxumPath<(String, String)>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let relative_path = normalize_request_path(&state, Some(&raw_path))?;
+    let session = state
+        .db
+        .signed_scope_session(&token, &relative_path)
+        .await?
+        .ok_or_else(ApiError::auth_required)?;
+    let client_ip = client_ip_for_request(
+        &headers,
+        peer.ip(),
+        &state.config.trusted_proxies,
+        state.config.forwarded_header,
+    );
+    let options = ServeOptions {
+        route: "/seed",
+        head_only: method == Method::HEAD,
+        attachment: true,
+        strip_metadata: false,
+    };
+    serve_file_for_session(
+        &state,
+        &headers,
+        &session,
+        client_ip,
+        relative_path,
+        options,
+    )
+    .await
+}
+
+fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(url_path_segment_encode)
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,81 @@
+use axum::Json;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use tokio::fs;
+
+use crate::db::ApiTokenScope;
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::private_file_marker_name;
+
+use super::helpers::{join_relative, require_scope};
+use super::manage::{created_entry, locate_dir, rename_error};
+use super::types::{AppState, ListEntry, TrashListResponse, TrashRestoreRequest};
+
+/// Entries in `MLIST_TRASH_DIR`, newest first. Admins see everything;
+/// other users only what they deleted themselves.
+pub async fn trash_list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<TrashListResponse>> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    if state.config.trash_dir.is_none() {
+        return Err(ApiError::not_found("The trash is not enabled."));
+    }
+    let deleted_by = (!session.user.role.is_admin()).then_some(session.user.id);
+    Ok(Json(TrashListResponse {
+        entries: state.db.list_trash_entries(deleted_by).await?,
+        retention_days: state.config.trash_retention_days,
+    }))
+}
+
+/// Moves a trashed entry back into the directory it was deleted from, or
+/// into `to`. This needs the same write access as creating the entry
+/// there, and an existing entry of the same name is never replaced.
+pub async fn trash_restore_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<TrashRestoreRequest>,
+) -> ApiResult<Json<ListEntry>> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let Some(trash_dir) = state.config.trash_dir.clone() else {
+        return Err(ApiError::not_found("The trash is not enabled."));
+    };
+    let entry = state
+        .db
+        .trash_entry(&payload.id)
+        .await?
+        .filter(|entry| {
+            session.user.role.is_admin() || entry.deleted_by_id == Some(session.user.id)
+        })
+        .ok_or_else(|| ApiError::not_found("Trash entry not found."))?;
+
+    let (original_parent, name) = entry.path.rsplit_once('/').unwrap_or(("", &entry.path));
+    let to = payload.to.as_deref().unwrap_or(original_parent);
+    let (dir, dir_relative) = locate_dir(&state, &session, to).await?;
+    let target = dir.join(name);
+    if fs::symlink_metadata(&target).await.is_ok() {
+        return Err(ApiError::conflict(
+            "An entry with this name already exists.",
+        ));
+    }
+    fs::rename(trash_dir.join(&entry.id), &target)
+        .await
+        .map_err(rename_error)?;
+    if let Some(marker) = &entry.marker {
+        let _ = fs::rename(
+            trash_dir.join(marker),
+            dir.join(private_file_marker_name(name)),
+        )
+        .await;
+    }
+    state.db.remove_trash_entry(&entry.id).await?;
+
+    let path = join_relative(&dir_relative, name);
+    tracing::info!(
+        user = session.user.username,
+        from = entry.path,
+        to = path,
+        "entry restored from trash"
+    );
+    created_entry(&state, &target, name.to_string(), path, entry.is_dir).await
+}
@@ -0,0 +1,269 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use tokio::fs;
+use tracing::debug;
+
+use crate::auth::{
+    PrivateAccess, find_private_anchor, has_private_file_marker, has_private_hide_marker,
+};
+use crate::errors::{ApiError, ApiResult};
+use crate::natsort::NameCollator;
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, is_marker_name,
+    resolve_existing_path, resolve_symlink_entry,
+};
+
+use super::helpers::{join_relative, normalize_request_path, require_session};
+use super::types::{AppState, EntryKind, TreeNode, TreeQuery, TreeResponse};
+
+const DEFAULT_TREE_DEPTH: usize = 3;
+const MAX_TREE_DEPTH: usize = 16;
+/// Upper bound on nodes returned, files included.
+const MAX_TREE_NODES: usize = 5_000;
+const MAX_SCANNED_ENTRIES: usize = 200_000;
+
+struct PendingDir {
+    resolved: PathBuf,
+    relative: String,
+    depth: usize,
+    private: bool,
+    node: Option<usize>,
+}
+
+/// Descendants of the requested directory, stored flat in breadth-first
+/// order so the node cap cuts the tree evenly instead of exhausting one
+/// deep branch first. `parents[i]` is `None` for children of `root`.
+struct Arena {
+    root: TreeNode,
+    nodes: Vec<TreeNode>,
+    parents: Vec<Option<usize>>,
+}
+
+impl Arena {
+    fn push(&mut self, parent: Option<usize>, node: TreeNode) -> usize {
+        self.nodes.push(node);
+        self.parents.push(parent);
+        self.nodes.len() - 1
+    }
+
+    /// Children always come after their parent, so folding from the back
+    /// completes every child list before its owner is folded.
+    fn into_tree(mut self, names: &NameCollator) -> TreeNode {
+        while let (Some(mut node), Some(parent)) = (self.nodes.pop(), self.parents.pop()) {
+            sort_children(&mut node, names);
+            let parent = match parent {
+                Some(id) => &mut self.nodes[id],
+                None => &mut self.root,
+            };
+            if let Some(children) = parent.children.as_mut() {
+                children.push(node);
+            }
+        }
+        sort_children(&mut self.root, names);
+        self.root
+    }
+}
+
+fn sort_children(node: &mut TreeNode, names: &NameCollator) {
+    if let Some(children) = node.children.as_mut() {
+        children.sort_by(|a, b| {
+            let a_dir = matches!(a.kind, EntryKind::Dir);
+            let b_dir = matches!(b.kind, EntryKind::Dir);
+            b_dir
+                .cmp(&a_dir)
+                .then_with(|| names.compare(&a.name, &b.name))
+        });
+    }
+}
+
+/// Returns the directory structure below `path` in one response, for
+/// sidebars and sitemaps. Directories deeper than `depth` are returned with
+/// `children` unset, meaning "not loaded"; files are included only with
+/// `files=true`. Private scopes are hidden from regular users and marked
+/// with `requiresAuth` for admins.
+pub async fn tree_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<TreeQuery>,
+) -> ApiResult<Json<TreeResponse>> {
+    let session = require_session(&state, &headers).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+    let max_depth = query
+        .depth
+        .unwrap_or(DEFAULT_TREE_DEPTH)
+        .clamp(1, MAX_TREE_DEPTH);
+    let with_files = query.files.unwrap_or(false);
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    let anchor = find_private_anchor(root, &resolved, true).await?;
+    if anchor.is_some() && !access.may_read(&relative_path) {
+        return Err(ApiError::not_found("Path not found."));
+    }
+
+    let mut arena = Arena {
+        root: TreeNode {
+            name: relative_path
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            path: relative_path.clone(),
+            kind: EntryKind::Dir,
+            requires_auth: anchor.is_some(),
+            children: Some(Vec::new()),
+        },
+        nodes: Vec::new(),
+        parents: Vec::new(),
+    };
+    let mut truncated = false;
+    let mut scanned = 0_usize;
+    let mut visited = HashSet::from([resolved.clone()]);
+    let mut pending = VecDeque::from([PendingDir {
+        resolved,
+        relative: relative_path.clone(),
+        depth: 1,
+        private: anchor.is_some(),
+        node: None,
+    }]);
+
+    let admin = session.user.role.is_admin();
+    'walk: while let Some(dir) = pending.pop_front() {
+        // 禁止列出的目录仍作为节点出现，但不展开其内容
+        let dir_config = state.dir_configs.load(&dir.resolved).await;
+        if !dir_config.lists_for(admin) {
+            if dir.depth == 1 {
+                return Err(ApiError::forbidden(
+                    "Listing is disabled for this directory.",
+                ));
+            }
+            continue;
+        }
+        let mut read_dir = match fs::read_dir(&dir.resolved).await {
+            Ok(value) => value,
+            Err(err) if dir.depth > 1 => {
+                debug!(path = dir.relative, "skipping unreadable directory: {err}");
+                continue;
+            }
+            Err(err) => return Err(ApiError::from_io(err, "directory")),
+        };
+
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            scanned += 1;
+            if scanned > MAX_SCANNED_ENTRIES {
+                truncated = true;
+                break 'walk;
+            }
+
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_marker_name(&name)
+                || is_hidden_name(&name, &state.config.hide_patterns)
+                || dir_config.hides(&name)
+            {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type().await else {
+                continue;
+            };
+            let (entry_resolved, is_dir, private) = if file_type.is_symlink() {
+                let Some(target) =
+                    resolve_symlink_entry(root, &entry.path(), state.config.symlinks).await
+                else {
+                    continue;
+                };
+                let Ok(target_meta) = fs::metadata(&target).await else {
+                    continue;
+                };
+                if !target_meta.is_dir() && !target_meta.is_file() {
+                    continue;
+                }
+                let is_dir = target_meta.is_dir();
+                let private = dir.private
+                    || find_private_anchor(root, &target, is_dir)
+                        .await
+                        .map(|anchor| anchor.is_some())
+                        .unwrap_or(true);
+                (target, is_dir, private)
+            } else {
+                if !file_type.is_dir() && !file_type.is_file() {
+                    continue;
+                }
+                let is_dir = file_type.is_dir();
+                let entry_resolved = dir.resolved.join(&name);
+                // 标记文件异常时按私有处理，与列表和搜索保持一致
+                let marked = if is_dir {
+                    has_private_hide_marker(&entry_resolved).await
+                } else {
+                    has_private_file_marker(&entry_resolved).await
+                };
+                (
+                    entry_resolved,
+                    is_dir,
+                    dir.private || marked.unwrap_or(true),
+                )
+            };
+            let entry_path = join_relative(&dir.relative, &name);
+            if private && !access.may_read(&entry_path) {
+                continue;
+            }
+            if !is_dir && !with_files {
+                continue;
+            }
+            if arena.nodes.len() >= MAX_TREE_NODES {
+                truncated = true;
+                break 'walk;
+            }
+
+            // 已到深度上限或已出现过的目录不再展开，children 留空表示未加载
+            let expand = is_dir && dir.depth < max_depth && visited.insert(entry_resolved.clone());
+            let id = arena.push(
+                dir.node,
+                TreeNode {
+                    name,
+                    path: entry_path.clone(),
+                    kind: if is_dir {
+                        EntryKind::Dir
+                    } else {
+                        EntryKind::File
+                    },
+                    requires_auth: private,
+                    children: expand.then(Vec::new),
+                },
+            );
+            if expand {
+                pending.push_back(PendingDir {
+                    resolved: entry_resolved,
+                    relative: entry_path,
+                    depth: dir.depth + 1,
+                    private,
+                    node: Some(id),
+                });
+            }
+        }
+    }
+
+    let names = NameCollator::new(
+        state.config.natural_sort,
+        state.config.sort_locale.as_deref(),
+    )
+    .map_err(|_| ApiError::internal("Failed to prepare name ordering."))?;
+    Ok(Json(TreeResponse {
+        path: relative_path,
+        root: arena.into_tree(&names),
+        truncated,
+    }))
+}
@@ -1,20 +1,108 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
 
-use crate::config::AppConfig;
+use crate::audit::AuditLog;
+use crate::challenge::{Challenge, ChallengeAnswer, LoginChallenges};
+use crate::checksum::{ChecksumAlgo, ChecksumCache};
+use crate::config::{AppConfig, LiveConfig};
+use crate::content_search::{ContentSearch, LineMatch};
+use crate::dir_config::DirConfigCache;
+use crate::du::DirSizeCache;
+use crate::index::FsIndex;
 use crate::db::{
-    ResourceAccessEventView, ResourceUsageView, UserFavoriteView, UserFileStateView, UserRoleInput,
+    ApiTokenScope, ApiTokenView, ResourceAccessEventView, ResourceStatsView, ResourceTotalsView,
+    ResourceUsageView, SessionView, TrashEntry, UserFavoriteView, UserFileStateView, UserRoleInput,
     UserView,
 };
 use crate::db::AuthDb;
+use crate::media::{MediaMeta, MediaMetaCache};
+use crate::oidc::OidcClient;
+use crate::photo::PhotoInfo;
 use crate::session::LoginRateLimiter;
+use crate::throttle::{BandwidthLimiter, DownloadQuotas, DownloadSlots, RequestRateLimiter};
+use crate::torrent::TorrentCache;
+use crate::transcode::TranscodeManager;
+use crate::uploads::UploadStore;
+use crate::watch::DirWatcher;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Arc<AppConfig>,
+    pub config: LiveConfig,
     pub db: AuthDb,
     pub login_limiter: LoginRateLimiter,
+    pub login_challenges: LoginChallenges,
+    pub dir_sizes: DirSizeCache,
+    pub dir_configs: DirConfigCache,
+    pub checksums: ChecksumCache,
+    pub media_meta: MediaMetaCache,
+    pub torrents: TorrentCache,
+    pub content_search: ContentSearch,
+    pub transcodes: TranscodeManager,
+    pub watcher: DirWatcher,
+    pub index: FsIndex,
+    pub bandwidth: BandwidthLimiter,
+    pub download_slots: DownloadSlots,
+    pub download_quotas: DownloadQuotas,
+    pub request_limiter: RequestRateLimiter,
+    pub uploads: UploadStore,
+    pub audit: AuditLog,
+    pub oidc: OidcClient,
+    /// Cancelled when the server starts shutting down, so endless streams
+    /// such as `/api/events` end instead of holding up the drain.
+    pub shutdown: CancellationToken,
+}
+
+impl AppState {
+    pub fn new(config: Arc<AppConfig>, db: AuthDb) -> Self {
+        let login_limiter =
+            LoginRateLimiter::new(config.login_max_failures, config.login_block_seconds);
+        let dir_sizes = DirSizeCache::new(config.dir_size_cache_ttl_seconds);
+        let bandwidth =
+            BandwidthLimiter::new(config.max_bytes_per_sec, config.max_bytes_per_sec_per_ip);
+        let transcodes = TranscodeManager::new(
+            config.transcode_dir.clone(),
+            config.max_transcodes,
+            config.transcode_idle_seconds,
+        );
+        let download_slots = DownloadSlots::new(
+            config.max_concurrent_downloads_per_ip,
+            config.max_concurrent_downloads_per_scope,
+        );
+        let download_quotas = DownloadQuotas::new(
+            config.download_quota_per_user,
+            config.download_quota_per_scope,
+            config.download_quota_window_seconds,
+        );
+        let request_limiter =
+            RequestRateLimiter::new(config.api_rate_limit, config.file_rate_limit);
+        let uploads = UploadStore::new(config.upload_staging_dir.clone());
+        Self {
+            config: LiveConfig::new(config),
+            db,
+            login_limiter,
+            login_challenges: LoginChallenges::new(),
+            dir_sizes,
+            dir_configs: DirConfigCache::default(),
+            checksums: ChecksumCache::default(),
+            media_meta: MediaMetaCache::default(),
+            torrents: TorrentCache::default(),
+            content_search: ContentSearch::new(config.search_content_concurrency),
+            transcodes,
+            watcher: DirWatcher::default(),
+            index: FsIndex::default(),
+            bandwidth,
+            download_slots,
+            download_quotas,
+            request_limiter,
+            uploads,
+            audit: AuditLog::default(),
+            oidc: OidcClient::new(),
+            shutdown: CancellationToken::new(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,11 +115,93 @@ pub struct PathQuery {
     pub limit: Option<i64>,
     pub favorites_only: Option<bool>,
     pub search: Option<String>,
+    pub with_dir_sizes: Option<bool>,
+    pub with_meta: Option<bool>,
+    pub stream: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+    pub path: Option<String>,
+    pub limit: Option<usize>,
+    pub depth: Option<usize>,
+    /// Match lines inside text files instead of names.
+    pub content: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TreeQuery {
+    pub path: Option<String>,
+    pub depth: Option<usize>,
+    pub files: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublicIndexQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicIndexResponse {
+    pub files: Vec<PublicIndexEntry>,
+    /// Files in the whole listing as of the last crawl.
+    pub total: usize,
+    pub offset: usize,
+    pub next_offset: Option<usize>,
+    pub generated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicIndexEntry {
+    pub path: String,
+    pub url: String,
+    pub size: u64,
+    pub modified: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlaylistQuery {
+    pub path: Option<String>,
+    pub recursive: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DirectFileQuery {
     pub token: Option<String>,
+    pub download: Option<String>,
+    /// Short alias for `download`.
+    pub dl: Option<String>,
+    /// `1` serves a JPEG without its EXIF and XMP segments.
+    pub sanitize: Option<String>,
+}
+
+impl DirectFileQuery {
+    pub fn wants_attachment(&self) -> bool {
+        self.download
+            .as_deref()
+            .or(self.dl.as_deref())
+            .is_some_and(|value| matches!(value.trim(), "1" | "true"))
+    }
+
+    pub fn wants_sanitized(&self) -> bool {
+        self.sanitize
+            .as_deref()
+            .is_some_and(|value| matches!(value.trim(), "1" | "true"))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,6 +221,229 @@ pub struct ListResponse {
     pub authorized: bool,
     pub total: usize,
     pub has_more: bool,
+    pub readme: Option<DirReadme>,
+    /// From the directory's `.mlist.toml`.
+    pub description: Option<String>,
+}
+
+/// Raw markdown of the directory's `README.md` or `index.md`, cut at
+/// `MLIST_README_MAX_BYTES`; rendering is left to the client.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirReadme {
+    pub name: String,
+    pub content: String,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DavTokenResponse {
+    pub url: String,
+    pub username: String,
+    pub token: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThumbQuery {
+    pub path: Option<String>,
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImageQuery {
+    pub path: Option<String>,
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub format: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HlsQuery {
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveQuery {
+    pub path: Option<String>,
+    /// Member name inside the archive, exactly as listed.
+    pub inner: Option<String>,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub mtime: Option<u64>,
+    pub encrypted: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchiveListResponse {
+    pub path: String,
+    pub entries: Vec<ArchiveEntry>,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChecksumQuery {
+    pub path: Option<String>,
+    pub algo: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChecksumResponse {
+    pub path: String,
+    pub algo: ChecksumAlgo,
+    pub digest: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    pub path: Option<String>,
+    pub lines: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewResponse {
+    pub path: String,
+    pub encoding: &'static str,
+    pub content: String,
+    pub lines: usize,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TorrentQuery {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TorrentProgressResponse {
+    pub path: String,
+    pub status: &'static str,
+    pub hashed_bytes: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExifQuery {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExifResponse {
+    pub path: String,
+    #[serde(flatten)]
+    pub info: PhotoInfo,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TailQuery {
+    pub path: Option<String>,
+    pub lines: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadRequest {
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadCreatedResponse {
+    pub id: String,
+    pub offset: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadResponse {
+    pub path: String,
+    pub files: Vec<ListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FsPathRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FsRenameRequest {
+    pub path: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FsMoveRequest {
+    pub path: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashListResponse {
+    pub entries: Vec<TrashEntry>,
+    /// Days an entry is kept before it is removed for good; 0 keeps it.
+    pub retention_days: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrashRestoreRequest {
+    pub id: String,
+    /// Directory to restore into instead of the one it was deleted from.
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+    pub path: String,
+    pub query: String,
+    pub entries: Vec<ListEntry>,
+    pub truncated: bool,
+    /// Matching lines by entry path, only for `content=true` searches.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<BTreeMap<String, Vec<LineMatch>>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeResponse {
+    pub path: String,
+    pub root: TreeNode,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeNode {
+    pub name: String,
+    pub path: String,
+    pub kind: EntryKind,
+    pub requires_auth: bool,
+    /// Unset for files and for directories below the requested depth.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<TreeNode>>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -65,6 +458,10 @@ pub struct ListEntry {
     pub requires_auth: bool,
     pub authorized: bool,
     pub favorite: bool,
+    /// Listed first because the directory's `.mlist.toml` pins it.
+    pub pinned: bool,
+    /// Media details, only filled in for `?withMeta=true` listings.
+    pub extra: Option<MediaMeta>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -74,10 +471,33 @@ pub enum EntryKind {
     File,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcLinkRequest {
+    /// The `sub` claim of the provider's ID tokens for this user.
+    pub subject: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub code: String,
+    /// Answer to the challenge of the previous rejected attempt.
+    pub challenge: Option<ChallengeAnswer>,
+}
+
+/// A rejected login whose next attempt must answer `challenge`.
+#[derive(Debug, Serialize)]
+pub struct LoginChallengeResponse {
+    pub code: &'static str,
+    pub message: &'static str,
+    pub challenge: Challenge,
 }
 
 #[derive(Debug, Serialize)]
@@ -97,6 +517,8 @@ pub struct MeResponse {
     pub user: Option<UserView>,
     pub access_expires_at: Option<String>,
     pub needs_bootstrap: bool,
+    /// Whether `/api/auth/oidc/login` is available.
+    pub oidc_enabled: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -133,6 +555,11 @@ pub struct FileStateRequest {
     pub highlighted: bool,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct BatchDownloadRequest {
+    pub paths: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SignedFileLinkRequest {
     pub path: String,
@@ -183,6 +610,82 @@ pub struct AuditResourcesResponse {
     pub has_more: bool,
 }
 
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionView>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<ApiTokenScope>,
+    pub ttl_seconds: Option<u64>,
+}
+
+/// `token` is the only time the secret is shown.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenCreatedResponse {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: ApiTokenView,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokensResponse {
+    pub tokens: Vec<ApiTokenView>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginBlockView {
+    pub key: String,
+    pub blocked_until: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginBlocksResponse {
+    pub blocks: Vec<LoginBlockView>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClearLoginBlocksResponse {
+    pub ok: bool,
+    pub cleared: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeView {
+    pub path: String,
+    pub kind: EntryKind,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopesResponse {
+    pub scopes: Vec<ScopeView>,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub path: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopResourcesResponse {
+    pub path: String,
+    pub resources: Vec<ResourceTotalsView>,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FileStatesResponse {
@@ -0,0 +1,172 @@
+use std::path::Path;
+
+use axum::Json;
+use axum::extract::multipart::{Field, MultipartError};
+use axum::extract::{Multipart, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use crate::auth::{PrivateAccess, find_private_anchor, find_writable_anchor};
+use crate::db::{ApiTokenScope, AuthSession};
+use crate::errors::{ApiError, ApiResult};
+use crate::path_guard::{
+    ensure_not_hidden_path, ensure_not_marker_path, is_hidden_name, resolve_existing_path,
+};
+
+use super::files::list_entry;
+use super::helpers::{normalize_request_path, require_scope, validate_entry_name};
+use super::types::{AppState, UploadQuery, UploadResponse};
+
+/// Accepts `multipart/form-data` file fields and writes each one into the
+/// directory named by `?path=`. Admins may upload anywhere once
+/// `upload_enabled` is set; any signed-in user may upload below a directory
+/// carrying a `.writable` marker. Existing files are never overwritten.
+pub async fn upload_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UploadQuery>,
+    mut multipart: Multipart,
+) -> ApiResult<Json<UploadResponse>> {
+    let session = require_scope(&state, &headers, ApiTokenScope::Write).await?;
+    let relative_path = normalize_request_path(&state, query.path.as_deref())?;
+    ensure_not_marker_path(&relative_path)?;
+    ensure_not_hidden_path(&relative_path, &state.config.hide_patterns)?;
+
+    let root = &state.config.root_dir;
+    let resolved = resolve_existing_path(root, &relative_path, state.config.symlinks).await?;
+    let metadata = fs::metadata(&resolved)
+        .await
+        .map_err(|err| ApiError::from_io(err, "directory"))?;
+    if !metadata.is_dir() {
+        return Err(ApiError::bad_request("Path is not a directory."));
+    }
+
+    ensure_upload_allowed(&state, &session, &resolved, &relative_path).await?;
+
+    let mut files = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        let name = validate_entry_name(&file_name)?;
+        if is_hidden_name(&name, &state.config.hide_patterns) {
+            return Err(ApiError::bad_request(
+                "Upload file name is hidden by configuration.",
+            ));
+        }
+        let target = resolved.join(&name);
+        let entry_path = if relative_path.is_empty() {
+            name.clone()
+        } else {
+            format!("{relative_path}/{name}")
+        };
+
+        let written = write_field(field, &target, state.config.upload_max_bytes).await?;
+        let entry_meta = fs::metadata(&target)
+            .await
+            .map_err(|err| ApiError::from_io(err, "uploaded file"))?;
+        tracing::info!(
+            user = session.user.username,
+            path = entry_path,
+            bytes = written,
+            "file uploaded"
+        );
+        files.push(list_entry(
+            &state.config,
+            name,
+            entry_path,
+            false,
+            &entry_meta,
+            false,
+            false,
+        ));
+    }
+
+    if files.is_empty() {
+        return Err(ApiError::bad_request("No files were provided."));
+    }
+
+    Ok(Json(UploadResponse {
+        path: relative_path,
+        files,
+    }))
+}
+
+/// Checks the caller may upload into `dir`, whose path below the root is
+/// `relative`. Private directories the caller cannot read are reported as
+/// missing.
+pub(super) async fn ensure_upload_allowed(
+    state: &AppState,
+    session: &AuthSession,
+    dir: &Path,
+    relative: &str,
+) -> ApiResult<()> {
+    let root = &state.config.root_dir;
+    let access = PrivateAccess::for_user(&state.config, &session.user);
+    let private = find_private_anchor(root, dir, true).await?.is_some();
+    if private && !access.may_read(relative) {
+        return Err(ApiError::not_found("Path not found."));
+    }
+    if state.config.upload_enabled && access.is_admin() {
+        return Ok(());
+    }
+    if private && access.may_write(relative) {
+        return Ok(());
+    }
+    if find_writable_anchor(root, dir).await?.is_some() {
+        return Ok(());
+    }
+    Err(ApiError::forbidden(
+        "Uploads are not allowed in this directory.",
+    ))
+}
+
+/// Streams one multipart field into a newly created file. The file is
+/// created with `create_new` so concurrent uploads of the same name cannot
+/// clobber each other, and it is removed again if the transfer fails.
+async fn write_field(mut field: Field<'_>, target: &Path, max_bytes: u64) -> ApiResult<u64> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(target)
+        .await
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::AlreadyExists => ApiError::conflict("File already exists."),
+            _ => ApiError::from_io(err, "upload target"),
+        })?;
+
+    let result = async {
+        let mut written = 0u64;
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            written = written.saturating_add(chunk.len() as u64);
+            if written > max_bytes {
+                return Err(ApiError::payload_too_large(
+                    "Uploaded file exceeds the configured size limit.",
+                ));
+            }
+            file.write_all(&chunk)
+                .await
+                .map_err(|err| ApiError::from_io(err, "upload target"))?;
+        }
+        file.flush()
+            .await
+            .map_err(|err| ApiError::from_io(err, "upload target"))?;
+        Ok(written)
+    }
+    .await;
+
+    if result.is_err() {
+        drop(file);
+        let _ = fs::remove_file(target).await;
+    }
+    result
+}
+
+fn multipart_error(err: MultipartError) -> ApiError {
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        ApiError::payload_too_large("Upload exceeds the configured size limit.")
+    } else {
+        ApiError::bad_request(format!("Invalid multipart body: {}", err.body_text()))
+    }
+}
@@ -0,0 +1,121 @@
+use axum::http::{HeaderMap, header};
+
+/// What happens to a `/d/` request made from a page on another site.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HotlinkPolicy {
+    /// Embedding is not checked.
+    #[default]
+    Off,
+    /// Foreign embeds get 403.
+    Forbid,
+    /// Foreign embeds are redirected to the file's directory listing, so a
+    /// visitor following the link still finds it.
+    Redirect,
+}
+
+impl std::str::FromStr for HotlinkPolicy {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" | "false" => Ok(Self::Off),
+            "forbid" | "deny" => Ok(Self::Forbid),
+            "redirect" => Ok(Self::Redirect),
+            other => Err(format!(
+                "unknown hotlink policy {other:?}, expected \"off\", \"forbid\" or \"redirect\""
+            )),
+        }
+    }
+}
+
+/// Whether the request was made by a page outside `own_host` and the
+/// `allowed` hosts, judging by `Origin`, or `Referer` when there is none.
+/// Requests that carry neither, such as media players, download managers
+/// and pasted URLs, are never foreign. Allow-list entries are host names;
+/// `*.example.com` also matches every subdomain of `example.com`.
+pub fn is_foreign_embed(headers: &HeaderMap, own_host: Option<&str>, allowed: &[String]) -> bool {
+    let Some(source) = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+    else {
+        return false;
+    };
+    // `Origin: null` and other values without a host come from sandboxed
+    // or opaque contexts and cannot be attributed to an allowed site.
+    let Some(host) = source.to_str().ok().and_then(url_host) else {
+        return true;
+    };
+    if own_host.is_some_and(|own| own.eq_ignore_ascii_case(&host)) {
+        return false;
+    }
+    !allowed.iter().any(|pattern| host_matches(pattern, &host))
+}
+
+/// Lower-cased host name of an absolute URL, without port or user info.
+pub fn url_host(url: &str) -> Option<String> {
+    let (_, rest) = url.split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => v6.split(']').next()?,
+        None => authority.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+
+    use super::{is_foreign_embed, url_host};
+
+    fn headers(name: &str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+            value.parse().unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn hosts_are_extracted_from_urls() {
+        assert_eq!(
+            url_host("https://Media.Example.com:8443/a?b").as_deref(),
+            Some("media.example.com")
+        );
+        assert_eq!(url_host("http://user@[::1]:3000/").as_deref(), Some("::1"));
+        assert_eq!(url_host("null"), None);
+    }
+
+    #[test]
+    fn only_requests_from_other_sites_are_foreign() {
+        let allowed = vec!["*.example.org".to_string(), "friend.net".to_string()];
+        let own = Some("media.example.com");
+
+        assert!(!is_foreign_embed(&HeaderMap::new(), own, &allowed));
+        let same = headers("referer", "https://media.example.com/movies");
+        assert!(!is_foreign_embed(&same, own, &allowed));
+        let sub = headers("referer", "https://blog.example.org/post");
+        assert!(!is_foreign_embed(&sub, own, &allowed));
+        let friend = headers("origin", "https://friend.net");
+        assert!(!is_foreign_embed(&friend, own, &allowed));
+
+        let apex = headers("referer", "https://example.org/");
+        assert!(is_foreign_embed(&apex, own, &allowed));
+        let lookalike = headers("referer", "https://notexample.org/");
+        assert!(is_foreign_embed(&lookalike, own, &allowed));
+        let opaque = headers("origin", "null");
+        assert!(is_foreign_embed(&opaque, own, &allowed));
+    }
+}
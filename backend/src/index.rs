@@ -0,0 +1,399 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::{Arc, PoisonError, RwLock, RwLockWriteGuard};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::config::LiveConfig;
+use crate::dir_config;
+use crate::path_guard::{
+    PRIVATE_MARKER_FILE, is_hidden_name, is_marker_name, private_file_marker_name,
+    relative_string_from_root,
+};
+use crate::session::now_unix;
+use crate::watch::{ChangeKind, classify};
+
+/// Every path below the root with its lowercase name, so search can match
+/// names without crawling. Built by a background crawl at startup, kept
+/// current from a recursive filesystem watch and rebuilt every
+/// `MLIST_INDEX_RESCAN_SECONDS` to pick up anything the watch missed.
+///
+/// Only names are indexed: hide patterns, private scopes and metadata are
+/// checked against the filesystem for each hit, so a stale index can miss
+/// or over-report entries but never expose them. Symlinked directories are
+/// not descended into.
+#[derive(Debug, Clone, Default)]
+pub struct FsIndex {
+    entries: Arc<RwLock<Option<BTreeMap<String, String>>>>,
+    public: Arc<RwLock<Option<Arc<PublicListing>>>>,
+}
+
+/// A file outside every private scope and hide pattern at crawl time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicFile {
+    pub path: String,
+    pub size: u64,
+    /// Unix seconds.
+    pub modified: u64,
+}
+
+/// The public files in path order, rebuilt on every crawl while
+/// `public_index_enabled` is on. Unlike the name index it is not updated
+/// from the watch, so it can be up to one rescan behind.
+#[derive(Debug)]
+pub struct PublicListing {
+    pub files: Vec<PublicFile>,
+    /// Unix seconds when the crawl finished.
+    pub built_at: u64,
+}
+
+impl FsIndex {
+    /// Starts the crawl and watch. Searches fall back to walking the tree
+    /// until the first crawl has finished.
+    pub fn start(&self, config: LiveConfig, shutdown: CancellationToken) {
+        let index = self.clone();
+        tokio::spawn(async move {
+            let root = config.root_dir.clone();
+            // Changes during a crawl may be lost when its result replaces
+            // the index; the next rescan picks them up.
+            let _watcher = index.watch(&root);
+            loop {
+                let started = Instant::now();
+                let crawl_root = root.clone();
+                match tokio::task::spawn_blocking(move || crawl(&crawl_root)).await {
+                    Ok(entries) => {
+                        info!(
+                            entries = entries.len(),
+                            elapsed_ms = started.elapsed().as_millis() as u64,
+                            "filesystem index built"
+                        );
+                        *index.write() = Some(entries);
+                    }
+                    Err(err) => warn!("filesystem index crawl failed: {err}"),
+                }
+                let latest = config.latest();
+                if latest.public_index_enabled {
+                    let crawl_root = root.clone();
+                    let hide_patterns = latest.hide_patterns.clone();
+                    match tokio::task::spawn_blocking(move || {
+                        crawl_public(&crawl_root, &hide_patterns)
+                    })
+                    .await
+                    {
+                        Ok(files) => {
+                            info!(files = files.len(), "public listing built");
+                            *index.write_public() = Some(Arc::new(PublicListing {
+                                files,
+                                built_at: now_unix(),
+                            }));
+                        }
+                        Err(err) => warn!("public listing crawl failed: {err}"),
+                    }
+                } else {
+                    *index.write_public() = None;
+                }
+                let rescan = Duration::from_secs(latest.index_rescan_seconds);
+                tokio::select! {
+                    () = tokio::time::sleep(rescan) => {}
+                    () = shutdown.cancelled() => return,
+                }
+            }
+        });
+    }
+
+    /// Paths below `dir`, at most `max_depth` levels down, whose name
+    /// contains `needle_lower`. Shallower paths come first, then path
+    /// order. At most `max_hits` are returned; `None` while the index is
+    /// still being built.
+    pub fn search(
+        &self,
+        dir: &str,
+        needle_lower: &str,
+        max_depth: usize,
+        max_hits: usize,
+    ) -> Option<Vec<String>> {
+        let entries = self.entries.read().unwrap_or_else(PoisonError::into_inner);
+        let entries = entries.as_ref()?;
+        let prefix = if dir.is_empty() {
+            String::new()
+        } else {
+            format!("{dir}/")
+        };
+        let mut hits = entries
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .filter(|(path, name)| {
+                name.contains(needle_lower) && path[prefix.len()..].matches('/').count() < max_depth
+            })
+            .map(|(path, _)| path.clone())
+            .take(max_hits)
+            .collect::<Vec<_>>();
+        hits.sort_by_key(|path| path.matches('/').count());
+        Some(hits)
+    }
+
+    /// The public files from the last crawl; `None` until one has finished
+    /// with `public_index_enabled` on.
+    pub fn public_listing(&self) -> Option<Arc<PublicListing>> {
+        self.public
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Option<BTreeMap<String, String>>> {
+        self.entries.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_public(&self) -> RwLockWriteGuard<'_, Option<Arc<PublicListing>>> {
+        self.public.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn watch(&self, root: &Path) -> Option<RecommendedWatcher> {
+        let index = self.clone();
+        let watched_root = root.to_path_buf();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+            if let Ok(event) = result {
+                index.apply(&watched_root, &event);
+            }
+        })
+        .map_err(|err| warn!("failed to create filesystem index watcher: {err}"))
+        .ok()?;
+        if let Err(err) = watcher.watch(root, RecursiveMode::Recursive) {
+            warn!("filesystem index is not watching for changes, relying on rescans: {err}");
+            return None;
+        }
+        Some(watcher)
+    }
+
+    fn apply(&self, root: &Path, event: &Event) {
+        for (kind, path) in classify(event) {
+            let Ok(relative) = relative_string_from_root(root, path) else {
+                continue;
+            };
+            let Some(name) = path.file_name().map(|name| name.to_string_lossy()) else {
+                continue;
+            };
+            if relative.is_empty() || is_marker_name(&name) {
+                continue;
+            }
+            match kind {
+                ChangeKind::Created => {
+                    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+                        continue;
+                    };
+                    let mut added = BTreeMap::new();
+                    if metadata.is_dir() {
+                        crawl_into(path, &relative, &mut added);
+                    }
+                    added.insert(relative, name.to_lowercase());
+                    if let Some(entries) = self.write().as_mut() {
+                        entries.extend(added);
+                    }
+                }
+                ChangeKind::Deleted => {
+                    if let Some(entries) = self.write().as_mut() {
+                        remove_subtree(entries, &relative);
+                    }
+                }
+                ChangeKind::Modified => {}
+            }
+        }
+    }
+}
+
+fn crawl(root: &Path) -> BTreeMap<String, String> {
+    let mut entries = BTreeMap::new();
+    crawl_into(root, "", &mut entries);
+    entries
+}
+
+fn crawl_into(dir: &Path, relative: &str, entries: &mut BTreeMap<String, String>) {
+    let mut pending = vec![(dir.to_path_buf(), relative.to_string())];
+    while let Some((dir, relative)) = pending.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read_dir.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if is_marker_name(&name) {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = if relative.is_empty() {
+                name.clone()
+            } else {
+                format!("{relative}/{name}")
+            };
+            if file_type.is_dir() {
+                pending.push((entry.path(), path.clone()));
+            }
+            entries.insert(path, name.to_lowercase());
+        }
+    }
+}
+
+/// Regular files a visitor without an account could be shown. A directory
+/// whose listing contains `.private`, or whose `.mlist.toml` disables
+/// listing, is skipped with everything below it, as are hidden names;
+/// symlinks are not followed.
+fn crawl_public(root: &Path, hide_patterns: &[String]) -> Vec<PublicFile> {
+    let mut files = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+    while let Some((dir, relative)) = pending.pop() {
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let entries = read_dir
+            .flatten()
+            .map(|entry| (entry.file_name().to_string_lossy().to_string(), entry))
+            .collect::<Vec<_>>();
+        let names = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<HashSet<_>>();
+        if names.contains(PRIVATE_MARKER_FILE) {
+            continue;
+        }
+        let dir_config = dir_config::load_blocking(&dir);
+        if !dir_config.lists_for(false) {
+            continue;
+        }
+        for (name, entry) in &entries {
+            if is_marker_name(name) || is_hidden_name(name, hide_patterns) || dir_config.hides(name)
+            {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let path = if relative.is_empty() {
+                name.clone()
+            } else {
+                format!("{relative}/{name}")
+            };
+            if file_type.is_dir() {
+                pending.push((entry.path(), path));
+                continue;
+            }
+            if !file_type.is_file() || names.contains(private_file_marker_name(name).as_str()) {
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            files.push(PublicFile {
+                path,
+                size: metadata.len(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |duration| duration.as_secs()),
+            });
+        }
+    }
+    files.sort_by(|left, right| left.path.cmp(&right.path));
+    files
+}
+
+fn remove_subtree(entries: &mut BTreeMap<String, String>, relative: &str) {
+    entries.remove(relative);
+    let prefix = format!("{relative}/");
+    let nested = entries
+        .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+        .take_while(|(path, _)| path.starts_with(&prefix))
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<_>>();
+    for path in nested {
+        entries.remove(&path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, RwLock};
+
+    use super::{FsIndex, crawl_public, remove_subtree};
+
+    fn index(paths: &[&str]) -> FsIndex {
+        let entries = paths
+            .iter()
+            .map(|path| {
+                let name = path.rsplit('/').next().unwrap_or_default();
+                (path.to_string(), name.to_lowercase())
+            })
+            .collect::<BTreeMap<_, _>>();
+        FsIndex {
+            entries: Arc::new(RwLock::new(Some(entries))),
+            ..FsIndex::default()
+        }
+    }
+
+    #[test]
+    fn search_stays_below_the_directory_and_depth() {
+        let index = index(&[
+            "films",
+            "films/Alien.mkv",
+            "films/extras/alien-trailer.mp4",
+            "filmsets/alien.txt",
+            "music/alien.flac",
+        ]);
+        assert_eq!(
+            index.search("films", "alien", 8, 100).unwrap(),
+            vec!["films/Alien.mkv", "films/extras/alien-trailer.mp4"]
+        );
+        assert_eq!(
+            index.search("films", "alien", 1, 100).unwrap(),
+            vec!["films/Alien.mkv"]
+        );
+        assert_eq!(index.search("", "alien", 8, 100).unwrap().len(), 4);
+        assert!(FsIndex::default().search("", "alien", 8, 100).is_none());
+    }
+
+    #[test]
+    fn deleting_a_directory_drops_its_subtree() {
+        let mut entries = ["a", "a/b", "a/b/c", "ab"]
+            .iter()
+            .map(|path| (path.to_string(), String::new()))
+            .collect::<BTreeMap<_, _>>();
+        remove_subtree(&mut entries, "a");
+        assert_eq!(entries.keys().collect::<Vec<_>>(), vec!["ab"]);
+    }
+
+    #[test]
+    fn public_crawl_leaves_out_private_and_hidden_entries() {
+        let root =
+            std::env::temp_dir().join(format!("mlist-public-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(root.join("films/extras")).unwrap();
+        std::fs::create_dir_all(root.join("home")).unwrap();
+        std::fs::write(root.join("films/a.mkv"), b"abc").unwrap();
+        std::fs::write(root.join("films/b.mkv"), b"").unwrap();
+        std::fs::write(root.join("films/.private.b.mkv"), b"").unwrap();
+        std::fs::write(root.join("films/extras/trailer.mp4"), b"").unwrap();
+        std::fs::write(root.join("films/Thumbs.db"), b"").unwrap();
+        std::fs::write(root.join("home/.private"), b"").unwrap();
+        std::fs::write(root.join("home/notes.txt"), b"").unwrap();
+        std::fs::write(root.join("films/a.srt"), b"").unwrap();
+        std::fs::write(root.join("films/.mlist.toml"), b"hide = [\"*.srt\"]").unwrap();
+        std::fs::create_dir_all(root.join("drafts")).unwrap();
+        std::fs::write(root.join("drafts/cut.mkv"), b"").unwrap();
+        std::fs::write(root.join("drafts/.mlist.toml"), b"listing = false").unwrap();
+
+        let files = crawl_public(&root, &["Thumbs.db".to_string()]);
+        let paths = files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, vec!["films/a.mkv", "films/extras/trailer.mp4"]);
+        assert_eq!(files[0].size, 3);
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,692 @@
+mod archive;
+mod audit;
+mod auth;
+mod cache_policy;
+mod challenge;
+mod checksum;
+mod compression;
+mod config;
+mod content_search;
+mod db;
+mod dir_config;
+mod doctor;
+mod du;
+mod errors;
+mod handlers;
+mod hotlink;
+mod index;
+mod listener;
+mod logging;
+mod media;
+mod natsort;
+mod net;
+mod oidc;
+mod path_guard;
+mod path_headers;
+mod photo;
+mod preview;
+mod reload;
+mod session;
+mod shutdown;
+mod tarball;
+mod throttle;
+mod thumbs;
+mod tls;
+mod torrent;
+mod transcode;
+mod trash;
+mod uploads;
+mod watch;
+mod webhooks;
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use audit::AuditEvent;
+use axum::Json;
+use axum::Router;
+use axum::body::Body;
+use axum::extract::{ConnectInfo, DefaultBodyLimit, Request, State};
+use axum::http::{HeaderName, HeaderValue, Method, StatusCode, Uri, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{any, delete, get, patch, post};
+use axum::serve::Listener;
+use axum_server::tls_rustls::RustlsAcceptor;
+use errors::ApiError;
+use handlers::{
+    admin_api_tokens_handler, admin_audit_events_handler, admin_audit_resources_handler,
+    admin_clear_login_blocks_handler, admin_create_api_token_handler, admin_create_user_handler,
+    admin_delete_user_handler, admin_diagnostics_handler, admin_disable_user_handler,
+    admin_enable_user_handler, admin_link_oidc_handler, admin_login_blocks_handler,
+    admin_reset_totp_handler, admin_revoke_api_token_handler, admin_revoke_session_handler,
+    admin_revoke_user_sessions_handler, admin_scopes_handler, admin_sessions_handler,
+    admin_stats_handler, admin_stats_top_handler, admin_unlink_oidc_handler, admin_users_handler,
+    archive_file_handler, archive_list_handler, batch_download_handler, bootstrap_finish_handler,
+    bootstrap_start_handler, cancel_upload_handler, checksum_handler, client_ip_for_request,
+    create_dav_token_handler, create_file_link_handler, create_upload_handler, dav_handler,
+    dav_root_handler, direct_file_handler, events_handler, exif_handler, favorites_handler,
+    feed_handler, file_states_handler, fs_delete_handler, fs_mkdir_handler, fs_move_handler,
+    fs_rename_handler, hls_handler, image_handler, list_handler, login_handler, logout_handler,
+    me_handler, oidc_callback_handler, oidc_login_handler, playlist_handler, preview_handler,
+    public_index_handler, refresh_handler, search_handler, seed_handler, set_favorite_handler,
+    set_file_state_handler, sitemap_handler, tail_handler, thumb_handler, torrent_handler,
+    trash_list_handler, trash_restore_handler, tree_handler, upload_chunk_handler, upload_handler,
+    upload_status_handler,
+};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use hyper_util::service::TowerToHyperService;
+use listener::{BoundListener, WriteTimeout, WriteTimeoutAcceptor};
+use path_headers::{HeaderTarget, apply_path_headers};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use tower_http::add_extension::AddExtension;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::services::ServeDir;
+use tower_http::set_header::SetResponseHeaderLayer;
+use tower_http::timeout::RequestBodyTimeoutLayer;
+use tower_http::trace::TraceLayer;
+use tracing::{debug, info, warn};
+use webhooks::WebhookDispatcher;
+
+pub use audit::AuditLog;
+pub use auth::ScopeGrant;
+pub use challenge::LoginChallengeKind;
+pub use config::{AppConfig, ConfigOverrides};
+pub use db::AuthDb;
+pub use doctor::diagnose;
+pub use handlers::AppState;
+pub use hotlink::HotlinkPolicy;
+pub use logging::{LogFormat, init_logging};
+pub use net::IpNet;
+pub use oidc::ClaimRule;
+pub use path_guard::SymlinkPolicy;
+pub use throttle::RequestRate;
+
+/// Validates `config`, opens the database and returns the API router with
+/// state attached. Static frontend assets are not included; see
+/// [`with_frontend`].
+pub async fn build_app(config: AppConfig) -> Result<Router, String> {
+    let state = build_state(config).await?;
+    Ok(build_router(state))
+}
+
+pub async fn build_state(config: AppConfig) -> Result<AppState, String> {
+    let config = Arc::new(config.validate()?);
+    let db = AuthDb::connect(&config.database_path).await?;
+    let mut state = AppState::new(Arc::clone(&config), db);
+    if let Some(path) = &config.audit_log_path {
+        state.audit = AuditLog::open(path)?;
+    }
+    state.audit = std::mem::take(&mut state.audit)
+        .with_webhooks(WebhookDispatcher::spawn(state.config.clone()));
+    if config.index_enabled {
+        state
+            .index
+            .start(state.config.clone(), state.shutdown.clone());
+    }
+    Ok(state)
+}
+
+pub fn build_router(state: AppState) -> Router {
+    let x_content_type_options = HeaderName::from_static("x-content-type-options");
+    let x_frame_options = HeaderName::from_static("x-frame-options");
+    let referrer_policy = HeaderName::from_static("referrer-policy");
+    // Multipart framing adds a little on top of the file bytes; the handler
+    // enforces the exact per-file limit while streaming.
+    let upload_body_limit =
+        usize::try_from(state.config.upload_max_bytes.saturating_add(64 * 1024))
+            .unwrap_or(usize::MAX);
+    let json_body_limit = usize::try_from(state.config.json_body_limit_bytes).unwrap_or(usize::MAX);
+    let read_timeout = Duration::from_secs(state.config.read_timeout_seconds);
+    let base_path = state.config.base_path.clone();
+
+    let router = Router::new()
+        .route("/api/list", get(list_handler))
+        .route("/api/search", get(search_handler))
+        .route("/api/tree", get(tree_handler))
+        .route("/api/playlist", get(playlist_handler))
+        .route("/api/feed.xml", get(feed_handler))
+        .route("/api/index", get(public_index_handler))
+        .route("/sitemap.xml", get(sitemap_handler))
+        .route("/api/events", get(events_handler))
+        .route("/api/thumb", get(thumb_handler))
+        .route("/api/image", get(image_handler))
+        .route("/api/hls/{*path}", get(hls_handler))
+        .route("/api/checksum", get(checksum_handler))
+        .route("/api/preview", get(preview_handler))
+        .route("/api/exif", get(exif_handler))
+        .route("/api/torrent", get(torrent_handler))
+        .route("/api/tail", get(tail_handler))
+        .route("/api/archive/list", get(archive_list_handler))
+        .route("/api/archive/file", get(archive_file_handler))
+        .route(
+            "/api/upload",
+            post(upload_handler).layer(DefaultBodyLimit::max(upload_body_limit)),
+        )
+        .route("/api/uploads", post(create_upload_handler))
+        .route(
+            "/api/uploads/{id}",
+            patch(upload_chunk_handler)
+                .head(upload_status_handler)
+                .delete(cancel_upload_handler),
+        )
+        .route("/api/fs/rename", post(fs_rename_handler))
+        .route("/api/fs/move", post(fs_move_handler))
+        .route("/api/fs/delete", post(fs_delete_handler))
+        .route("/api/fs/mkdir", post(fs_mkdir_handler))
+        .route("/api/trash", get(trash_list_handler))
+        .route("/api/trash/restore", post(trash_restore_handler))
+        .route("/d/{*path}", get(direct_file_handler))
+        .route("/seed/{token}/{*path}", get(seed_handler))
+        .route("/dav", any(dav_root_handler))
+        .route("/dav/", any(dav_root_handler))
+        .route("/dav/{*path}", any(dav_handler))
+        .route("/api/bootstrap/start", post(bootstrap_start_handler))
+        .route("/api/bootstrap/finish", post(bootstrap_finish_handler))
+        .route("/api/auth/login", post(login_handler))
+        .route("/api/auth/refresh", post(refresh_handler))
+        .route("/api/auth/logout", post(logout_handler))
+        .route("/api/auth/oidc/login", get(oidc_login_handler))
+        .route("/api/auth/oidc/callback", get(oidc_callback_handler))
+        .route("/api/me", get(me_handler))
+        .route("/api/file-link", post(create_file_link_handler))
+        .route("/api/batch-download", post(batch_download_handler))
+        .route("/api/dav-token", post(create_dav_token_handler))
+        .route("/api/file-states", get(file_states_handler))
+        .route("/api/file-states", post(set_file_state_handler))
+        .route("/api/favorites", get(favorites_handler))
+        .route("/api/favorites", post(set_favorite_handler))
+        .route("/api/admin/users", get(admin_users_handler))
+        .route("/api/admin/users", post(admin_create_user_handler))
+        .route("/api/admin/users/{id}", delete(admin_delete_user_handler))
+        .route("/api/admin/audit/events", get(admin_audit_events_handler))
+        .route(
+            "/api/admin/audit/resources",
+            get(admin_audit_resources_handler),
+        )
+        .route("/api/admin/stats", get(admin_stats_handler))
+        .route("/api/admin/stats/top", get(admin_stats_top_handler))
+        .route(
+            "/api/admin/users/{id}/disable",
+            post(admin_disable_user_handler),
+        )
+        .route(
+            "/api/admin/users/{id}/enable",
+            post(admin_enable_user_handler),
+        )
+        .route(
+            "/api/admin/users/{id}/reset-totp",
+            post(admin_reset_totp_handler),
+        )
+        .route(
+            "/api/admin/users/{id}/revoke-sessions",
+            post(admin_revoke_user_sessions_handler),
+        )
+        .route("/api/admin/users/{id}/oidc", post(admin_link_oidc_handler))
+        .route(
+            "/api/admin/users/{id}/oidc",
+            delete(admin_unlink_oidc_handler),
+        )
+        .route(
+            "/api/admin/users/{id}/api-tokens",
+            post(admin_create_api_token_handler),
+        )
+        .route("/api/admin/api-tokens", get(admin_api_tokens_handler))
+        .route(
+            "/api/admin/api-tokens/{id}",
+            delete(admin_revoke_api_token_handler),
+        )
+        .route("/api/admin/sessions", get(admin_sessions_handler))
+        .route(
+            "/api/admin/sessions/{id}",
+            delete(admin_revoke_session_handler),
+        )
+        .route(
+            "/api/admin/login-blocks",
+            get(admin_login_blocks_handler).delete(admin_clear_login_blocks_handler),
+        )
+        .route("/api/admin/scopes", get(admin_scopes_handler))
+        .route("/api/admin/diagnostics", get(admin_diagnostics_handler))
+        .route("/api", any(api_not_found_handler))
+        .route("/api/{*path}", any(api_not_found_handler))
+        // The upload route sets its own, larger limit, which takes precedence.
+        .layer(DefaultBodyLimit::max(json_body_limit))
+        .layer(RequestBodyTimeoutLayer::new(read_timeout))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            x_content_type_options,
+            HeaderValue::from_static("nosniff"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            x_frame_options,
+            HeaderValue::from_static("SAMEORIGIN"),
+        ))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            referrer_policy,
+            HeaderValue::from_static("no-referrer"),
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            content_security_policy,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            api_cache_control,
+        ))
+        .layer(compression::compression_layer())
+        .layer(middleware::from_fn_with_state(state.clone(), rate_limit))
+        .layer(middleware::from_fn_with_state(state.clone(), ip_filter))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(logging::RequestSpan::new(state.config.clone()))
+                .on_response(logging::AccessLog),
+        )
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state);
+    if base_path.is_empty() {
+        router
+    } else {
+        Router::new().nest(&base_path, router)
+    }
+}
+
+/// Marks a response from the frontend fallback with the asset it serves,
+/// relative to `base_path`; client routes are served as `index.html`.
+#[derive(Debug, Clone)]
+struct StaticAsset(String);
+
+/// Serves the built frontend from `frontend_dist` for every path the API
+/// router does not handle, falling back to `index.html` for client routes.
+/// `.br`/`.gz` siblings produced at build time are preferred when accepted.
+/// Paths outside `base_path` are not found; `index.html` is rewritten for the
+/// prefix once (see [`rebase_index_html`]) and served from memory.
+pub fn with_frontend(app: Router, frontend_dist: &Path, base_path: &str) -> Router {
+    let index = match std::fs::read_to_string(frontend_dist.join("index.html")) {
+        Ok(html) => Html(rebase_index_html(&html, base_path)),
+        Err(err) => {
+            warn!("failed to read frontend index.html: {err}");
+            Html(String::new())
+        }
+    };
+    let static_service = ServeDir::new(frontend_dist)
+        .precompressed_br()
+        .precompressed_gzip()
+        .append_index_html_on_directories(false);
+    let base_path = base_path.to_string();
+
+    app.fallback(move |mut request: Request| {
+        let mut static_service = static_service.clone();
+        let index = index.clone();
+        let base_path = base_path.clone();
+        async move {
+            if request.method() != Method::GET && request.method() != Method::HEAD {
+                return StatusCode::METHOD_NOT_ALLOWED.into_response();
+            }
+            let Some(uri) = strip_base_path(request.uri(), &base_path) else {
+                return StatusCode::NOT_FOUND.into_response();
+            };
+            let asset = uri.path().trim_start_matches('/').to_string();
+            *request.uri_mut() = uri;
+            let (mut response, asset) = match static_service.try_call(request).await {
+                Ok(response) if response.status() != StatusCode::NOT_FOUND => {
+                    (response.map(Body::new), asset)
+                }
+                _ => (index.into_response(), "index.html".to_string()),
+            };
+            response.extensions_mut().insert(StaticAsset(asset));
+            response
+        }
+    })
+}
+
+/// Points the relative (`./`) asset references Vite emits at `base_path`
+/// and exposes the prefix to the frontend in a `<meta>` tag, so one build
+/// works at any mount point.
+fn rebase_index_html(html: &str, base_path: &str) -> String {
+    let html = html.replace("=\"./", &format!("=\"{base_path}/"));
+    let meta = format!("<meta name=\"mlist-base-path\" content=\"{base_path}\" />\n");
+    match html.find("</head>") {
+        Some(at) => format!("{}{meta}{}", &html[..at], &html[at..]),
+        None => html,
+    }
+}
+
+fn strip_base_path(uri: &Uri, base_path: &str) -> Option<Uri> {
+    let rest = uri.path().strip_prefix(base_path)?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    let path = if rest.is_empty() { "/" } else { rest };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path.to_string(),
+    };
+    path_and_query.parse().ok()
+}
+
+/// Runs the standalone server: API routes, the bundled frontend when
+/// `frontend-dist` exists, and the listener on `config.bind_addr`.
+pub async fn serve(config: AppConfig) -> Result<(), String> {
+    let state = build_state(config).await?;
+    let config = state.config.clone();
+    let app = build_router(state.clone());
+
+    let frontend_dist = PathBuf::from("frontend-dist");
+    let app = if frontend_dist.is_dir() {
+        // The fallback sits outside the router's layers, so frontend rules
+        // are applied around the whole app.
+        let static_headers = middleware::from_fn_with_state(state.clone(), static_path_headers);
+        with_frontend(app, &frontend_dist, &config.base_path).layer(static_headers)
+    } else {
+        warn!("frontend static files not found, serving API routes only");
+        app
+    };
+
+    let listener = BoundListener::bind(&config.bind_addr, config.socket_mode).await?;
+    let socket_path = listener.owned_path();
+    info!(
+        tls = config.tls_cert_path.is_some(),
+        "starting server on {} with root {}",
+        config.bind_addr,
+        config.root_dir.display()
+    );
+
+    reload::spawn_on_sighup(state.clone());
+    trash::spawn_purger(state.clone());
+    let token = state.shutdown.clone();
+    let drain_timeout = Duration::from_secs(config.shutdown_drain_seconds);
+    let timeouts = ConnTimeouts {
+        header: Duration::from_secs(config.header_timeout_seconds),
+        write: Duration::from_secs(config.write_timeout_seconds),
+    };
+    let result = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let listener = listener.into_tcp()?;
+            serve_tls(
+                listener,
+                app,
+                cert_path,
+                key_path,
+                token,
+                drain_timeout,
+                timeouts,
+            )
+            .await
+        }
+        _ => serve_plain(listener, app, token, drain_timeout, timeouts).await,
+    };
+
+    state.transcodes.shutdown().await;
+    let audit = state.audit.clone();
+    let _ = tokio::task::spawn_blocking(move || audit.flush(Duration::from_secs(5))).await;
+    state.db.close().await;
+    if let Some(path) = socket_path {
+        let _ = std::fs::remove_file(path);
+    }
+    info!("server stopped");
+    result
+}
+
+/// Limits applied to every connection, whichever way it is served.
+#[derive(Debug, Clone, Copy)]
+struct ConnTimeouts {
+    /// Time allowed for a request's headers to arrive.
+    header: Duration,
+    /// Time a response write may stall; see [`WriteTimeout`].
+    write: Duration,
+}
+
+impl ConnTimeouts {
+    fn configure(self, builder: &mut auto::Builder<TokioExecutor>) {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(self.header);
+    }
+}
+
+/// Accepts connections and serves them with hyper directly, since
+/// `axum::serve` offers no way to set a header read timeout.
+async fn serve_plain(
+    mut listener: BoundListener,
+    app: Router,
+    token: CancellationToken,
+    drain_timeout: Duration,
+    timeouts: ConnTimeouts,
+) -> Result<(), String> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    timeouts.configure(&mut builder);
+    let graceful = GracefulShutdown::new();
+    let shutdown = shutdown_requested(token, drain_timeout);
+    tokio::pin!(shutdown);
+
+    loop {
+        let (io, peer) = tokio::select! {
+            accepted = Listener::accept(&mut listener) => accepted,
+            () = &mut shutdown => break,
+        };
+        let service = TowerToHyperService::new(AddExtension::new(app.clone(), ConnectInfo(peer)));
+        let io = TokioIo::new(WriteTimeout::new(io, timeouts.write));
+        let connection = builder
+            .serve_connection_with_upgrades(io, service)
+            .into_owned();
+        let connection = graceful.watch(connection);
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!(%peer, "connection closed: {err}");
+            }
+        });
+    }
+    drop(listener);
+
+    // Connections still open when the drain timer fires are dropped with
+    // the runtime.
+    tokio::select! {
+        () = graceful.shutdown() => {}
+        () = tokio::time::sleep(drain_timeout) => {
+            warn!("drain timeout elapsed, closing remaining connections");
+        }
+    }
+    Ok(())
+}
+
+async fn serve_tls(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    cert_path: &Path,
+    key_path: &Path,
+    token: CancellationToken,
+    drain_timeout: Duration,
+    timeouts: ConnTimeouts,
+) -> Result<(), String> {
+    let tls_config = tls::load(cert_path, key_path).await?;
+    tls::spawn_reloader(
+        tls_config.clone(),
+        cert_path.to_path_buf(),
+        key_path.to_path_buf(),
+        token.clone(),
+    );
+
+    let handle = axum_server::Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown_requested(token, drain_timeout).await;
+            handle.graceful_shutdown(Some(drain_timeout));
+        }
+    });
+
+    let listener = listener
+        .into_std()
+        .map_err(|err| format!("failed to prepare listener: {err}"))?;
+    let acceptor = RustlsAcceptor::new(tls_config).acceptor(WriteTimeoutAcceptor {
+        timeout: timeouts.write,
+    });
+    let mut server = axum_server::from_tcp(listener).acceptor(acceptor);
+    timeouts.configure(server.http_builder());
+    server
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .map_err(|err| format!("server error: {err}"))
+}
+
+async fn shutdown_requested(token: CancellationToken, drain_timeout: Duration) {
+    shutdown::signal().await;
+    info!(
+        "shutdown requested, draining connections for up to {}s",
+        drain_timeout.as_secs()
+    );
+    token.cancel();
+}
+
+/// Adds the configured CSP unless the handler set its own. Read per
+/// request so a configuration reload applies without rebuilding the router.
+async fn content_security_policy(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let value = HeaderValue::from_str(&state.config.content_security_policy)
+        .unwrap_or_else(|_| HeaderValue::from_static("default-src 'self'"));
+    response
+        .headers_mut()
+        .entry(header::CONTENT_SECURITY_POLICY)
+        .or_insert(value);
+    response
+}
+
+/// Applies the `static:` rules of `MLIST_PATH_HEADERS` to frontend assets.
+async fn static_path_headers(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    if let Some(StaticAsset(asset)) = response.extensions().get::<StaticAsset>().cloned() {
+        apply_path_headers(
+            &state.config.path_headers,
+            HeaderTarget::Static,
+            &asset,
+            response.headers_mut(),
+        );
+    }
+    response
+}
+
+/// Gives API responses the configured `Cache-Control` (`no-store` by
+/// default) unless the handler chose its own, so shared caches in front of
+/// the server never keep per-user JSON. An empty setting sends nothing.
+async fn api_cache_control(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_api = request.uri().path().starts_with("/api/");
+    let mut response = next.run(request).await;
+    let value = &state.config.api_cache_control;
+    if !is_api || value.is_empty() {
+        return response;
+    }
+    if let Ok(value) = HeaderValue::from_str(value) {
+        response
+            .headers_mut()
+            .entry(header::CACHE_CONTROL)
+            .or_insert(value);
+    }
+    response
+}
+
+/// Enforces `allow_cidrs`/`deny_cidrs` for every route, and the admin
+/// lists on top of them for administration and write endpoints.
+async fn ip_filter(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip());
+    let Some(peer) = peer else {
+        return next.run(request).await;
+    };
+    let config = &state.config;
+    let client_ip = client_ip_for_request(
+        request.headers(),
+        peer,
+        &config.trusted_proxies,
+        config.forwarded_header,
+    );
+    let allowed = net::ip_allowed(client_ip, &config.allow_cidrs, &config.deny_cidrs)
+        && (!is_admin_or_write_path(request.uri().path())
+            || net::ip_allowed(
+                client_ip,
+                &config.admin_allow_cidrs,
+                &config.admin_deny_cidrs,
+            ));
+    if !allowed {
+        return ApiError::forbidden("Access from this address is not allowed.").into_response();
+    }
+    next.run(request).await
+}
+
+fn is_admin_or_write_path(path: &str) -> bool {
+    [
+        "/api/admin",
+        "/api/fs",
+        "/api/trash",
+        "/api/upload",
+        "/api/uploads",
+    ]
+    .iter()
+    .any(|prefix| {
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Turns away clients that exceed the per-IP request rate for their route
+/// class with 429 and a `Retry-After` hint.
+async fn rate_limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let class = throttle::RequestClass::of(request.uri().path());
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip());
+    if let (Some(class), Some(peer)) = (class, peer) {
+        let client_ip = client_ip_for_request(
+            request.headers(),
+            peer,
+            &state.config.trusted_proxies,
+            state.config.forwarded_header,
+        );
+        if let Err(throttled) = state.request_limiter.check(class, client_ip) {
+            if throttled.first {
+                state.audit.record(&AuditEvent::rate_limited(
+                    client_ip.to_string(),
+                    request.uri().path(),
+                ));
+            }
+            let mut response = ApiError::rate_limited("Too many requests.").into_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(throttled.retry_after.as_secs_f64().ceil() as u64),
+            );
+            return response;
+        }
+    }
+    next.run(request).await
+}
+
+async fn api_not_found_handler() -> impl IntoResponse {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "code": "NOT_FOUND",
+            "message": "API route not found."
+        })),
+    )
+}
@@ -0,0 +1,358 @@
+use std::future::{Ready, ready};
+use std::io;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::serve::Listener;
+use axum_server::accept::Accept;
+use listenfd::ListenFd;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::time::Sleep;
+#[cfg(unix)]
+use tokio_util::either::Either;
+use tracing::{info, warn};
+
+/// Peer address reported for Unix socket connections. Only local processes
+/// can connect, so they count as loopback clients; trusting `127.0.0.1` in
+/// `MLIST_TRUSTED_PROXIES` makes the proxy's forwarded headers apply.
+#[cfg(unix)]
+const UNIX_PEER: SocketAddr =
+    SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 0);
+
+/// The socket the server accepts connections on.
+pub enum BoundListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix {
+        listener: UnixListener,
+        /// Set when the socket file was created here and should be removed
+        /// on exit; sockets inherited from systemd are left alone.
+        owned_path: Option<PathBuf>,
+    },
+}
+
+impl BoundListener {
+    /// Uses the socket passed by systemd socket activation (`LISTEN_FDS`)
+    /// when there is one; otherwise binds `bind_addr`, which is either
+    /// `host:port` or `unix:/path/to.sock`.
+    pub async fn bind(bind_addr: &str, socket_mode: u32) -> Result<Self, String> {
+        if let Some(listener) = Self::from_systemd()? {
+            return Ok(listener);
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = bind_addr.strip_prefix("unix:") {
+            return bind_unix(Path::new(path), socket_mode);
+        }
+        #[cfg(not(unix))]
+        let _ = socket_mode;
+
+        let addr: SocketAddr = bind_addr
+            .parse()
+            .map_err(|err| format!("invalid bind_addr {bind_addr}: {err}"))?;
+        TcpListener::bind(addr)
+            .await
+            .map(Self::Tcp)
+            .map_err(|err| format!("failed to bind {addr}: {err}"))
+    }
+
+    fn from_systemd() -> Result<Option<Self>, String> {
+        let mut fds = ListenFd::from_env();
+        if fds.len() == 0 {
+            return Ok(None);
+        }
+        if fds.len() > 1 {
+            warn!(
+                "systemd passed {} sockets, only the first is used",
+                fds.len()
+            );
+        }
+        let activation_error = |err: io::Error| format!("invalid activation socket: {err}");
+
+        if let Ok(Some(listener)) = fds.take_tcp_listener(0) {
+            listener.set_nonblocking(true).map_err(activation_error)?;
+            info!("using TCP socket from systemd activation");
+            return TcpListener::from_std(listener)
+                .map(|listener| Some(Self::Tcp(listener)))
+                .map_err(activation_error);
+        }
+        #[cfg(unix)]
+        if let Ok(Some(listener)) = fds.take_unix_listener(0) {
+            listener.set_nonblocking(true).map_err(activation_error)?;
+            info!("using Unix socket from systemd activation");
+            return UnixListener::from_std(listener)
+                .map(|listener| {
+                    Some(Self::Unix {
+                        listener,
+                        owned_path: None,
+                    })
+                })
+                .map_err(activation_error);
+        }
+        Err("systemd passed a socket that is not a TCP or Unix stream listener.".to_string())
+    }
+
+    /// TLS is only offered on TCP listeners.
+    pub fn into_tcp(self) -> Result<TcpListener, String> {
+        match self {
+            Self::Tcp(listener) => Ok(listener),
+            #[cfg(unix)]
+            Self::Unix { .. } => Err("TLS cannot be served on a Unix socket.".to_string()),
+        }
+    }
+
+    #[cfg(unix)]
+    pub fn owned_path(&self) -> Option<PathBuf> {
+        match self {
+            Self::Unix { owned_path, .. } => owned_path.clone(),
+            Self::Tcp(_) => None,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn owned_path(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+}
+
+/// Binds inside a private directory next to `path` and renames the socket
+/// into place once it has `socket_mode`, so no client can connect while it
+/// still carries the permissions the umask gave it.
+#[cfg(unix)]
+fn bind_unix(path: &Path, socket_mode: u32) -> Result<BoundListener, String> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    // A socket left behind by an unclean exit would make bind fail; any
+    // other kind of file at that path is not ours to remove.
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(format!("{} exists and is not a socket.", path.display()));
+        }
+        std::fs::remove_file(path)
+            .map_err(|err| format!("failed to remove stale socket {}: {err}", path.display()))?;
+    }
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let staging = parent.join(format!(".mlist-socket-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .map_err(|err| format!("failed to create {}: {err}", staging.display()))?;
+    let staged = staging.join("socket");
+    let bound = UnixListener::bind(&staged)
+        .map_err(|err| format!("failed to bind {}: {err}", path.display()))
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(socket_mode))
+                .map_err(|err| format!("failed to set permissions on {}: {err}", path.display()))?;
+            std::fs::rename(&staged, path)
+                .map_err(|err| format!("failed to move socket to {}: {err}", path.display()))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_dir_all(&staging);
+    let listener = bound?;
+    Ok(BoundListener::Unix {
+        listener,
+        owned_path: Some(path.to_path_buf()),
+    })
+}
+
+impl Listener for BoundListener {
+    #[cfg(unix)]
+    type Io = Either<TcpStream, UnixStream>;
+    #[cfg(not(unix))]
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self {
+            #[cfg(unix)]
+            Self::Tcp(listener) => {
+                let (io, addr) = Listener::accept(listener).await;
+                (Either::Left(io), addr)
+            }
+            #[cfg(not(unix))]
+            Self::Tcp(listener) => Listener::accept(listener).await,
+            #[cfg(unix)]
+            Self::Unix { listener, .. } => {
+                let (io, _) = Listener::accept(listener).await;
+                (Either::Right(io), UNIX_PEER)
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr(),
+            #[cfg(unix)]
+            Self::Unix { .. } => Ok(UNIX_PEER),
+        }
+    }
+}
+
+/// Fails a write that has made no progress for `timeout`, so a client that
+/// stops reading a response cannot hold its connection open. Reads pass
+/// straight through; hyper's header timeout and the request body timeout
+/// cover the other direction.
+pub struct WriteTimeout<IO> {
+    inner: IO,
+    timeout: Duration,
+    stalled: Option<Pin<Box<Sleep>>>,
+}
+
+impl<IO> WriteTimeout<IO> {
+    pub fn new(inner: IO, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            stalled: None,
+        }
+    }
+
+    /// Maps the inner result, starting the stall timer on `Pending` and
+    /// clearing it on any progress.
+    fn track<T>(&mut self, cx: &mut Context<'_>, poll: Poll<io::Result<T>>) -> Poll<io::Result<T>> {
+        if poll.is_ready() {
+            self.stalled = None;
+            return poll;
+        }
+        let timeout = self.timeout;
+        let stalled = self
+            .stalled
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+        match stalled.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "client stopped reading the response",
+            ))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for WriteTimeout<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for WriteTimeout<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.track(cx, poll)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        self.track(cx, poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_flush(cx);
+        self.track(cx, poll)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_shutdown(cx);
+        self.track(cx, poll)
+    }
+}
+
+/// Applies [`WriteTimeout`] to TLS connections, below the TLS layer.
+#[derive(Debug, Clone, Copy)]
+pub struct WriteTimeoutAcceptor {
+    pub timeout: Duration,
+}
+
+impl<I, S> Accept<I, S> for WriteTimeoutAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin,
+{
+    type Stream = WriteTimeout<I>;
+    type Service = S;
+    type Future = Ready<io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        ready(Ok((WriteTimeout::new(stream, self.timeout), service)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::WriteTimeout;
+
+    #[tokio::test]
+    async fn stalled_writes_time_out() {
+        let (client, _peer) = tokio::io::duplex(16);
+        let mut io = WriteTimeout::new(client, Duration::from_millis(50));
+        let err = io.write_all(&[0; 64]).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn slow_readers_keep_the_connection() {
+        let (client, mut peer) = tokio::io::duplex(16);
+        let mut io = WriteTimeout::new(client, Duration::from_millis(200));
+        let reader = tokio::spawn(async move {
+            let mut received = Vec::new();
+            let mut chunk = [0; 16];
+            while received.len() < 64 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let read = peer.read(&mut chunk).await.unwrap();
+                received.extend_from_slice(&chunk[..read]);
+            }
+            received.len()
+        });
+        io.write_all(&[0; 64]).await.unwrap();
+        assert_eq!(reader.await.unwrap(), 64);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_sockets_appear_with_their_final_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("mlist-listener-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mlist.sock");
+
+        let _listener = super::bind_unix(&path, 0o600).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Only the socket is left behind, not the staging directory.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,121 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use axum::extract::ConnectInfo;
+use axum::http::{Request, Response, header};
+use tower_http::request_id::RequestId;
+use tower_http::trace::{MakeSpan, OnResponse};
+use tracing::{Span, info, info_span};
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LiveConfig;
+use crate::handlers::client_ip_for_request;
+
+const DEFAULT_FILTER: &str = "mlist=info,backend=info,tower_http=info";
+
+/// Output format of the process log.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the enclosing request
+    /// span flattened in, for Loki, Elasticsearch and similar collectors.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(format!(
+                "unknown log format {other:?}, expected \"text\" or \"json\""
+            )),
+        }
+    }
+}
+
+/// Installs the global subscriber. `RUST_LOG` overrides the default filter.
+pub fn init_logging(format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
+
+/// Opens a span per request carrying its id, client address, method and
+/// path, so every event logged while handling it can be correlated.
+#[derive(Clone)]
+pub struct RequestSpan {
+    config: LiveConfig,
+}
+
+impl RequestSpan {
+    pub fn new(config: LiveConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .and_then(|id| id.header_value().to_str().ok())
+            .unwrap_or_default();
+        let client_ip = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(peer)| {
+                let config = self.config.latest();
+                client_ip_for_request(
+                    request.headers(),
+                    peer.ip(),
+                    &config.trusted_proxies,
+                    config.forwarded_header,
+                )
+                .to_string()
+            })
+            .unwrap_or_default();
+        info_span!(
+            "request",
+            request_id,
+            client_ip,
+            method = %request.method(),
+            path = request.uri().path(),
+        )
+    }
+}
+
+/// Logs one access line per response with its status, latency and size.
+/// The size comes from `Content-Length` and is omitted for streamed or
+/// compressed bodies whose length is not known up front.
+#[derive(Clone, Copy)]
+pub struct AccessLog;
+
+impl<B> OnResponse<B> for AccessLog {
+    fn on_response(self, response: &Response<B>, latency: Duration, _span: &Span) {
+        let bytes = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        info!(
+            status = response.status().as_u16(),
+            latency_ms = u64::try_from(latency.as_millis()).unwrap_or(u64::MAX),
+            bytes,
+            "request completed"
+        );
+    }
+}
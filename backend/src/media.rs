@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tokio::sync::{RwLock, Semaphore};
+use tracing::{debug, warn};
+
+/// Once the cache holds this many files it is emptied before inserting
+/// another.
+const MAX_CACHED_FILES: usize = 16_384;
+/// ffprobe processes allowed to run at once across all listings.
+const MAX_CONCURRENT_PROBES: usize = 4;
+const FFPROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Technical details shown next to a media file. Fields the source does not
+/// carry, or that could not be read, stay empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaMeta {
+    pub duration_seconds: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+}
+
+impl MediaKind {
+    pub fn for_mime(mime: &str) -> Option<Self> {
+        if mime.starts_with("image/") {
+            Some(Self::Image)
+        } else if mime.starts_with("video/") {
+            Some(Self::Video)
+        } else if mime.starts_with("audio/") {
+            Some(Self::Audio)
+        } else {
+            None
+        }
+    }
+}
+
+/// Metadata keyed by resolved path and valid while the file's size and
+/// mtime are unchanged. Failed probes are cached too, so a broken file is
+/// not re-probed on every listing. Image dimensions come from the file
+/// header; video and audio need `ffprobe`.
+#[derive(Debug, Clone)]
+pub struct MediaMetaCache {
+    inner: Arc<RwLock<HashMap<PathBuf, CachedMeta>>>,
+    probe_slots: Arc<Semaphore>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedMeta {
+    size: u64,
+    mtime: Option<SystemTime>,
+    meta: Option<MediaMeta>,
+}
+
+impl Default for MediaMetaCache {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(HashMap::new())),
+            probe_slots: Arc::new(Semaphore::new(MAX_CONCURRENT_PROBES)),
+        }
+    }
+}
+
+impl MediaMetaCache {
+    pub async fn lookup(
+        &self,
+        path: &Path,
+        kind: MediaKind,
+        metadata: &std::fs::Metadata,
+        ffprobe: Option<&Path>,
+    ) -> Option<MediaMeta> {
+        let size = metadata.len();
+        let mtime = metadata.modified().ok();
+        {
+            let cached = self.inner.read().await;
+            if let Some(entry) = cached.get(path) {
+                if entry.size == size && entry.mtime == mtime {
+                    return entry.meta.clone();
+                }
+            }
+        }
+
+        let meta = match kind {
+            MediaKind::Image => image_meta(path).await,
+            MediaKind::Video | MediaKind::Audio => {
+                let ffprobe = ffprobe?;
+                let _permit = self.probe_slots.acquire().await.ok()?;
+                let probed = probe_meta(ffprobe, path).await;
+                if kind == MediaKind::Audio {
+                    // Embedded cover art is reported as a video stream.
+                    probed.map(|meta| MediaMeta {
+                        width: None,
+                        height: None,
+                        video_codec: None,
+                        ..meta
+                    })
+                } else {
+                    probed
+                }
+            }
+        };
+
+        let mut cached = self.inner.write().await;
+        if cached.len() >= MAX_CACHED_FILES {
+            cached.clear();
+        }
+        cached.insert(
+            path.to_path_buf(),
+            CachedMeta {
+                size,
+                mtime,
+                meta: meta.clone(),
+            },
+        );
+        meta
+    }
+}
+
+async fn image_meta(path: &Path) -> Option<MediaMeta> {
+    let source = path.to_path_buf();
+    let dimensions = tokio::task::spawn_blocking(move || {
+        image::ImageReader::open(&source)
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()?
+            .into_dimensions()
+            .ok()
+    })
+    .await
+    .ok()??;
+    Some(MediaMeta {
+        width: Some(dimensions.0),
+        height: Some(dimensions.1),
+        ..MediaMeta::default()
+    })
+}
+
+async fn probe_meta(ffprobe: &Path, path: &Path) -> Option<MediaMeta> {
+    let child = Command::new(ffprobe)
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| warn!("failed to spawn ffprobe {}: {err}", ffprobe.display()))
+        .ok()?;
+    let output = tokio::time::timeout(FFPROBE_TIMEOUT, child.wait_with_output())
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        debug!("ffprobe could not read {}", path.display());
+        return None;
+    }
+    parse_ffprobe(&output.stdout)
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+fn parse_ffprobe(raw: &[u8]) -> Option<MediaMeta> {
+    let probe: ProbeOutput = serde_json::from_slice(raw).ok()?;
+    let video = probe
+        .streams
+        .iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("video") && stream.width.is_some());
+    let audio = probe
+        .streams
+        .iter()
+        .find(|stream| stream.codec_type.as_deref() == Some("audio"));
+    Some(MediaMeta {
+        duration_seconds: probe
+            .format
+            .and_then(|format| format.duration)
+            .and_then(|value| value.parse::<f64>().ok()),
+        width: video.and_then(|stream| stream.width),
+        height: video.and_then(|stream| stream.height),
+        video_codec: video.and_then(|stream| stream.codec_name.clone()),
+        audio_codec: audio.and_then(|stream| stream.codec_name.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MediaMeta, parse_ffprobe};
+
+    #[test]
+    fn ffprobe_output_is_summarized() {
+        let raw = br#"{
+            "streams": [
+                {"index": 0, "codec_type": "video", "codec_name": "hevc", "width": 3840, "height": 2160},
+                {"index": 1, "codec_type": "audio", "codec_name": "eac3"},
+                {"index": 2, "codec_type": "subtitle", "codec_name": "subrip"}
+            ],
+            "format": {"duration": "5412.096000"}
+        }"#;
+        assert_eq!(
+            parse_ffprobe(raw),
+            Some(MediaMeta {
+                duration_seconds: Some(5412.096),
+                width: Some(3840),
+                height: Some(2160),
+                video_codec: Some("hevc".to_string()),
+                audio_codec: Some("eac3".to_string()),
+            })
+        );
+        assert_eq!(parse_ffprobe(b"not json"), None);
+    }
+}
@@ -0,0 +1,147 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Orders file names for listings. Plain order compares lowercased names;
+/// natural order additionally compares runs of digits by value, so
+/// `Episode 2` sorts before `Episode 10`. With the `collation` feature a
+/// locale's collation rules replace the lowercase comparison.
+pub struct NameCollator {
+    natural: bool,
+    #[cfg(feature = "collation")]
+    collator: Option<icu_collator::Collator>,
+}
+
+impl NameCollator {
+    /// Fails when `locale` is not a valid BCP 47 tag, or when it is set on
+    /// a build without the `collation` feature.
+    pub fn new(natural: bool, locale: Option<&str>) -> Result<Self, String> {
+        #[cfg(feature = "collation")]
+        {
+            let collator = locale
+                .map(|tag| locale_collator(tag, natural))
+                .transpose()?;
+            Ok(Self { natural, collator })
+        }
+        #[cfg(not(feature = "collation"))]
+        {
+            if locale.is_some() {
+                return Err("MLIST_SORT_LOCALE requires the `collation` feature.".to_string());
+            }
+            Ok(Self { natural })
+        }
+    }
+
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        #[cfg(feature = "collation")]
+        if let Some(collator) = &self.collator {
+            return collator.compare(a, b).then_with(|| a.cmp(b));
+        }
+        if self.natural {
+            natural_cmp(a, b)
+        } else {
+            a.to_lowercase().cmp(&b.to_lowercase())
+        }
+    }
+}
+
+#[cfg(feature = "collation")]
+fn locale_collator(tag: &str, natural: bool) -> Result<icu_collator::Collator, String> {
+    use icu_collator::{Collator, CollatorOptions, Numeric, Strength};
+
+    let locale: icu_locid::Locale = tag
+        .parse()
+        .map_err(|err| format!("MLIST_SORT_LOCALE {tag:?} is not a valid locale: {err}"))?;
+    let mut options = CollatorOptions::new();
+    // Secondary strength ignores case but keeps accents apart.
+    options.strength = Some(Strength::Secondary);
+    if natural {
+        options.numeric = Some(Numeric::On);
+    }
+    Collator::try_new(&(&locale).into(), options)
+        .map_err(|err| format!("No collation data for MLIST_SORT_LOCALE {tag:?}: {err}"))
+}
+
+/// Case-insensitive comparison that treats each run of ASCII digits as one
+/// number. Equal numbers with different leading zeros order the shorter
+/// spelling first, and names equal in every other way fall back to a
+/// byte-wise comparison so the order stays total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut left, mut right) = (a.chars().peekable(), b.chars().peekable());
+    let mut zeros = Ordering::Equal;
+    loop {
+        let (l, r) = match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) => (l, r),
+        };
+        if l.is_ascii_digit() && r.is_ascii_digit() {
+            let (l_digits, r_digits) = (take_digits(&mut left), take_digits(&mut right));
+            let (l_value, r_value) = (
+                l_digits.trim_start_matches('0'),
+                r_digits.trim_start_matches('0'),
+            );
+            let ordering = l_value
+                .len()
+                .cmp(&r_value.len())
+                .then_with(|| l_value.cmp(r_value));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            if zeros == Ordering::Equal {
+                zeros = l_digits.len().cmp(&r_digits.len());
+            }
+            continue;
+        }
+        let ordering = l.to_lowercase().cmp(r.to_lowercase());
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        left.next();
+        right.next();
+    }
+    zeros.then_with(|| a.cmp(b))
+}
+
+fn take_digits(chars: &mut Peekable<Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::{NameCollator, natural_cmp};
+
+    #[test]
+    fn numbers_compare_by_value() {
+        let mut names = vec!["Episode 10", "episode 2", "Episode 1", "Episode 02"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            vec!["Episode 1", "episode 2", "Episode 02", "Episode 10"]
+        );
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("b1", "a2"), Ordering::Greater);
+        assert_eq!(
+            natural_cmp("x99999999999999999999", "x100000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn plain_order_stays_lexical() {
+        let plain = NameCollator::new(false, None).unwrap();
+        assert_eq!(plain.compare("Episode 10", "Episode 2"), Ordering::Less);
+        let natural = NameCollator::new(true, None).unwrap();
+        assert_eq!(
+            natural.compare("Episode 10", "Episode 2"),
+            Ordering::Greater
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An address block such as `10.0.0.0/8` or `::1/128`. A bare address is
+/// accepted as a single-host block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => prefix_matches(
+                u128::from(u32::from(net)),
+                u128::from(u32::from(ip)),
+                self.prefix,
+                32,
+            ),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_matches(u128::from(net), u128::from(ip), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, prefix: u8, bits: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = u32::from(bits - prefix);
+    (net >> shift) == (ip >> shift)
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let raw = raw.trim();
+        let (addr, prefix) = match raw.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (raw, None),
+        };
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| format!("invalid address in {raw:?}"))?
+            .to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(value) => value
+                .parse::<u8>()
+                .ok()
+                .filter(|value| *value <= max)
+                .ok_or_else(|| format!("invalid prefix length in {raw:?}"))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Whether `ip` passes an allow/deny pair. A deny match always rejects; an
+/// empty allow list admits every address that is not denied.
+pub fn ip_allowed(ip: IpAddr, allow: &[IpNet], deny: &[IpNet]) -> bool {
+    if deny.iter().any(|net| net.contains(ip)) {
+        return false;
+    }
+    allow.is_empty() || allow.iter().any(|net| net.contains(ip))
+}
+
+/// The header a trusted proxy records the client address in. Only this
+/// header is read, since a client can send the other one itself and a
+/// proxy that does not set it will pass it through untouched.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For`, falling back to `X-Real-IP`.
+    #[default]
+    XForwardedFor,
+    /// RFC 7239 `Forwarded`.
+    Forwarded,
+}
+
+impl FromStr for ForwardedHeader {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "x-forwarded-for" => Ok(Self::XForwardedFor),
+            "forwarded" => Ok(Self::Forwarded),
+            other => Err(format!(
+                "unknown header {other:?}, expected \"x-forwarded-for\" or \"forwarded\""
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{IpNet, ip_allowed};
+
+    fn ip(raw: &str) -> IpAddr {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_blocks_match_their_range() {
+        let net: IpNet = "10.0.0.0/8".parse().unwrap();
+        assert!(net.contains(ip("10.1.2.3")));
+        assert!(!net.contains(ip("11.0.0.1")));
+        assert!(!net.contains(ip("::1")));
+
+        let v6: IpNet = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12::1")));
+        assert!(!v6.contains(ip("fe80::1")));
+    }
+
+    #[test]
+    fn bare_addresses_are_single_hosts() {
+        let net: IpNet = "127.0.0.1".parse().unwrap();
+        assert!(net.contains(ip("127.0.0.1")));
+        assert!(!net.contains(ip("127.0.0.2")));
+        // IPv4-mapped peers from a dual-stack listener still match.
+        assert!(net.contains(ip("::ffff:127.0.0.1")));
+    }
+
+    #[test]
+    fn zero_prefix_matches_everything_in_family() {
+        let net: IpNet = "0.0.0.0/0".parse().unwrap();
+        assert!(net.contains(ip("203.0.113.9")));
+        assert!(!net.contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn malformed_blocks_are_rejected() {
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("nope/8".parse::<IpNet>().is_err());
+        assert!("::1/129".parse::<IpNet>().is_err());
+    }
+
+    #[test]
+    fn deny_rules_win_over_allow_rules() {
+        let lan: Vec<IpNet> = vec!["192.168.0.0/16".parse().unwrap()];
+        let printer: Vec<IpNet> = vec!["192.168.1.50".parse().unwrap()];
+        assert!(ip_allowed(ip("203.0.113.9"), &[], &[]));
+        assert!(ip_allowed(ip("192.168.1.10"), &lan, &printer));
+        assert!(!ip_allowed(ip("192.168.1.50"), &lan, &printer));
+        assert!(!ip_allowed(ip("203.0.113.9"), &lan, &[]));
+        assert!(!ip_allowed(ip("192.168.1.50"), &[], &printer));
+    }
+}
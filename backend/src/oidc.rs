@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::config::AppConfig;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long the provider's discovery document is reused.
+const METADATA_TTL_SECONDS: u64 = 3_600;
+/// How long a user may take at the provider before the login is abandoned.
+pub const PENDING_TTL_SECONDS: u64 = 600;
+/// Holds the `state` of the login the browser started, so a callback URL
+/// produced in someone else's browser cannot finish a login in this one.
+pub const STATE_COOKIE_NAME: &str = "mlist_oidc_state";
+/// Unfinished logins kept at most, so unauthenticated visitors cannot grow
+/// the map without bound.
+const MAX_PENDING_LOGINS: usize = 1_000;
+
+/// One `claim=value` entry of `MLIST_OIDC_ADMIN_CLAIMS`, such as
+/// `groups=media-admins`. Matches when the claim equals the value or, for
+/// list claims, contains it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaimRule {
+    claim: String,
+    value: String,
+}
+
+impl ClaimRule {
+    pub fn matches(&self, claims: &Map<String, Value>) -> bool {
+        let matches_value = |value: &Value| match value {
+            Value::String(value) => *value == self.value,
+            Value::Bool(value) => value.to_string() == self.value,
+            Value::Number(value) => value.to_string() == self.value,
+            _ => false,
+        };
+        match claims.get(&self.claim) {
+            Some(Value::Array(values)) => values.iter().any(matches_value),
+            Some(value) => matches_value(value),
+            None => false,
+        }
+    }
+}
+
+impl FromStr for ClaimRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (claim, expected) = value
+            .split_once('=')
+            .map(|(claim, expected)| (claim.trim(), expected.trim()))
+            .filter(|(claim, expected)| !claim.is_empty() && !expected.is_empty())
+            .ok_or_else(|| format!("invalid claim rule {value:?}, expected claim=value"))?;
+        Ok(Self {
+            claim: claim.to_string(),
+            value: expected.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(Debug)]
+struct PendingLogin {
+    nonce: String,
+    verifier: String,
+    redirect_uri: String,
+    started_at: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Authorization code flow with PKCE against the provider named by
+/// `MLIST_OIDC_ISSUER`. Logins in progress are kept in memory, keyed by
+/// their `state` parameter, until the provider redirects back.
+#[derive(Debug, Clone, Default)]
+pub struct OidcClient {
+    http: reqwest::Client,
+    metadata: Arc<Mutex<Option<(String, ProviderMetadata, u64)>>>,
+    pending: Arc<Mutex<HashMap<String, PendingLogin>>>,
+}
+
+impl OidcClient {
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+
+    /// Starts a login and returns the provider URL to send the browser to,
+    /// along with the login's `state`. `redirect_uri` must lead back to the
+    /// callback handler.
+    pub async fn authorization_url(
+        &self,
+        config: &AppConfig,
+        redirect_uri: String,
+        now: u64,
+    ) -> Result<(String, String), String> {
+        let (issuer, client_id) = oidc_client(config)?;
+        let metadata = self.metadata(issuer, now).await?;
+
+        let state = random_token();
+        let nonce = random_token();
+        let verifier = format!("{}{}", random_token(), random_token());
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        let scope = config.oidc_scopes.join(" ");
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            [
+                ("response_type", "code"),
+                ("client_id", client_id),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("scope", scope.as_str()),
+                ("state", state.as_str()),
+                ("nonce", nonce.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|err| format!("invalid authorization endpoint: {err}"))?;
+
+        let mut pending = self
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        pending.retain(|_, login| login.started_at + PENDING_TTL_SECONDS > now);
+        if pending.len() >= MAX_PENDING_LOGINS {
+            return Err("too many logins in progress".to_string());
+        }
+        pending.insert(
+            state.clone(),
+            PendingLogin {
+                nonce,
+                verifier,
+                redirect_uri,
+                started_at: now,
+            },
+        );
+        Ok((url.into(), state))
+    }
+
+    /// Finishes the login identified by `state`: redeems `code` at the token
+    /// endpoint and returns the verified claims of the ID token. The token
+    /// comes straight from the provider over TLS, so as OIDC Core 3.1.3.7
+    /// allows, its signature is not checked; issuer, audience, expiry and
+    /// nonce are.
+    pub async fn finish(
+        &self,
+        config: &AppConfig,
+        state: &str,
+        code: &str,
+        now: u64,
+    ) -> Result<Map<String, Value>, String> {
+        let login = self
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(state)
+            .filter(|login| login.started_at + PENDING_TTL_SECONDS > now)
+            .ok_or_else(|| "unknown or expired login state".to_string())?;
+        let (issuer, client_id) = oidc_client(config)?;
+        let metadata = self.metadata(issuer, now).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", login.redirect_uri.as_str()),
+            ("client_id", client_id),
+            ("code_verifier", login.verifier.as_str()),
+        ];
+        if let Some(secret) = config.oidc_client_secret.as_deref() {
+            form.push(("client_secret", secret));
+        }
+        let body = serde_urlencoded::to_string(&form).map_err(|err| err.to_string())?;
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .body(body)
+            .send()
+            .await
+            .map_err(|err| format!("token request failed: {err}"))?;
+        let status = response.status();
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|err| format!("token request failed: {err}"))?;
+        if !status.is_success() {
+            return Err(format!("token endpoint answered {status}"));
+        }
+        let tokens: TokenResponse = serde_json::from_slice(&bytes)
+            .map_err(|err| format!("invalid token response: {err}"))?;
+
+        let claims = decode_id_token(&tokens.id_token)?;
+        validate_claims(&claims, &metadata.issuer, client_id, &login.nonce, now)?;
+        Ok(claims)
+    }
+
+    async fn metadata(&self, issuer: &str, now: u64) -> Result<ProviderMetadata, String> {
+        let cached = self
+            .metadata
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        if let Some((cached_issuer, metadata, fetched_at)) = cached {
+            if cached_issuer == issuer && fetched_at + METADATA_TTL_SECONDS > now {
+                return Ok(metadata);
+            }
+        }
+
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|err| format!("provider discovery failed: {err}"))?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|err| format!("provider discovery failed: {err}"))?;
+        let metadata: ProviderMetadata = serde_json::from_slice(&bytes)
+            .map_err(|err| format!("invalid provider metadata: {err}"))?;
+        *self
+            .metadata
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+            Some((issuer.to_string(), metadata.clone(), now));
+        Ok(metadata)
+    }
+}
+
+/// The stable identifier of the ID token's user. Only this picks the
+/// account a login signs into; usernames can often be edited at the
+/// provider and so only name new accounts.
+pub fn claimed_subject(claims: &Map<String, Value>) -> Option<&str> {
+    claims
+        .get("sub")
+        .and_then(Value::as_str)
+        .filter(|value| !value.is_empty())
+}
+
+/// The name given to an account created for an OIDC login, taken from
+/// `MLIST_OIDC_USERNAME_CLAIM`.
+pub fn claimed_username<'a>(config: &AppConfig, claims: &'a Map<String, Value>) -> Option<&'a str> {
+    claims
+        .get(&config.oidc_username_claim)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn oidc_client(config: &AppConfig) -> Result<(&str, &str), String> {
+    config
+        .oidc_issuer
+        .as_deref()
+        .zip(config.oidc_client_id.as_deref())
+        .ok_or_else(|| "OIDC login is not configured".to_string())
+}
+
+fn random_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+fn decode_id_token(token: &str) -> Result<Map<String, Value>, String> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| "malformed ID token".to_string())?;
+    let bytes = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|_| "malformed ID token".to_string())?;
+    serde_json::from_slice(&bytes).map_err(|_| "malformed ID token".to_string())
+}
+
+fn validate_claims(
+    claims: &Map<String, Value>,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: u64,
+) -> Result<(), String> {
+    if claims.get("iss").and_then(Value::as_str) != Some(issuer) {
+        return Err("ID token issuer mismatch".to_string());
+    }
+    let audience_ok = match claims.get("aud") {
+        Some(Value::String(audience)) => audience == client_id,
+        Some(Value::Array(audiences)) => audiences
+            .iter()
+            .any(|audience| audience.as_str() == Some(client_id)),
+        _ => false,
+    };
+    if !audience_ok {
+        return Err("ID token audience mismatch".to_string());
+    }
+    if claims
+        .get("exp")
+        .and_then(Value::as_u64)
+        .is_none_or(|exp| exp <= now)
+    {
+        return Err("ID token expired".to_string());
+    }
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err("ID token nonce mismatch".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use serde_json::{Map, Value, json};
+
+    use super::{ClaimRule, decode_id_token, validate_claims};
+
+    fn claims(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn claim_rules_match_values_and_lists() {
+        let rule: ClaimRule = "groups=media-admins".parse().unwrap();
+        assert!(rule.matches(&claims(json!({ "groups": ["staff", "media-admins"] }))));
+        assert!(rule.matches(&claims(json!({ "groups": "media-admins" }))));
+        assert!(!rule.matches(&claims(json!({ "groups": ["staff"] }))));
+        assert!(!rule.matches(&claims(json!({}))));
+
+        let verified: ClaimRule = "email_verified=true".parse().unwrap();
+        assert!(verified.matches(&claims(json!({ "email_verified": true }))));
+        assert!("groups".parse::<ClaimRule>().is_err());
+        assert!("=admins".parse::<ClaimRule>().is_err());
+    }
+
+    #[test]
+    fn id_tokens_are_checked_against_the_login() {
+        let payload = json!({
+            "iss": "https://id.example.com",
+            "aud": ["mlist", "other"],
+            "exp": 2_000,
+            "nonce": "n-1",
+            "preferred_username": "ivy",
+        });
+        let token = format!("e30.{}.c2ln", URL_SAFE_NO_PAD.encode(payload.to_string()));
+        let decoded = decode_id_token(&token).unwrap();
+        assert_eq!(decoded["preferred_username"], "ivy");
+
+        let check = |issuer, client_id, nonce, now| {
+            validate_claims(&decoded, issuer, client_id, nonce, now)
+        };
+        assert!(check("https://id.example.com", "mlist", "n-1", 1_000).is_ok());
+        assert!(check("https://evil.example.com", "mlist", "n-1", 1_000).is_err());
+        assert!(check("https://id.example.com", "someone", "n-1", 1_000).is_err());
+        assert!(check("https://id.example.com", "mlist", "n-2", 1_000).is_err());
+        assert!(check("https://id.example.com", "mlist", "n-1", 2_000).is_err());
+        assert!(decode_id_token("not-a-jwt").is_err());
+    }
+}
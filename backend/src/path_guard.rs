@@ -1,10 +1,54 @@
 use crate::errors::{ApiError, ApiResult};
+use std::collections::BTreeMap;
 use std::path::{Component, Path, PathBuf};
 
 pub const PRIVATE_MARKER_FILE: &str = ".private";
+pub const WRITABLE_MARKER_FILE: &str = ".writable";
+/// Keeps a directory and everything below it out of Atom feeds.
+pub const NOFEED_MARKER_FILE: &str = ".nofeed";
+/// Per-directory listing and download settings; see `dir_config`.
+pub const DIR_CONFIG_FILE: &str = ".mlist.toml";
+/// `.private.<name>` next to a file makes just that file admin-only.
+pub const PRIVATE_FILE_MARKER_PREFIX: &str = ".private.";
 
-pub fn is_private_marker_name(name: &str) -> bool {
+/// Marker files steer access control; they are never listed or served.
+pub fn is_marker_name(name: &str) -> bool {
     name == PRIVATE_MARKER_FILE
+        || name == WRITABLE_MARKER_FILE
+        || name == NOFEED_MARKER_FILE
+        || name == DIR_CONFIG_FILE
+        || name
+            .strip_prefix(PRIVATE_FILE_MARKER_PREFIX)
+            .is_some_and(|rest| !rest.is_empty())
+}
+
+pub fn private_file_marker_name(file_name: &str) -> String {
+    format!("{PRIVATE_FILE_MARKER_PREFIX}{file_name}")
+}
+
+/// How symbolic links below the root are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Any symlink in a requested path is rejected and listings skip them.
+    #[default]
+    Deny,
+    /// Symlinks are followed when their canonical target stays inside the
+    /// root; access control is then evaluated against the target.
+    WithinRoot,
+}
+
+impl std::str::FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "deny" | "none" | "false" => Ok(Self::Deny),
+            "within-root" => Ok(Self::WithinRoot),
+            other => Err(format!(
+                "unknown symlink policy {other:?}, expected \"deny\" or \"within-root\""
+            )),
+        }
+    }
 }
 
 pub fn normalize_relative_path(raw: Option<&str>) -> ApiResult<String> {
@@ -37,28 +81,97 @@ pub fn normalize_relative_path(raw: Option<&str>) -> ApiResult<String> {
     Ok(segments.join("/"))
 }
 
+/// Rewrites a normalized path whose first segment is an alias to the
+/// aliased directory, so `iso/debian.iso` can stand for
+/// `distros/linux/isos/debian.iso`. Aliases shadow real entries of the
+/// same name at the root.
+pub fn expand_alias(path: String, aliases: &BTreeMap<String, String>) -> String {
+    let Some(target) = aliases.get(path.split('/').next().unwrap_or_default()) else {
+        return path;
+    };
+    match path.split_once('/') {
+        Some((_, rest)) => format!("{target}/{rest}"),
+        None => target.clone(),
+    }
+}
+
 pub fn ensure_not_marker_path(path: &str) -> ApiResult<()> {
-    if path.rsplit('/').next().is_some_and(is_private_marker_name) {
+    if path.rsplit('/').next().is_some_and(is_marker_name) {
         return Err(ApiError::not_found("File not found."));
     }
     Ok(())
 }
 
-pub async fn resolve_existing_path(root: &Path, relative_path: &str) -> ApiResult<PathBuf> {
-    check_symlink_segments(root, relative_path).await?;
+/// Whether `name` matches one of the operator's `hide_patterns`.
+pub fn is_hidden_name(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|pattern| glob_matches(pattern, name))
+}
+
+/// Hidden entries behave as if they did not exist, and so does everything
+/// beneath a hidden directory.
+pub fn ensure_not_hidden_path(path: &str, patterns: &[String]) -> ApiResult<()> {
+    let hidden = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .any(|segment| is_hidden_name(segment, patterns));
+    if hidden {
+        return Err(ApiError::not_found("Path not found."));
+    }
+    Ok(())
+}
+
+/// Shell-style matching of a single name: `*` matches any run of
+/// characters and `?` exactly one. There are no character classes.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
 
+pub async fn resolve_existing_path(
+    root: &Path,
+    relative_path: &str,
+    symlinks: SymlinkPolicy,
+) -> ApiResult<PathBuf> {
     let candidate = if relative_path.is_empty() {
         root.to_path_buf()
     } else {
         root.join(relative_path)
     };
 
-    let candidate_meta = tokio::fs::symlink_metadata(&candidate)
-        .await
-        .map_err(|err| ApiError::from_io(err, "path"))?;
+    if symlinks == SymlinkPolicy::Deny {
+        check_symlink_segments(root, relative_path).await?;
+
+        let candidate_meta = tokio::fs::symlink_metadata(&candidate)
+            .await
+            .map_err(|err| ApiError::from_io(err, "path"))?;
 
-    if candidate_meta.file_type().is_symlink() {
-        return Err(ApiError::forbidden("Symbolic links are not allowed."));
+        if candidate_meta.file_type().is_symlink() {
+            return Err(ApiError::forbidden("Symbolic links are not allowed."));
+        }
     }
 
     let canonical = tokio::fs::canonicalize(&candidate)
@@ -74,6 +187,21 @@ pub async fn resolve_existing_path(root: &Path, relative_path: &str) -> ApiResul
     Ok(canonical)
 }
 
+/// Resolves a symlink found while enumerating a directory. Returns `None`
+/// when the policy denies symlinks or the target leaves the root, in which
+/// case the entry is simply not shown.
+pub async fn resolve_symlink_entry(
+    root: &Path,
+    link: &Path,
+    symlinks: SymlinkPolicy,
+) -> Option<PathBuf> {
+    if symlinks == SymlinkPolicy::Deny {
+        return None;
+    }
+    let target = tokio::fs::canonicalize(link).await.ok()?;
+    target.starts_with(root).then_some(target)
+}
+
 pub fn relative_string_from_root(root: &Path, absolute_path: &Path) -> ApiResult<String> {
     let stripped = absolute_path
         .strip_prefix(root)
@@ -115,7 +243,11 @@ async fn check_symlink_segments(root: &Path, relative_path: &str) -> ApiResult<(
 
 #[cfg(test)]
 mod tests {
-    use super::normalize_relative_path;
+    use std::collections::BTreeMap;
+
+    use super::{
+        ensure_not_hidden_path, expand_alias, glob_matches, is_marker_name, normalize_relative_path,
+    };
 
     #[test]
     fn normalize_accepts_root() {
@@ -144,4 +276,48 @@ mod tests {
             "movies/2026/trailer.mp4"
         );
     }
+
+    #[test]
+    fn marker_names_include_per_file_sidecars() {
+        assert!(is_marker_name(".private"));
+        assert!(is_marker_name(".writable"));
+        assert!(is_marker_name(".nofeed"));
+        assert!(is_marker_name(".mlist.toml"));
+        assert!(is_marker_name(".private.report.pdf"));
+        assert!(!is_marker_name(".private."));
+        assert!(!is_marker_name("report.pdf"));
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_matches("*.nfo", "movie.nfo"));
+        assert!(!glob_matches("*.nfo", "movie.nfo.bak"));
+        assert!(glob_matches("Thumbs.db", "Thumbs.db"));
+        assert!(!glob_matches("Thumbs.db", "thumbs.db"));
+        assert!(glob_matches(".*", ".DS_Store"));
+        assert!(!glob_matches(".*", "visible"));
+        assert!(glob_matches("s??e*", "s01e02.mkv"));
+        assert!(glob_matches("*a*b", "xxaxxab"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn hidden_paths_cover_every_segment() {
+        let patterns = vec!["@eaDir".to_string(), "*.nfo".to_string()];
+        assert!(ensure_not_hidden_path("movies/@eaDir/thumb.jpg", &patterns).is_err());
+        assert!(ensure_not_hidden_path("movies/a.nfo", &patterns).is_err());
+        assert!(ensure_not_hidden_path("movies/a.mkv", &patterns).is_ok());
+        assert!(ensure_not_hidden_path("", &patterns).is_ok());
+    }
+
+    #[test]
+    fn aliases_replace_the_first_segment() {
+        let aliases = BTreeMap::from([("iso".to_string(), "distros/linux/isos".to_string())]);
+        let expand = |path: &str| expand_alias(path.to_string(), &aliases);
+        assert_eq!(expand("iso"), "distros/linux/isos");
+        assert_eq!(expand("iso/debian.iso"), "distros/linux/isos/debian.iso");
+        assert_eq!(expand("isos/debian.iso"), "isos/debian.iso");
+        assert_eq!(expand("media/iso"), "media/iso");
+        assert_eq!(expand(""), "");
+    }
 }
@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue, header};
+
+use crate::path_guard::glob_matches;
+
+/// Headers that describe the body or the connection, which the server
+/// frames itself.
+const RESERVED: [HeaderName; 6] = [
+    header::CONNECTION,
+    header::CONTENT_ENCODING,
+    header::CONTENT_LENGTH,
+    header::CONTENT_RANGE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+];
+
+/// What a rule's pattern is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderTarget {
+    /// Files below the root, by their path relative to it.
+    File,
+    /// The bundled frontend, by the path below `base_path`.
+    Static,
+}
+
+/// One `pattern=Name: value` entry of `MLIST_PATH_HEADERS`, such as
+/// `fonts/*=Access-Control-Allow-Origin: *` or, for the frontend,
+/// `static:assets/*=Cache-Control: max-age=31536000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderRule {
+    target: HeaderTarget,
+    /// Glob over the path, as for hide patterns.
+    pattern: String,
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+impl FromStr for HeaderRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid header rule {value:?}, expected pattern=Name: value");
+        let (pattern, header) = value.split_once('=').ok_or_else(invalid)?;
+        let (name, header_value) = header.split_once(':').ok_or_else(invalid)?;
+        let pattern = pattern.trim();
+        let (target, pattern) = match pattern.strip_prefix("static:") {
+            Some(pattern) => (HeaderTarget::Static, pattern),
+            None => (HeaderTarget::File, pattern),
+        };
+        if pattern.is_empty() {
+            return Err(invalid());
+        }
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid())?;
+        if RESERVED.contains(&name) {
+            return Err(format!("header rule {value:?} may not set {name}"));
+        }
+        let value = HeaderValue::from_str(header_value.trim()).map_err(|_| invalid())?;
+        Ok(Self {
+            target,
+            pattern: pattern.to_string(),
+            name,
+            value,
+        })
+    }
+}
+
+/// Sets the headers of every rule for `target` matching `path`, in order.
+/// The first matching rule for a header replaces whatever the handler sent;
+/// later ones add further values.
+pub fn apply_path_headers(
+    rules: &[HeaderRule],
+    target: HeaderTarget,
+    path: &str,
+    headers: &mut HeaderMap,
+) {
+    let mut replaced = HashSet::new();
+    for rule in rules
+        .iter()
+        .filter(|rule| rule.target == target && glob_matches(&rule.pattern, path))
+    {
+        if replaced.insert(&rule.name) {
+            headers.insert(rule.name.clone(), rule.value.clone());
+        } else {
+            headers.append(rule.name.clone(), rule.value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue, header};
+
+    use super::{HeaderRule, HeaderTarget, apply_path_headers};
+
+    fn rules(raw: &[&str]) -> Vec<HeaderRule> {
+        raw.iter().map(|rule| rule.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn matching_rules_replace_then_add() {
+        let rules = rules(&[
+            "site/*=Content-Security-Policy: default-src 'self'; img-src *",
+            "site/*.html=X-Robots-Tag: noindex",
+            "*=X-Robots-Tag: nofollow",
+            "static:*=X-Robots-Tag: none",
+        ]);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static("sandbox"),
+        );
+        apply_path_headers(&rules, HeaderTarget::File, "site/a.html", &mut headers);
+        assert_eq!(
+            headers[header::CONTENT_SECURITY_POLICY],
+            "default-src 'self'; img-src *"
+        );
+        let robots = headers.get_all("x-robots-tag").iter().collect::<Vec<_>>();
+        assert_eq!(robots, vec!["noindex", "nofollow"]);
+
+        let mut headers = HeaderMap::new();
+        apply_path_headers(&rules, HeaderTarget::Static, "index.html", &mut headers);
+        assert_eq!(headers["x-robots-tag"], "none");
+    }
+
+    #[test]
+    fn rules_are_validated() {
+        assert!("fonts/*".parse::<HeaderRule>().is_err());
+        assert!(
+            "fonts/*=Access-Control-Allow-Origin"
+                .parse::<HeaderRule>()
+                .is_err()
+        );
+        assert!("=X-Robots-Tag: noindex".parse::<HeaderRule>().is_err());
+        assert!(
+            "static:=X-Robots-Tag: noindex"
+                .parse::<HeaderRule>()
+                .is_err()
+        );
+        assert!("*=Content-Length: 1".parse::<HeaderRule>().is_err());
+        assert!("*=Bad Name: 1".parse::<HeaderRule>().is_err());
+    }
+}
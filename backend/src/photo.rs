@@ -0,0 +1,272 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::ops::Range;
+use std::path::Path;
+
+use exif::{Exif, In, Tag, Value};
+use serde::Serialize;
+
+/// APP1 segments starting with this carry XMP, which can repeat the EXIF
+/// position and camera fields.
+const XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const EXIF_SIGNATURE: &[u8] = b"Exif\0\0";
+
+/// What a gallery shows about a photo, taken from its EXIF block. Every
+/// field is optional; cameras and editors vary in what they write.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhotoInfo {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// EXIF orientation, 1 to 8; viewers rotate by it before display.
+    pub orientation: Option<u32>,
+    /// Local capture time as `YYYY-MM-DDTHH:MM:SS`, with the offset
+    /// appended when the camera recorded one.
+    pub taken_at: Option<String>,
+    pub exposure_time: Option<String>,
+    pub f_number: Option<String>,
+    pub iso: Option<u32>,
+    pub focal_length: Option<String>,
+    pub gps: Option<GpsPosition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Metres above sea level.
+    pub altitude: Option<f64>,
+}
+
+/// Reads the EXIF block of a JPEG, TIFF, PNG, WebP or HEIF image. `None`
+/// when the file has none or it cannot be parsed. Blocking.
+pub fn read_photo_info(path: &Path, include_gps: bool) -> io::Result<Option<PhotoInfo>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let exif = match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => exif,
+        Err(exif::Error::Io(err)) => return Err(err),
+        Err(_) => return Ok(None),
+    };
+    let display = |tag: Tag| {
+        exif.get_field(tag, In::PRIMARY)
+            .map(|field| field.display_value().with_unit(&exif).to_string())
+    };
+    Ok(Some(PhotoInfo {
+        make: ascii(&exif, Tag::Make),
+        model: ascii(&exif, Tag::Model),
+        lens: ascii(&exif, Tag::LensModel),
+        orientation: uint(&exif, Tag::Orientation),
+        taken_at: ascii(&exif, Tag::DateTimeOriginal)
+            .or_else(|| ascii(&exif, Tag::DateTime))
+            .and_then(|raw| exif_datetime(&raw))
+            .map(|local| {
+                let offset = ascii(&exif, Tag::OffsetTimeOriginal).unwrap_or_default();
+                format!("{local}{offset}")
+            }),
+        exposure_time: display(Tag::ExposureTime),
+        f_number: display(Tag::FNumber),
+        iso: uint(&exif, Tag::PhotographicSensitivity),
+        focal_length: display(Tag::FocalLength),
+        gps: if include_gps { gps(&exif) } else { None },
+    }))
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => {
+            let text = String::from_utf8_lossy(values.first()?);
+            let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            (!text.is_empty()).then(|| text.to_string())
+        }
+        _ => None,
+    }
+}
+
+fn uint(exif: &Exif, tag: Tag) -> Option<u32> {
+    exif.get_field(tag, In::PRIMARY)?.value.get_uint(0)
+}
+
+fn gps(exif: &Exif) -> Option<GpsPosition> {
+    let coordinate = |tag: Tag, reference: Tag, negative: &str| {
+        let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        let [degrees, minutes, seconds] = parts.get(..3)? else {
+            return None;
+        };
+        let value = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+        let sign = if ascii(exif, reference).as_deref() == Some(negative) {
+            -1.0
+        } else {
+            1.0
+        };
+        value.is_finite().then_some(sign * value)
+    };
+    let altitude = exif
+        .get_field(Tag::GPSAltitude, In::PRIMARY)
+        .and_then(|field| match &field.value {
+            Value::Rational(parts) => parts.first().map(|value| value.to_f64()),
+            _ => None,
+        })
+        // Reference 1 means the altitude is below sea level.
+        .map(|value| {
+            if uint(exif, Tag::GPSAltitudeRef) == Some(1) {
+                -value
+            } else {
+                value
+            }
+        });
+    Some(GpsPosition {
+        latitude: coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S")?,
+        longitude: coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W")?,
+        altitude: altitude.filter(|value| value.is_finite()),
+    })
+}
+
+/// `2023:05:01 12:34:56` to `2023-05-01T12:34:56`. Cameras without a set
+/// clock write blanks or zeros, which give `None`.
+fn exif_datetime(raw: &str) -> Option<String> {
+    let bytes = raw.as_bytes();
+    let well_formed = bytes.len() == 19
+        && bytes.iter().enumerate().all(|(index, byte)| match index {
+            4 | 7 | 13 | 16 => *byte == b':',
+            10 => *byte == b' ',
+            _ => byte.is_ascii_digit(),
+        });
+    if !well_formed || raw.starts_with("0000") {
+        return None;
+    }
+    Some(format!(
+        "{}-{}-{}T{}",
+        &raw[..4],
+        &raw[5..7],
+        &raw[8..10],
+        &raw[11..]
+    ))
+}
+
+/// Byte ranges of the EXIF and XMP segments in a JPEG's header, so the
+/// file can be served without them. `None` when the file is not a JPEG
+/// whose header can be walked. Blocking.
+pub fn jpeg_metadata_segments(path: &Path) -> io::Result<Option<Vec<Range<u64>>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    match walk_jpeg_header(&mut reader) {
+        Ok(segments) => Ok(Some(segments)),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+            ) =>
+        {
+            Ok(None)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn walk_jpeg_header(reader: &mut BufReader<File>) -> io::Result<Vec<Range<u64>>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not a JPEG");
+    let mut byte = [0u8; 1];
+    let mut soi = [0u8; 2];
+    reader.read_exact(&mut soi)?;
+    if soi != [0xFF, 0xD8] {
+        return Err(invalid());
+    }
+    let mut segments = Vec::new();
+    let mut offset = 2_u64;
+    loop {
+        let start = offset;
+        reader.read_exact(&mut byte)?;
+        if byte[0] != 0xFF {
+            return Err(invalid());
+        }
+        // Any number of 0xFF fill bytes may precede the marker code.
+        let marker = loop {
+            reader.read_exact(&mut byte)?;
+            offset += 1;
+            if byte[0] != 0xFF {
+                break byte[0];
+            }
+        };
+        offset += 1;
+        match marker {
+            // Start of scan or end of image: no metadata segments follow.
+            0xDA | 0xD9 => return Ok(segments),
+            0x01 | 0xD0..=0xD7 => continue,
+            _ => {}
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length)?;
+        let length = u64::from(u16::from_be_bytes(length));
+        if length < 2 {
+            return Err(invalid());
+        }
+        let mut remaining = length - 2;
+        if marker == 0xE1 {
+            let mut signature = [0u8; XMP_SIGNATURE.len()];
+            let peek = remaining.min(signature.len() as u64) as usize;
+            reader.read_exact(&mut signature[..peek])?;
+            remaining -= peek as u64;
+            let signature = &signature[..peek];
+            if signature.starts_with(EXIF_SIGNATURE) || signature == XMP_SIGNATURE {
+                segments.push(start..offset + length);
+            }
+        }
+        reader.seek_relative(remaining as i64)?;
+        offset += length;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{exif_datetime, jpeg_metadata_segments};
+
+    fn segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0xFF, marker];
+        bytes.extend(((payload.len() + 2) as u16).to_be_bytes());
+        bytes.extend(payload);
+        bytes
+    }
+
+    #[test]
+    fn exif_and_xmp_segments_are_found_before_the_scan() {
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend(segment(0xE0, b"JFIF\0\x01\x02"));
+        let exif_start = jpeg.len() as u64;
+        jpeg.extend(segment(0xE1, b"Exif\0\0MM\0*"));
+        let xmp_start = jpeg.len() as u64;
+        let mut xmp = b"http://ns.adobe.com/xap/1.0/\0".to_vec();
+        xmp.extend(b"<x:xmpmeta/>");
+        jpeg.extend(segment(0xE1, &xmp));
+        let xmp_end = jpeg.len() as u64;
+        jpeg.extend(segment(0xE1, b"other"));
+        jpeg.extend(segment(0xDB, &[0; 4]));
+        jpeg.extend(segment(0xDA, &[0; 6]));
+        jpeg.extend([0xFF, 0xE1, 0x00, 0x00, 0xFF, 0xD9]);
+
+        let path =
+            std::env::temp_dir().join(format!("mlist-photo-{}.jpg", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&path, &jpeg).unwrap();
+        let segments = jpeg_metadata_segments(&path).unwrap().unwrap();
+        assert_eq!(
+            segments,
+            vec![exif_start..exif_start + 14, xmp_start..xmp_end]
+        );
+
+        std::fs::write(&path, b"\x89PNG\r\n").unwrap();
+        assert_eq!(jpeg_metadata_segments(&path).unwrap(), None);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn exif_datetimes_become_iso() {
+        assert_eq!(
+            exif_datetime("2023:05:01 12:34:56").as_deref(),
+            Some("2023-05-01T12:34:56")
+        );
+        assert_eq!(exif_datetime("    :  :     :  :  "), None);
+        assert_eq!(exif_datetime("0000:00:00 00:00:00"), None);
+    }
+}
@@ -0,0 +1,129 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+
+/// Leading bytes inspected when deciding whether a file is text.
+const SNIFF_BYTES: usize = 8 * 1024;
+
+/// MIME top-level types that are never previewed, whatever their bytes
+/// look like.
+const BINARY_TOP_LEVEL_TYPES: [&str; 4] = ["image", "audio", "video", "font"];
+
+/// The decoded head of a text file.
+#[derive(Debug)]
+pub struct TextPreview {
+    /// WHATWG name of the charset the bytes were decoded from.
+    pub encoding: &'static str,
+    pub content: String,
+    pub lines: usize,
+    /// Set when the file goes on past `content`.
+    pub truncated: bool,
+}
+
+/// Decides from the guessed MIME type and the first bytes of a file whether
+/// it is worth previewing. Unknown types (`.nfo`, `.log`, ...) count as text
+/// as long as they contain no NUL bytes and hardly any control characters.
+pub fn is_text_like(mime: &str, head: &[u8]) -> bool {
+    let top_level = mime.split('/').next().unwrap_or_default();
+    if BINARY_TOP_LEVEL_TYPES.contains(&top_level) {
+        return false;
+    }
+    // UTF-16 text is full of NULs, so a BOM settles it.
+    if Encoding::for_bom(head).is_some() {
+        return true;
+    }
+    let sample = &head[..head.len().min(SNIFF_BYTES)];
+    let controls = sample
+        .iter()
+        .filter(|&&byte| byte < 0x20 && !matches!(byte, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    !sample.contains(&0) && controls * 100 <= sample.len()
+}
+
+/// Decodes `head`, the first bytes of a file, to UTF-8 and keeps at most
+/// `max_lines` lines. `more` says whether the file continues past `head`;
+/// the line cut there is dropped, since it may end inside a character.
+pub fn decode_preview(head: &[u8], max_lines: usize, more: bool) -> TextPreview {
+    let (text, encoding, _) = detect_encoding(head, more).decode(head);
+    let mut content = String::new();
+    let mut lines = 0;
+    let mut cut = false;
+    for line in text.split_inclusive('\n') {
+        if lines == max_lines || (more && lines > 0 && !line.ends_with('\n')) {
+            cut = true;
+            break;
+        }
+        content.push_str(line);
+        lines += 1;
+    }
+    TextPreview {
+        encoding: encoding.name(),
+        content,
+        lines,
+        truncated: more || cut,
+    }
+}
+
+/// A byte order mark wins, then valid UTF-8; anything else is left to
+/// statistical detection, which covers the legacy code pages NFO files and
+/// subtitles tend to come in.
+fn detect_encoding(head: &[u8], more: bool) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(head) {
+        return encoding;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => return UTF_8,
+        // Only the last character was cut off by the byte cap.
+        Err(err) if more && err.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(head, !more);
+    detector.guess(None, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_preview, is_text_like};
+
+    #[test]
+    fn binary_files_are_not_text() {
+        assert!(is_text_like("text/plain", b"hello\r\n\tworld\n"));
+        assert!(is_text_like("application/octet-stream", b"release.nfo"));
+        assert!(is_text_like("text/plain", b"\xff\xfeh\0i\0"));
+        assert!(!is_text_like("application/octet-stream", b"PK\x03\x04\0\0"));
+        assert!(!is_text_like("image/svg+xml", b"<svg/>"));
+    }
+
+    #[test]
+    fn previews_are_cut_at_whole_lines() {
+        let preview = decode_preview(b"one\ntwo\nthree\n", 2, false);
+        assert_eq!(preview.content, "one\ntwo\n");
+        assert_eq!(preview.lines, 2);
+        assert!(preview.truncated);
+
+        let preview = decode_preview(b"one\ntwo\nthr", 10, true);
+        assert_eq!(preview.content, "one\ntwo\n");
+        assert!(preview.truncated);
+
+        let preview = decode_preview(b"one\ntwo", 10, false);
+        assert_eq!(preview.content, "one\ntwo");
+        assert!(!preview.truncated);
+    }
+
+    #[test]
+    fn legacy_charsets_are_transcoded() {
+        let preview = decode_preview("caf\u{e9}\n".as_bytes(), 10, false);
+        assert_eq!(preview.encoding, "UTF-8");
+
+        let preview = decode_preview(b"\xff\xfeh\0i\0", 10, false);
+        assert_eq!(preview.encoding, "UTF-16LE");
+        assert_eq!(preview.content, "hi");
+
+        // "你好，世界。这是一个测试文件。" in GBK.
+        let gbk = b"\xc4\xe3\xba\xc3\xa3\xac\xca\xc0\xbd\xe7\xa1\xa3\xd5\xe2\xca\xc7\
+                    \xd2\xbb\xb8\xf6\xb2\xe2\xca\xd4\xce\xc4\xbc\xfe\xa1\xa3";
+        let preview = decode_preview(gbk, 10, false);
+        assert_eq!(preview.encoding, "GBK");
+        assert_eq!(preview.content, "你好，世界。这是一个测试文件。");
+    }
+}
@@ -0,0 +1,134 @@
+use tracing::{error, info, warn};
+
+use crate::config::AppConfig;
+use crate::handlers::AppState;
+
+/// Reads the configuration again the way it was first loaded and applies
+/// it. Settings baked into long-lived components at startup (listener,
+/// database, caches, worker pools) keep their old values and are reported
+/// as needing a restart; everything handlers read per request, such as
+/// hide patterns, CSP and TTLs, takes effect for the next request. Login,
+/// request-rate, bandwidth and quota limits apply to attempts and transfers
+/// started afterwards.
+pub fn reload(state: &AppState) -> Result<(), String> {
+    let current = state.config.latest();
+    let mut next = AppConfig::load(&current.overrides)?;
+
+    let kept = keep_startup_settings(&current, &mut next);
+    if !kept.is_empty() {
+        warn!("restart required to apply changes to: {}", kept.join(", "));
+    }
+
+    if (next.login_max_failures, next.login_block_seconds)
+        != (current.login_max_failures, current.login_block_seconds)
+    {
+        state
+            .login_limiter
+            .set_limits(next.login_max_failures, next.login_block_seconds);
+    }
+    if (next.max_bytes_per_sec, next.max_bytes_per_sec_per_ip)
+        != (current.max_bytes_per_sec, current.max_bytes_per_sec_per_ip)
+    {
+        state
+            .bandwidth
+            .set_rates(next.max_bytes_per_sec, next.max_bytes_per_sec_per_ip);
+    }
+    let quota_limits = |config: &AppConfig| {
+        (
+            config.download_quota_per_user,
+            config.download_quota_per_scope,
+            config.download_quota_window_seconds,
+        )
+    };
+    if quota_limits(&next) != quota_limits(&current) {
+        let (per_user, per_scope, window_seconds) = quota_limits(&next);
+        state
+            .download_quotas
+            .set_limits(per_user, per_scope, window_seconds);
+    }
+    if (next.api_rate_limit, next.file_rate_limit)
+        != (current.api_rate_limit, current.file_rate_limit)
+    {
+        state
+            .request_limiter
+            .set_rates(next.api_rate_limit, next.file_rate_limit);
+    }
+    state.config.store(next);
+    info!("configuration reloaded");
+    Ok(())
+}
+
+/// Reloads on every SIGHUP until shutdown. A configuration that fails to
+/// load or validate is logged and the running one stays in place.
+#[cfg(unix)]
+pub fn spawn_on_sighup(state: AppState) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(stream) => stream,
+        Err(err) => {
+            warn!("failed to listen for SIGHUP, configuration reload disabled: {err}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                received = hangups.recv() => {
+                    if received.is_none() {
+                        return;
+                    }
+                }
+                () = state.shutdown.cancelled() => return,
+            }
+            if let Err(err) = reload(&state) {
+                error!("configuration reload failed, keeping the current one: {err}");
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_on_sighup(_state: AppState) {}
+
+fn keep_startup_settings(current: &AppConfig, next: &mut AppConfig) -> Vec<&'static str> {
+    let mut kept = Vec::new();
+    macro_rules! keep {
+        ($($field:ident),+ $(,)?) => {
+            $(
+                if next.$field != current.$field {
+                    kept.push(stringify!($field));
+                    next.$field = current.$field.clone();
+                }
+            )+
+        };
+    }
+    keep!(
+        root_dir,
+        database_path,
+        bind_addr,
+        base_path,
+        socket_mode,
+        tls_cert_path,
+        tls_key_path,
+        upload_max_bytes,
+        upload_staging_dir,
+        dir_size_cache_ttl_seconds,
+        index_enabled,
+        thumb_cache_dir,
+        transcode_dir,
+        max_transcodes,
+        transcode_idle_seconds,
+        max_concurrent_downloads_per_ip,
+        max_concurrent_downloads_per_scope,
+        search_content_concurrency,
+        audit_log_path,
+        shutdown_drain_seconds,
+        json_body_limit_bytes,
+        header_timeout_seconds,
+        read_timeout_seconds,
+        write_timeout_seconds,
+        log_format,
+    );
+    kept
+}
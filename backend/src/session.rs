@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use time::OffsetDateTime;
@@ -11,8 +12,8 @@ pub const REFRESH_COOKIE_NAME: &str = "mlist_refresh";
 #[derive(Debug, Clone)]
 pub struct LoginRateLimiter {
     inner: Arc<RwLock<HashMap<String, LoginAttempt>>>,
-    max_failures: u32,
-    block_seconds: u64,
+    max_failures: Arc<AtomicU32>,
+    block_seconds: Arc<AtomicU64>,
 }
 
 #[derive(Debug, Clone)]
@@ -25,11 +26,18 @@ impl LoginRateLimiter {
     pub fn new(max_failures: u32, block_seconds: u64) -> Self {
         Self {
             inner: Arc::new(RwLock::new(HashMap::new())),
-            max_failures,
-            block_seconds,
+            max_failures: Arc::new(AtomicU32::new(max_failures)),
+            block_seconds: Arc::new(AtomicU64::new(block_seconds)),
         }
     }
 
+    /// Changes the limits for failures recorded from now on; existing
+    /// blocks keep their expiry.
+    pub fn set_limits(&self, max_failures: u32, block_seconds: u64) {
+        self.max_failures.store(max_failures, Ordering::Relaxed);
+        self.block_seconds.store(block_seconds, Ordering::Relaxed);
+    }
+
     pub async fn blocked_until(&self, key: &str, now: u64) -> Option<u64> {
         let mut attempts = self.inner.write().await;
         let entry = attempts.get_mut(key)?;
@@ -60,8 +68,8 @@ impl LoginRateLimiter {
         }
 
         entry.failures = entry.failures.saturating_add(1);
-        if entry.failures >= self.max_failures {
-            let until = now.saturating_add(self.block_seconds);
+        if entry.failures >= self.max_failures.load(Ordering::Relaxed) {
+            let until = now.saturating_add(self.block_seconds.load(Ordering::Relaxed));
             entry.blocked_until = Some(until);
             entry.failures = 0;
             return Some(until);
@@ -74,6 +82,33 @@ impl LoginRateLimiter {
         let mut attempts = self.inner.write().await;
         attempts.remove(key);
     }
+
+    /// Keys (`ip:username`) currently locked out, with their unblock time.
+    pub async fn blocked(&self, now: u64) -> Vec<(String, u64)> {
+        let attempts = self.inner.read().await;
+        let mut blocked = attempts
+            .iter()
+            .filter_map(|(key, attempt)| {
+                attempt
+                    .blocked_until
+                    .filter(|until| *until > now)
+                    .map(|until| (key.clone(), until))
+            })
+            .collect::<Vec<_>>();
+        blocked.sort();
+        blocked
+    }
+
+    /// Forgets every failure and block; returns how many keys were blocked.
+    pub async fn clear(&self, now: u64) -> usize {
+        let mut attempts = self.inner.write().await;
+        let blocked = attempts
+            .values()
+            .filter(|attempt| attempt.blocked_until.is_some_and(|until| until > now))
+            .count();
+        attempts.clear();
+        blocked
+    }
 }
 
 pub fn now_unix() -> u64 {
@@ -0,0 +1,258 @@
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use axum::body::Bytes;
+use futures_core::Stream;
+use tokio::sync::mpsc;
+
+const BLOCK: u64 = 512;
+const CHUNK_BYTES: usize = 64 * 1024;
+/// Chunks buffered between the blocking writer and the response body.
+const CHANNEL_CHUNKS: usize = 8;
+/// Largest size the 11 octal digits of a ustar header can hold; bigger
+/// files carry their size in a PAX record.
+const MAX_USTAR_SIZE: u64 = 0o77_777_777_777;
+
+/// One entry of a tar stream. Directories have no `source`.
+#[derive(Debug, Clone)]
+pub struct TarMember {
+    pub name: String,
+    pub source: Option<PathBuf>,
+    pub size: u64,
+    pub mtime: u64,
+}
+
+impl TarMember {
+    fn is_dir(&self) -> bool {
+        self.source.is_none()
+    }
+
+    fn header_name(&self) -> String {
+        if self.is_dir() {
+            format!("{}/", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// Exact length of the archive [`stream_tar`] writes for `members`, so it
+/// can be sent as `Content-Length`.
+pub fn tar_len(members: &[TarMember]) -> u64 {
+    let entries = members
+        .iter()
+        .map(|member| {
+            let pax = pax_records(member)
+                .map(|records| BLOCK + padded(records.len() as u64))
+                .unwrap_or(0);
+            pax + BLOCK + padded(member.size)
+        })
+        .sum::<u64>();
+    entries + 2 * BLOCK
+}
+
+/// Writes `members` as an uncompressed tar on a blocking thread and yields
+/// it in chunks. A file that shrank since it was listed ends the stream
+/// with an error instead of producing a corrupt archive; one that grew is
+/// cut at its listed size. Writing stops as soon as the stream is dropped.
+pub fn stream_tar(members: Vec<TarMember>) -> TarStream {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = write_tar(&members, &sender) {
+            let _ = sender.blocking_send(Err(err));
+        }
+    });
+    TarStream { receiver }
+}
+
+pub struct TarStream {
+    receiver: mpsc::Receiver<std::io::Result<Bytes>>,
+}
+
+impl Stream for TarStream {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+fn write_tar(
+    members: &[TarMember],
+    sender: &mpsc::Sender<std::io::Result<Bytes>>,
+) -> std::io::Result<()> {
+    let send = |bytes: Vec<u8>| sender.blocking_send(Ok(Bytes::from(bytes))).is_ok();
+    for member in members {
+        if !send(member_header(member)) {
+            return Ok(());
+        }
+        let Some(source) = &member.source else {
+            continue;
+        };
+        let mut file = File::open(source)?.take(member.size);
+        let mut remaining = member.size;
+        let mut buffer = vec![0u8; CHUNK_BYTES];
+        while remaining > 0 {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("{} shrank while being archived", source.display()),
+                ));
+            }
+            remaining -= read as u64;
+            if !send(buffer[..read].to_vec()) {
+                return Ok(());
+            }
+        }
+        let padding = padded(member.size) - member.size;
+        if padding > 0 && !send(vec![0; padding as usize]) {
+            return Ok(());
+        }
+    }
+    send(vec![0; 2 * BLOCK as usize]);
+    Ok(())
+}
+
+/// The PAX extended header, if any, followed by the ustar header.
+fn member_header(member: &TarMember) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if let Some(records) = pax_records(member) {
+        let len = records.len() as u64;
+        bytes.extend(ustar_header("PaxHeader", len, member.mtime, b'x'));
+        bytes.extend(records);
+        bytes.resize(bytes.len() + (padded(len) - len) as usize, 0);
+    }
+    let (kind, size) = if member.is_dir() {
+        (b'5', 0)
+    } else {
+        (b'0', member.size)
+    };
+    bytes.extend(ustar_header(
+        &member.header_name(),
+        size.min(MAX_USTAR_SIZE),
+        member.mtime,
+        kind,
+    ));
+    bytes
+}
+
+/// Names that do not fit the 100-byte ustar field and sizes beyond its
+/// octal range go into a PAX record, which every current tar reads.
+fn pax_records(member: &TarMember) -> Option<Vec<u8>> {
+    let name = member.header_name();
+    let mut records = Vec::new();
+    if name.len() > 100 {
+        records.extend(pax_record("path", &name));
+    }
+    if member.size > MAX_USTAR_SIZE {
+        records.extend(pax_record("size", &member.size.to_string()));
+    }
+    (!records.is_empty()).then_some(records)
+}
+
+/// `"<len> <key>=<value>\n"`, where `len` counts the whole record
+/// including its own digits.
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let body = key.len() + value.len() + 3;
+    let mut len = body + 1;
+    while len != body + len.to_string().len() {
+        len = body + len.to_string().len();
+    }
+    format!("{len} {key}={value}\n").into_bytes()
+}
+
+fn ustar_header(name: &str, size: u64, mtime: u64, kind: u8) -> [u8; BLOCK as usize] {
+    let mut header = [0u8; BLOCK as usize];
+    let name = name.as_bytes();
+    let name = &name[..floor_char_boundary(name, 100)];
+    header[..name.len()].copy_from_slice(name);
+    let mode = if kind == b'5' { 0o755 } else { 0o644 };
+    write_octal(&mut header[100..108], mode);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], mtime.min(0o77_777_777_777));
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|byte| u64::from(*byte)).sum::<u64>();
+    write_octal(&mut header[148..155], checksum);
+    header
+}
+
+/// Zero-padded octal digits followed by a NUL, filling `field`.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+/// Cuts a truncated name at a UTF-8 boundary; the full name is in the PAX
+/// record anyway.
+fn floor_char_boundary(bytes: &[u8], max: usize) -> usize {
+    if bytes.len() <= max {
+        return bytes.len();
+    }
+    (0..=max)
+        .rev()
+        .find(|index| bytes.get(*index).is_none_or(|byte| (*byte as i8) >= -0x40))
+        .unwrap_or(0)
+}
+
+fn padded(len: u64) -> u64 {
+    len.div_ceil(BLOCK) * BLOCK
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BLOCK, TarMember, member_header, pax_record, tar_len, ustar_header};
+
+    #[test]
+    fn headers_carry_a_valid_checksum() {
+        let header = ustar_header("films/a.mkv", 1234, 1_700_000_000, b'0');
+        assert_eq!(&header[..11], b"films/a.mkv");
+        assert_eq!(&header[124..136], b"00000002322\0");
+        assert_eq!(&header[257..263], b"ustar\0");
+        let stored = std::str::from_utf8(&header[148..154]).unwrap();
+        let mut blank = header;
+        blank[148..156].fill(b' ');
+        let sum = blank.iter().map(|byte| u64::from(*byte)).sum::<u64>();
+        assert_eq!(u64::from_str_radix(stored, 8).unwrap(), sum);
+    }
+
+    #[test]
+    fn pax_record_length_counts_itself() {
+        assert_eq!(pax_record("path", "a"), b"9 path=a\n");
+        let long = "x".repeat(95);
+        let record = pax_record("path", &long);
+        assert_eq!(record.len(), 105);
+        assert!(record.starts_with(b"105 path="));
+    }
+
+    #[test]
+    fn long_names_get_a_pax_header_and_lengths_add_up() {
+        let members = vec![
+            TarMember {
+                name: "dir".to_string(),
+                source: None,
+                size: 0,
+                mtime: 0,
+            },
+            TarMember {
+                name: format!("dir/{}", "长".repeat(40)),
+                source: Some("/dev/null".into()),
+                size: 10,
+                mtime: 0,
+            },
+        ];
+        assert_eq!(member_header(&members[0]).len() as u64, BLOCK);
+        assert_eq!(member_header(&members[1]).len() as u64, 3 * BLOCK);
+        assert_eq!(tar_len(&members), BLOCK + 3 * BLOCK + BLOCK + 2 * BLOCK);
+    }
+}
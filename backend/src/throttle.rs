@@ -0,0 +1,793 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use futures_core::Stream;
+use tokio::time::{Instant, Sleep};
+
+use crate::session::now_unix;
+
+/// How far a bucket may run ahead of its rate, so short bursts (the first
+/// chunks of a seek, a player refilling its buffer) are not delayed.
+const BURST: Duration = Duration::from_millis(500);
+
+/// Idle request buckets are swept once this many clients are tracked.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Download bandwidth caps shared by every streaming response. The global
+/// bucket is drained by all transfers together; per-IP buckets are shared
+/// by concurrent transfers from the same client and dropped once the last
+/// of them finishes.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    rates: Arc<Mutex<Rates>>,
+    per_ip: Arc<Mutex<HashMap<IpAddr, Weak<TokenBucket>>>>,
+}
+
+#[derive(Debug)]
+struct Rates {
+    global: Option<Arc<TokenBucket>>,
+    per_ip: Option<u64>,
+}
+
+impl Rates {
+    fn new(max_bytes_per_sec: Option<u64>, max_bytes_per_sec_per_ip: Option<u64>) -> Self {
+        Self {
+            global: max_bytes_per_sec.map(|rate| Arc::new(TokenBucket::new(rate))),
+            per_ip: max_bytes_per_sec_per_ip,
+        }
+    }
+}
+
+impl BandwidthLimiter {
+    pub fn new(max_bytes_per_sec: Option<u64>, max_bytes_per_sec_per_ip: Option<u64>) -> Self {
+        Self {
+            rates: Arc::new(Mutex::new(Rates::new(
+                max_bytes_per_sec,
+                max_bytes_per_sec_per_ip,
+            ))),
+            per_ip: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Replaces the caps for transfers started from now on. Running
+    /// transfers keep the buckets they started with, so nothing in flight
+    /// is interrupted.
+    pub fn set_rates(&self, max_bytes_per_sec: Option<u64>, max_bytes_per_sec_per_ip: Option<u64>) {
+        *self
+            .rates
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) =
+            Rates::new(max_bytes_per_sec, max_bytes_per_sec_per_ip);
+        self.per_ip
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+
+    /// Wraps `inner` so that it yields no faster than the limits that apply
+    /// to `client_ip`. Without any configured limit the stream is passed
+    /// through untouched apart from the wrapper.
+    pub fn throttle<S>(&self, client_ip: IpAddr, inner: S) -> ThrottledStream<S> {
+        let (global, per_ip_rate) = {
+            let rates = self
+                .rates
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            (rates.global.clone(), rates.per_ip)
+        };
+        let mut buckets = Vec::with_capacity(2);
+        if let Some(global) = global {
+            buckets.push(global);
+        }
+        if let Some(rate) = per_ip_rate {
+            buckets.push(self.bucket_for_ip(client_ip, rate));
+        }
+        ThrottledStream {
+            inner,
+            buckets,
+            pending: None,
+            sleep: None,
+        }
+    }
+
+    fn bucket_for_ip(&self, ip: IpAddr, rate: u64) -> Arc<TokenBucket> {
+        let mut buckets = self
+            .per_ip
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(bucket) = buckets.get(&ip).and_then(Weak::upgrade) {
+            return bucket;
+        }
+        buckets.retain(|_, bucket| bucket.strong_count() > 0);
+        let bucket = Arc::new(TokenBucket::new(rate));
+        buckets.insert(ip, Arc::downgrade(&bucket));
+        bucket
+    }
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    bytes_per_sec: u64,
+    /// Instant at which everything reserved so far has been paid for.
+    next_free: Mutex<Instant>,
+}
+
+impl TokenBucket {
+    fn new(bytes_per_sec: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            next_free: Mutex::new(now.checked_sub(BURST).unwrap_or(now)),
+        }
+    }
+
+    /// Books `len` bytes and returns how long the caller must wait before
+    /// sending them.
+    fn reserve(&self, len: usize) -> Duration {
+        let cost = Duration::from_secs_f64(len as f64 / self.bytes_per_sec as f64);
+        let now = Instant::now();
+        let floor = now.checked_sub(BURST).unwrap_or(now);
+        let mut next_free = self
+            .next_free
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *next_free = (*next_free).max(floor) + cost;
+        next_free.saturating_duration_since(now)
+    }
+}
+
+/// A byte stream that holds each chunk back until every bucket it draws
+/// from has capacity for it.
+pub struct ThrottledStream<S> {
+    inner: S,
+    buckets: Vec<Arc<TokenBucket>>,
+    pending: Option<Bytes>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> Stream for ThrottledStream<S>
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
+{
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(sleep) = self.sleep.as_mut() {
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.sleep = None;
+            if let Some(bytes) = self.pending.take() {
+                return Poll::Ready(Some(Ok(bytes)));
+            }
+        }
+
+        match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(bytes))) => {
+                let delay = self
+                    .buckets
+                    .iter()
+                    .map(|bucket| bucket.reserve(bytes.len()))
+                    .max()
+                    .unwrap_or_default();
+                if delay.is_zero() {
+                    return Poll::Ready(Some(Ok(bytes)));
+                }
+                let mut sleep = Box::pin(tokio::time::sleep(delay));
+                if sleep.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(Some(Ok(bytes)));
+                }
+                self.pending = Some(bytes);
+                self.sleep = Some(sleep);
+                Poll::Pending
+            }
+            other => other,
+        }
+    }
+}
+
+/// The request budget a path draws from. Listings and other API calls are
+/// counted separately from `/d/` downloads, so a player fetching ranges
+/// does not use up the allowance for browsing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestClass {
+    Api,
+    Files,
+}
+
+impl RequestClass {
+    pub fn of(path: &str) -> Option<Self> {
+        if path == "/api" || path.starts_with("/api/") {
+            Some(Self::Api)
+        } else if path.starts_with("/d/") || path.starts_with("/seed/") {
+            Some(Self::Files)
+        } else {
+            None
+        }
+    }
+}
+
+/// A sustained request rate with room for `burst` requests at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestRate {
+    pub per_sec: u32,
+    pub burst: u32,
+}
+
+/// Per-IP request rate limits, one token bucket per client and class.
+#[derive(Debug, Clone)]
+pub struct RequestRateLimiter {
+    inner: Arc<Mutex<RequestBuckets>>,
+}
+
+#[derive(Debug)]
+struct RequestBuckets {
+    api: Option<RequestRate>,
+    files: Option<RequestRate>,
+    buckets: HashMap<(RequestClass, IpAddr), RequestBucket>,
+}
+
+#[derive(Debug)]
+struct RequestBucket {
+    tokens: f64,
+    updated: Instant,
+    /// The last request was turned away.
+    limited: bool,
+}
+
+/// A request turned away by [`RequestRateLimiter::check`].
+#[derive(Debug, Clone, Copy)]
+pub struct Throttled {
+    /// How long until the next request would be admitted.
+    pub retry_after: Duration,
+    /// Set on the first rejection since the client was last admitted, so a
+    /// burst can be reported once rather than per request.
+    pub first: bool,
+}
+
+impl RequestBucket {
+    fn refill(&mut self, rate: RequestRate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * f64::from(rate.per_sec)).min(f64::from(rate.burst));
+        self.updated = now;
+    }
+}
+
+impl RequestBuckets {
+    fn rate(&self, class: RequestClass) -> Option<RequestRate> {
+        match class {
+            RequestClass::Api => self.api,
+            RequestClass::Files => self.files,
+        }
+        .filter(|rate| rate.per_sec > 0)
+        .map(|rate| RequestRate {
+            per_sec: rate.per_sec,
+            burst: rate.burst.max(1),
+        })
+    }
+}
+
+impl RequestRateLimiter {
+    pub fn new(api: Option<RequestRate>, files: Option<RequestRate>) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(RequestBuckets {
+                api,
+                files,
+                buckets: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Replaces both limits; every client starts again with a full bucket.
+    pub fn set_rates(&self, api: Option<RequestRate>, files: Option<RequestRate>) {
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.api = api;
+        inner.files = files;
+        inner.buckets.clear();
+    }
+
+    /// Takes one request from `client_ip`'s bucket for `class`, or reports
+    /// when the next one would be admitted if it is empty.
+    pub fn check(&self, class: RequestClass, client_ip: IpAddr) -> Result<(), Throttled> {
+        let now = Instant::now();
+        let mut inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(rate) = inner.rate(class) else {
+            return Ok(());
+        };
+
+        if inner.buckets.len() >= MAX_TRACKED_CLIENTS {
+            let api = inner.rate(RequestClass::Api);
+            let files = inner.rate(RequestClass::Files);
+            inner.buckets.retain(|(class, _), bucket| {
+                let rate = match class {
+                    RequestClass::Api => api,
+                    RequestClass::Files => files,
+                };
+                rate.is_some_and(|rate| {
+                    bucket.refill(rate, now);
+                    bucket.tokens < f64::from(rate.burst)
+                })
+            });
+        }
+
+        let bucket = inner
+            .buckets
+            .entry((class, client_ip))
+            .or_insert(RequestBucket {
+                tokens: f64::from(rate.burst),
+                updated: now,
+                limited: false,
+            });
+        bucket.refill(rate, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.limited = false;
+            return Ok(());
+        }
+        let first = !std::mem::replace(&mut bucket.limited, true);
+        Err(Throttled {
+            retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / f64::from(rate.per_sec)),
+            first,
+        })
+    }
+}
+
+/// Caps on simultaneous streaming responses, counted per client IP and per
+/// private scope. Download managers that open dozens of parallel segments
+/// are turned away with 429 instead of starving everyone else.
+#[derive(Debug, Clone)]
+pub struct DownloadSlots {
+    per_ip_cap: Option<u32>,
+    per_scope_cap: Option<u32>,
+    active: Arc<Mutex<ActiveDownloads>>,
+}
+
+#[derive(Debug, Default)]
+struct ActiveDownloads {
+    by_ip: HashMap<IpAddr, u32>,
+    by_scope: HashMap<String, u32>,
+}
+
+impl DownloadSlots {
+    pub fn new(per_ip_cap: Option<u32>, per_scope_cap: Option<u32>) -> Self {
+        Self {
+            per_ip_cap,
+            per_scope_cap,
+            active: Arc::new(Mutex::new(ActiveDownloads::default())),
+        }
+    }
+
+    /// Claims a slot for a transfer to `client_ip`, optionally inside the
+    /// private scope `scope`. Returns `None` when either cap is reached; the
+    /// slot is released when the returned permit is dropped.
+    pub fn try_acquire(&self, client_ip: IpAddr, scope: Option<&str>) -> Option<DownloadPermit> {
+        self.try_acquire_all(client_ip, scope.as_slice())
+    }
+
+    /// Like [`DownloadSlots::try_acquire`] for a transfer that draws on
+    /// several private scopes at once, taking a slot in each of them.
+    pub fn try_acquire_all(&self, client_ip: IpAddr, scopes: &[&str]) -> Option<DownloadPermit> {
+        let mut active = self
+            .active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let ip_count = active.by_ip.get(&client_ip).copied().unwrap_or(0);
+        if self.per_ip_cap.is_some_and(|cap| ip_count >= cap) {
+            return None;
+        }
+        let mut scopes = match self.per_scope_cap {
+            Some(_) => scopes.iter().map(|scope| scope.to_string()).collect(),
+            None => Vec::new(),
+        };
+        scopes.sort();
+        scopes.dedup();
+        let full = scopes.iter().any(|scope| {
+            let scope_count = active.by_scope.get(scope).copied().unwrap_or(0);
+            self.per_scope_cap.is_some_and(|cap| scope_count >= cap)
+        });
+        if full {
+            return None;
+        }
+        for scope in &scopes {
+            *active.by_scope.entry(scope.clone()).or_default() += 1;
+        }
+        *active.by_ip.entry(client_ip).or_default() += 1;
+
+        Some(DownloadPermit {
+            active: Arc::clone(&self.active),
+            client_ip,
+            scopes,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DownloadPermit {
+    active: Arc<Mutex<ActiveDownloads>>,
+    client_ip: IpAddr,
+    scopes: Vec<String>,
+}
+
+impl DownloadPermit {
+    /// Ties the permit to `inner`, so the slot stays taken for as long as the
+    /// response body is alive.
+    pub fn attach<S>(self, inner: S) -> PermitStream<S> {
+        PermitStream {
+            inner,
+            _permit: self,
+        }
+    }
+}
+
+impl Drop for DownloadPermit {
+    fn drop(&mut self) {
+        let mut active = self
+            .active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        release(&mut active.by_ip, &self.client_ip);
+        for scope in &self.scopes {
+            release(&mut active.by_scope, scope);
+        }
+    }
+}
+
+fn release<K, Q>(counts: &mut HashMap<K, u32>, key: &Q)
+where
+    K: std::borrow::Borrow<Q> + std::hash::Hash + Eq,
+    Q: std::hash::Hash + Eq + ?Sized,
+{
+    if let Some(count) = counts.get_mut(key) {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            counts.remove(key);
+        }
+    }
+}
+
+pub struct PermitStream<S> {
+    inner: S,
+    _permit: DownloadPermit,
+}
+
+impl<S> Stream for PermitStream<S>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+/// Byte allowances per user and per private scope over a fixed window,
+/// which for each of them starts with its first counted download. Bytes are
+/// counted as they are sent, so an aborted transfer only costs what went
+/// out; a transfer admitted below the quota may finish past it.
+#[derive(Debug, Clone)]
+pub struct DownloadQuotas {
+    inner: Arc<Mutex<Quotas>>,
+}
+
+#[derive(Debug)]
+struct Quotas {
+    per_user: Option<u64>,
+    per_scope: Option<u64>,
+    window_seconds: u64,
+    usage: HashMap<QuotaKey, QuotaUsage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum QuotaKey {
+    User(i64),
+    Scope(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct QuotaUsage {
+    bytes: u64,
+    /// Unix time at which `bytes` starts over from zero.
+    resets_at: u64,
+}
+
+impl Quotas {
+    fn limit(&self, key: &QuotaKey) -> Option<u64> {
+        match key {
+            QuotaKey::User(_) => self.per_user,
+            QuotaKey::Scope(_) => self.per_scope,
+        }
+    }
+
+    fn charge(&mut self, keys: &[QuotaKey], bytes: u64, now: u64) {
+        let window_seconds = self.window_seconds;
+        for key in keys {
+            let usage = self.usage.entry(key.clone()).or_insert(QuotaUsage {
+                bytes: 0,
+                resets_at: now + window_seconds,
+            });
+            if usage.resets_at <= now {
+                *usage = QuotaUsage {
+                    bytes: 0,
+                    resets_at: now + window_seconds,
+                };
+            }
+            usage.bytes = usage.bytes.saturating_add(bytes);
+        }
+    }
+}
+
+impl DownloadQuotas {
+    pub fn new(per_user: Option<u64>, per_scope: Option<u64>, window_seconds: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Quotas {
+                per_user,
+                per_scope,
+                window_seconds,
+                usage: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Replaces the allowances. Usage counted so far, and the windows it
+    /// falls in, carry over.
+    pub fn set_limits(&self, per_user: Option<u64>, per_scope: Option<u64>, window_seconds: u64) {
+        let mut quotas = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        quotas.per_user = per_user;
+        quotas.per_scope = per_scope;
+        quotas.window_seconds = window_seconds;
+    }
+
+    /// Admits a transfer for `user_id`, optionally inside the private scope
+    /// `scope`, unless either has used up its allowance. The error is the
+    /// Unix time at which downloading becomes possible again.
+    pub fn check(&self, user_id: i64, scope: Option<&str>, now: u64) -> Result<QuotaMeter, u64> {
+        self.check_all(user_id, scope.as_slice(), now)
+    }
+
+    /// Like [`DownloadQuotas::check`] for a transfer that draws on several
+    /// private scopes at once; every byte is charged to each of them.
+    pub fn check_all(&self, user_id: i64, scopes: &[&str], now: u64) -> Result<QuotaMeter, u64> {
+        let mut quotas = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        quotas.usage.retain(|_, usage| usage.resets_at > now);
+        let mut keys = Vec::new();
+        let scoped = scopes
+            .iter()
+            .map(|scope| QuotaKey::Scope(scope.to_string()));
+        for key in std::iter::once(QuotaKey::User(user_id)).chain(scoped) {
+            if quotas.limit(&key).is_some() && !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        let exhausted_until = keys
+            .iter()
+            .filter_map(|key| {
+                let usage = quotas.usage.get(key)?;
+                let limit = quotas.limit(key)?;
+                (usage.bytes >= limit).then_some(usage.resets_at)
+            })
+            .max();
+        match exhausted_until {
+            Some(resets_at) => Err(resets_at),
+            None => Ok(QuotaMeter {
+                quotas: Arc::clone(&self.inner),
+                keys,
+            }),
+        }
+    }
+}
+
+/// Charges the bytes of one admitted transfer to the quotas it falls under.
+#[derive(Debug)]
+pub struct QuotaMeter {
+    quotas: Arc<Mutex<Quotas>>,
+    keys: Vec<QuotaKey>,
+}
+
+impl QuotaMeter {
+    pub fn attach<S>(self, inner: S) -> MeteredStream<S> {
+        MeteredStream { inner, meter: self }
+    }
+
+    fn charge(&self, bytes: u64, now: u64) {
+        if self.keys.is_empty() {
+            return;
+        }
+        self.quotas
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .charge(&self.keys, bytes, now);
+    }
+}
+
+pub struct MeteredStream<S> {
+    inner: S,
+    meter: QuotaMeter,
+}
+
+impl<S> Stream for MeteredStream<S>
+where
+    S: Stream<Item = std::io::Result<Bytes>> + Unpin,
+{
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let polled = Pin::new(&mut self.inner).poll_next(cx);
+        if let Poll::Ready(Some(Ok(bytes))) = &polled {
+            self.meter.charge(bytes.len() as u64, now_unix());
+        }
+        polled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    use axum::body::Bytes;
+
+    use super::{
+        BandwidthLimiter, DownloadQuotas, DownloadSlots, RequestClass, RequestRate,
+        RequestRateLimiter, TokenBucket,
+    };
+
+    #[test]
+    fn bucket_allows_burst_then_paces() {
+        let bucket = TokenBucket::new(1000);
+        assert_eq!(bucket.reserve(400), Duration::ZERO);
+        let delay = bucket.reserve(1000);
+        assert!(delay > Duration::from_millis(800), "{delay:?}");
+        assert!(delay <= Duration::from_millis(1000), "{delay:?}");
+    }
+
+    #[test]
+    fn per_ip_buckets_are_shared_and_released() {
+        let limiter = BandwidthLimiter::new(None, Some(1000));
+        let ip: IpAddr = "203.0.113.9".parse().unwrap();
+        let first = limiter.bucket_for_ip(ip, 1000);
+        let second = limiter.bucket_for_ip(ip, 1000);
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+
+        drop((first, second));
+        let other: IpAddr = "203.0.113.10".parse().unwrap();
+        let _bucket = limiter.bucket_for_ip(other, 1000);
+        assert_eq!(limiter.per_ip.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn throttled_stream_delays_chunks() {
+        let limiter = BandwidthLimiter::new(Some(10_000), None);
+        let chunks = (0..4).map(|_| Ok::<_, std::io::Error>(Bytes::from(vec![0u8; 2000])));
+        let mut stream = limiter.throttle(
+            "127.0.0.1".parse().unwrap(),
+            futures_util::stream::iter(chunks),
+        );
+
+        let started = tokio::time::Instant::now();
+        let mut total = 0;
+        while let Some(chunk) = futures_util::StreamExt::next(&mut stream).await {
+            total += chunk.unwrap().len();
+        }
+        // 8000 bytes at 10 kB/s, less the half-second burst allowance.
+        assert_eq!(total, 8000);
+        assert!(started.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn download_slots_cap_ip_and_scope() {
+        let slots = DownloadSlots::new(Some(2), Some(1));
+        let ip: IpAddr = "203.0.113.9".parse().unwrap();
+        let other: IpAddr = "203.0.113.10".parse().unwrap();
+
+        let first = slots.try_acquire(ip, None).unwrap();
+        let _second = slots.try_acquire(ip, None).unwrap();
+        assert!(slots.try_acquire(ip, None).is_none());
+        drop(first);
+        let _third = slots.try_acquire(ip, None).unwrap();
+
+        let scoped = slots.try_acquire(other, Some("private")).unwrap();
+        assert!(slots.try_acquire(other, Some("private")).is_none());
+        assert!(slots.try_acquire(other, Some("other")).is_some());
+        drop(scoped);
+        assert!(slots.try_acquire(other, Some("private")).is_some());
+
+        let third: IpAddr = "203.0.113.11".parse().unwrap();
+        let both = slots.try_acquire_all(third, &["a", "b", "a"]).unwrap();
+        assert!(slots.try_acquire(third, Some("b")).is_none());
+        assert!(slots.try_acquire_all(third, &["c", "a"]).is_none());
+        drop(both);
+        assert!(slots.try_acquire_all(third, &["a", "b"]).is_some());
+    }
+
+    #[test]
+    fn download_quotas_block_until_the_window_resets() {
+        let quotas = DownloadQuotas::new(Some(100), Some(150), 60);
+        let now = 1_000;
+
+        let meter = quotas.check(1, Some("private"), now).unwrap();
+        meter.charge(60, now);
+        meter.charge(40, now + 1);
+        assert_eq!(quotas.check(1, None, now + 2).unwrap_err(), now + 60);
+
+        // The scope still has room for another user, until it runs out too.
+        quotas
+            .check(2, Some("private"), now + 2)
+            .unwrap()
+            .charge(50, now + 2);
+        assert_eq!(
+            quotas.check(3, Some("private"), now + 3).unwrap_err(),
+            now + 60
+        );
+        assert!(quotas.check(3, Some("other"), now + 3).is_ok());
+        // A transfer spanning scopes is refused if any of them is spent,
+        // and charges all of them.
+        assert!(quotas.check_all(4, &["other", "private"], now + 3).is_err());
+        quotas
+            .check_all(4, &["other", "third"], now + 3)
+            .unwrap()
+            .charge(150, now + 3);
+        assert!(quotas.check(5, Some("third"), now + 4).is_err());
+        assert!(quotas.check(5, Some("other"), now + 4).is_err());
+
+        assert!(quotas.check(1, Some("private"), now + 60).is_ok());
+        quotas.set_limits(None, None, 60);
+        assert!(quotas.check(1, None, now + 3).is_ok());
+    }
+
+    #[test]
+    fn request_limiter_allows_burst_per_ip_and_class() {
+        let rate = RequestRate {
+            per_sec: 1,
+            burst: 2,
+        };
+        let limiter = RequestRateLimiter::new(Some(rate), None);
+        let ip: IpAddr = "203.0.113.9".parse().unwrap();
+        let other: IpAddr = "203.0.113.10".parse().unwrap();
+
+        assert!(limiter.check(RequestClass::Api, ip).is_ok());
+        assert!(limiter.check(RequestClass::Api, ip).is_ok());
+        let throttled = limiter.check(RequestClass::Api, ip).unwrap_err();
+        assert!(
+            throttled.retry_after <= Duration::from_secs(1),
+            "{throttled:?}"
+        );
+        assert!(throttled.first);
+        assert!(!limiter.check(RequestClass::Api, ip).unwrap_err().first);
+        assert!(limiter.check(RequestClass::Api, other).is_ok());
+        assert!(limiter.check(RequestClass::Files, ip).is_ok());
+
+        limiter.set_rates(None, None);
+        assert!(limiter.check(RequestClass::Api, ip).is_ok());
+    }
+
+    #[test]
+    fn request_classes_follow_route_prefixes() {
+        assert_eq!(RequestClass::of("/api/list"), Some(RequestClass::Api));
+        assert_eq!(RequestClass::of("/api"), Some(RequestClass::Api));
+        assert_eq!(RequestClass::of("/d/movie.mkv"), Some(RequestClass::Files));
+        assert_eq!(
+            RequestClass::of("/seed/abc/movie.mkv"),
+            Some(RequestClass::Files)
+        );
+        assert_eq!(RequestClass::of("/apidocs"), None);
+        assert_eq!(RequestClass::of("/dav/"), None);
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, UNIX_EPOCH};
+
+use image::DynamicImage;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::errors::{ApiError, ApiResult};
+
+pub const DEFAULT_THUMB_EDGE: u32 = 256;
+pub const MIN_THUMB_EDGE: u32 = 16;
+pub const MAX_THUMB_EDGE: u32 = 1024;
+pub const MAX_RESIZE_EDGE: u32 = 4096;
+const JPEG_QUALITY: u8 = 80;
+/// Seek offset for the video keyframe; most files have a title card or
+/// black frame at zero.
+const VIDEO_SEEK_SECONDS: &str = "5";
+const FFMPEG_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbSource {
+    Image,
+    Video,
+}
+
+impl ThumbSource {
+    pub fn for_mime(mime: &str) -> Option<Self> {
+        if mime.starts_with("image/") {
+            Some(Self::Image)
+        } else if mime.starts_with("video/") {
+            Some(Self::Video)
+        } else {
+            None
+        }
+    }
+}
+
+pub struct ThumbRequest<'a> {
+    pub source: &'a Path,
+    pub kind: ThumbSource,
+    pub metadata: &'a std::fs::Metadata,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Returns JPEG bytes for a thumbnail of `request.source` that fits inside
+/// `width`x`height`, generating and caching it under `cache_dir` on first
+/// use. Cache entries are keyed by source path, size, mtime and box, so an
+/// edited file never serves a stale preview. Video thumbnails need `ffmpeg`.
+pub async fn load_or_generate(
+    cache_dir: &Path,
+    ffmpeg: Option<&Path>,
+    request: ThumbRequest<'_>,
+) -> ApiResult<Vec<u8>> {
+    let key = cache_key(
+        request.source,
+        request.metadata,
+        request.width,
+        request.height,
+        None,
+    );
+    let cache_path = cache_dir.join(format!("{key}.jpg"));
+    if let Some(bytes) = read_cached(&cache_path).await? {
+        return Ok(bytes);
+    }
+
+    let temp_path = temp_cache_path(cache_dir).await?;
+    let generated = match request.kind {
+        ThumbSource::Image => {
+            generate_image(request.source, &temp_path, request.width, request.height).await
+        }
+        ThumbSource::Video => {
+            let Some(ffmpeg) = ffmpeg else {
+                return Err(ApiError::not_found("Video thumbnails are not enabled."));
+            };
+            generate_video(
+                ffmpeg,
+                request.source,
+                &temp_path,
+                request.width,
+                request.height,
+            )
+            .await
+        }
+    };
+    publish(&temp_path, &cache_path, generated).await
+}
+
+/// Encoding for resized images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeFormat {
+    Jpeg,
+    Webp,
+    Png,
+}
+
+impl ResizeFormat {
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Jpeg => "image/jpeg",
+            Self::Webp => "image/webp",
+            Self::Png => "image/png",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Webp => "webp",
+            Self::Png => "png",
+        }
+    }
+}
+
+impl TryFrom<&str> for ResizeFormat {
+    type Error = ApiError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(Self::Jpeg),
+            "webp" => Ok(Self::Webp),
+            "png" => Ok(Self::Png),
+            _ => Err(ApiError::bad_request(
+                "Unsupported image format; use jpeg, webp or png.",
+            )),
+        }
+    }
+}
+
+pub struct ResizeRequest<'a> {
+    pub source: &'a Path,
+    pub metadata: &'a std::fs::Metadata,
+    pub width: u32,
+    pub height: u32,
+    pub format: ResizeFormat,
+}
+
+/// Returns `request.source` scaled down to fit inside `width`x`height` and
+/// re-encoded as `format`, cached under `cache_dir` like thumbnails. Unlike
+/// thumbnails this uses a high-quality filter and never upscales, so it is
+/// meant for full-screen viewing rather than grids.
+pub async fn load_or_resize(cache_dir: &Path, request: ResizeRequest<'_>) -> ApiResult<Vec<u8>> {
+    let key = cache_key(
+        request.source,
+        request.metadata,
+        request.width,
+        request.height,
+        Some(request.format.extension()),
+    );
+    let cache_path = cache_dir.join(format!("{key}.{}", request.format.extension()));
+    if let Some(bytes) = read_cached(&cache_path).await? {
+        return Ok(bytes);
+    }
+
+    let temp_path = temp_cache_path(cache_dir).await?;
+    let generated = resize_image(
+        request.source,
+        &temp_path,
+        request.width,
+        request.height,
+        request.format,
+    )
+    .await;
+    publish(&temp_path, &cache_path, generated).await
+}
+
+async fn read_cached(cache_path: &Path) -> ApiResult<Option<Vec<u8>>> {
+    match fs::read(cache_path).await {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(ApiError::from_io(err, "thumbnail cache")),
+    }
+}
+
+async fn temp_cache_path(cache_dir: &Path) -> ApiResult<PathBuf> {
+    fs::create_dir_all(cache_dir)
+        .await
+        .map_err(|err| ApiError::from_io(err, "thumbnail cache"))?;
+    Ok(cache_dir.join(format!(".{}.tmp", uuid::Uuid::new_v4().simple())))
+}
+
+/// Moves a freshly written cache file into place, or cleans it up when
+/// generation failed.
+async fn publish(
+    temp_path: &Path,
+    cache_path: &Path,
+    generated: ApiResult<()>,
+) -> ApiResult<Vec<u8>> {
+    if let Err(err) = generated {
+        let _ = fs::remove_file(temp_path).await;
+        return Err(err);
+    }
+
+    fs::rename(temp_path, cache_path)
+        .await
+        .map_err(|err| ApiError::from_io(err, "thumbnail cache"))?;
+    fs::read(cache_path)
+        .await
+        .map_err(|err| ApiError::from_io(err, "thumbnail cache"))
+}
+
+fn cache_key(
+    source: &Path,
+    metadata: &std::fs::Metadata,
+    width: u32,
+    height: u32,
+    variant: Option<&str>,
+) -> String {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|value| value.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(source.as_os_str().as_encoded_bytes());
+    hasher.update(metadata.len().to_le_bytes());
+    hasher.update(mtime.as_secs().to_le_bytes());
+    hasher.update(mtime.subsec_nanos().to_le_bytes());
+    hasher.update(width.to_le_bytes());
+    hasher.update(height.to_le_bytes());
+    if let Some(variant) = variant {
+        hasher.update(b"resize:");
+        hasher.update(variant.as_bytes());
+    }
+
+    let mut key = String::with_capacity(64);
+    for byte in hasher.finalize() {
+        let _ = write!(key, "{byte:02x}");
+    }
+    key
+}
+
+async fn generate_image(source: &Path, target: &Path, width: u32, height: u32) -> ApiResult<()> {
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let decoded = image::ImageReader::open(&source)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|err| ApiError::from_io(err, "image"))?
+            .decode()
+            .map_err(|_| ApiError::bad_request("Image could not be decoded."))?;
+        let resized = decoded.thumbnail(width, height).into_rgb8();
+
+        let file = std::fs::File::create(&target)
+            .map_err(|err| ApiError::from_io(err, "thumbnail cache"))?;
+        let mut writer = std::io::BufWriter::new(file);
+        JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY)
+            .encode_image(&resized)
+            .map_err(|_| ApiError::internal("Failed to encode thumbnail."))?;
+        writer
+            .flush()
+            .map_err(|err| ApiError::from_io(err, "thumbnail cache"))
+    })
+    .await
+    .map_err(|_| ApiError::internal("Thumbnail worker failed."))?
+}
+
+async fn resize_image(
+    source: &Path,
+    target: &Path,
+    width: u32,
+    height: u32,
+    format: ResizeFormat,
+) -> ApiResult<()> {
+    let source = source.to_path_buf();
+    let target = target.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let decoded = image::ImageReader::open(&source)
+            .and_then(|reader| reader.with_guessed_format())
+            .map_err(|err| ApiError::from_io(err, "image"))?
+            .decode()
+            .map_err(|_| ApiError::bad_request("Image could not be decoded."))?;
+        let resized = if decoded.width() <= width && decoded.height() <= height {
+            decoded
+        } else {
+            decoded.resize(width, height, FilterType::Lanczos3)
+        };
+
+        let file = std::fs::File::create(&target)
+            .map_err(|err| ApiError::from_io(err, "thumbnail cache"))?;
+        let mut writer = std::io::BufWriter::new(file);
+        let encoded = match format {
+            ResizeFormat::Jpeg => JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY)
+                .encode_image(&resized.into_rgb8()),
+            // The webp codec only encodes losslessly.
+            ResizeFormat::Webp => DynamicImage::ImageRgba8(resized.into_rgba8())
+                .write_with_encoder(WebPEncoder::new_lossless(&mut writer)),
+            ResizeFormat::Png => DynamicImage::ImageRgba8(resized.into_rgba8())
+                .write_with_encoder(PngEncoder::new(&mut writer)),
+        };
+        encoded.map_err(|_| ApiError::internal("Failed to encode image."))?;
+        writer
+            .flush()
+            .map_err(|err| ApiError::from_io(err, "thumbnail cache"))
+    })
+    .await
+    .map_err(|_| ApiError::internal("Image worker failed."))?
+}
+
+async fn generate_video(
+    ffmpeg: &Path,
+    source: &Path,
+    target: &Path,
+    width: u32,
+    height: u32,
+) -> ApiResult<()> {
+    let scale = format!("scale={width}:{height}:force_original_aspect_ratio=decrease");
+    let child = Command::new(ffmpeg)
+        .arg("-nostdin")
+        .args(["-loglevel", "error", "-y"])
+        .args(["-ss", VIDEO_SEEK_SECONDS])
+        .arg("-i")
+        .arg(source)
+        .args(["-frames:v", "1", "-vf", &scale])
+        .args(["-f", "image2", "-c:v", "mjpeg"])
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| {
+            warn!("failed to spawn ffmpeg {}: {err}", ffmpeg.display());
+            ApiError::internal("Failed to start ffmpeg.")
+        })?;
+
+    let output = tokio::time::timeout(FFMPEG_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| ApiError::internal("ffmpeg timed out."))?
+        .map_err(|_| ApiError::internal("Failed to run ffmpeg."))?;
+    if !output.status.success() {
+        warn!(
+            "ffmpeg failed for {}: {}",
+            source.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Err(ApiError::bad_request("Video frame could not be extracted."));
+    }
+
+    // Older ffmpeg builds exit 0 without writing when the seek lands past the
+    // end of a short clip.
+    let written = fs::metadata(target)
+        .await
+        .map(|metadata| metadata.len() > 0)
+        .unwrap_or(false);
+    if !written {
+        return Err(ApiError::bad_request("Video frame could not be extracted."));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use axum_server::tls_rustls::RustlsConfig;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// How often the certificate files are checked for changes. Renewal tools
+/// such as certbot rewrite them in place, so polling is enough.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+pub async fn load(cert_path: &Path, key_path: &Path) -> Result<RustlsConfig, String> {
+    RustlsConfig::from_pem_file(cert_path, key_path)
+        .await
+        .map_err(|err| {
+            format!(
+                "failed to load TLS certificate {} / key {}: {err}",
+                cert_path.display(),
+                key_path.display()
+            )
+        })
+}
+
+/// Reloads `config` whenever either file's modification time changes,
+/// until `shutdown` is cancelled. New connections pick up the new
+/// certificate; established ones keep the one they negotiated. A broken
+/// pair is logged and the previous certificate stays in use.
+pub fn spawn_reloader(
+    config: RustlsConfig,
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    shutdown: CancellationToken,
+) {
+    tokio::spawn(async move {
+        let mut seen = modified(&cert_path, &key_path).await;
+        loop {
+            tokio::select! {
+                () = tokio::time::sleep(RELOAD_INTERVAL) => {}
+                () = shutdown.cancelled() => return,
+            }
+            let current = modified(&cert_path, &key_path).await;
+            if current == seen {
+                continue;
+            }
+            match config.reload_from_pem_file(&cert_path, &key_path).await {
+                Ok(()) => {
+                    info!("reloaded TLS certificate {}", cert_path.display());
+                    seen = current;
+                }
+                // Renewal may have written only one of the two files so
+                // far; `seen` stays put so the next tick retries.
+                Err(err) => warn!("failed to reload TLS certificate: {err}"),
+            }
+        }
+    });
+}
+
+async fn modified(cert_path: &Path, key_path: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    (mtime(cert_path).await, mtime(key_path).await)
+}
+
+async fn mtime(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use sha1::{Digest, Sha1};
+use tokio::sync::Semaphore;
+use tracing::warn;
+
+/// Torrents hashed at the same time; later requests queue behind them.
+const MAX_CONCURRENT_JOBS: usize = 2;
+/// Once the cache holds this many torrents the finished ones are dropped
+/// before adding another; rehashing is slow but always correct.
+const MAX_CACHED_TORRENTS: usize = 1_024;
+const MIN_PIECE_LENGTH: u64 = 256 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+/// Pieces a torrent aims for before its piece length is rounded to a power
+/// of two and clamped.
+const TARGET_PIECES: u64 = 1_500;
+const READ_BUFFER_BYTES: usize = 1024 * 1024;
+
+/// One file of a torrent, in the order it is hashed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentFile {
+    /// Components of the path below the torrent's directory; unused for a
+    /// single-file torrent.
+    pub path: Vec<String>,
+    pub source: PathBuf,
+    pub size: u64,
+    pub mtime: u64,
+}
+
+/// A single file, or the files below a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentSource {
+    /// Name of the file or directory.
+    pub name: String,
+    pub files: Vec<TorrentFile>,
+    /// Directories use the multi-file layout even when they hold one file.
+    pub is_dir: bool,
+}
+
+impl TorrentSource {
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+pub enum TorrentStatus {
+    /// The bencoded `info` dictionary.
+    Ready(Arc<Vec<u8>>),
+    Hashing {
+        hashed: u64,
+        total: u64,
+    },
+    Failed(String),
+}
+
+/// `info` dictionaries keyed by the torrent's resolved root. An entry only
+/// counts while every file's size and mtime still match what was hashed,
+/// so a changed release is rehashed on the next request.
+#[derive(Debug, Clone)]
+pub struct TorrentCache {
+    jobs: Arc<Mutex<HashMap<PathBuf, Arc<TorrentJob>>>>,
+    slots: Arc<Semaphore>,
+}
+
+#[derive(Debug)]
+struct TorrentJob {
+    source: TorrentSource,
+    hashed: AtomicU64,
+    result: OnceLock<Result<Arc<Vec<u8>>, String>>,
+}
+
+impl Default for TorrentCache {
+    fn default() -> Self {
+        Self {
+            jobs: Arc::default(),
+            slots: Arc::new(Semaphore::new(MAX_CONCURRENT_JOBS)),
+        }
+    }
+}
+
+impl TorrentCache {
+    /// Returns the finished `info` dictionary for `source`, or reports the
+    /// progress of hashing it, starting a background job if none is
+    /// running. A failed job is reported once; the next request retries.
+    pub fn request(&self, key: PathBuf, source: TorrentSource) -> TorrentStatus {
+        let mut jobs = self.jobs.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(job) = jobs.get(&key).filter(|job| job.source == source) {
+            match job.result.get() {
+                Some(Ok(info)) => return TorrentStatus::Ready(Arc::clone(info)),
+                Some(Err(message)) => {
+                    let message = message.clone();
+                    jobs.remove(&key);
+                    return TorrentStatus::Failed(message);
+                }
+                None => {
+                    return TorrentStatus::Hashing {
+                        hashed: job.hashed.load(Ordering::Relaxed),
+                        total: job.source.total_size(),
+                    };
+                }
+            }
+        }
+
+        if jobs.len() >= MAX_CACHED_TORRENTS {
+            jobs.retain(|_, job| job.result.get().is_none());
+        }
+        let total = source.total_size();
+        let job = Arc::new(TorrentJob {
+            source,
+            hashed: AtomicU64::new(0),
+            result: OnceLock::new(),
+        });
+        // A job replaced here because the files changed runs to completion
+        // unobserved.
+        jobs.insert(key, Arc::clone(&job));
+        drop(jobs);
+
+        let slots = Arc::clone(&self.slots);
+        tokio::spawn(async move {
+            let Ok(_permit) = slots.acquire().await else {
+                return;
+            };
+            let worker = Arc::clone(&job);
+            let result = tokio::task::spawn_blocking(move || {
+                let piece_length = piece_length(worker.source.total_size());
+                build_info(&worker.source, piece_length, &worker.hashed)
+            })
+            .await;
+            let result = match result {
+                Ok(Ok(info)) => Ok(Arc::new(info)),
+                Ok(Err(err)) => Err(err.to_string()),
+                Err(_) => Err("hashing was interrupted".to_string()),
+            };
+            if let Err(message) = &result {
+                warn!(name = job.source.name, "torrent hashing failed: {message}");
+            }
+            let _ = job.result.set(result);
+        });
+        TorrentStatus::Hashing { hashed: 0, total }
+    }
+}
+
+/// Wraps a finished `info` dictionary into a `.torrent` whose BEP 19
+/// `url-list` points at `web_seed`. There is no tracker; clients find
+/// peers through DHT and fetch from the web seed.
+pub fn encode_torrent(info: &[u8], web_seed: &str, created_at: u64) -> Vec<u8> {
+    let mut out = vec![b'd'];
+    bytes(&mut out, b"created by");
+    bytes(&mut out, b"mlist");
+    bytes(&mut out, b"creation date");
+    int(&mut out, created_at);
+    bytes(&mut out, b"info");
+    out.extend_from_slice(info);
+    bytes(&mut out, b"url-list");
+    bytes(&mut out, web_seed.as_bytes());
+    out.push(b'e');
+    out
+}
+
+/// The smallest power of two that keeps the torrent near `TARGET_PIECES`
+/// pieces, within the range clients handle well.
+fn piece_length(total: u64) -> u64 {
+    (total / TARGET_PIECES)
+        .next_power_of_two()
+        .clamp(MIN_PIECE_LENGTH, MAX_PIECE_LENGTH)
+}
+
+/// Hashes the files back to back, as BitTorrent treats them as one stream,
+/// and returns the bencoded `info` dictionary. `hashed` counts the bytes
+/// read so far.
+fn build_info(
+    source: &TorrentSource,
+    piece_length: u64,
+    hashed: &AtomicU64,
+) -> std::io::Result<Vec<u8>> {
+    let mut pieces = Vec::new();
+    let mut hasher = Sha1::new();
+    let mut in_piece = 0_u64;
+    let mut buffer = vec![0u8; READ_BUFFER_BYTES];
+    for file in &source.files {
+        let mut reader = File::open(&file.source)?.take(file.size);
+        let mut remaining = file.size;
+        while remaining > 0 {
+            let want = (buffer.len() as u64)
+                .min(remaining)
+                .min(piece_length - in_piece) as usize;
+            let read = reader.read(&mut buffer[..want])?;
+            if read == 0 {
+                return Err(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("{} shrank while being hashed", file.source.display()),
+                ));
+            }
+            hasher.update(&buffer[..read]);
+            in_piece += read as u64;
+            remaining -= read as u64;
+            hashed.fetch_add(read as u64, Ordering::Relaxed);
+            if in_piece == piece_length {
+                pieces.extend(hasher.finalize_reset());
+                in_piece = 0;
+            }
+        }
+    }
+    if in_piece > 0 {
+        pieces.extend(hasher.finalize_reset());
+    }
+    Ok(encode_info(source, piece_length, &pieces))
+}
+
+/// Keys are written in the sorted order bencoding requires.
+fn encode_info(source: &TorrentSource, piece_length: u64, pieces: &[u8]) -> Vec<u8> {
+    let mut out = vec![b'd'];
+    if source.is_dir {
+        bytes(&mut out, b"files");
+        out.push(b'l');
+        for file in &source.files {
+            out.push(b'd');
+            bytes(&mut out, b"length");
+            int(&mut out, file.size);
+            bytes(&mut out, b"path");
+            out.push(b'l');
+            for part in &file.path {
+                bytes(&mut out, part.as_bytes());
+            }
+            out.extend_from_slice(b"ee");
+        }
+        out.push(b'e');
+    } else {
+        bytes(&mut out, b"length");
+        int(&mut out, source.total_size());
+    }
+    bytes(&mut out, b"name");
+    bytes(&mut out, source.name.as_bytes());
+    bytes(&mut out, b"piece length");
+    int(&mut out, piece_length);
+    bytes(&mut out, b"pieces");
+    bytes(&mut out, pieces);
+    out.push(b'e');
+    out
+}
+
+fn bytes(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(format!("{}:", value.len()).as_bytes());
+    out.extend_from_slice(value);
+}
+
+fn int(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(format!("i{value}e").as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use sha1::{Digest, Sha1};
+
+    use super::{
+        MAX_PIECE_LENGTH, MIN_PIECE_LENGTH, TorrentFile, TorrentSource, build_info, piece_length,
+    };
+
+    fn file(dir: &std::path::Path, name: &str, content: &[u8]) -> TorrentFile {
+        let source = dir.join(name);
+        std::fs::write(&source, content).unwrap();
+        TorrentFile {
+            path: vec![name.to_string()],
+            source,
+            size: content.len() as u64,
+            mtime: 0,
+        }
+    }
+
+    #[test]
+    fn pieces_run_across_file_boundaries() {
+        let dir =
+            std::env::temp_dir().join(format!("mlist-torrent-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let single = TorrentSource {
+            name: "a.txt".to_string(),
+            files: vec![file(&dir, "a.txt", b"hello")],
+            is_dir: false,
+        };
+        let hashed = AtomicU64::new(0);
+        let info = build_info(&single, MIN_PIECE_LENGTH, &hashed).unwrap();
+        let mut expected = b"d6:lengthi5e4:name5:a.txt12:piece lengthi262144e6:pieces20:".to_vec();
+        expected.extend(Sha1::digest(b"hello"));
+        expected.push(b'e');
+        assert_eq!(info, expected);
+        assert_eq!(hashed.load(Ordering::Relaxed), 5);
+
+        let release = TorrentSource {
+            name: "release".to_string(),
+            files: vec![file(&dir, "ab", b"ab"), file(&dir, "cdef", b"cdef")],
+            is_dir: true,
+        };
+        let info = build_info(&release, 4, &AtomicU64::new(0)).unwrap();
+        let mut expected =
+            b"d5:filesld6:lengthi2e4:pathl2:abeed6:lengthi4e4:pathl4:cdefeee".to_vec();
+        expected.extend(b"4:name7:release12:piece lengthi4e6:pieces40:");
+        expected.extend(Sha1::digest(b"abcd"));
+        expected.extend(Sha1::digest(b"ef"));
+        expected.push(b'e');
+        assert_eq!(info, expected);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn piece_length_stays_in_range() {
+        assert_eq!(piece_length(0), MIN_PIECE_LENGTH);
+        assert_eq!(piece_length(4 * 1024 * 1024 * 1024), 4 * 1024 * 1024);
+        assert_eq!(piece_length(u64::MAX / 2), MAX_PIECE_LENGTH);
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::errors::{ApiError, ApiResult};
+use crate::session::now_unix;
+
+const PLAYLIST_NAME: &str = "index.m3u8";
+const SEGMENT_SECONDS: &str = "6";
+/// How long a playlist request waits for ffmpeg to finish the first segment.
+const PLAYLIST_WAIT: Duration = Duration::from_secs(20);
+const PLAYLIST_POLL: Duration = Duration::from_millis(250);
+
+/// Runs ffmpeg to turn videos browsers cannot play into HLS (H.264/AAC in
+/// MPEG-TS segments) under `work_dir`. One job exists per file version and
+/// is shared by everyone watching it. A user watches one video at a time:
+/// moving to another leaves the previous job, which is stopped only when
+/// nobody else is still watching it. `max_jobs` bounds the running total.
+/// Jobs nobody has touched for `idle_seconds` are killed and their segments
+/// deleted on the next request.
+#[derive(Debug, Clone)]
+pub struct TranscodeManager {
+    jobs: Arc<Mutex<HashMap<String, Job>>>,
+    work_dir: PathBuf,
+    max_jobs: usize,
+    idle_seconds: u64,
+}
+
+#[derive(Debug)]
+struct Job {
+    dir: PathBuf,
+    /// Users watching this job, with when each last fetched from it.
+    viewers: HashMap<i64, u64>,
+    /// `None` once ffmpeg has exited.
+    child: Option<Child>,
+    failed: bool,
+    last_access: u64,
+}
+
+impl Job {
+    fn running(&mut self) -> bool {
+        let Some(child) = self.child.as_mut() else {
+            return false;
+        };
+        match child.try_wait() {
+            Ok(None) => true,
+            Ok(Some(status)) => {
+                self.failed = !status.success();
+                self.child = None;
+                false
+            }
+            Err(_) => {
+                self.failed = true;
+                self.child = None;
+                false
+            }
+        }
+    }
+}
+
+impl TranscodeManager {
+    pub fn new(work_dir: PathBuf, max_jobs: u32, idle_seconds: u64) -> Self {
+        Self {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            work_dir,
+            max_jobs: max_jobs as usize,
+            idle_seconds,
+        }
+    }
+
+    /// Returns the HLS playlist for `source`, starting a transcode for
+    /// `user_id` if none exists yet. The playlist grows while ffmpeg runs;
+    /// players re-fetch it until it carries `#EXT-X-ENDLIST`.
+    pub async fn playlist(
+        &self,
+        ffmpeg: &Path,
+        source: &Path,
+        metadata: &std::fs::Metadata,
+        user_id: i64,
+    ) -> ApiResult<String> {
+        let key = job_key(source, metadata);
+        let playlist_path = {
+            let mut jobs = self.jobs.lock().await;
+            self.sweep(&mut jobs).await;
+            let mut superseded = Vec::new();
+            for (other, job) in jobs.iter_mut() {
+                if *other == key || !job.viewers.contains_key(&user_id) {
+                    continue;
+                }
+                if job.viewers.len() == 1 && job.running() {
+                    superseded.push(other.clone());
+                } else {
+                    job.viewers.remove(&user_id);
+                }
+            }
+            for other in superseded {
+                if let Some(mut job) = jobs.remove(&other) {
+                    stop(&mut job);
+                    let _ = fs::remove_dir_all(&job.dir).await;
+                }
+            }
+            let now = now_unix();
+            if let Some(job) = jobs.get_mut(&key) {
+                job.viewers.insert(user_id, now);
+                job.last_access = now;
+                job.dir.join(PLAYLIST_NAME)
+            } else {
+                let mut active = 0;
+                for job in jobs.values_mut() {
+                    if job.running() {
+                        active += 1;
+                    }
+                }
+                if active >= self.max_jobs {
+                    return Err(ApiError::rate_limited(
+                        "Too many videos are being transcoded.",
+                    ));
+                }
+                let dir = self.work_dir.join(&key);
+                let _ = fs::remove_dir_all(&dir).await;
+                fs::create_dir_all(&dir)
+                    .await
+                    .map_err(|err| ApiError::from_io(err, "transcode directory"))?;
+                let child = spawn_ffmpeg(ffmpeg, source, &dir)?;
+                info!(source = %source.display(), user_id, "started HLS transcode");
+                let playlist_path = dir.join(PLAYLIST_NAME);
+                jobs.insert(
+                    key.clone(),
+                    Job {
+                        dir,
+                        viewers: HashMap::from([(user_id, now)]),
+                        child: Some(child),
+                        failed: false,
+                        last_access: now,
+                    },
+                );
+                playlist_path
+            }
+        };
+
+        let deadline = tokio::time::Instant::now() + PLAYLIST_WAIT;
+        loop {
+            if let Ok(playlist) = fs::read_to_string(&playlist_path).await {
+                if playlist.contains("#EXTINF") || playlist.contains("#EXT-X-ENDLIST") {
+                    return Ok(playlist);
+                }
+            }
+            {
+                let mut jobs = self.jobs.lock().await;
+                let Some(job) = jobs.get_mut(&key) else {
+                    return Err(ApiError::not_found("Transcode was stopped."));
+                };
+                if !job.running() && job.failed {
+                    let dir = job.dir.clone();
+                    jobs.remove(&key);
+                    let _ = fs::remove_dir_all(dir).await;
+                    return Err(ApiError::bad_request("Video could not be transcoded."));
+                }
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(ApiError::internal("Transcode did not start in time."));
+            }
+            tokio::time::sleep(PLAYLIST_POLL).await;
+        }
+    }
+
+    /// Resolves a segment written by the job for `source`, keeping `user_id`
+    /// among its viewers. Only names ffmpeg produces are accepted, so the
+    /// lookup cannot leave the job directory.
+    pub async fn segment(
+        &self,
+        source: &Path,
+        metadata: &std::fs::Metadata,
+        name: &str,
+        user_id: i64,
+    ) -> ApiResult<PathBuf> {
+        if !is_segment_name(name) {
+            return Err(ApiError::not_found("Segment not found."));
+        }
+        let key = job_key(source, metadata);
+        let mut jobs = self.jobs.lock().await;
+        let job = jobs
+            .get_mut(&key)
+            .ok_or_else(|| ApiError::not_found("Segment not found."))?;
+        let now = now_unix();
+        job.viewers.insert(user_id, now);
+        job.last_access = now;
+        Ok(job.dir.join(name))
+    }
+
+    /// Stops every job and removes its segments.
+    pub async fn shutdown(&self) {
+        let mut jobs = self.jobs.lock().await;
+        for (_, mut job) in jobs.drain() {
+            stop(&mut job);
+            let _ = fs::remove_dir_all(&job.dir).await;
+        }
+    }
+
+    async fn sweep(&self, jobs: &mut HashMap<String, Job>) {
+        let now = now_unix();
+        // Viewers who stopped fetching no longer count, so the last active
+        // one still stops the job by moving on to another video.
+        for job in jobs.values_mut() {
+            job.viewers
+                .retain(|_, seen| now.saturating_sub(*seen) < self.idle_seconds);
+        }
+        let idle: Vec<String> = jobs
+            .iter()
+            .filter(|(_, job)| now.saturating_sub(job.last_access) >= self.idle_seconds)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in idle {
+            if let Some(mut job) = jobs.remove(&key) {
+                stop(&mut job);
+                let _ = fs::remove_dir_all(&job.dir).await;
+            }
+        }
+    }
+}
+
+fn stop(job: &mut Job) {
+    if let Some(mut child) = job.child.take() {
+        let _ = child.start_kill();
+    }
+}
+
+fn spawn_ffmpeg(ffmpeg: &Path, source: &Path, dir: &Path) -> ApiResult<Child> {
+    Command::new(ffmpeg)
+        .arg("-nostdin")
+        .args(["-loglevel", "error"])
+        .arg("-i")
+        .arg(source)
+        .args(["-map", "0:v:0", "-map", "0:a:0?"])
+        .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23"])
+        .args(["-pix_fmt", "yuv420p"])
+        .args(["-c:a", "aac", "-ac", "2", "-b:a", "160k"])
+        .args(["-f", "hls", "-hls_time", SEGMENT_SECONDS])
+        .args(["-hls_playlist_type", "event"])
+        .arg("-hls_segment_filename")
+        .arg(dir.join("seg-%05d.ts"))
+        .arg(dir.join(PLAYLIST_NAME))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| {
+            warn!("failed to spawn ffmpeg {}: {err}", ffmpeg.display());
+            ApiError::internal("Failed to start ffmpeg.")
+        })
+}
+
+fn is_segment_name(name: &str) -> bool {
+    name.strip_prefix("seg-")
+        .and_then(|rest| rest.strip_suffix(".ts"))
+        .is_some_and(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Keys a job by source path, size and mtime, so an edited file starts a
+/// fresh transcode.
+fn job_key(source: &Path, metadata: &std::fs::Metadata) -> String {
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|value| value.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(source.as_os_str().as_encoded_bytes());
+    hasher.update(metadata.len().to_le_bytes());
+    hasher.update(mtime.as_secs().to_le_bytes());
+    hasher.update(mtime.subsec_nanos().to_le_bytes());
+
+    let mut key = String::with_capacity(32);
+    for byte in &hasher.finalize()[..16] {
+        let _ = write!(key, "{byte:02x}");
+    }
+    key
+}
+
+/// Appends `?token=` to every URI line so players that cannot send an
+/// `Authorization` header can still fetch segments.
+pub fn with_token(playlist: &str, token: Option<&str>) -> String {
+    let Some(token) = token else {
+        return playlist.to_string();
+    };
+    playlist
+        .lines()
+        .map(|line| {
+            if line.is_empty() || line.starts_with('#') {
+                line.to_string()
+            } else {
+                format!("{line}?token={token}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_segment_name, with_token};
+
+    #[test]
+    fn only_ffmpeg_segment_names_are_served() {
+        assert!(is_segment_name("seg-00042.ts"));
+        assert!(!is_segment_name("seg-.ts"));
+        assert!(!is_segment_name("index.m3u8"));
+        assert!(!is_segment_name("seg-../../etc.ts"));
+    }
+
+    #[test]
+    fn playlist_uris_carry_the_token() {
+        let playlist = "#EXTM3U\n#EXTINF:6.0,\nseg-00000.ts\n";
+        assert_eq!(
+            with_token(playlist, Some("abc")),
+            "#EXTM3U\n#EXTINF:6.0,\nseg-00000.ts?token=abc\n"
+        );
+        assert_eq!(with_token(playlist, None), playlist);
+    }
+}
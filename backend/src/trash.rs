@@ -0,0 +1,68 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use tokio::fs;
+use tracing::{info, warn};
+
+use crate::handlers::AppState;
+use crate::session::now_unix;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(3_600);
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Removes trashed entries older than `MLIST_TRASH_RETENTION_DAYS`, at
+/// startup and then hourly, until shutdown.
+pub fn spawn_purger(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            purge_expired(&state).await;
+            tokio::select! {
+                () = tokio::time::sleep(PURGE_INTERVAL) => {}
+                () = state.shutdown.cancelled() => return,
+            }
+        }
+    });
+}
+
+async fn purge_expired(state: &AppState) {
+    let config = state.config.latest();
+    let Some(trash_dir) = &config.trash_dir else {
+        return;
+    };
+    if config.trash_retention_days == 0 {
+        return;
+    }
+    let cutoff = now_unix().saturating_sub(config.trash_retention_days * SECONDS_PER_DAY);
+    let Ok(entries) = state.db.expired_trash_entries(cutoff as i64).await else {
+        warn!("failed to look up expired trash entries");
+        return;
+    };
+
+    let mut purged = 0;
+    for entry in entries {
+        let trashed = trash_dir.join(&entry.id);
+        let removed = if entry.is_dir {
+            fs::remove_dir_all(&trashed).await
+        } else {
+            fs::remove_file(&trashed).await
+        };
+        match removed {
+            Ok(()) => {}
+            // Emptied by hand; only the record is left to drop.
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => {
+                warn!(id = entry.id, "failed to purge trash entry: {err}");
+                continue;
+            }
+        }
+        if let Some(marker) = &entry.marker {
+            let _ = fs::remove_file(trash_dir.join(marker)).await;
+        }
+        if state.db.remove_trash_entry(&entry.id).await.is_ok() {
+            purged += 1;
+        }
+    }
+    if purged > 0 {
+        info!(purged, "expired trash entries removed");
+    }
+}
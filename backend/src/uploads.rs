@@ -0,0 +1,183 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::session::now_unix;
+
+/// Unfinished uploads untouched for this long are removed.
+const STALE_UPLOAD_SECONDS: u64 = 24 * 60 * 60;
+
+/// Stages resumable uploads under `dir` until every byte has arrived. Each
+/// upload is a `<id>.part` file holding the bytes received so far plus a
+/// `<id>.json` record of where it goes, so the current offset is simply the
+/// part file's length and uploads survive a restart. Finished files are
+/// renamed into place, which needs `dir` on the same filesystem as the root.
+#[derive(Debug, Clone)]
+pub struct UploadStore {
+    dir: PathBuf,
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+/// What the client declared when creating the upload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StagedUpload {
+    pub user_id: i64,
+    /// Target directory relative to the root.
+    pub dir: String,
+    pub name: String,
+    pub size: u64,
+    pub created_at: u64,
+}
+
+/// Marks an upload as being written; dropping it releases the upload.
+pub struct UploadLock {
+    id: String,
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        lock(&self.busy).remove(&self.id);
+    }
+}
+
+impl UploadStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            busy: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub async fn create(&self, upload: &StagedUpload) -> ApiResult<String> {
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|err| ApiError::from_io(err, "upload staging directory"))?;
+        self.sweep().await;
+
+        let id = Uuid::new_v4().simple().to_string();
+        let record = serde_json::to_vec(upload)
+            .map_err(|_| ApiError::internal("Failed to record upload."))?;
+        fs::write(self.part_path(&id), b"")
+            .await
+            .map_err(|err| ApiError::from_io(err, "upload staging file"))?;
+        fs::write(self.record_path(&id), record)
+            .await
+            .map_err(|err| ApiError::from_io(err, "upload staging file"))?;
+        Ok(id)
+    }
+
+    /// Returns the upload and its current offset. Uploads of other users are
+    /// reported as missing.
+    pub async fn get(&self, id: &str, user_id: i64) -> ApiResult<(StagedUpload, u64)> {
+        let not_found = || ApiError::not_found("Upload not found.");
+        if !is_upload_id(id) {
+            return Err(not_found());
+        }
+        let record = fs::read(self.record_path(id))
+            .await
+            .map_err(|_| not_found())?;
+        let upload: StagedUpload = serde_json::from_slice(&record).map_err(|_| not_found())?;
+        if upload.user_id != user_id {
+            return Err(not_found());
+        }
+        let offset = fs::metadata(self.part_path(id))
+            .await
+            .map_err(|_| not_found())?
+            .len();
+        Ok((upload, offset))
+    }
+
+    /// Claims the upload for one writer, so concurrent `PATCH` requests
+    /// cannot interleave bytes.
+    pub fn lock(&self, id: &str) -> ApiResult<UploadLock> {
+        if !lock(&self.busy).insert(id.to_string()) {
+            return Err(ApiError::conflict("Upload is already receiving data."));
+        }
+        Ok(UploadLock {
+            id: id.to_string(),
+            busy: Arc::clone(&self.busy),
+        })
+    }
+
+    pub fn part_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.part"))
+    }
+
+    fn record_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    /// Moves the completed part file to `target`.
+    pub async fn finish(&self, id: &str, target: &Path) -> ApiResult<()> {
+        fs::rename(self.part_path(id), target)
+            .await
+            .map_err(|err| {
+                warn!(
+                    "failed to move finished upload to {}: {err}",
+                    target.display()
+                );
+                ApiError::internal("Failed to store uploaded file.")
+            })?;
+        let _ = fs::remove_file(self.record_path(id)).await;
+        Ok(())
+    }
+
+    pub async fn remove(&self, id: &str) {
+        let _ = fs::remove_file(self.part_path(id)).await;
+        let _ = fs::remove_file(self.record_path(id)).await;
+    }
+
+    async fn sweep(&self) {
+        let Ok(mut entries) = fs::read_dir(&self.dir).await else {
+            return;
+        };
+        let now = now_unix();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(id) = name.strip_suffix(".part") else {
+                continue;
+            };
+            let modified = entry
+                .metadata()
+                .await
+                .ok()
+                .and_then(|meta| meta.modified().ok())
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|age| age.as_secs())
+                .unwrap_or(0);
+            if now.saturating_sub(modified) >= STALE_UPLOAD_SECONDS
+                && !lock(&self.busy).contains(id)
+            {
+                self.remove(id).await;
+            }
+        }
+    }
+}
+
+fn is_upload_id(id: &str) -> bool {
+    id.len() == 32 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_upload_id;
+
+    #[test]
+    fn upload_ids_cannot_address_other_files() {
+        assert!(is_upload_id("0123456789abcdef0123456789abcdef"));
+        assert!(!is_upload_id("../../etc/passwd"));
+        assert!(!is_upload_id("0123456789abcdef0123456789abcde/"));
+    }
+}
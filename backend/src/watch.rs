@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::errors::{ApiError, ApiResult};
+
+/// Changes buffered per directory before slow subscribers start lagging.
+const CHANNEL_CAPACITY: usize = 256;
+/// Each watched directory costs an inotify watch (or the platform's
+/// equivalent), so the total is capped.
+const MAX_WATCHED_DIRS: usize = 1_024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Modified => "modified",
+            Self::Deleted => "deleted",
+        }
+    }
+}
+
+/// A change to a direct child of a watched directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirChange {
+    pub kind: ChangeKind,
+    pub name: String,
+}
+
+/// Shares one filesystem watcher between everyone viewing a directory.
+/// Directories are watched non-recursively while at least one
+/// [`DirSubscription`] for them is alive; the OS watcher itself is created
+/// on first use so servers nobody subscribes to never touch inotify.
+#[derive(Clone, Default)]
+pub struct DirWatcher {
+    // Lock order: `watcher` before `dirs`. The notify callback only takes
+    // `dirs`, and watch/unwatch are never called while holding it, since
+    // the backend may wait on its event thread.
+    watcher: Arc<Mutex<Option<RecommendedWatcher>>>,
+    dirs: Arc<Mutex<HashMap<PathBuf, Watched>>>,
+}
+
+struct Watched {
+    sender: broadcast::Sender<DirChange>,
+    subscribers: usize,
+}
+
+/// Receives changes for one directory; dropping it stops the watch once no
+/// other subscriber remains.
+pub struct DirSubscription {
+    pub receiver: broadcast::Receiver<DirChange>,
+    dir: PathBuf,
+    owner: DirWatcher,
+}
+
+impl Drop for DirSubscription {
+    fn drop(&mut self) {
+        self.owner.release(&self.dir);
+    }
+}
+
+impl DirWatcher {
+    pub fn subscribe(&self, dir: &Path) -> ApiResult<DirSubscription> {
+        let mut watcher = lock(&self.watcher);
+        {
+            let mut dirs = lock(&self.dirs);
+            if let Some(watched) = dirs.get_mut(dir) {
+                watched.subscribers += 1;
+                return Ok(self.subscription(dir, watched.sender.subscribe()));
+            }
+            if dirs.len() >= MAX_WATCHED_DIRS {
+                return Err(ApiError::rate_limited(
+                    "Too many directories are being watched.",
+                ));
+            }
+        }
+
+        if watcher.is_none() {
+            *watcher = Some(self.create_watcher()?);
+        }
+        if let Some(watcher) = watcher.as_mut() {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|err| {
+                    warn!("failed to watch {}: {err}", dir.display());
+                    ApiError::internal("Failed to watch directory.")
+                })?;
+        }
+        let (sender, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        lock(&self.dirs).insert(
+            dir.to_path_buf(),
+            Watched {
+                sender,
+                subscribers: 1,
+            },
+        );
+        Ok(self.subscription(dir, receiver))
+    }
+
+    fn subscription(
+        &self,
+        dir: &Path,
+        receiver: broadcast::Receiver<DirChange>,
+    ) -> DirSubscription {
+        DirSubscription {
+            receiver,
+            dir: dir.to_path_buf(),
+            owner: self.clone(),
+        }
+    }
+
+    fn release(&self, dir: &Path) {
+        let mut watcher = lock(&self.watcher);
+        {
+            let mut dirs = lock(&self.dirs);
+            let Some(watched) = dirs.get_mut(dir) else {
+                return;
+            };
+            watched.subscribers = watched.subscribers.saturating_sub(1);
+            if watched.subscribers > 0 {
+                return;
+            }
+            dirs.remove(dir);
+        }
+        if let Some(watcher) = watcher.as_mut() {
+            let _ = watcher.unwatch(dir);
+        }
+    }
+
+    fn create_watcher(&self) -> ApiResult<RecommendedWatcher> {
+        let dirs = Arc::clone(&self.dirs);
+        notify::recommended_watcher(move |result: notify::Result<Event>| {
+            let Ok(event) = result else {
+                return;
+            };
+            let dirs = lock(&dirs);
+            for (kind, path) in classify(&event) {
+                let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                    continue;
+                };
+                if let Some(watched) = dirs.get(parent) {
+                    let _ = watched.sender.send(DirChange {
+                        kind,
+                        name: name.to_string_lossy().to_string(),
+                    });
+                }
+            }
+        })
+        .map_err(|err| {
+            warn!("failed to create filesystem watcher: {err}");
+            ApiError::internal("Directory watching is unavailable.")
+        })
+    }
+}
+
+/// Maps a notify event onto the coarse kinds clients care about. Renames
+/// surface as a deletion of the old name and a creation of the new one.
+pub(crate) fn classify(event: &Event) -> Vec<(ChangeKind, &PathBuf)> {
+    let kind = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            ChangeKind::Created
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            ChangeKind::Deleted
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let mut changes = Vec::with_capacity(2);
+            if let Some(from) = event.paths.first() {
+                changes.push((ChangeKind::Deleted, from));
+            }
+            if let Some(to) = event.paths.get(1) {
+                changes.push((ChangeKind::Created, to));
+            }
+            return changes;
+        }
+        EventKind::Modify(_) => ChangeKind::Modified,
+        _ => return Vec::new(),
+    };
+    event.paths.iter().map(|path| (kind, path)).collect()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use notify::event::{CreateKind, DataChange, ModifyKind, RenameMode};
+    use notify::{Event, EventKind};
+
+    use super::{ChangeKind, classify};
+
+    #[test]
+    fn renames_become_delete_and_create() {
+        let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from("/srv/a.txt"))
+            .add_path(PathBuf::from("/srv/b.txt"));
+        let changes = classify(&event)
+            .into_iter()
+            .map(|(kind, path)| (kind, path.to_string_lossy().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                (ChangeKind::Deleted, "/srv/a.txt".to_string()),
+                (ChangeKind::Created, "/srv/b.txt".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_and_creations_are_classified() {
+        let created = Event::new(EventKind::Create(CreateKind::File)).add_path("/srv/a".into());
+        assert_eq!(classify(&created)[0].0, ChangeKind::Created);
+        let written = Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content)))
+            .add_path("/srv/a".into());
+        assert_eq!(classify(&written)[0].0, ChangeKind::Modified);
+        let accessed = Event::new(EventKind::Access(notify::event::AccessKind::Any));
+        assert!(classify(&accessed).is_empty());
+    }
+}
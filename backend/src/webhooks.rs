@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::http::header;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::audit::{AuditEvent, AuditKind};
+use crate::config::{AppConfig, LiveConfig};
+use crate::hotlink::url_host;
+use crate::session::{now_unix, unix_to_rfc3339};
+
+/// Events waiting for delivery before new ones are dropped.
+const QUEUE_CAPACITY: usize = 1_024;
+/// Payloads waiting for one endpoint before new ones to it are dropped.
+const ENDPOINT_QUEUE_CAPACITY: usize = 256;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// POSTs audit events as JSON to `MLIST_WEBHOOK_URLS` from background
+/// tasks, retrying failed deliveries with exponential backoff. Each
+/// endpoint has its own queue and task, so one that is down or slow only
+/// delays its own events. Settings are read per event, so a reload adds or
+/// removes endpoints immediately.
+#[derive(Debug, Clone)]
+pub struct WebhookDispatcher {
+    config: LiveConfig,
+    sender: mpsc::Sender<AuditEvent>,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    at: String,
+    #[serde(flatten)]
+    event: &'a AuditEvent,
+    /// One-line summary under the keys Slack (`text`) and Discord
+    /// (`content`) display, so their incoming webhooks work as is.
+    text: &'a str,
+    content: &'a str,
+}
+
+impl WebhookDispatcher {
+    /// Starts the delivery task; must be called inside the Tokio runtime.
+    pub fn spawn(config: LiveConfig) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<AuditEvent>(QUEUE_CAPACITY);
+        let task_config = config.clone();
+        tokio::spawn(async move {
+            let client = reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default();
+            let mut endpoints = HashMap::<String, mpsc::Sender<Arc<[u8]>>>::new();
+            while let Some(event) = receiver.recv().await {
+                let config = task_config.latest();
+                let summary = summarize(&event);
+                let payload = WebhookPayload {
+                    at: unix_to_rfc3339(now_unix()),
+                    event: &event,
+                    text: &summary,
+                    content: &summary,
+                };
+                let Ok(body) = serde_json::to_vec(&payload) else {
+                    continue;
+                };
+                let body = Arc::<[u8]>::from(body);
+                // Dropping the queue of a removed endpoint lets its task
+                // finish what is already queued and exit.
+                endpoints.retain(|url, _| config.webhook_urls.contains(url));
+                for url in &config.webhook_urls {
+                    let queue = endpoints.entry(url.clone()).or_insert_with(|| {
+                        spawn_endpoint(client.clone(), url.clone(), task_config.clone())
+                    });
+                    if queue.try_send(Arc::clone(&body)).is_err() {
+                        warn!(
+                            host = %url_host(url).unwrap_or_default(),
+                            event = event.event.as_str(),
+                            "webhook endpoint is falling behind, dropping event"
+                        );
+                    }
+                }
+            }
+        });
+        Self { config, sender }
+    }
+
+    /// Queues `event` if any endpoint wants it. Never blocks: when the
+    /// queue is full the event is dropped.
+    pub fn notify(&self, event: &AuditEvent) {
+        if !wanted(&self.config.latest(), event) {
+            return;
+        }
+        if self.sender.try_send(event.clone()).is_err() {
+            warn!(
+                event = event.event.as_str(),
+                "webhook queue full, dropping event"
+            );
+        }
+    }
+}
+
+/// Downloads are only announced once they completed in full; players
+/// seeking through a video would otherwise report every range request.
+fn wanted(config: &AppConfig, event: &AuditEvent) -> bool {
+    if config.webhook_urls.is_empty() || !config.webhook_events.contains(&event.event) {
+        return false;
+    }
+    event.event != AuditKind::Download
+        || (event.transfer == Some("completed") && event.range_start.is_none())
+}
+
+fn summarize(event: &AuditEvent) -> String {
+    let user = event.user.as_deref().unwrap_or("anonymous");
+    let path = event.path.as_deref().unwrap_or_default();
+    let ip = &event.ip;
+    match event.event {
+        AuditKind::Download => format!(
+            "{user} downloaded {path} ({} bytes) from {ip}",
+            event.bytes.unwrap_or_default()
+        ),
+        AuditKind::LoginSucceeded => format!("{user} logged in from {ip}"),
+        AuditKind::LoginFailed => format!("Failed login as {user} from {ip}"),
+        AuditKind::LoginBlocked => format!("Blocked login attempts as {user} from {ip}"),
+        AuditKind::RateLimited => format!("{ip} is being rate limited on {path}"),
+    }
+}
+
+/// Delivers queued payloads to `url` one at a time, in order.
+fn spawn_endpoint(
+    client: reqwest::Client,
+    url: String,
+    config: LiveConfig,
+) -> mpsc::Sender<Arc<[u8]>> {
+    let (sender, mut receiver) = mpsc::channel::<Arc<[u8]>>(ENDPOINT_QUEUE_CAPACITY);
+    tokio::spawn(async move {
+        while let Some(body) = receiver.recv().await {
+            deliver(&client, &url, &body, config.latest().webhook_retries).await;
+        }
+    });
+    sender
+}
+
+/// Tries once plus `retries` more times. Client errors other than 429 are
+/// not retried, since repeating the request will not fix them. Only the
+/// host is logged: chat webhook URLs embed their secret.
+async fn deliver(client: &reqwest::Client, url: &str, body: &[u8], retries: u64) {
+    let host = url_host(url).unwrap_or_default();
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+        let result = client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_vec())
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => {
+                let status = response.status();
+                warn!(host = %host, attempt, %status, "webhook delivery failed");
+                if status.is_client_error() && status.as_u16() != 429 {
+                    return;
+                }
+            }
+            Err(err) => warn!(host = %host, attempt, "webhook delivery failed: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    use super::{WebhookDispatcher, summarize, wanted};
+    use crate::audit::{AuditEvent, AuditKind};
+    use crate::config::{AppConfig, LiveConfig};
+
+    fn download(transfer: &'static str, range_start: Option<u64>) -> AuditEvent {
+        AuditEvent {
+            event: AuditKind::Download,
+            ip: "203.0.113.9".to_string(),
+            user: Some("ivy".to_string()),
+            path: Some("releases/v1.iso".to_string()),
+            status: Some(200),
+            bytes: Some(1024),
+            range_start,
+            range_end: None,
+            transfer: Some(transfer),
+        }
+    }
+
+    #[test]
+    fn only_configured_events_and_complete_downloads_are_sent() {
+        let mut config = AppConfig {
+            webhook_urls: vec!["https://hooks.example.com/x".to_string()],
+            ..AppConfig::default()
+        };
+        assert!(wanted(&config, &download("completed", None)));
+        assert!(!wanted(&config, &download("aborted", None)));
+        assert!(!wanted(&config, &download("completed", Some(0))));
+
+        let login = AuditEvent::login(AuditKind::LoginFailed, "203.0.113.9", "ivy");
+        assert!(wanted(&config, &login));
+        config.webhook_events = vec![AuditKind::Download];
+        assert!(!wanted(&config, &login));
+        config.webhook_urls.clear();
+        assert!(!wanted(&config, &download("completed", None)));
+    }
+
+    #[test]
+    fn summaries_name_who_did_what() {
+        assert_eq!(
+            summarize(&download("completed", None)),
+            "ivy downloaded releases/v1.iso (1024 bytes) from 203.0.113.9"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_hung_endpoint_does_not_delay_the_others() {
+        // Accepts connections but never answers.
+        let hung = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hung_url = format!("http://{}/hook", hung.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = hung.accept().await {
+                held.push(socket);
+            }
+        });
+        let alive = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let alive_url = format!("http://{}/hook", alive.local_addr().unwrap());
+        let (delivered, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = alive.accept().await {
+                let mut request = vec![0; 4096];
+                let _ = socket.read(&mut request).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 204 No Content\r\nconnection: close\r\n\r\n")
+                    .await;
+                let _ = delivered.send(());
+            }
+        });
+
+        let config = AppConfig {
+            webhook_urls: vec![hung_url, alive_url],
+            ..AppConfig::default()
+        };
+        let dispatcher = WebhookDispatcher::spawn(LiveConfig::new(Arc::new(config)));
+        let login = AuditEvent::login(AuditKind::LoginFailed, "203.0.113.9", "ivy");
+        dispatcher.notify(&login);
+        dispatcher.notify(&login);
+        for _ in 0..2 {
+            tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .expect("delivery to the healthy endpoint was held up")
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! Shared harness for end-to-end tests: a real listener bound to an
+//! ephemeral port, a temp root populated with fixtures, and a minimal
+//! HTTP/1.1 client so requests travel over an actual socket.
+
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use mlist::AppConfig;
+use serde_json::{Value, json};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+pub const ADMIN_NAME: &str = "admin";
+pub const README_BODY: &[u8] = b"hello mlist\n";
+pub const UNICODE_BODY: &[u8] = b"\x00\x01clip-bytes";
+pub const SPARSE_LEN: u64 = 6 * 1024 * 1024 * 1024;
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub root: PathBuf,
+    base: PathBuf,
+    task: JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start(name: &str) -> Self {
+        Self::start_with(name, |_| {}).await
+    }
+
+    pub async fn start_with(name: &str, configure: impl FnOnce(&mut AppConfig)) -> Self {
+        let base = std::env::temp_dir().join(format!(
+            "mlist-e2e-{name}-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        let root = base.join("files");
+        write_fixture(&root);
+
+        let mut config = AppConfig {
+            root_dir: root.clone(),
+            database_path: base.join("mlist.sqlite3"),
+            thumb_cache_dir: base.join("thumbs"),
+            transcode_dir: base.join("transcode"),
+            upload_staging_dir: base.join("uploads"),
+            ..AppConfig::default()
+        };
+        configure(&mut config);
+
+        let state = mlist::build_state(config).await.unwrap();
+        let app = mlist::build_router(state);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let task = tokio::spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .unwrap();
+        });
+
+        Self {
+            addr,
+            root,
+            base,
+            task,
+        }
+    }
+
+    pub async fn get(&self, target: &str, token: Option<&str>) -> TestResponse {
+        self.send("GET", target, &auth_headers(token), None).await
+    }
+
+    pub async fn post_json(&self, target: &str, token: Option<&str>, body: &Value) -> TestResponse {
+        let mut headers = auth_headers(token);
+        headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        let body = serde_json::to_vec(body).unwrap();
+        self.send("POST", target, &headers, Some(&body)).await
+    }
+
+    /// Posts `files` as `multipart/form-data` file fields.
+    pub async fn post_multipart(
+        &self,
+        target: &str,
+        token: Option<&str>,
+        files: &[(&str, &[u8])],
+    ) -> TestResponse {
+        let boundary = "mlist-e2e-boundary";
+        let mut body = Vec::new();
+        for (name, content) in files {
+            body.extend_from_slice(
+                format!(
+                    "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{name}\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+                )
+                .as_bytes(),
+            );
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+        let mut headers = auth_headers(token);
+        headers.push(header(
+            "Content-Type",
+            &format!("multipart/form-data; boundary={boundary}"),
+        ));
+        self.send("POST", target, &headers, Some(&body)).await
+    }
+
+    pub async fn send(
+        &self,
+        method: &str,
+        target: &str,
+        headers: &[(String, String)],
+        body: Option<&[u8]>,
+    ) -> TestResponse {
+        let mut stream = TcpStream::connect(self.addr).await.unwrap();
+        let mut head = format!(
+            "{method} {target} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            self.addr
+        );
+        for (name, value) in headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        if let Some(body) = body {
+            head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+        }
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes()).await.unwrap();
+        if let Some(body) = body {
+            stream.write_all(body).await.unwrap();
+        }
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).await.unwrap();
+        parse_response(&raw)
+    }
+
+    /// Completes the first-run flow and returns the admin's access token.
+    pub async fn bootstrap_admin(&self) -> String {
+        let start = self
+            .post_json(
+                "/api/bootstrap/start",
+                None,
+                &json!({ "username": ADMIN_NAME }),
+            )
+            .await;
+        assert_eq!(start.status, 200, "bootstrap start failed");
+        let secret = start.json()["secret"].as_str().unwrap().to_string();
+
+        let finish = self
+            .post_json(
+                "/api/bootstrap/finish",
+                None,
+                &json!({
+                    "username": ADMIN_NAME,
+                    "secret": secret,
+                    "code": totp_code(ADMIN_NAME, &secret),
+                }),
+            )
+            .await;
+        assert_eq!(finish.status, 200, "bootstrap finish failed");
+        finish.json()["accessToken"].as_str().unwrap().to_string()
+    }
+
+    /// Creates a regular user and returns its TOTP secret.
+    pub async fn create_user(&self, admin_token: &str, username: &str) -> String {
+        let response = self
+            .post_json(
+                "/api/admin/users",
+                Some(admin_token),
+                &json!({ "username": username, "role": "user" }),
+            )
+            .await;
+        assert_eq!(response.status, 200, "create user failed");
+        response.json()["secret"].as_str().unwrap().to_string()
+    }
+
+    pub async fn login(&self, username: &str, code: &str) -> TestResponse {
+        self.post_json(
+            "/api/auth/login",
+            None,
+            &json!({ "username": username, "code": code }),
+        )
+        .await
+    }
+
+    /// Logs in with a freshly generated code and returns the access token.
+    pub async fn login_token(&self, username: &str, secret: &str) -> String {
+        let response = self.login(username, &totp_code(username, secret)).await;
+        assert_eq!(response.status, 200, "login failed");
+        response.json()["accessToken"].as_str().unwrap().to_string()
+    }
+
+    /// Mints a signed `/d/` link for `path` and returns its request target.
+    pub async fn file_link(&self, token: &str, path: &str) -> String {
+        let response = self
+            .post_json("/api/file-link", Some(token), &json!({ "path": path }))
+            .await;
+        assert_eq!(response.status, 200, "file link failed for {path}");
+        response.json()["url"].as_str().unwrap().to_string()
+    }
+
+    /// Sends a GET whose response never ends, such as an SSE stream, and
+    /// hands back the socket once the response head has arrived. The bytes
+    /// read so far land in `received`.
+    pub async fn open_stream(
+        &self,
+        target: &str,
+        token: &str,
+        received: &mut Vec<u8>,
+    ) -> TcpStream {
+        let mut stream = TcpStream::connect(self.addr).await.unwrap();
+        let request = format!(
+            "GET {target} HTTP/1.1\r\nHost: {}\r\nAuthorization: Bearer {token}\r\n\r\n",
+            self.addr
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        read_until(&mut stream, received, "\r\n\r\n").await;
+        stream
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_dir_all(&self.base);
+    }
+}
+
+pub struct TestResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl TestResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_else(|err| {
+            panic!(
+                "response body is not JSON ({err}): {}",
+                String::from_utf8_lossy(&self.body)
+            )
+        })
+    }
+
+    pub fn entry_names(&self) -> Vec<String> {
+        self.json()["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    /// Returns the `name=value` pair of a `Set-Cookie` header for `name`.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("set-cookie"))
+            .filter_map(|(_, value)| value.split(';').next())
+            .find(|pair| pair.starts_with(&format!("{name}=")))
+            .map(str::to_string)
+    }
+}
+
+pub fn totp_code(username: &str, secret: &str) -> String {
+    let secret_bytes = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret_bytes,
+        Some("mlist".to_string()),
+        username.to_string(),
+    )
+    .unwrap()
+    .generate_current()
+    .unwrap()
+}
+
+/// Reads from an open stream until `needle` shows up, failing after ten
+/// seconds.
+pub async fn read_until(stream: &mut TcpStream, received: &mut Vec<u8>, needle: &str) {
+    let mut chunk = [0_u8; 1024];
+    let wait = async {
+        while !String::from_utf8_lossy(received).contains(needle) {
+            let read = stream.read(&mut chunk).await.unwrap();
+            assert!(read > 0, "stream closed early");
+            received.extend_from_slice(&chunk[..read]);
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(10), wait)
+        .await
+        .unwrap_or_else(|_| panic!("timed out waiting for {needle:?}"));
+}
+
+pub fn header(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+fn auth_headers(token: Option<&str>) -> Vec<(String, String)> {
+    token
+        .map(|value| vec![header("Authorization", &format!("Bearer {value}"))])
+        .unwrap_or_default()
+}
+
+/// Lays out the fixture tree shared by all end-to-end tests:
+///
+/// ```text
+/// public/readme.txt
+/// private/.private
+/// private/secret.txt
+/// private/nested/deep.txt
+/// 电影/片段 一.mp4
+/// big/sparse.bin        (SPARSE_LEN bytes, sparse)
+/// escape -> /etc        (unix only)
+/// ```
+fn write_fixture(root: &Path) {
+    std::fs::create_dir_all(root.join("public")).unwrap();
+    std::fs::write(root.join("public/readme.txt"), README_BODY).unwrap();
+
+    std::fs::create_dir_all(root.join("private/nested")).unwrap();
+    std::fs::write(root.join("private/.private"), b"").unwrap();
+    std::fs::write(root.join("private/secret.txt"), b"top secret").unwrap();
+    std::fs::write(root.join("private/nested/deep.txt"), b"deep").unwrap();
+
+    std::fs::create_dir_all(root.join("电影")).unwrap();
+    std::fs::write(root.join("电影/片段 一.mp4"), UNICODE_BODY).unwrap();
+
+    std::fs::create_dir_all(root.join("big")).unwrap();
+    let sparse = std::fs::File::create(root.join("big/sparse.bin")).unwrap();
+    sparse.set_len(SPARSE_LEN).unwrap();
+
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("/etc", root.join("escape")).unwrap();
+}
+
+fn parse_response(raw: &[u8]) -> TestResponse {
+    let split = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .expect("response has no header terminator");
+    let head = String::from_utf8_lossy(&raw[..split]).to_string();
+    let rest = &raw[split + 4..];
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|code| code.parse::<u16>().ok())
+        .expect("malformed status line");
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect::<Vec<_>>();
+
+    let chunked = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("transfer-encoding") && value.eq_ignore_ascii_case("chunked")
+    });
+    let body = if chunked {
+        decode_chunked(rest)
+    } else {
+        rest.to_vec()
+    };
+
+    TestResponse {
+        status,
+        headers,
+        body,
+    }
+}
+
+fn decode_chunked(mut raw: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = raw
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .expect("malformed chunk size line");
+        let size_line = String::from_utf8_lossy(&raw[..line_end]).to_string();
+        let size_hex = size_line.split(';').next().unwrap().trim();
+        let size = usize::from_str_radix(size_hex, 16).expect("malformed chunk size");
+        raw = &raw[line_end + 2..];
+        if size == 0 {
+            return body;
+        }
+        body.extend_from_slice(&raw[..size]);
+        raw = &raw[size + 2..];
+    }
+}
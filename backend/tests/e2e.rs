@@ -0,0 +1,3343 @@
+mod common;
+
+use common::{
+    ADMIN_NAME, README_BODY, SPARSE_LEN, TestResponse, TestServer, UNICODE_BODY, header,
+    read_until, totp_code,
+};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+#[tokio::test]
+async fn unauthenticated_requests_are_rejected() {
+    let server = TestServer::start("unauthenticated").await;
+
+    let list = server.get("/api/list?path=", None).await;
+    assert_eq!(list.status, 401);
+    assert_eq!(list.json()["code"], "AUTH_REQUIRED");
+
+    let direct = server.get("/d/public/readme.txt", None).await;
+    assert_eq!(direct.status, 401);
+
+    let forged = server.get("/d/public/readme.txt?token=forged", None).await;
+    assert_eq!(forged.status, 401);
+}
+
+#[tokio::test]
+async fn admin_listing_shows_private_scopes_but_never_markers() {
+    let server = TestServer::start("admin-listing").await;
+    let admin = server.bootstrap_admin().await;
+
+    let root = server.get("/api/list?path=", Some(&admin)).await;
+    assert_eq!(root.status, 200);
+    let names = root.entry_names();
+    assert!(names.contains(&"public".to_string()));
+    assert!(names.contains(&"private".to_string()));
+    assert!(names.contains(&"电影".to_string()));
+    assert!(
+        !names.contains(&"escape".to_string()),
+        "symlinks must not be listed"
+    );
+
+    let private = server.get("/api/list?path=private", Some(&admin)).await;
+    assert_eq!(private.status, 200);
+    assert_eq!(private.json()["requiresAuth"], true);
+    let names = private.entry_names();
+    assert!(names.contains(&"secret.txt".to_string()));
+    assert!(names.contains(&"nested".to_string()));
+    assert!(!names.contains(&".private".to_string()));
+}
+
+#[tokio::test]
+async fn regular_users_cannot_see_private_scopes() {
+    let server = TestServer::start("user-listing").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "alice").await;
+    let alice = server.login_token("alice", &secret).await;
+
+    let root = server.get("/api/list?path=", Some(&alice)).await;
+    assert_eq!(root.status, 200);
+    assert!(!root.entry_names().contains(&"private".to_string()));
+
+    for path in ["private", "private/nested"] {
+        let listing = server
+            .get(&format!("/api/list?path={path}"), Some(&alice))
+            .await;
+        assert_eq!(listing.status, 404, "{path} must look nonexistent");
+    }
+
+    let link = server
+        .post_json(
+            "/api/file-link",
+            Some(&alice),
+            &json!({ "path": "private/nested/deep.txt" }),
+        )
+        .await;
+    assert_eq!(link.status, 404);
+}
+
+#[tokio::test]
+async fn private_grants_open_scopes_to_named_users() {
+    let server = TestServer::start_with("private-grants", |config| {
+        config.private_read_grants = vec!["alice=private/nested".parse().unwrap()];
+        config.private_write_grants = vec!["bob=private".parse().unwrap()];
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "alice").await;
+    let alice = server.login_token("alice", &secret).await;
+    let secret = server.create_user(&admin, "bob").await;
+    let bob = server.login_token("bob", &secret).await;
+    let secret = server.create_user(&admin, "carol").await;
+    let carol = server.login_token("carol", &secret).await;
+
+    let nested = server
+        .get("/api/list?path=private/nested", Some(&alice))
+        .await;
+    assert_eq!(nested.status, 200);
+    assert!(nested.entry_names().contains(&"deep.txt".to_string()));
+    let top = server.get("/api/list?path=private", Some(&alice)).await;
+    assert_eq!(top.status, 404, "grant must not reach the parent scope");
+
+    let listing = server.get("/api/list?path=private", Some(&bob)).await;
+    assert_eq!(listing.status, 200);
+    assert!(listing.entry_names().contains(&"secret.txt".to_string()));
+    let root = server.get("/api/list?path=", Some(&bob)).await;
+    assert!(root.entry_names().contains(&"private".to_string()));
+    let uploaded = server
+        .post_multipart(
+            "/api/upload?path=private",
+            Some(&bob),
+            &[("drop.txt", b"from bob".as_slice())],
+        )
+        .await;
+    assert_eq!(uploaded.status, 200);
+    let denied = server
+        .post_multipart(
+            "/api/upload?path=private/nested",
+            Some(&alice),
+            &[("drop.txt", b"from alice".as_slice())],
+        )
+        .await;
+    assert_eq!(denied.status, 403, "read grants do not allow uploads");
+
+    let root = server.get("/api/list?path=", Some(&carol)).await;
+    assert!(!root.entry_names().contains(&"private".to_string()));
+    let listing = server.get("/api/list?path=private", Some(&carol)).await;
+    assert_eq!(listing.status, 404);
+}
+
+#[tokio::test]
+async fn login_failures_are_rate_limited() {
+    let server = TestServer::start_with("rate-limit", |config| {
+        config.login_max_failures = 3;
+    })
+    .await;
+    server.bootstrap_admin().await;
+
+    for _ in 0..2 {
+        let response = server.login(ADMIN_NAME, "000000").await;
+        assert_eq!(response.status, 401);
+    }
+    let blocked = server.login(ADMIN_NAME, "000000").await;
+    assert_eq!(blocked.status, 429);
+    assert_eq!(blocked.json()["code"], "RATE_LIMITED");
+}
+
+#[tokio::test]
+async fn repeated_failures_require_a_proof_of_work_challenge() {
+    let server = TestServer::start_with("login-challenge", |config| {
+        config.login_challenge = mlist::LoginChallengeKind::ProofOfWork;
+        config.login_challenge_after = 2;
+        config.login_pow_difficulty = 4;
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "dora").await;
+
+    assert_eq!(
+        server.login("dora", "000000").await.json()["code"],
+        "UNAUTHORIZED"
+    );
+    let failed = server.login("dora", "000000").await;
+    assert_eq!(failed.status, 401);
+    assert_eq!(failed.json()["challenge"]["kind"], "pow");
+
+    // A correct code alone is not enough any more.
+    let code = totp_code("dora", &secret);
+    let unanswered = server.login("dora", &code).await;
+    assert_eq!(unanswered.status, 401);
+    assert_eq!(unanswered.json()["code"], "CHALLENGE_REQUIRED");
+
+    let challenge = &unanswered.json()["challenge"];
+    let nonce = challenge["nonce"].as_str().unwrap().to_string();
+    let difficulty = challenge["difficulty"].as_u64().unwrap() as u32;
+    let solution = (0u64..)
+        .map(|value| value.to_string())
+        .find(|candidate| {
+            let digest = Sha256::digest(format!("{nonce}:{candidate}"));
+            u128::from_be_bytes(digest[..16].try_into().unwrap()).leading_zeros() >= difficulty
+        })
+        .unwrap();
+    let answered = server
+        .post_json(
+            "/api/auth/login",
+            None,
+            &json!({
+                "username": "dora",
+                "code": code,
+                "challenge": { "nonce": nonce, "solution": solution },
+            }),
+        )
+        .await;
+    assert_eq!(answered.status, 200);
+
+    // Other accounts are unaffected, even from the same address.
+    let fresh = server.login(ADMIN_NAME, "000000").await;
+    assert!(fresh.json().get("challenge").is_none());
+}
+
+#[tokio::test]
+async fn refresh_cookie_rotates_and_logout_revokes_access() {
+    let server = TestServer::start("refresh").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "bob").await;
+
+    let login = server.login("bob", &totp_code("bob", &secret)).await;
+    assert_eq!(login.status, 200);
+    let cookie = login.cookie("mlist_refresh").expect("refresh cookie");
+
+    let refreshed = server
+        .send(
+            "POST",
+            "/api/auth/refresh",
+            &[header("Cookie", &cookie)],
+            None,
+        )
+        .await;
+    assert_eq!(refreshed.status, 200);
+    let access = refreshed.json()["accessToken"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(refreshed.cookie("mlist_refresh").is_some());
+
+    let reused = server
+        .send(
+            "POST",
+            "/api/auth/refresh",
+            &[header("Cookie", &cookie)],
+            None,
+        )
+        .await;
+    assert_eq!(
+        reused.status, 401,
+        "rotated refresh token must not be reusable"
+    );
+
+    let me = server.get("/api/me", Some(&access)).await;
+    assert_eq!(me.json()["authenticated"], true);
+
+    let logout = server
+        .post_json("/api/auth/logout", Some(&access), &json!({}))
+        .await;
+    assert_eq!(logout.status, 200);
+    let me = server.get("/api/me", Some(&access)).await;
+    assert_eq!(me.json()["authenticated"], false);
+}
+
+#[tokio::test]
+async fn range_requests_on_sparse_file() {
+    let server = TestServer::start("ranges").await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "big/sparse.bin").await;
+
+    let head = server
+        .send("GET", &link, &[header("Range", "bytes=0-15")], None)
+        .await;
+    assert_eq!(head.status, 206);
+    assert_eq!(
+        head.header("content-range"),
+        Some(format!("bytes 0-15/{SPARSE_LEN}").as_str())
+    );
+    assert_eq!(head.body, vec![0_u8; 16]);
+
+    let tail = server
+        .send("GET", &link, &[header("Range", "bytes=-4")], None)
+        .await;
+    assert_eq!(tail.status, 206);
+    assert_eq!(
+        tail.header("content-range"),
+        Some(format!("bytes {}-{}/{SPARSE_LEN}", SPARSE_LEN - 4, SPARSE_LEN - 1).as_str())
+    );
+    assert_eq!(tail.body.len(), 4);
+
+    let beyond = server
+        .send(
+            "GET",
+            &link,
+            &[header("Range", &format!("bytes={SPARSE_LEN}-"))],
+            None,
+        )
+        .await;
+    assert_eq!(beyond.status, 416);
+    assert_eq!(
+        beyond.header("content-range"),
+        Some(format!("bytes */{SPARSE_LEN}").as_str())
+    );
+}
+
+#[tokio::test]
+async fn download_flag_forces_attachment_disposition() {
+    let server = TestServer::start("download-flag").await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "public/readme.txt").await;
+
+    let inline = server.get(&link, None).await;
+    let disposition = inline.header("content-disposition").unwrap();
+    assert!(disposition.starts_with("inline;"));
+    for flag in ["download=1", "dl=1"] {
+        let saved = server.get(&format!("{link}&{flag}"), None).await;
+        assert_eq!(saved.status, 200);
+        assert_eq!(
+            saved.header("content-disposition"),
+            Some("attachment; filename=\"readme.txt\"; filename*=UTF-8''readme.txt")
+        );
+    }
+}
+
+#[tokio::test]
+async fn mime_overrides_apply_to_listings_and_downloads() {
+    let server = TestServer::start_with("mime-overrides", |config| {
+        config.mime_overrides = [
+            ("txt".to_string(), "text/x-release-notes".parse().unwrap()),
+            ("cue".to_string(), "application/x-cue".parse().unwrap()),
+        ]
+        .into();
+    })
+    .await;
+    std::fs::write(server.root.join("public/album.cue"), b"FILE").unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let listing = server.get("/api/list?path=public", Some(&admin)).await;
+    let entries = listing.json()["entries"].as_array().unwrap().clone();
+    let mime_of = |name: &str| {
+        entries
+            .iter()
+            .find(|entry| entry["name"] == name)
+            .map(|entry| entry["mime"].clone())
+            .unwrap()
+    };
+    assert_eq!(mime_of("readme.txt"), "text/x-release-notes");
+    assert_eq!(mime_of("album.cue"), "application/x-cue");
+
+    let link = server.file_link(&admin, "public/album.cue").await;
+    let download = server.get(&link, None).await;
+    assert_eq!(download.header("content-type"), Some("application/x-cue"));
+}
+
+#[tokio::test]
+async fn multi_range_requests_return_multipart_byteranges() {
+    let server = TestServer::start_with("multi-range", |config| {
+        config.max_byte_ranges = 2;
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "public/readme.txt").await;
+
+    let parts = server
+        .send("GET", &link, &[header("Range", "bytes=0-4,6-10")], None)
+        .await;
+    assert_eq!(parts.status, 206);
+    assert!(parts.header("content-range").is_none());
+    let boundary = parts
+        .header("content-type")
+        .and_then(|value| value.strip_prefix("multipart/byteranges; boundary="))
+        .expect("multipart content type")
+        .to_string();
+    assert_eq!(
+        parts.header("content-length"),
+        Some(parts.body.len().to_string().as_str())
+    );
+    let body = String::from_utf8(parts.body.clone()).unwrap();
+    assert_eq!(
+        body,
+        format!(
+            "--{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-4/12\r\n\r\nhello\r\n\
+             --{boundary}\r\nContent-Type: text/plain\r\nContent-Range: bytes 6-10/12\r\n\r\nmlist\r\n\
+             --{boundary}--\r\n"
+        )
+    );
+
+    let too_many = server
+        .send("GET", &link, &[header("Range", "bytes=0-1,2-3,4-5")], None)
+        .await;
+    assert_eq!(too_many.status, 416);
+}
+
+#[tokio::test]
+async fn unicode_names_round_trip_through_signed_links() {
+    let server = TestServer::start("unicode").await;
+    let admin = server.bootstrap_admin().await;
+
+    let listing = server
+        .get("/api/list?path=%E7%94%B5%E5%BD%B1", Some(&admin))
+        .await;
+    assert_eq!(listing.status, 200);
+    assert_eq!(listing.entry_names(), vec!["片段 一.mp4".to_string()]);
+
+    let link = server.file_link(&admin, "电影/片段 一.mp4").await;
+    assert!(link.starts_with("/d/%E7%94%B5%E5%BD%B1/"));
+    let file = server.get(&link, None).await;
+    assert_eq!(file.status, 200);
+    assert_eq!(file.body, UNICODE_BODY);
+    assert!(
+        file.header("content-disposition")
+            .unwrap()
+            .contains("filename*=UTF-8''%E7%89%87%E6%AE%B5%20%E4%B8%80.mp4")
+    );
+}
+
+#[tokio::test]
+async fn conditional_requests_return_not_modified() {
+    let server = TestServer::start("conditional").await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "public/readme.txt").await;
+
+    let first = server.get(&link, None).await;
+    assert_eq!(first.status, 200);
+    assert_eq!(first.body, README_BODY);
+    let etag = first.header("etag").unwrap().to_string();
+
+    let second = server
+        .send("GET", &link, &[header("If-None-Match", &etag)], None)
+        .await;
+    assert_eq!(second.status, 304);
+    assert!(second.body.is_empty());
+}
+
+#[tokio::test]
+async fn cache_rules_set_caching_headers() {
+    let server = TestServer::start_with("cache-rules", |config| {
+        config.file_cache_rules = vec![
+            "public/*=no-cache".parse().unwrap(),
+            "mime:text/*=600".parse().unwrap(),
+        ];
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+
+    let me = server.get("/api/me", Some(&admin)).await;
+    assert_eq!(me.header("cache-control"), Some("no-store"));
+
+    let link = server.file_link(&admin, "private/secret.txt").await;
+    let secret = server.get(&link, None).await;
+    assert_eq!(secret.header("cache-control"), Some("private, max-age=600"));
+    assert!(secret.header("expires").is_some());
+    let etag = secret.header("etag").unwrap().to_string();
+    let revalidated = server
+        .send("GET", &link, &[header("If-None-Match", &etag)], None)
+        .await;
+    assert_eq!(revalidated.status, 304);
+    assert_eq!(
+        revalidated.header("cache-control"),
+        Some("private, max-age=600")
+    );
+
+    let link = server.file_link(&admin, "public/readme.txt").await;
+    let readme = server.get(&link, None).await;
+    assert_eq!(readme.header("cache-control"), Some("public, no-cache"));
+    assert!(readme.header("expires").is_none());
+
+    let link = server.file_link(&admin, "电影/片段 一.mp4").await;
+    let clip = server.get(&link, None).await;
+    assert!(clip.header("cache-control").is_none());
+}
+
+#[tokio::test]
+async fn path_header_rules_add_and_override_response_headers() {
+    let server = TestServer::start_with("path-headers", |config| {
+        config.path_headers = vec![
+            "public/*=Content-Security-Policy: sandbox".parse().unwrap(),
+            "private/*=X-Robots-Tag: noindex".parse().unwrap(),
+        ];
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+
+    let link = server.file_link(&admin, "public/readme.txt").await;
+    let readme = server.get(&link, None).await;
+    assert_eq!(readme.header("content-security-policy"), Some("sandbox"));
+    assert!(readme.header("x-robots-tag").is_none());
+
+    let link = server.file_link(&admin, "private/secret.txt").await;
+    let secret = server.get(&link, None).await;
+    assert_eq!(secret.header("x-robots-tag"), Some("noindex"));
+    assert_ne!(secret.header("content-security-policy"), Some("sandbox"));
+    let etag = secret.header("etag").unwrap().to_string();
+    let revalidated = server
+        .send("GET", &link, &[header("If-None-Match", &etag)], None)
+        .await;
+    assert_eq!(revalidated.status, 304);
+    assert_eq!(revalidated.header("x-robots-tag"), Some("noindex"));
+}
+
+#[tokio::test]
+async fn path_guard_rejects_escapes_and_markers() {
+    let server = TestServer::start("path-guard").await;
+    let admin = server.bootstrap_admin().await;
+
+    for target in [
+        "/api/list?path=..",
+        "/api/list?path=public%2F..%2Fprivate",
+        "/api/list?path=%2Fetc",
+        "/api/list?path=public%5Creadme.txt",
+        "/api/list?path=public%2F%2Freadme.txt",
+    ] {
+        let response = server.get(target, Some(&admin)).await;
+        assert_eq!(response.status, 400, "{target} should be rejected");
+    }
+
+    let traversal = server
+        .get("/d/public/..%2F..%2Fetc/passwd?token=x", None)
+        .await;
+    assert_eq!(traversal.status, 400);
+
+    let marker = server
+        .post_json(
+            "/api/file-link",
+            Some(&admin),
+            &json!({ "path": "private/.private" }),
+        )
+        .await;
+    assert_eq!(marker.status, 404);
+
+    #[cfg(unix)]
+    {
+        let symlink = server.get("/api/list?path=escape", Some(&admin)).await;
+        assert_eq!(symlink.status, 403);
+
+        let link = server
+            .post_json(
+                "/api/file-link",
+                Some(&admin),
+                &json!({ "path": "escape/hostname" }),
+            )
+            .await;
+        assert_eq!(link.status, 403);
+    }
+}
+
+#[tokio::test]
+async fn search_walks_subtrees_and_respects_private_scopes() {
+    let server = TestServer::start("search").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "carol").await;
+    let carol = server.login_token("carol", &secret).await;
+
+    let found = server.get("/api/search?q=DEEP", Some(&admin)).await;
+    assert_eq!(found.status, 200);
+    let body = found.json();
+    assert_eq!(body["entries"][0]["path"], "private/nested/deep.txt");
+    assert_eq!(body["entries"][0]["requiresAuth"], true);
+    assert_eq!(body["truncated"], false);
+
+    let hidden = server.get("/api/search?q=deep", Some(&carol)).await;
+    assert_eq!(hidden.status, 200);
+    assert!(hidden.entry_names().is_empty());
+
+    let shallow = server
+        .get("/api/search?q=deep&path=private&depth=1", Some(&admin))
+        .await;
+    assert!(shallow.entry_names().is_empty());
+
+    let capped = server.get("/api/search?q=e&limit=1", Some(&admin)).await;
+    assert_eq!(capped.entry_names().len(), 1);
+    assert_eq!(capped.json()["truncated"], true);
+
+    let empty = server.get("/api/search?q=%20", Some(&admin)).await;
+    assert_eq!(empty.status, 400);
+}
+
+#[tokio::test]
+async fn content_search_returns_matching_lines() {
+    let server = TestServer::start_with("search-content", |config| {
+        config.search_content_max_bytes = 64;
+    })
+    .await;
+    std::fs::write(
+        server.root.join("public/episode.srt"),
+        "1\n00:00:01,000 --> 00:00:02,000\nWinter is coming\n",
+    )
+    .unwrap();
+    std::fs::write(server.root.join("public/winter.mp4"), b"winter").unwrap();
+    std::fs::write(server.root.join("public/long.txt"), "winter\n".repeat(20)).unwrap();
+    std::fs::write(server.root.join("private/notes.md"), "winter notes").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "carol").await;
+    let carol = server.login_token("carol", &secret).await;
+
+    let found = server
+        .get("/api/search?q=WINTER&content=true", Some(&admin))
+        .await;
+    assert_eq!(found.status, 200);
+    let body = found.json();
+    // Names are not matched, media files are not read and files above the
+    // size cap are skipped.
+    assert_eq!(
+        found.entry_names(),
+        vec!["notes.md".to_string(), "episode.srt".to_string()]
+    );
+    let lines = &body["matches"]["public/episode.srt"];
+    assert_eq!(lines[0]["line"], 3);
+    assert_eq!(lines[0]["offset"], 32);
+    assert_eq!(lines[0]["text"], "Winter is coming");
+
+    let hidden = server
+        .get("/api/search?q=winter&content=true", Some(&carol))
+        .await;
+    assert_eq!(hidden.entry_names(), vec!["episode.srt".to_string()]);
+
+    let names = server.get("/api/search?q=winter", Some(&admin)).await;
+    assert_eq!(names.entry_names(), vec!["winter.mp4".to_string()]);
+    assert!(names.json().get("matches").is_none());
+}
+
+#[tokio::test]
+async fn indexed_search_follows_changes_and_private_scopes() {
+    let server = TestServer::start_with("search-index", |config| {
+        config.index_enabled = true;
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "carol").await;
+    let carol = server.login_token("carol", &secret).await;
+
+    std::fs::create_dir_all(server.root.join("public/later")).unwrap();
+    std::fs::write(server.root.join("public/later/fresh.txt"), b"new").unwrap();
+    let mut found = Vec::new();
+    for _ in 0..50 {
+        found = server
+            .get("/api/search?q=fresh", Some(&carol))
+            .await
+            .entry_names();
+        if !found.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(found, vec!["fresh.txt".to_string()]);
+
+    let deep = server.get("/api/search?q=deep", Some(&admin)).await;
+    assert_eq!(deep.json()["entries"][0]["requiresAuth"], true);
+    let hidden = server.get("/api/search?q=deep", Some(&carol)).await;
+    assert!(hidden.entry_names().is_empty());
+    let shallow = server
+        .get("/api/search?q=deep&path=private&depth=1", Some(&admin))
+        .await;
+    assert!(shallow.entry_names().is_empty());
+
+    std::fs::remove_dir_all(server.root.join("public/later")).unwrap();
+    let gone = server.get("/api/search?q=fresh", Some(&carol)).await;
+    assert!(gone.entry_names().is_empty());
+}
+
+#[tokio::test]
+async fn last_modified_validators_are_honored() {
+    let server = TestServer::start("last-modified").await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "public/readme.txt").await;
+
+    let first = server.get(&link, None).await;
+    let last_modified = first.header("last-modified").unwrap().to_string();
+    assert!(first.header("etag").unwrap().starts_with("W/\""));
+
+    let unchanged = server
+        .send(
+            "GET",
+            &link,
+            &[header("If-Modified-Since", &last_modified)],
+            None,
+        )
+        .await;
+    assert_eq!(unchanged.status, 304);
+    assert_eq!(
+        unchanged.header("last-modified"),
+        Some(last_modified.as_str())
+    );
+
+    // If-None-Match takes precedence over If-Modified-Since.
+    let mismatched = server
+        .send(
+            "GET",
+            &link,
+            &[
+                header("If-None-Match", "W/\"stale\""),
+                header("If-Modified-Since", &last_modified),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(mismatched.status, 200);
+    assert_eq!(mismatched.body, README_BODY);
+}
+
+#[tokio::test]
+async fn head_requests_return_headers_without_body() {
+    let server = TestServer::start("head").await;
+    let admin = server.bootstrap_admin().await;
+
+    let link = server.file_link(&admin, "public/readme.txt").await;
+    let probe = server.send("HEAD", &link, &[], None).await;
+    assert_eq!(probe.status, 200);
+    assert_eq!(
+        probe.header("content-length"),
+        Some(README_BODY.len().to_string().as_str())
+    );
+    assert_eq!(probe.header("accept-ranges"), Some("bytes"));
+    assert!(
+        probe
+            .header("content-type")
+            .unwrap()
+            .starts_with("text/plain")
+    );
+    assert!(probe.body.is_empty());
+
+    let sparse = server.file_link(&admin, "big/sparse.bin").await;
+    let ranged = server
+        .send("HEAD", &sparse, &[header("Range", "bytes=100-199")], None)
+        .await;
+    assert_eq!(ranged.status, 206);
+    assert_eq!(ranged.header("content-length"), Some("100"));
+    assert!(ranged.body.is_empty());
+}
+
+#[tokio::test]
+async fn uploads_respect_writable_scopes() {
+    let server = TestServer::start("upload").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "dave").await;
+    let user = server.login_token("dave", &secret).await;
+
+    let denied = server
+        .post_multipart(
+            "/api/upload?path=public",
+            Some(&user),
+            &[("new.txt", b"hi".as_slice())],
+        )
+        .await;
+    assert_eq!(denied.status, 403);
+
+    let anonymous = server
+        .post_multipart(
+            "/api/upload?path=public",
+            None,
+            &[("new.txt", b"hi".as_slice())],
+        )
+        .await;
+    assert_eq!(anonymous.status, 401);
+
+    std::fs::write(server.root.join("public/.writable"), b"").unwrap();
+    let uploaded = server
+        .post_multipart(
+            "/api/upload?path=public",
+            Some(&user),
+            &[("new.txt", b"hello upload".as_slice())],
+        )
+        .await;
+    assert_eq!(uploaded.status, 200);
+    assert_eq!(uploaded.json()["files"][0]["path"], "public/new.txt");
+    assert_eq!(
+        std::fs::read(server.root.join("public/new.txt")).unwrap(),
+        b"hello upload"
+    );
+
+    let duplicate = server
+        .post_multipart(
+            "/api/upload?path=public",
+            Some(&user),
+            &[("new.txt", b"x".as_slice())],
+        )
+        .await;
+    assert_eq!(duplicate.status, 409);
+
+    for name in ["../escape.txt", ".private", ".writable"] {
+        let rejected = server
+            .post_multipart(
+                "/api/upload?path=public",
+                Some(&user),
+                &[(name, b"x".as_slice())],
+            )
+            .await;
+        assert_eq!(rejected.status, 400, "{name} should be rejected");
+    }
+
+    let listing = server.get("/api/list?path=public", Some(&user)).await;
+    assert!(!listing.entry_names().contains(&".writable".to_string()));
+
+    // Private scopes stay hidden from regular users even when writable.
+    std::fs::write(server.root.join("private/.writable"), b"").unwrap();
+    let private = server
+        .post_multipart(
+            "/api/upload?path=private",
+            Some(&user),
+            &[("x.txt", b"x".as_slice())],
+        )
+        .await;
+    assert_eq!(private.status, 404);
+}
+
+#[tokio::test]
+async fn upload_enabled_lets_admins_write_anywhere() {
+    let server = TestServer::start_with("upload-admin", |config| {
+        config.upload_enabled = true;
+        config.upload_max_bytes = 8;
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+
+    let uploaded = server
+        .post_multipart(
+            "/api/upload?path=",
+            Some(&admin),
+            &[("root.txt", b"root".as_slice())],
+        )
+        .await;
+    assert_eq!(uploaded.status, 200);
+    assert!(server.root.join("root.txt").is_file());
+
+    let too_large = server
+        .post_multipart(
+            "/api/upload?path=",
+            Some(&admin),
+            &[("big.txt", b"0123456789".as_slice())],
+        )
+        .await;
+    assert_eq!(too_large.status, 413);
+    assert!(!server.root.join("big.txt").exists());
+}
+
+#[tokio::test]
+async fn writable_scopes_allow_file_management() {
+    let server = TestServer::start("manage-scope").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "lee").await;
+    let user = server.login_token("lee", &secret).await;
+
+    let denied = server
+        .post_json(
+            "/api/fs/mkdir",
+            Some(&user),
+            &json!({ "path": "public/new" }),
+        )
+        .await;
+    assert_eq!(denied.status, 403);
+
+    std::fs::write(server.root.join("public/.writable"), b"").unwrap();
+    let created = server
+        .post_json(
+            "/api/fs/mkdir",
+            Some(&user),
+            &json!({ "path": "public/new" }),
+        )
+        .await;
+    assert_eq!(created.status, 200);
+    assert_eq!(created.json()["kind"], "dir");
+    assert!(server.root.join("public/new").is_dir());
+
+    std::fs::write(server.root.join("public/a.txt"), b"a").unwrap();
+    std::fs::write(server.root.join("public/.private.a.txt"), b"").unwrap();
+    let renamed = server
+        .post_json(
+            "/api/fs/rename",
+            Some(&admin),
+            &json!({ "path": "public/a.txt", "name": "b.txt" }),
+        )
+        .await;
+    assert_eq!(
+        renamed.status, 403,
+        "admins need MLIST_MANAGE_ENABLED outside scopes"
+    );
+    let hidden = server
+        .post_json(
+            "/api/fs/rename",
+            Some(&user),
+            &json!({ "path": "public/a.txt", "name": "b.txt" }),
+        )
+        .await;
+    assert_eq!(hidden.status, 404);
+    std::fs::remove_file(server.root.join("public/.private.a.txt")).unwrap();
+
+    let renamed = server
+        .post_json(
+            "/api/fs/rename",
+            Some(&user),
+            &json!({ "path": "public/a.txt", "name": "b.txt" }),
+        )
+        .await;
+    assert_eq!(renamed.status, 200);
+    assert_eq!(renamed.json()["path"], "public/b.txt");
+
+    let moved = server
+        .post_json(
+            "/api/fs/move",
+            Some(&user),
+            &json!({ "path": "public/b.txt", "to": "public/new" }),
+        )
+        .await;
+    assert_eq!(moved.status, 200);
+    assert!(server.root.join("public/new/b.txt").is_file());
+
+    let clash = server
+        .post_json(
+            "/api/fs/rename",
+            Some(&user),
+            &json!({ "path": "public/new/b.txt", "name": "b.txt" }),
+        )
+        .await;
+    assert_eq!(clash.status, 400);
+    let into_self = server
+        .post_json(
+            "/api/fs/move",
+            Some(&user),
+            &json!({ "path": "public/new", "to": "public/new" }),
+        )
+        .await;
+    assert_eq!(into_self.status, 400);
+    for name in [".writable", "../x", ""] {
+        let rejected = server
+            .post_json(
+                "/api/fs/rename",
+                Some(&user),
+                &json!({ "path": "public/new/b.txt", "name": name }),
+            )
+            .await;
+        assert_eq!(rejected.status, 400, "{name:?} should be rejected");
+    }
+
+    // The scope root itself stays out of reach for regular users.
+    let scope_root = server
+        .post_json("/api/fs/delete", Some(&user), &json!({ "path": "public" }))
+        .await;
+    assert_eq!(scope_root.status, 403);
+    let marker = server
+        .post_json(
+            "/api/fs/delete",
+            Some(&user),
+            &json!({ "path": "public/.writable" }),
+        )
+        .await;
+    assert_eq!(marker.status, 404);
+
+    let deleted = server
+        .post_json(
+            "/api/fs/delete",
+            Some(&user),
+            &json!({ "path": "public/new" }),
+        )
+        .await;
+    assert_eq!(deleted.status, 200);
+    assert!(!server.root.join("public/new").exists());
+}
+
+#[tokio::test]
+async fn admins_manage_anywhere_and_deletes_go_to_trash() {
+    let server = TestServer::start_with("manage-admin", |config| {
+        config.manage_enabled = true;
+        config.trash_dir = Some(config.root_dir.with_file_name("trash"));
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let trash = server.root.with_file_name("trash");
+
+    let moved = server
+        .post_json(
+            "/api/fs/move",
+            Some(&admin),
+            &json!({ "path": "private/secret.txt", "to": "public" }),
+        )
+        .await;
+    assert_eq!(moved.status, 200);
+    assert_eq!(moved.json()["requiresAuth"], false);
+
+    let deleted = server
+        .post_json(
+            "/api/fs/delete",
+            Some(&admin),
+            &json!({ "path": "public/readme.txt" }),
+        )
+        .await;
+    assert_eq!(deleted.status, 200);
+    assert!(!server.root.join("public/readme.txt").exists());
+    let trashed = std::fs::read_dir(&trash)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(trashed.len(), 1);
+    assert!(trashed[0].ends_with("-readme.txt"));
+
+    let root = server
+        .post_json("/api/fs/delete", Some(&admin), &json!({ "path": "" }))
+        .await;
+    assert_eq!(root.status, 400);
+}
+
+#[tokio::test]
+async fn trashed_entries_can_be_listed_and_restored() {
+    let server = TestServer::start_with("manage-trash", |config| {
+        config.manage_enabled = true;
+        config.trash_dir = Some(config.root_dir.with_file_name("trash"));
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "max").await;
+    let user = server.login_token("max", &secret).await;
+
+    let deleted = server
+        .post_json(
+            "/api/fs/delete",
+            Some(&admin),
+            &json!({ "path": "public/readme.txt" }),
+        )
+        .await;
+    assert_eq!(deleted.status, 200);
+
+    let listed = server.get("/api/trash", Some(&admin)).await;
+    assert_eq!(listed.status, 200);
+    let body = listed.json();
+    assert_eq!(body["retentionDays"], 30);
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["path"], "public/readme.txt");
+    assert_eq!(entries[0]["deletedBy"], "admin");
+    let id = entries[0]["id"].as_str().unwrap().to_string();
+
+    // Other users neither see nor restore what they did not delete.
+    let foreign = server.get("/api/trash", Some(&user)).await;
+    assert_eq!(foreign.json()["entries"].as_array().unwrap().len(), 0);
+    let stolen = server
+        .post_json("/api/trash/restore", Some(&user), &json!({ "id": id }))
+        .await;
+    assert_eq!(stolen.status, 404);
+
+    std::fs::write(server.root.join("public/readme.txt"), "replacement").unwrap();
+    let conflict = server
+        .post_json("/api/trash/restore", Some(&admin), &json!({ "id": id }))
+        .await;
+    assert_eq!(conflict.status, 409);
+    std::fs::remove_file(server.root.join("public/readme.txt")).unwrap();
+
+    let restored = server
+        .post_json("/api/trash/restore", Some(&admin), &json!({ "id": id }))
+        .await;
+    assert_eq!(restored.status, 200);
+    assert_eq!(restored.json()["path"], "public/readme.txt");
+    assert!(server.root.join("public/readme.txt").exists());
+    let emptied = server.get("/api/trash", Some(&admin)).await;
+    assert_eq!(emptied.json()["entries"].as_array().unwrap().len(), 0);
+
+    let again = server
+        .post_json("/api/trash/restore", Some(&admin), &json!({ "id": id }))
+        .await;
+    assert_eq!(again.status, 404);
+}
+
+#[tokio::test]
+async fn uploads_resume_from_the_received_offset() {
+    let server = TestServer::start_with("resumable-upload", |config| {
+        config.upload_enabled = true;
+        config.upload_max_bytes = 64;
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "max").await;
+    let user = server.login_token("max", &secret).await;
+    let auth = |token: &str| header("Authorization", &format!("Bearer {token}"));
+
+    let too_large = server
+        .post_json(
+            "/api/uploads?path=public",
+            Some(&admin),
+            &json!({ "name": "big.bin", "size": 65 }),
+        )
+        .await;
+    assert_eq!(too_large.status, 413);
+
+    let created = server
+        .post_json(
+            "/api/uploads?path=public",
+            Some(&admin),
+            &json!({ "name": "movie.bin", "size": 10 }),
+        )
+        .await;
+    assert_eq!(created.status, 201);
+    let location = created.header("location").unwrap().to_string();
+    assert_eq!(
+        location,
+        format!("/api/uploads/{}", created.json()["id"].as_str().unwrap())
+    );
+
+    let first = server
+        .send(
+            "PATCH",
+            &location,
+            &[auth(&admin), header("Upload-Offset", "0")],
+            Some(b"01234"),
+        )
+        .await;
+    assert_eq!(first.status, 204);
+    assert_eq!(first.header("upload-offset"), Some("5"));
+
+    let progress = server.send("HEAD", &location, &[auth(&admin)], None).await;
+    assert_eq!(progress.status, 200);
+    assert_eq!(progress.header("upload-offset"), Some("5"));
+    assert_eq!(progress.header("upload-length"), Some("10"));
+
+    let stale = server
+        .send(
+            "PATCH",
+            &location,
+            &[auth(&admin), header("Upload-Offset", "0")],
+            Some(b"01234"),
+        )
+        .await;
+    assert_eq!(stale.status, 409);
+    let foreign = server.send("HEAD", &location, &[auth(&user)], None).await;
+    assert_eq!(foreign.status, 404);
+    assert!(!server.root.join("public/movie.bin").exists());
+
+    let overflow = server
+        .send(
+            "PATCH",
+            &location,
+            &[auth(&admin), header("Upload-Offset", "5")],
+            Some(b"56789X"),
+        )
+        .await;
+    assert_eq!(overflow.status, 413);
+
+    let rest = server
+        .send(
+            "PATCH",
+            &location,
+            &[auth(&admin), header("Upload-Offset", "5")],
+            Some(b"56789"),
+        )
+        .await;
+    assert_eq!(rest.status, 204);
+    assert_eq!(
+        std::fs::read(server.root.join("public/movie.bin")).unwrap(),
+        b"0123456789"
+    );
+    let finished = server.send("HEAD", &location, &[auth(&admin)], None).await;
+    assert_eq!(finished.status, 404);
+
+    let cancelled = server
+        .post_json(
+            "/api/uploads?path=public",
+            Some(&admin),
+            &json!({ "name": "other.bin", "size": 4 }),
+        )
+        .await;
+    let location = cancelled.header("location").unwrap().to_string();
+    let removed = server
+        .send("DELETE", &location, &[auth(&admin)], None)
+        .await;
+    assert_eq!(removed.status, 204);
+    let gone = server.send("HEAD", &location, &[auth(&admin)], None).await;
+    assert_eq!(gone.status, 404);
+}
+
+#[tokio::test]
+async fn webdav_serves_tree_with_basic_auth() {
+    use base64::Engine;
+
+    let server = TestServer::start("dav").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "erin").await;
+    let user = server.login_token("erin", &secret).await;
+
+    let options = server.send("OPTIONS", "/dav/", &[], None).await;
+    assert_eq!(options.status, 200);
+    assert_eq!(options.header("dav"), Some("1"));
+
+    let anonymous = server
+        .send("PROPFIND", "/dav/", &[header("Depth", "1")], None)
+        .await;
+    assert_eq!(anonymous.status, 401);
+    assert!(
+        anonymous
+            .header("www-authenticate")
+            .unwrap()
+            .starts_with("Basic")
+    );
+
+    let minted = server
+        .post_json("/api/dav-token", Some(&user), &json!({}))
+        .await;
+    assert_eq!(minted.status, 200);
+    let token = minted.json()["token"].as_str().unwrap().to_string();
+    let credentials = base64::engine::general_purpose::STANDARD.encode(format!("erin:{token}"));
+    let basic = header("Authorization", &format!("Basic {credentials}"));
+
+    let wrong_user = base64::engine::general_purpose::STANDARD.encode(format!("admin:{token}"));
+    let rejected = server
+        .send(
+            "PROPFIND",
+            "/dav/",
+            &[header("Authorization", &format!("Basic {wrong_user}"))],
+            None,
+        )
+        .await;
+    assert_eq!(rejected.status, 401);
+
+    let root = server
+        .send(
+            "PROPFIND",
+            "/dav/",
+            &[basic.clone(), header("Depth", "1")],
+            None,
+        )
+        .await;
+    assert_eq!(root.status, 207);
+    let listing = String::from_utf8(root.body.clone()).unwrap();
+    assert!(listing.contains("<D:href>/dav/public/</D:href>"));
+    assert!(listing.contains("<D:href>/dav/%E7%94%B5%E5%BD%B1/</D:href>"));
+    assert!(!listing.contains("/dav/private/"));
+    assert!(!listing.contains("escape"));
+
+    let file = server
+        .send(
+            "PROPFIND",
+            "/dav/public/readme.txt",
+            &[basic.clone(), header("Depth", "0")],
+            None,
+        )
+        .await;
+    assert_eq!(file.status, 207);
+    assert!(String::from_utf8(file.body).unwrap().contains(&format!(
+        "<D:getcontentlength>{}</D:getcontentlength>",
+        README_BODY.len()
+    )));
+
+    let download = server
+        .send("GET", "/dav/public/readme.txt", &[basic.clone()], None)
+        .await;
+    assert_eq!(download.status, 200);
+    assert_eq!(download.body, README_BODY);
+
+    let ranged = server
+        .send(
+            "GET",
+            "/dav/big/sparse.bin",
+            &[basic.clone(), header("Range", "bytes=0-9")],
+            None,
+        )
+        .await;
+    assert_eq!(ranged.status, 206);
+    assert_eq!(ranged.body.len(), 10);
+
+    let private = server
+        .send("GET", "/dav/private/secret.txt", &[basic.clone()], None)
+        .await;
+    assert_eq!(private.status, 404);
+
+    let infinite = server
+        .send(
+            "PROPFIND",
+            "/dav/",
+            &[basic, header("Depth", "infinity")],
+            None,
+        )
+        .await;
+    assert_eq!(infinite.status, 403);
+}
+
+#[tokio::test]
+async fn thumbnails_are_resized_and_cached() {
+    let server = TestServer::start("thumb").await;
+    let admin = server.bootstrap_admin().await;
+    image::RgbImage::from_pixel(64, 32, image::Rgb([200, 40, 40]))
+        .save(server.root.join("public/pic.png"))
+        .unwrap();
+
+    let thumb = server
+        .get("/api/thumb?path=public/pic.png&w=16&h=16", Some(&admin))
+        .await;
+    assert_eq!(thumb.status, 200);
+    assert_eq!(thumb.header("content-type"), Some("image/jpeg"));
+    let decoded = image::load_from_memory(&thumb.body).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (16, 8));
+
+    // `<img>` tags authenticate with the signed link token instead.
+    let link = server.file_link(&admin, "public/pic.png").await;
+    let token = link.split_once("token=").unwrap().1;
+    let signed = server
+        .get(
+            &format!("/api/thumb?path=public/pic.png&w=16&h=16&token={token}"),
+            None,
+        )
+        .await;
+    assert_eq!(signed.status, 200);
+    assert_eq!(signed.body, thumb.body);
+
+    let anonymous = server.get("/api/thumb?path=public/pic.png", None).await;
+    assert_eq!(anonymous.status, 401);
+
+    let not_media = server
+        .get("/api/thumb?path=public/readme.txt", Some(&admin))
+        .await;
+    assert_eq!(not_media.status, 400);
+}
+
+#[tokio::test]
+async fn hide_patterns_hide_entries_everywhere() {
+    let server = TestServer::start_with("hidden", |config| {
+        config.hide_patterns = vec!["*.nfo".to_string(), "@eaDir".to_string()];
+    })
+    .await;
+    std::fs::write(server.root.join("public/readme.nfo"), b"sidecar").unwrap();
+    std::fs::create_dir_all(server.root.join("public/@eaDir")).unwrap();
+    std::fs::write(server.root.join("public/@eaDir/readme.txt"), b"thumb").unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let listing = server.get("/api/list?path=public", Some(&admin)).await;
+    assert_eq!(listing.status, 200);
+    assert_eq!(listing.entry_names(), vec!["readme.txt"]);
+
+    let nested = server
+        .get("/api/list?path=public/@eaDir", Some(&admin))
+        .await;
+    assert_eq!(nested.status, 404);
+
+    for path in ["public/readme.nfo", "public/@eaDir/readme.txt"] {
+        let link = server
+            .post_json("/api/file-link", Some(&admin), &json!({ "path": path }))
+            .await;
+        assert_eq!(link.status, 404, "{path} should be hidden");
+    }
+
+    let search = server.get("/api/search?q=readme", Some(&admin)).await;
+    assert_eq!(search.status, 200);
+    let paths = search.json()["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["path"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(paths, vec!["public/readme.txt"]);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks_within_root_are_followed_when_enabled() {
+    let server = TestServer::start_with("symlinks", |config| {
+        config.symlinks = mlist::SymlinkPolicy::WithinRoot;
+    })
+    .await;
+    std::os::unix::fs::symlink(server.root.join("public"), server.root.join("linked")).unwrap();
+    std::os::unix::fs::symlink(
+        server.root.join("private/secret.txt"),
+        server.root.join("public/secret-link.txt"),
+    )
+    .unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "frank").await;
+    let user = server.login_token("frank", &secret).await;
+
+    let root = server.get("/api/list?path=", Some(&user)).await;
+    let names = root.entry_names();
+    assert!(names.contains(&"linked".to_string()));
+    assert!(!names.contains(&"escape".to_string()));
+
+    let linked = server.get("/api/list?path=linked", Some(&user)).await;
+    assert_eq!(linked.status, 200);
+    assert_eq!(linked.entry_names(), vec!["readme.txt"]);
+
+    let link = server.file_link(&user, "linked/readme.txt").await;
+    let download = server.get(&link, None).await;
+    assert_eq!(download.status, 200);
+    assert_eq!(download.body, README_BODY);
+
+    // A link into a private scope is judged by where it points.
+    let leaked = server
+        .post_json(
+            "/api/file-link",
+            Some(&user),
+            &json!({ "path": "public/secret-link.txt" }),
+        )
+        .await;
+    assert_eq!(leaked.status, 404);
+    let admin_view = server.get("/api/list?path=public", Some(&admin)).await;
+    assert!(
+        admin_view
+            .entry_names()
+            .contains(&"secret-link.txt".to_string())
+    );
+
+    let escape = server.get("/api/list?path=escape", Some(&admin)).await;
+    assert_eq!(escape.status, 403);
+}
+
+#[tokio::test]
+async fn downloads_are_throttled_per_ip() {
+    let server = TestServer::start_with("throttle", |config| {
+        config.max_bytes_per_sec_per_ip = Some(64 * 1024);
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "big/sparse.bin").await;
+
+    // 64 KiB at 64 KiB/s: the half-second burst covers only half of it.
+    let started = std::time::Instant::now();
+    let response = server
+        .send("GET", &link, &[header("Range", "bytes=0-65535")], None)
+        .await;
+    assert_eq!(response.status, 206);
+    assert_eq!(response.body.len(), 65536);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(400));
+}
+
+#[tokio::test]
+async fn api_requests_are_rate_limited_per_ip() {
+    let server = TestServer::start_with("request-rate", |config| {
+        config.api_rate_limit = Some(mlist::RequestRate {
+            per_sec: 1,
+            burst: 3,
+        });
+    })
+    .await;
+
+    for _ in 0..3 {
+        assert_eq!(server.get("/api/me", None).await.status, 200);
+    }
+    let limited = server.get("/api/me", None).await;
+    assert_eq!(limited.status, 429);
+    assert_eq!(limited.json()["code"], "RATE_LIMITED");
+    assert_eq!(limited.header("retry-after"), Some("1"));
+
+    // Downloads draw from their own, here unlimited, budget.
+    assert_ne!(server.get("/d/public/a.txt", None).await.status, 429);
+}
+
+#[tokio::test]
+async fn ip_rules_restrict_admin_and_write_endpoints() {
+    let server = TestServer::start_with("ip-rules", |config| {
+        config.admin_allow_cidrs = vec!["10.0.0.0/8".parse().unwrap()];
+    })
+    .await;
+
+    assert_eq!(server.get("/api/me", None).await.status, 200);
+    let admin = server.get("/api/admin/users", None).await;
+    assert_eq!(admin.status, 403);
+    assert_eq!(admin.json()["code"], "FORBIDDEN");
+    let mkdir = server
+        .post_json("/api/fs/mkdir", None, &json!({ "path": "public/new" }))
+        .await;
+    assert_eq!(mkdir.status, 403);
+
+    let denied = TestServer::start_with("ip-deny", |config| {
+        config.deny_cidrs = vec!["127.0.0.0/8".parse().unwrap()];
+    })
+    .await;
+    assert_eq!(denied.get("/api/me", None).await.status, 403);
+}
+
+#[tokio::test]
+async fn hotlinked_downloads_are_redirected_to_the_listing() {
+    let server = TestServer::start_with("hotlink", |config| {
+        config.hotlink_policy = mlist::HotlinkPolicy::Redirect;
+        config.hotlink_allowed_hosts = vec!["friend.net".to_string()];
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "big/sparse.bin").await;
+    let range = header("Range", "bytes=0-0");
+
+    let foreign = server
+        .send(
+            "GET",
+            &link,
+            &[range.clone(), header("Referer", "https://leech.example/")],
+            None,
+        )
+        .await;
+    assert_eq!(foreign.status, 303);
+    assert_eq!(foreign.header("location"), Some("/big"));
+
+    let own_page = format!("http://{}/", server.addr);
+    for referer in ["https://friend.net/post", own_page.as_str()] {
+        let response = server
+            .send(
+                "GET",
+                &link,
+                &[range.clone(), header("Referer", referer)],
+                None,
+            )
+            .await;
+        assert_eq!(response.status, 206, "{referer}");
+    }
+    let direct = server.send("GET", &link, &[range], None).await;
+    assert_eq!(direct.status, 206);
+}
+
+#[tokio::test]
+async fn concurrent_downloads_per_ip_are_capped() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::start_with("download-slots", |config| {
+        config.max_concurrent_downloads_per_ip = Some(1);
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "big/sparse.bin").await;
+
+    // Hold one transfer open by reading only the start of the body.
+    let mut open = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+    let request = format!("GET {link} HTTP/1.1\r\nHost: {}\r\n\r\n", server.addr);
+    open.write_all(request.as_bytes()).await.unwrap();
+    let mut first = [0u8; 12];
+    open.read_exact(&mut first).await.unwrap();
+    assert_eq!(&first, b"HTTP/1.1 200");
+
+    let second = server.get(&link, None).await;
+    assert_eq!(second.status, 429);
+    // Header-only probes do not occupy a slot.
+    let head = server.send("HEAD", &link, &[], None).await;
+    assert_eq!(head.status, 200);
+
+    drop(open);
+    let mut status = 0;
+    for _ in 0..50 {
+        let retry = server
+            .send("GET", &link, &[header("Range", "bytes=0-15")], None)
+            .await;
+        status = retry.status;
+        if status == 206 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(status, 206);
+}
+
+#[tokio::test]
+async fn download_quota_is_enforced_per_user() {
+    let server = TestServer::start_with("download-quota", |config| {
+        config.download_quota_per_user = Some(README_BODY.len() as u64 + 1);
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "public/readme.txt").await;
+
+    // A transfer admitted below the quota finishes even if it goes past it.
+    for _ in 0..2 {
+        let response = server.get(&link, None).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, README_BODY);
+    }
+    // Header-only probes are not counted or refused.
+    assert_eq!(server.send("HEAD", &link, &[], None).await.status, 200);
+
+    let exceeded = server.get(&link, None).await;
+    assert_eq!(exceeded.status, 429);
+    assert_eq!(exceeded.json()["code"], "RATE_LIMITED");
+    let reset: u64 = exceeded
+        .header("x-ratelimit-reset")
+        .unwrap()
+        .parse()
+        .unwrap();
+    let retry_after: u64 = exceeded.header("retry-after").unwrap().parse().unwrap();
+    assert!(reset > 0);
+    assert!(retry_after <= 86_400);
+}
+
+#[tokio::test]
+async fn per_file_private_markers_hide_single_files() {
+    let server = TestServer::start("private-file").await;
+    std::fs::write(server.root.join("public/notes.txt"), b"only admins").unwrap();
+    std::fs::write(server.root.join("public/.private.notes.txt"), b"").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "grace").await;
+    let user = server.login_token("grace", &secret).await;
+
+    let listing = server.get("/api/list?path=public", Some(&user)).await;
+    assert_eq!(listing.entry_names(), vec!["readme.txt"]);
+    let link = server
+        .post_json(
+            "/api/file-link",
+            Some(&user),
+            &json!({ "path": "public/notes.txt" }),
+        )
+        .await;
+    assert_eq!(link.status, 404);
+
+    let admin_listing = server.get("/api/list?path=public", Some(&admin)).await;
+    let entries = admin_listing.json()["entries"].as_array().unwrap().clone();
+    let notes = entries
+        .iter()
+        .find(|entry| entry["name"] == "notes.txt")
+        .unwrap();
+    assert_eq!(notes["requiresAuth"], true);
+    let readme = entries
+        .iter()
+        .find(|entry| entry["name"] == "readme.txt")
+        .unwrap();
+    assert_eq!(readme["requiresAuth"], false);
+    assert!(
+        !admin_listing
+            .entry_names()
+            .contains(&".private.notes.txt".to_string())
+    );
+
+    let marker = server
+        .post_json(
+            "/api/file-link",
+            Some(&admin),
+            &json!({ "path": "public/.private.notes.txt" }),
+        )
+        .await;
+    assert_eq!(marker.status, 404);
+}
+
+#[tokio::test]
+async fn audit_log_records_logins_and_downloads() {
+    let log_path = std::env::temp_dir().join(format!(
+        "mlist-audit-{}.jsonl",
+        uuid::Uuid::new_v4().simple()
+    ));
+    let configured = log_path.clone();
+    let server = TestServer::start_with("audit-log", move |config| {
+        config.audit_log_path = Some(configured);
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "heidi").await;
+    let failed = server.login("heidi", "000000").await;
+    assert_eq!(failed.status, 401);
+    let user = server.login_token("heidi", &secret).await;
+
+    let link = server.file_link(&user, "public/readme.txt").await;
+    let download = server.get(&link, None).await;
+    assert_eq!(download.status, 200);
+
+    let mut lines = Vec::new();
+    for _ in 0..50 {
+        lines = std::fs::read_to_string(&log_path)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        if lines.iter().any(|line| line["event"] == "download") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let events = lines
+        .iter()
+        .filter(|line| line["user"] == "heidi")
+        .map(|line| line["event"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(events, vec!["login_failed", "login_succeeded", "download"]);
+
+    let download = lines
+        .iter()
+        .find(|line| line["event"] == "download")
+        .unwrap();
+    assert_eq!(download["path"], "public/readme.txt");
+    assert_eq!(download["bytes"], README_BODY.len());
+    assert_eq!(download["transfer"], "completed");
+    assert_eq!(download["ip"], "127.0.0.1");
+    assert!(download["at"].is_string());
+    let _ = std::fs::remove_file(log_path);
+}
+
+#[tokio::test]
+async fn admin_can_revoke_sessions_and_clear_login_blocks() {
+    let server = TestServer::start("admin-sessions").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "ivan").await;
+
+    let login = server.login("ivan", &totp_code("ivan", &secret)).await;
+    assert_eq!(login.status, 200);
+    let cookie = login.cookie("mlist_refresh").expect("refresh cookie");
+
+    let sessions = server.get("/api/admin/sessions", Some(&admin)).await;
+    assert_eq!(sessions.status, 200);
+    let ivan_session = sessions.json()["sessions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|session| session["username"] == "ivan")
+        .expect("ivan session listed")["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let auth = header("Authorization", &format!("Bearer {admin}"));
+    let revoked = server
+        .send(
+            "DELETE",
+            &format!("/api/admin/sessions/{ivan_session}"),
+            std::slice::from_ref(&auth),
+            None,
+        )
+        .await;
+    assert_eq!(revoked.status, 200);
+    let refresh = server
+        .send(
+            "POST",
+            "/api/auth/refresh",
+            &[header("Cookie", &cookie)],
+            None,
+        )
+        .await;
+    assert_eq!(refresh.status, 401);
+
+    let access = server.login_token("ivan", &secret).await;
+    let user_id = server.get("/api/me", Some(&access)).await.json()["user"]["id"]
+        .as_i64()
+        .unwrap();
+    let everywhere = server
+        .post_json(
+            &format!("/api/admin/users/{user_id}/revoke-sessions"),
+            Some(&admin),
+            &json!({}),
+        )
+        .await;
+    assert_eq!(everywhere.status, 200);
+    let me = server.get("/api/me", Some(&access)).await;
+    assert_eq!(me.json()["authenticated"], false);
+
+    for _ in 0..5 {
+        server.login("ivan", "000000").await;
+    }
+    let blocked = server.login("ivan", &totp_code("ivan", &secret)).await;
+    assert_eq!(blocked.status, 429);
+    let blocks = server.get("/api/admin/login-blocks", Some(&admin)).await;
+    assert_eq!(blocks.json()["blocks"].as_array().unwrap().len(), 1);
+    let cleared = server
+        .send("DELETE", "/api/admin/login-blocks", &[auth], None)
+        .await;
+    assert_eq!(cleared.json()["cleared"], 1);
+    server.login_token("ivan", &secret).await;
+
+    let scopes = server.get("/api/admin/scopes", Some(&admin)).await;
+    assert_eq!(
+        scopes.json()["scopes"],
+        json!([{ "path": "private", "kind": "dir" }])
+    );
+}
+
+#[tokio::test]
+async fn api_tokens_authenticate_within_their_scopes() {
+    let server = TestServer::start("api-tokens").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "judy").await;
+    let access = server.login_token("judy", &secret).await;
+    let user_id = server.get("/api/me", Some(&access)).await.json()["user"]["id"]
+        .as_i64()
+        .unwrap();
+
+    let denied = server
+        .post_json(
+            &format!("/api/admin/users/{user_id}/api-tokens"),
+            Some(&admin),
+            &json!({ "name": "rclone", "scopes": ["read", "admin"] }),
+        )
+        .await;
+    assert_eq!(denied.status, 400);
+
+    let issued = server
+        .post_json(
+            &format!("/api/admin/users/{user_id}/api-tokens"),
+            Some(&admin),
+            &json!({ "name": "rclone", "scopes": ["read"] }),
+        )
+        .await;
+    assert_eq!(issued.status, 200);
+    let body = issued.json();
+    let token = body["token"].as_str().unwrap().to_string();
+    let token_id = body["id"].as_i64().unwrap();
+    assert!(token.starts_with("mlt_"));
+    assert_eq!(body["scopes"], json!(["read"]));
+
+    let listing = server.get("/api/list?path=public", Some(&token)).await;
+    assert_eq!(listing.status, 200);
+    assert_eq!(listing.entry_names(), vec!["readme.txt".to_string()]);
+    let favorite = server
+        .post_json(
+            "/api/favorites",
+            Some(&token),
+            &json!({ "path": "public/readme.txt", "favorite": true }),
+        )
+        .await;
+    assert_eq!(favorite.status, 403);
+    assert_eq!(
+        server.get("/api/admin/users", Some(&token)).await.status,
+        403
+    );
+
+    let listed = server.get("/api/admin/api-tokens", Some(&admin)).await;
+    assert_eq!(listed.json()["tokens"][0]["name"], "rclone");
+    assert!(listed.json()["tokens"][0].get("token").is_none());
+
+    let auth = header("Authorization", &format!("Bearer {admin}"));
+    let revoked = server
+        .send(
+            "DELETE",
+            &format!("/api/admin/api-tokens/{token_id}"),
+            &[auth],
+            None,
+        )
+        .await;
+    assert_eq!(revoked.status, 200);
+    let after = server.get("/api/list?path=public", Some(&token)).await;
+    assert_eq!(after.status, 401);
+}
+
+#[tokio::test]
+async fn text_responses_are_compressed_when_accepted() {
+    let server = TestServer::start("compression").await;
+    for index in 0..20 {
+        let name = format!("public/episode-{index:02}.txt");
+        std::fs::write(server.root.join(name), b"notes").unwrap();
+    }
+    let cue = "1\n00:00:01,000 --> 00:00:02,000\nhello\n\n";
+    std::fs::write(server.root.join("public/subs.srt"), cue.repeat(100)).unwrap();
+    let admin = server.bootstrap_admin().await;
+    let gzip = header("Accept-Encoding", "gzip");
+    let auth = header("Authorization", &format!("Bearer {admin}"));
+
+    let listing = server
+        .send("GET", "/api/list?path=public", &[auth, gzip.clone()], None)
+        .await;
+    assert_eq!(listing.status, 200);
+    assert_eq!(listing.header("content-encoding"), Some("gzip"));
+
+    let link = server.file_link(&admin, "public/subs.srt").await;
+    let subs = server.send("GET", &link, &[gzip.clone()], None).await;
+    assert_eq!(subs.status, 200);
+    assert_eq!(subs.header("content-encoding"), Some("gzip"));
+
+    let partial = server
+        .send("GET", &link, &[gzip, header("Range", "bytes=0-99")], None)
+        .await;
+    assert_eq!(partial.status, 206);
+    assert!(partial.header("content-encoding").is_none());
+
+    let identity = server.get(&link, None).await;
+    assert!(identity.header("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn precompressed_sidecars_are_served_when_accepted() {
+    let server = TestServer::start("precompressed").await;
+    std::fs::write(server.root.join("public/subs.vtt"), b"WEBVTT\n").unwrap();
+    std::fs::write(server.root.join("public/subs.vtt.gz"), b"gzip bytes").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "public/subs.vtt").await;
+
+    let sidecar = server
+        .send("GET", &link, &[header("Accept-Encoding", "br, gzip")], None)
+        .await;
+    assert_eq!(sidecar.status, 200);
+    assert_eq!(sidecar.header("content-encoding"), Some("gzip"));
+    assert_eq!(sidecar.header("content-type"), Some("text/vtt"));
+    assert_eq!(sidecar.body, b"gzip bytes");
+
+    let ranged = server
+        .send(
+            "GET",
+            &link,
+            &[
+                header("Accept-Encoding", "gzip"),
+                header("Range", "bytes=0-5"),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(ranged.status, 206);
+    assert!(ranged.header("content-encoding").is_none());
+    assert_eq!(ranged.body, b"WEBVTT");
+
+    let identity = server.get(&link, None).await;
+    assert_eq!(identity.body, b"WEBVTT\n");
+    assert_eq!(identity.header("vary"), Some("accept-encoding"));
+    let gzip_etag = sidecar.header("etag").unwrap();
+    let identity_etag = identity.header("etag").unwrap();
+    assert!(gzip_etag.ends_with("-gz\""), "{gzip_etag}");
+    assert_ne!(gzip_etag, identity_etag);
+
+    // A cached gzip body must not satisfy a client that cannot decode it.
+    let revalidated = server
+        .send("GET", &link, &[header("If-None-Match", gzip_etag)], None)
+        .await;
+    assert_eq!(revalidated.status, 200);
+    assert_eq!(revalidated.body, b"WEBVTT\n");
+    let revalidated = server
+        .send(
+            "GET",
+            &link,
+            &[
+                header("Accept-Encoding", "gzip"),
+                header("If-None-Match", gzip_etag),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(revalidated.status, 304);
+    assert_eq!(revalidated.header("vary"), Some("accept-encoding"));
+}
+
+#[tokio::test]
+async fn stale_precompressed_sidecars_are_ignored() {
+    let server = TestServer::start("stale-sidecar").await;
+    let original = server.root.join("public/subs.vtt");
+    std::fs::write(&original, b"WEBVTT\n").unwrap();
+    std::fs::write(server.root.join("public/subs.vtt.gz"), b"old gzip").unwrap();
+    // The original was replaced after its sidecar was generated.
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+    std::fs::File::options()
+        .write(true)
+        .open(&original)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    let admin = server.bootstrap_admin().await;
+    let link = server.file_link(&admin, "public/subs.vtt").await;
+
+    let response = server
+        .send("GET", &link, &[header("Accept-Encoding", "gzip")], None)
+        .await;
+    assert_eq!(response.status, 200);
+    assert!(response.header("content-encoding").is_none());
+    assert!(response.header("vary").is_none());
+    assert_eq!(response.body, b"WEBVTT\n");
+}
+
+#[tokio::test]
+async fn checksums_are_computed_and_advertised_on_downloads() {
+    let server = TestServer::start("checksum").await;
+    let admin = server.bootstrap_admin().await;
+    let sha256 = "4c5ac73dc20b13225ea240ce7e83baf16ecd5421f724fdc789044f76cbb7eb2a";
+
+    let link = server.file_link(&admin, "public/readme.txt").await;
+    let before = server.get(&link, None).await;
+    assert!(before.header("x-checksum-sha256").is_none());
+
+    let checksum = server
+        .get("/api/checksum?path=public/readme.txt", Some(&admin))
+        .await;
+    assert_eq!(checksum.status, 200);
+    let body = checksum.json();
+    assert_eq!(body["algo"], "sha256");
+    assert_eq!(body["digest"], sha256);
+    assert_eq!(body["size"], 12);
+
+    let md5 = server
+        .get(
+            "/api/checksum?path=public/readme.txt&algo=md5",
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(md5.json()["digest"], "fb13a8c22443381d78f0a76c00e35722");
+
+    let unsupported = server
+        .get(
+            "/api/checksum?path=public/readme.txt&algo=crc32",
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(unsupported.status, 400);
+
+    let after = server.get(&link, None).await;
+    assert_eq!(after.header("x-checksum-sha256"), Some(sha256));
+}
+
+#[tokio::test]
+async fn text_files_are_previewed_as_utf8() {
+    let server = TestServer::start_with("preview", |config| {
+        config.preview_max_bytes = 32;
+    })
+    .await;
+    std::fs::write(server.root.join("public/job.log"), b"one\ntwo\nthree\n").unwrap();
+    std::fs::write(
+        server.root.join("public/release.nfo"),
+        b"Caf\xe9 cr\xe8me\n",
+    )
+    .unwrap();
+    std::fs::write(server.root.join("public/long.txt"), "line\n".repeat(20)).unwrap();
+    std::fs::write(server.root.join("public/blob.dat"), b"\0\x01\x02binary").unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let log = server
+        .get("/api/preview?path=public/job.log&lines=2", Some(&admin))
+        .await;
+    assert_eq!(log.status, 200);
+    let body = log.json();
+    assert_eq!(body["content"], "one\ntwo\n");
+    assert_eq!(body["lines"], 2);
+    assert_eq!(body["truncated"], true);
+
+    let nfo = server
+        .get("/api/preview?path=public/release.nfo", Some(&admin))
+        .await;
+    assert_eq!(nfo.json()["encoding"], "windows-1252");
+    assert_eq!(nfo.json()["content"], "Caf\u{e9} cr\u{e8}me\n");
+
+    let long = server
+        .get("/api/preview?path=public/long.txt", Some(&admin))
+        .await;
+    assert_eq!(long.json()["lines"], 6);
+    assert_eq!(long.json()["truncated"], true);
+
+    let binary = server
+        .get("/api/preview?path=public/blob.dat", Some(&admin))
+        .await;
+    assert_eq!(binary.status, 415);
+    let private = server
+        .get("/api/preview?path=private/secret.txt", None)
+        .await;
+    assert_eq!(private.status, 401);
+}
+
+#[tokio::test]
+async fn zip_members_can_be_listed_and_streamed() {
+    use std::io::Write;
+
+    let server = TestServer::start("archive").await;
+    let file = std::fs::File::create(server.root.join("public/bundle.zip")).unwrap();
+    let mut writer = zip::ZipWriter::new(file);
+    let deflated = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    writer.add_directory("docs/", deflated).unwrap();
+    writer.start_file("docs/notes.txt", deflated).unwrap();
+    writer.write_all(&b"release notes\n".repeat(50)).unwrap();
+    writer.finish().unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let listing = server
+        .get("/api/archive/list?path=public/bundle.zip", Some(&admin))
+        .await;
+    assert_eq!(listing.status, 200);
+    let body = listing.json();
+    assert_eq!(body["truncated"], false);
+    assert_eq!(body["entries"][0]["name"], "docs/");
+    assert_eq!(body["entries"][0]["kind"], "dir");
+    assert_eq!(body["entries"][1]["name"], "docs/notes.txt");
+    assert_eq!(body["entries"][1]["size"], 700);
+
+    let member = server
+        .get(
+            "/api/archive/file?path=public/bundle.zip&inner=docs/notes.txt",
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(member.status, 200);
+    assert_eq!(member.header("content-type"), Some("text/plain"));
+    assert_eq!(member.body, b"release notes\n".repeat(50));
+
+    let missing = server
+        .get(
+            "/api/archive/file?path=public/bundle.zip&inner=docs/missing.txt",
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(missing.status, 404);
+
+    let not_archive = server
+        .get("/api/archive/list?path=public/readme.txt", Some(&admin))
+        .await;
+    assert_eq!(not_archive.status, 400);
+}
+
+#[tokio::test]
+async fn listings_include_the_directory_readme() {
+    let server = TestServer::start_with("dir-readme", |config| {
+        config.readme_max_bytes = 16;
+    })
+    .await;
+    std::fs::write(
+        server.root.join("public/README.md"),
+        b"# Release\n\nChangelog follows.\n",
+    )
+    .unwrap();
+    std::fs::create_dir_all(server.root.join("public/hidden")).unwrap();
+    std::fs::write(server.root.join("public/hidden/README.md"), b"secret").unwrap();
+    std::fs::write(server.root.join("public/hidden/.private.README.md"), b"").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "ivy").await;
+    let user = server.login_token("ivy", &secret).await;
+
+    let listing = server.get("/api/list?path=public", Some(&user)).await;
+    let readme = &listing.json()["readme"];
+    assert_eq!(readme["name"], "README.md");
+    assert_eq!(readme["content"], "# Release\n\nChang");
+    assert_eq!(readme["truncated"], true);
+
+    let hidden = server
+        .get("/api/list?path=public/hidden", Some(&user))
+        .await;
+    assert!(hidden.json()["readme"].is_null());
+    let admin_view = server
+        .get("/api/list?path=public/hidden", Some(&admin))
+        .await;
+    assert_eq!(admin_view.json()["readme"]["content"], "secret");
+
+    let root = server.get("/api/list", Some(&user)).await;
+    assert!(root.json()["readme"].is_null());
+}
+
+#[tokio::test]
+async fn listings_answer_if_none_match_with_not_modified() {
+    let server = TestServer::start("list-etag").await;
+    let admin = server.bootstrap_admin().await;
+    let auth = header("Authorization", &format!("Bearer {admin}"));
+    let target = "/api/list?path=public";
+
+    let first = server.get(target, Some(&admin)).await;
+    assert_eq!(first.status, 200);
+    let etag = first.header("etag").unwrap().to_string();
+    assert!(etag.starts_with("W/\"list-"));
+
+    let unchanged = server
+        .send(
+            "GET",
+            target,
+            &[auth.clone(), header("If-None-Match", &etag)],
+            None,
+        )
+        .await;
+    assert_eq!(unchanged.status, 304);
+    assert!(unchanged.body.is_empty());
+    // Only the listing that was actually sent is recorded.
+    let events = server.get("/api/admin/audit/events", Some(&admin)).await;
+    let listed = events.json()["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|event| event["path"] == "public" && event["route"] == "/api/list")
+        .count();
+    assert_eq!(listed, 1);
+
+    // Editing a file in place leaves the directory mtime alone but still
+    // changes the tag.
+    std::fs::write(server.root.join("public/readme.txt"), "longer than before").unwrap();
+    let edited = server
+        .send(
+            "GET",
+            target,
+            &[auth.clone(), header("If-None-Match", &etag)],
+            None,
+        )
+        .await;
+    assert_eq!(edited.status, 200);
+    let edited_etag = edited.header("etag").unwrap().to_string();
+    assert_ne!(edited_etag, etag);
+
+    let favorite = server
+        .post_json(
+            "/api/favorites",
+            Some(&admin),
+            &json!({ "path": "public/readme.txt", "favorite": true }),
+        )
+        .await;
+    assert_eq!(favorite.status, 200);
+    let favorited = server
+        .send(
+            "GET",
+            target,
+            &[auth, header("If-None-Match", &edited_etag)],
+            None,
+        )
+        .await;
+    assert_eq!(favorited.status, 200);
+    assert_eq!(favorited.json()["entries"][0]["favorite"], true);
+}
+
+#[tokio::test]
+async fn pinned_entries_lead_the_listing_in_order() {
+    let server = TestServer::start("dir-config-pin").await;
+    std::fs::create_dir_all(server.root.join("public/docs")).unwrap();
+    std::fs::write(server.root.join("public/a.txt"), b"a").unwrap();
+    std::fs::write(server.root.join("public/b.txt"), b"b").unwrap();
+    std::fs::write(server.root.join("public/z-first.txt"), b"z").unwrap();
+    std::fs::write(
+        server.root.join("public/.mlist.toml"),
+        "pin = [\"z-first.txt\", \"missing.txt\", \"b.txt\"]\n",
+    )
+    .unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let listing = server.get("/api/list?path=public", Some(&admin)).await;
+    assert_eq!(
+        listing.entry_names(),
+        vec!["z-first.txt", "b.txt", "docs", "a.txt", "readme.txt"]
+    );
+    let body = listing.json();
+    assert_eq!(body["entries"][0]["pinned"], true);
+    assert_eq!(body["entries"][2]["pinned"], false);
+
+    // Pins stay on top whatever the requested sort and direction.
+    let by_size = server
+        .get("/api/list?path=public&sort=size&order=desc", Some(&admin))
+        .await;
+    assert_eq!(
+        by_size.entry_names(),
+        vec!["z-first.txt", "b.txt", "readme.txt", "a.txt", "docs"]
+    );
+}
+
+#[tokio::test]
+async fn directory_config_files_tune_listings_and_downloads() {
+    let server = TestServer::start("dir-config").await;
+    std::fs::write(server.root.join("public/a.txt"), b"a").unwrap();
+    std::fs::write(server.root.join("public/b.srt"), b"b").unwrap();
+    std::fs::write(
+        server.root.join("public/.mlist.toml"),
+        "sort = \"name\"\norder = \"desc\"\nhide = [\"*.srt\"]\n\
+         download = \"attachment\"\ndescription = \"Shared files\"\n",
+    )
+    .unwrap();
+    std::fs::write(server.root.join("big/.mlist.toml"), "listing = false\n").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "uma").await;
+    let user = server.login_token("uma", &secret).await;
+
+    let listing = server.get("/api/list?path=public", Some(&user)).await;
+    assert_eq!(listing.entry_names(), vec!["readme.txt", "a.txt"]);
+    assert_eq!(listing.json()["description"], "Shared files");
+    let ascending = server
+        .get("/api/list?path=public&order=asc", Some(&user))
+        .await;
+    assert_eq!(ascending.entry_names(), vec!["a.txt", "readme.txt"]);
+
+    let link = server.file_link(&user, "public/a.txt").await;
+    let download = server.get(&link, None).await;
+    assert!(
+        download
+            .header("content-disposition")
+            .unwrap()
+            .starts_with("attachment")
+    );
+    let config_file = server
+        .post_json(
+            "/api/file-link",
+            Some(&user),
+            &json!({ "path": "public/.mlist.toml" }),
+        )
+        .await;
+    assert_eq!(config_file.status, 404);
+
+    let unlisted = server.get("/api/list?path=big", Some(&user)).await;
+    assert_eq!(unlisted.status, 403);
+    let admin_view = server.get("/api/list?path=big", Some(&admin)).await;
+    assert_eq!(admin_view.entry_names(), vec!["sparse.bin"]);
+    // Files in an unlisted directory stay reachable through links.
+    server.file_link(&user, "big/sparse.bin").await;
+}
+
+#[tokio::test]
+async fn directory_config_applies_to_every_way_of_enumerating() {
+    use base64::Engine;
+
+    let server = TestServer::start("dir-config-apis").await;
+    std::fs::write(server.root.join("public/notes.srt"), b"s").unwrap();
+    std::fs::write(
+        server.root.join("public/.mlist.toml"),
+        "hide = [\"*.srt\"]\n",
+    )
+    .unwrap();
+    std::fs::write(server.root.join("big/.mlist.toml"), "listing = false\n").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "uma").await;
+    let user = server.login_token("uma", &secret).await;
+
+    let hidden = server.get("/api/search?q=notes", Some(&user)).await;
+    assert!(hidden.entry_names().is_empty());
+    let unlisted = server.get("/api/search?q=sparse", Some(&user)).await;
+    assert!(unlisted.entry_names().is_empty());
+    let admin_search = server.get("/api/search?q=sparse", Some(&admin)).await;
+    assert_eq!(admin_search.entry_names(), vec!["sparse.bin"]);
+    let refused = server.get("/api/search?q=s&path=big", Some(&user)).await;
+    assert_eq!(refused.status, 403);
+
+    let tree = server.get("/api/tree?files=true", Some(&user)).await;
+    assert_eq!(tree.status, 200);
+    let tree = tree.json().to_string();
+    assert!(tree.contains("\"big\""));
+    assert!(!tree.contains("sparse.bin"));
+    assert!(!tree.contains("notes.srt"));
+    let refused = server.get("/api/tree?path=big", Some(&user)).await;
+    assert_eq!(refused.status, 403);
+    let refused = server.get("/api/events?path=big", Some(&user)).await;
+    assert_eq!(refused.status, 403);
+
+    let archive = server
+        .post_json(
+            "/api/batch-download",
+            Some(&user),
+            &json!({ "paths": ["big", "public"] }),
+        )
+        .await;
+    assert_eq!(archive.status, 200);
+    let names = tar_entries(&archive.body)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert!(names.contains(&"big/".to_string()));
+    assert!(names.contains(&"public/readme.txt".to_string()));
+    assert!(!names.iter().any(|name| name.ends_with("sparse.bin")));
+    assert!(!names.iter().any(|name| name.ends_with("notes.srt")));
+
+    let minted = server
+        .post_json("/api/dav-token", Some(&user), &json!({}))
+        .await;
+    let token = minted.json()["token"].as_str().unwrap().to_string();
+    let credentials = base64::engine::general_purpose::STANDARD.encode(format!("uma:{token}"));
+    let basic = header("Authorization", &format!("Basic {credentials}"));
+    let public = server
+        .send(
+            "PROPFIND",
+            "/dav/public/",
+            &[basic.clone(), header("Depth", "1")],
+            None,
+        )
+        .await;
+    assert_eq!(public.status, 207);
+    let public = String::from_utf8(public.body).unwrap();
+    assert!(public.contains("readme.txt"));
+    assert!(!public.contains("notes.srt"));
+    let refused = server
+        .send(
+            "PROPFIND",
+            "/dav/big/",
+            &[basic, header("Depth", "1")],
+            None,
+        )
+        .await;
+    assert_eq!(refused.status, 403);
+
+    // Files in either directory stay reachable by path.
+    server.file_link(&user, "big/sparse.bin").await;
+    server.file_link(&user, "public/notes.srt").await;
+}
+
+#[tokio::test]
+async fn aliases_expose_nested_directories_at_the_root() {
+    let server = TestServer::start_with("aliases", |config| {
+        config.aliases = [("docs".to_string(), "public".to_string())].into();
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+
+    let listing = server.get("/api/list?path=docs", Some(&admin)).await;
+    assert_eq!(listing.status, 200);
+    assert_eq!(listing.json()["path"], "public");
+    assert_eq!(listing.entry_names(), vec!["readme.txt"]);
+
+    let link = server.file_link(&admin, "docs/readme.txt").await;
+    let download = server.get(&link, None).await;
+    assert_eq!(download.body, README_BODY);
+}
+
+#[tokio::test]
+async fn listings_can_carry_media_metadata() {
+    let server = TestServer::start("media-meta").await;
+    image::RgbImage::new(40, 30)
+        .save(server.root.join("public/cover.png"))
+        .unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let entry = |listing: &serde_json::Value, name: &str| {
+        listing["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["name"] == name)
+            .cloned()
+            .unwrap()
+    };
+
+    let plain = server.get("/api/list?path=public", Some(&admin)).await;
+    assert!(entry(&plain.json(), "cover.png")["extra"].is_null());
+
+    let rich = server
+        .get("/api/list?path=public&withMeta=true", Some(&admin))
+        .await;
+    let listing = rich.json();
+    let cover = entry(&listing, "cover.png");
+    assert_eq!(cover["extra"]["width"], 40);
+    assert_eq!(cover["extra"]["height"], 30);
+    assert!(entry(&listing, "readme.txt")["extra"].is_null());
+}
+
+#[tokio::test]
+async fn tree_returns_nested_directories_in_one_call() {
+    let server = TestServer::start("tree").await;
+    std::fs::create_dir_all(server.root.join("public/season 1/extras")).unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "kai").await;
+    let user = server.login_token("kai", &secret).await;
+
+    let child = |node: &serde_json::Value, name: &str| {
+        node["children"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|child| child["name"] == name)
+            .cloned()
+    };
+
+    let tree = server.get("/api/tree", Some(&user)).await.json();
+    let root = &tree["root"];
+    assert!(child(root, "private").is_none());
+    let public = child(root, "public").unwrap();
+    assert!(child(&public, "readme.txt").is_none());
+    let season = child(&public, "season 1").unwrap();
+    assert_eq!(season["path"], "public/season 1");
+    assert!(child(&season, "extras").is_some());
+    assert_eq!(tree["truncated"], false);
+
+    let shallow = server
+        .get("/api/tree?path=public&depth=1&files=true", Some(&user))
+        .await
+        .json();
+    assert!(child(&shallow["root"], "readme.txt").is_some());
+    let season = child(&shallow["root"], "season 1").unwrap();
+    assert!(season.get("children").is_none());
+
+    let admin_tree = server.get("/api/tree", Some(&admin)).await.json();
+    let private = child(&admin_tree["root"], "private").unwrap();
+    assert_eq!(private["requiresAuth"], true);
+    assert!(child(&private, "nested").is_some());
+
+    let denied = server.get("/api/tree?path=private", Some(&user)).await;
+    assert_eq!(denied.status, 404);
+}
+
+#[tokio::test]
+async fn listings_sort_numbers_naturally() {
+    let server = TestServer::start("natural-sort").await;
+    for name in ["Episode 10.mkv", "Episode 2.mkv", "Episode 1.mkv"] {
+        std::fs::write(server.root.join("public").join(name), b"").unwrap();
+    }
+    let admin = server.bootstrap_admin().await;
+
+    let plain = server
+        .get("/api/list?path=public&sort=name", Some(&admin))
+        .await;
+    assert_eq!(
+        plain.entry_names()[..3],
+        ["Episode 1.mkv", "Episode 10.mkv", "Episode 2.mkv"]
+    );
+    let natural = server
+        .get("/api/list?path=public&sort=natural", Some(&admin))
+        .await;
+    assert_eq!(
+        natural.entry_names()[..3],
+        ["Episode 1.mkv", "Episode 2.mkv", "Episode 10.mkv"]
+    );
+
+    let by_default = TestServer::start_with("natural-sort-default", |config| {
+        config.natural_sort = true;
+    })
+    .await;
+    for name in ["Episode 10.mkv", "Episode 2.mkv"] {
+        std::fs::write(by_default.root.join("public").join(name), b"").unwrap();
+    }
+    let admin = by_default.bootstrap_admin().await;
+    let listing = by_default.get("/api/list?path=public", Some(&admin)).await;
+    assert_eq!(
+        listing.entry_names(),
+        ["Episode 2.mkv", "Episode 10.mkv", "readme.txt"]
+    );
+}
+
+#[tokio::test]
+async fn listings_stream_as_ndjson() {
+    let server = TestServer::start("list-ndjson").await;
+    std::fs::create_dir_all(server.root.join("public/hidden")).unwrap();
+    std::fs::write(server.root.join("public/hidden/.private"), b"").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "jun").await;
+    let user = server.login_token("jun", &secret).await;
+
+    let names = |response: &common::TestResponse| {
+        let mut names = std::str::from_utf8(&response.body)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+                entry["name"].as_str().unwrap().to_string()
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+
+    let by_query = server
+        .get("/api/list?path=public&stream=true", Some(&user))
+        .await;
+    assert_eq!(by_query.status, 200);
+    assert_eq!(
+        by_query.header("content-type"),
+        Some("application/x-ndjson")
+    );
+    assert_eq!(names(&by_query), vec!["readme.txt"]);
+
+    let by_accept = server
+        .send(
+            "GET",
+            "/api/list?path=public",
+            &[
+                header("Authorization", &format!("Bearer {admin}")),
+                header("Accept", "application/x-ndjson"),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(names(&by_accept), vec!["hidden", "readme.txt"]);
+
+    let missing = server
+        .get("/api/list?path=nope&stream=true", Some(&user))
+        .await;
+    assert_eq!(missing.status, 404);
+}
+
+#[tokio::test]
+async fn images_are_resized_and_reencoded_on_request() {
+    let server = TestServer::start("image-resize").await;
+    let admin = server.bootstrap_admin().await;
+    image::RgbImage::from_pixel(400, 200, image::Rgb([20, 120, 220]))
+        .save(server.root.join("public/photo.png"))
+        .unwrap();
+
+    let webp = server
+        .get(
+            "/api/image?path=public/photo.png&w=100&format=webp",
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(webp.status, 200);
+    assert_eq!(webp.header("content-type"), Some("image/webp"));
+    let decoded = image::load_from_memory(&webp.body).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (100, 50));
+
+    // A box larger than the source never upscales.
+    let original = server
+        .get("/api/image?path=public/photo.png&w=4000", Some(&admin))
+        .await;
+    assert_eq!(original.header("content-type"), Some("image/jpeg"));
+    let decoded = image::load_from_memory(&original.body).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (400, 200));
+
+    let bad_format = server
+        .get("/api/image?path=public/photo.png&format=tiff", Some(&admin))
+        .await;
+    assert_eq!(bad_format.status, 400);
+
+    let not_image = server
+        .get("/api/image?path=public/readme.txt", Some(&admin))
+        .await;
+    assert_eq!(not_image.status, 400);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn videos_are_restreamed_as_hls() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // Stands in for ffmpeg: writes one segment and a finished playlist to
+    // the output path, which is always the last argument.
+    let ffmpeg = std::env::temp_dir().join(format!(
+        "mlist-fake-ffmpeg-{}.sh",
+        uuid::Uuid::new_v4().simple()
+    ));
+    std::fs::write(
+        &ffmpeg,
+        "#!/bin/sh\n\
+         for last in \"$@\"; do :; done\n\
+         dir=$(dirname \"$last\")\n\
+         printf 'segment' > \"$dir/seg-00000.ts\"\n\
+         printf '#EXTM3U\\n#EXTINF:6.0,\\nseg-00000.ts\\n#EXT-X-ENDLIST\\n' > \"$last\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let server = TestServer::start_with("hls", |config| {
+        config.hls_enabled = true;
+        config.ffmpeg_path = Some(ffmpeg.clone());
+    })
+    .await;
+    std::fs::write(server.root.join("public/clip.mkv"), b"not really a video").unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let playlist = server
+        .get("/api/hls/public/clip.mkv/master.m3u8", Some(&admin))
+        .await;
+    assert_eq!(playlist.status, 200);
+    assert_eq!(
+        playlist.header("content-type"),
+        Some("application/vnd.apple.mpegurl")
+    );
+    assert!(String::from_utf8_lossy(&playlist.body).contains("\nseg-00000.ts\n"));
+
+    let segment = server
+        .get("/api/hls/public/clip.mkv/seg-00000.ts", Some(&admin))
+        .await;
+    assert_eq!(segment.status, 200);
+    assert_eq!(segment.header("content-type"), Some("video/mp2t"));
+    assert_eq!(segment.body, b"segment");
+
+    // Players without a bearer header follow the token through the playlist.
+    let link = server.file_link(&admin, "public/clip.mkv").await;
+    let token = link.split_once("token=").unwrap().1;
+    let signed = server
+        .get(
+            &format!("/api/hls/public/clip.mkv/master.m3u8?token={token}"),
+            None,
+        )
+        .await;
+    assert_eq!(signed.status, 200);
+    let body = String::from_utf8_lossy(&signed.body).to_string();
+    let uri = body.lines().find(|line| line.starts_with("seg-")).unwrap();
+    assert_eq!(uri, format!("seg-00000.ts?token={token}"));
+    let signed_segment = server
+        .get(&format!("/api/hls/public/clip.mkv/{uri}"), None)
+        .await;
+    assert_eq!(signed_segment.status, 200);
+
+    let anonymous = server
+        .get("/api/hls/public/clip.mkv/master.m3u8", None)
+        .await;
+    assert_eq!(anonymous.status, 401);
+
+    let escape = server
+        .get("/api/hls/public/clip.mkv/index.m3u8", Some(&admin))
+        .await;
+    assert_eq!(escape.status, 404);
+
+    let not_video = server
+        .get("/api/hls/public/readme.txt/master.m3u8", Some(&admin))
+        .await;
+    assert_eq!(not_video.status, 400);
+
+    let _ = std::fs::remove_file(&ffmpeg);
+}
+
+#[tokio::test]
+async fn directories_export_m3u_playlists_with_signed_links() {
+    let server = TestServer::start("playlist").await;
+    let album = server.root.join("public/album");
+    std::fs::create_dir_all(album.join("cd2")).unwrap();
+    std::fs::write(album.join("01 intro.mp3"), b"intro").unwrap();
+    std::fs::write(album.join("cd2/02 outro.flac"), b"outro").unwrap();
+    std::fs::write(album.join("notes.txt"), b"liner notes").unwrap();
+    std::fs::write(server.root.join("private/song.mp3"), b"secret").unwrap();
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "grace").await;
+    let user = server.login_token("grace", &secret).await;
+
+    let tracks = |response: &common::TestResponse| {
+        String::from_utf8_lossy(&response.body)
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+
+    let flat = server
+        .get("/api/playlist?path=public/album", Some(&user))
+        .await;
+    assert_eq!(flat.status, 200);
+    assert!(String::from_utf8_lossy(&flat.body).starts_with("#EXTM3U\n#EXTINF:-1,01 intro\n"));
+    let flat_tracks = tracks(&flat);
+    assert_eq!(flat_tracks.len(), 1);
+    let prefix = format!(
+        "http://{}/d/public/album/01%20intro.mp3?token=",
+        server.addr
+    );
+    assert!(flat_tracks[0].starts_with(&prefix), "{}", flat_tracks[0]);
+
+    // The links carry their own token and need no session.
+    let target = flat_tracks[0].trim_start_matches(&format!("http://{}", server.addr));
+    let download = server.get(target, None).await;
+    assert_eq!(download.status, 200);
+    assert_eq!(download.body, b"intro");
+
+    let deep = server
+        .get("/api/playlist?path=public&recursive=true", Some(&user))
+        .await;
+    let deep_tracks = tracks(&deep);
+    assert_eq!(deep_tracks.len(), 2);
+    assert!(deep_tracks[1].contains("/d/public/album/cd2/02%20outro.flac?token="));
+
+    let everything = server
+        .get("/api/playlist?path=&recursive=true", Some(&user))
+        .await;
+    assert!(
+        tracks(&everything)
+            .iter()
+            .all(|url| !url.contains("song.mp3"))
+    );
+    let admin_view = server
+        .get("/api/playlist?path=&recursive=true", Some(&admin))
+        .await;
+    assert!(
+        tracks(&admin_view)
+            .iter()
+            .any(|url| url.contains("/d/private/song.mp3"))
+    );
+
+    let hidden = server.get("/api/playlist?path=private", Some(&user)).await;
+    assert_eq!(hidden.status, 404);
+    let anonymous = server.get("/api/playlist?path=public", None).await;
+    assert_eq!(anonymous.status, 401);
+}
+
+#[tokio::test]
+async fn atom_feed_lists_newest_public_files() {
+    let disabled = TestServer::start("feed-off").await;
+    assert_eq!(
+        disabled.get("/api/feed.xml?path=public", None).await.status,
+        404
+    );
+
+    let server = TestServer::start_with("feed", |config| {
+        config.feed_enabled = true;
+    })
+    .await;
+    let releases = server.root.join("public/releases");
+    std::fs::create_dir_all(releases.join("drafts")).unwrap();
+    std::fs::write(releases.join("drafts/.nofeed"), b"").unwrap();
+    std::fs::write(releases.join("drafts/wip.zip"), b"wip").unwrap();
+    let base = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    for (offset, name) in [(0, "v1.0 & notes.zip"), (60, "v1.1.zip")] {
+        let file = std::fs::File::create(releases.join(name)).unwrap();
+        file.set_modified(base + std::time::Duration::from_secs(offset))
+            .unwrap();
+    }
+
+    let feed = server.get("/api/feed.xml?path=public", None).await;
+    assert_eq!(feed.status, 200);
+    assert_eq!(
+        feed.header("content-type"),
+        Some("application/atom+xml; charset=utf-8")
+    );
+    let body = String::from_utf8_lossy(&feed.body).to_string();
+    let newest = body.find("<title>v1.1.zip</title>").unwrap();
+    let older = body.find("<title>v1.0 &amp; notes.zip</title>").unwrap();
+    assert!(newest < older);
+    assert!(body.contains("/public/releases/v1.1.zip\"/>"));
+    assert!(!body.contains("wip.zip"));
+    assert!(!body.contains(".nofeed"));
+
+    let opted_out = server
+        .get("/api/feed.xml?path=public/releases/drafts", None)
+        .await;
+    assert_eq!(opted_out.status, 404);
+    let private = server.get("/api/feed.xml?path=private", None).await;
+    assert_eq!(private.status, 404);
+}
+
+#[tokio::test]
+async fn public_index_and_sitemap_list_only_public_files() {
+    let disabled = TestServer::start("public-index-off").await;
+    assert_eq!(disabled.get("/sitemap.xml", None).await.status, 404);
+
+    let server = TestServer::start_with("public-index", |config| {
+        config.index_enabled = true;
+        config.public_index_enabled = true;
+    })
+    .await;
+    let mut page = server.get("/api/index?limit=2", None).await;
+    for _ in 0..50 {
+        if page.status != 503 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        page = server.get("/api/index?limit=2", None).await;
+    }
+    assert_eq!(page.status, 200);
+    let body = page.json();
+    assert_eq!(body["total"], 3);
+    assert_eq!(body["nextOffset"], 2);
+    assert_eq!(body["files"][0]["path"], "big/sparse.bin");
+    assert_eq!(body["files"][1]["path"], "public/readme.txt");
+    assert_eq!(body["files"][1]["url"], "/d/public/readme.txt");
+
+    let etag = page.header("etag").unwrap().to_string();
+    let cached = server
+        .send(
+            "GET",
+            "/api/index?limit=2",
+            &[header("If-None-Match", &etag)],
+            None,
+        )
+        .await;
+    assert_eq!(cached.status, 304);
+
+    // Markers added after the crawl take effect before the next one.
+    std::fs::write(server.root.join("public/.private"), b"").unwrap();
+    let sitemap = server.get("/sitemap.xml", None).await;
+    assert_eq!(sitemap.status, 200);
+    let body = String::from_utf8_lossy(&sitemap.body).to_string();
+    assert!(body.contains("/d/%E7%94%B5%E5%BD%B1/%E7%89%87%E6%AE%B5%20%E4%B8%80.mp4</loc>"));
+    assert!(!body.contains("readme.txt"));
+    assert!(!body.contains("secret.txt"));
+}
+
+#[tokio::test]
+async fn directory_changes_are_pushed_over_sse() {
+    let server = TestServer::start("events").await;
+    let admin = server.bootstrap_admin().await;
+    let secret = server.create_user(&admin, "heidi").await;
+    let user = server.login_token("heidi", &secret).await;
+
+    let anonymous = server.get("/api/events?path=public", None).await;
+    assert_eq!(anonymous.status, 401);
+    let private = server.get("/api/events?path=private", Some(&user)).await;
+    assert_eq!(private.status, 404);
+
+    // The stream never ends, so it is read off a raw socket until the
+    // expected event shows up.
+    let mut received = Vec::new();
+    let mut stream = server
+        .open_stream("/api/events?path=public", &user, &mut received)
+        .await;
+    std::fs::write(server.root.join("public/fresh.txt"), b"new").unwrap();
+    read_until(&mut stream, &mut received, "public/fresh.txt").await;
+
+    let text = String::from_utf8_lossy(&received).to_string();
+    assert!(text.starts_with("HTTP/1.1 200"));
+    assert!(text.contains("text/event-stream"));
+    assert!(text.contains("event: created"));
+
+    // A private file's name must not leak, not even when it is deleted;
+    // entries the subscriber could see are still reported gone.
+    std::fs::write(server.root.join("public/.private.hidden.txt"), b"").unwrap();
+    std::fs::write(server.root.join("public/hidden.txt"), b"secret").unwrap();
+    std::fs::remove_file(server.root.join("public/hidden.txt")).unwrap();
+    std::fs::remove_file(server.root.join("public/readme.txt")).unwrap();
+    read_until(
+        &mut stream,
+        &mut received,
+        r#"{"kind":"deleted","path":"public/readme.txt"}"#,
+    )
+    .await;
+    assert!(!String::from_utf8_lossy(&received).contains("hidden.txt"));
+}
+
+#[tokio::test]
+async fn appended_log_lines_are_streamed() {
+    use std::io::Write;
+
+    let server = TestServer::start("tail").await;
+    let log_path = server.root.join("public/job.log");
+    let old_lines: String = (1..=30).map(|n| format!("old {n}\n")).collect();
+    std::fs::write(&log_path, old_lines).unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    std::fs::write(server.root.join("public/clip.mp4"), b"").unwrap();
+    let video = server
+        .get("/api/tail?path=public/clip.mp4", Some(&admin))
+        .await;
+    assert_eq!(video.status, 415);
+
+    let mut received = Vec::new();
+    let mut stream = server
+        .open_stream(
+            "/api/tail?path=public/job.log&lines=2",
+            &admin,
+            &mut received,
+        )
+        .await;
+    read_until(&mut stream, &mut received, "old 30").await;
+    let backlog = String::from_utf8_lossy(&received).to_string();
+    assert!(backlog.contains(r#"{"lines":["old 29","old 30"]}"#));
+
+    let mut log = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&log_path)
+        .unwrap();
+    log.write_all(b"step one\nstep ").unwrap();
+    log.flush().unwrap();
+    read_until(&mut stream, &mut received, "step one").await;
+    log.write_all(b"two\n").unwrap();
+    log.flush().unwrap();
+    read_until(&mut stream, &mut received, "step two").await;
+    assert!(String::from_utf8_lossy(&received).contains("event: append"));
+}
+
+#[tokio::test]
+async fn api_is_served_under_the_base_path() {
+    let server = TestServer::start_with("base-path", |config| {
+        config.base_path = "files/".to_string();
+    })
+    .await;
+
+    let me = server.get("/files/api/me", None).await;
+    assert_eq!(me.status, 200);
+    assert_eq!(me.json()["needsBootstrap"], true);
+
+    assert_eq!(server.get("/api/me", None).await.status, 404);
+    let unknown = server.get("/files/api/nope", None).await;
+    assert_eq!(unknown.status, 404);
+    assert_eq!(unknown.json()["code"], "NOT_FOUND");
+}
+
+/// Splits a tar stream into `(name, contents)` pairs, skipping the
+/// end-of-archive blocks.
+fn tar_entries(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 512 <= archive.len() && archive[offset] != 0 {
+        let header = &archive[offset..offset + 512];
+        let name = String::from_utf8_lossy(&header[..100])
+            .trim_end_matches('\0')
+            .to_string();
+        let size = std::str::from_utf8(&header[124..135]).unwrap();
+        let size = usize::from_str_radix(size, 8).unwrap();
+        let start = offset + 512;
+        entries.push((name, archive[start..start + size].to_vec()));
+        offset = start + size.div_ceil(512) * 512;
+    }
+    entries
+}
+
+#[tokio::test]
+async fn batch_download_streams_selected_paths_as_tar() {
+    let server = TestServer::start("batch-download").await;
+    let admin = server.bootstrap_admin().await;
+
+    let response = server
+        .post_json(
+            "/api/batch-download",
+            Some(&admin),
+            &json!({ "paths": ["public/readme.txt", "public", "private/secret.txt"] }),
+        )
+        .await;
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("content-type"), Some("application/x-tar"));
+    assert_eq!(
+        response.header("content-length"),
+        Some(response.body.len().to_string().as_str())
+    );
+    let entries = tar_entries(&response.body);
+    let names = entries
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert!(names.contains(&"public/"));
+    assert_eq!(
+        names
+            .iter()
+            .filter(|name| **name == "public/readme.txt")
+            .count(),
+        1
+    );
+    let secret = entries
+        .iter()
+        .find(|(name, _)| name == "private/secret.txt")
+        .unwrap();
+    assert_eq!(secret.1, b"top secret");
+    let readme = entries
+        .iter()
+        .find(|(name, _)| name == "public/readme.txt")
+        .unwrap();
+    assert_eq!(readme.1, README_BODY);
+
+    let nested = server
+        .post_json(
+            "/api/batch-download",
+            Some(&admin),
+            &json!({ "paths": ["private/nested"] }),
+        )
+        .await;
+    assert_eq!(nested.status, 200);
+    let names = tar_entries(&nested.body)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["nested/", "nested/deep.txt"]);
+
+    let secret = server.create_user(&admin, "bob").await;
+    let bob = server.login_token("bob", &secret).await;
+    let denied = server
+        .post_json(
+            "/api/batch-download",
+            Some(&bob),
+            &json!({ "paths": ["public/readme.txt", "private/secret.txt"] }),
+        )
+        .await;
+    assert_eq!(denied.status, 404);
+    let empty = server
+        .post_json("/api/batch-download", Some(&bob), &json!({ "paths": [] }))
+        .await;
+    assert_eq!(empty.status, 400);
+}
+
+#[tokio::test]
+async fn json_bodies_above_the_limit_are_rejected() {
+    let server = TestServer::start_with("body-limit", |config| {
+        config.json_body_limit_bytes = 1024;
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+
+    let oversized = server
+        .post_json(
+            "/api/auth/login",
+            None,
+            &json!({ "username": "x".repeat(2048), "code": "000000" }),
+        )
+        .await;
+    assert_eq!(oversized.status, 413);
+
+    // Uploads keep their own, much larger limit.
+    std::fs::write(server.root.join("public/.writable"), b"").unwrap();
+    let uploaded = server
+        .post_multipart(
+            "/api/upload?path=public",
+            Some(&admin),
+            &[("large.bin", vec![7u8; 4096].as_slice())],
+        )
+        .await;
+    assert_eq!(uploaded.status, 200);
+}
+
+#[tokio::test]
+async fn photo_metadata_can_be_read_and_stripped() {
+    let server = TestServer::start("exif").await;
+    let admin = server.bootstrap_admin().await;
+
+    // SOI, an Exif APP1 segment with an empty TIFF header, then the scan.
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x10];
+    jpeg.extend(b"Exif\0\0MM\0*\0\0\0\x08");
+    let kept = [0xFF, 0xDA, 0x00, 0x02, 0x12, 0x34, 0xFF, 0xD9];
+    jpeg.extend(kept);
+    std::fs::write(server.root.join("public/photo.jpg"), &jpeg).unwrap();
+
+    let info = server
+        .get("/api/exif?path=public/photo.jpg", Some(&admin))
+        .await;
+    assert_eq!(info.status, 200);
+    assert_eq!(info.json()["path"], "public/photo.jpg");
+    assert!(info.json().get("gps").is_none_or(|gps| gps.is_null()));
+    let not_image = server
+        .get("/api/exif?path=public/readme.txt", Some(&admin))
+        .await;
+    assert_eq!(not_image.status, 415);
+
+    let link = server.file_link(&admin, "public/photo.jpg").await;
+    let original = server.get(&link, None).await;
+    assert_eq!(original.body, jpeg);
+    let stripped = server.get(&format!("{link}&sanitize=1"), None).await;
+    assert_eq!(stripped.status, 200);
+    assert_eq!(stripped.body, [&[0xFF, 0xD8][..], &kept].concat());
+    assert_eq!(stripped.header("content-length"), Some("10"));
+    assert_ne!(stripped.header("etag"), original.header("etag"));
+    let text_link = server.file_link(&admin, "public/readme.txt").await;
+    let refused = server.get(&format!("{text_link}&sanitize=1"), None).await;
+    assert_eq!(refused.status, 415);
+}
+
+/// A provider that knows one user, `ivy` with subject `ivy-0001`, in the
+/// `media-admins` group. The test hands it the nonce to put in the ID
+/// token.
+async fn start_fake_oidc_provider(nonce: std::sync::Arc<std::sync::Mutex<String>>) -> String {
+    use axum::routing::{get, post};
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let metadata = json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+    });
+    let token_issuer = issuer.clone();
+    let app = axum::Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            get(move || std::future::ready(axum::Json(metadata.clone()))),
+        )
+        .route(
+            "/token",
+            post(move |body: String| {
+                let claims = json!({
+                    "iss": token_issuer,
+                    "aud": "mlist",
+                    "exp": 4_102_444_800_u64,
+                    "nonce": nonce.lock().unwrap().clone(),
+                    "sub": "ivy-0001",
+                    "preferred_username": "ivy",
+                    "groups": ["staff", "media-admins"],
+                });
+                let id_token = format!("e30.{}.sig", URL_SAFE_NO_PAD.encode(claims.to_string()));
+                async move {
+                    assert!(body.contains("code=granted"), "{body}");
+                    assert!(body.contains("code_verifier="), "{body}");
+                    axum::Json(json!({ "id_token": id_token, "token_type": "Bearer" }))
+                }
+            }),
+        );
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    issuer
+}
+
+fn query_param<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    url.split_once('?')?
+        .1
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Starts a login and returns the callback URL the provider would send
+/// the browser to, along with the state cookie set by the login.
+async fn start_oidc_login(
+    server: &TestServer,
+    issuer: &str,
+    nonce: &std::sync::Mutex<String>,
+) -> (String, String) {
+    let start = server.get("/api/auth/oidc/login", None).await;
+    assert_eq!(start.status, 303);
+    let location = start.header("location").unwrap().to_string();
+    assert!(
+        location.starts_with(&format!("{issuer}/authorize?")),
+        "{location}"
+    );
+    assert_eq!(
+        query_param(&location, "code_challenge_method"),
+        Some("S256")
+    );
+    assert_eq!(query_param(&location, "client_id"), Some("mlist"));
+    let state = query_param(&location, "state").unwrap().to_string();
+    *nonce.lock().unwrap() = query_param(&location, "nonce").unwrap().to_string();
+    let cookie = start.cookie("mlist_oidc_state").expect("state cookie");
+    (
+        format!("/api/auth/oidc/callback?code=granted&state={state}"),
+        cookie,
+    )
+}
+
+async fn finish_oidc_login(server: &TestServer, callback: &str, cookie: &str) -> TestResponse {
+    server
+        .send("GET", callback, &[header("Cookie", cookie)], None)
+        .await
+}
+
+#[tokio::test]
+async fn oidc_login_creates_accounts_from_claims() {
+    let nonce = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+    let issuer = start_fake_oidc_provider(nonce.clone()).await;
+    let server = TestServer::start_with("oidc", |config| {
+        config.oidc_issuer = Some(issuer.clone());
+        config.oidc_client_id = Some("mlist".to_string());
+        config.oidc_auto_create = true;
+        config.oidc_admin_claims = vec!["groups=media-admins".parse().unwrap()];
+    })
+    .await;
+    assert_eq!(
+        server.get("/api/me", None).await.json()["oidcEnabled"],
+        true
+    );
+
+    let (callback, cookie) = start_oidc_login(&server, &issuer, &nonce).await;
+    // A callback URL opened in a browser that did not start the login is
+    // refused, so nobody can be signed into someone else's account.
+    assert_eq!(server.get(&callback, None).await.status, 401);
+    let foreign = finish_oidc_login(&server, &callback, "mlist_oidc_state=other").await;
+    assert_eq!(foreign.status, 401);
+
+    let finished = finish_oidc_login(&server, &callback, &cookie).await;
+    assert_eq!(finished.status, 303);
+    assert_eq!(finished.header("location"), Some("/"));
+    let refresh = finished.cookie("mlist_refresh").expect("refresh cookie");
+
+    let refreshed = server
+        .send(
+            "POST",
+            "/api/auth/refresh",
+            &[header("Cookie", &refresh)],
+            None,
+        )
+        .await;
+    assert_eq!(refreshed.status, 200);
+    let access = refreshed.json()["accessToken"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let me = server.get("/api/me", Some(&access)).await.json();
+    assert_eq!(me["user"]["username"], "ivy");
+    assert_eq!(me["user"]["role"], "admin");
+
+    // Each login state is good for one callback only.
+    let replayed = finish_oidc_login(&server, &callback, &cookie).await;
+    assert_eq!(replayed.status, 401);
+
+    // Later logins find the account through its subject.
+    let (callback, cookie) = start_oidc_login(&server, &issuer, &nonce).await;
+    assert_eq!(
+        finish_oidc_login(&server, &callback, &cookie).await.status,
+        303
+    );
+}
+
+#[tokio::test]
+async fn oidc_logins_never_take_over_unlinked_local_accounts() {
+    let nonce = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+    let issuer = start_fake_oidc_provider(nonce.clone()).await;
+    let server = TestServer::start_with("oidc-link", |config| {
+        config.oidc_issuer = Some(issuer.clone());
+        config.oidc_client_id = Some("mlist".to_string());
+        config.oidc_auto_create = true;
+        config.oidc_admin_claims = vec!["groups=media-admins".parse().unwrap()];
+    })
+    .await;
+    let admin = server.bootstrap_admin().await;
+    server.create_user(&admin, "ivy").await;
+
+    let (callback, cookie) = start_oidc_login(&server, &issuer, &nonce).await;
+    let refused = finish_oidc_login(&server, &callback, &cookie).await;
+    assert_eq!(refused.status, 409);
+    assert!(refused.cookie("mlist_refresh").is_none());
+
+    let users = server.get("/api/admin/users", Some(&admin)).await.json();
+    let ivy = users["users"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|user| user["username"] == "ivy")
+        .unwrap()["id"]
+        .as_i64()
+        .unwrap();
+    let linked = server
+        .post_json(
+            &format!("/api/admin/users/{ivy}/oidc"),
+            Some(&admin),
+            &json!({ "subject": "ivy-0001" }),
+        )
+        .await;
+    assert_eq!(linked.status, 200);
+
+    let (callback, cookie) = start_oidc_login(&server, &issuer, &nonce).await;
+    let finished = finish_oidc_login(&server, &callback, &cookie).await;
+    assert_eq!(finished.status, 303);
+    let refresh = finished.cookie("mlist_refresh").expect("refresh cookie");
+    let refreshed = server
+        .send(
+            "POST",
+            "/api/auth/refresh",
+            &[header("Cookie", &refresh)],
+            None,
+        )
+        .await;
+    let access = refreshed.json()["accessToken"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    // The linked account keeps its own role; claims only apply on creation.
+    let me = server.get("/api/me", Some(&access)).await.json();
+    assert_eq!(me["user"]["username"], "ivy");
+    assert_eq!(me["user"]["role"], "user");
+
+    let unlinked = server
+        .send(
+            "DELETE",
+            &format!("/api/admin/users/{ivy}/oidc"),
+            &[header("Authorization", &format!("Bearer {admin}"))],
+            None,
+        )
+        .await;
+    assert_eq!(unlinked.status, 200);
+    let (callback, cookie) = start_oidc_login(&server, &issuer, &nonce).await;
+    assert_eq!(
+        finish_oidc_login(&server, &callback, &cookie).await.status,
+        409
+    );
+}
+
+#[tokio::test]
+async fn oidc_routes_are_absent_unless_configured() {
+    let server = TestServer::start("oidc-off").await;
+    assert_eq!(
+        server.get("/api/me", None).await.json()["oidcEnabled"],
+        false
+    );
+    assert_eq!(server.get("/api/auth/oidc/login", None).await.status, 404);
+}
+
+#[tokio::test]
+async fn torrents_are_hashed_in_the_background_and_seeded_over_http() {
+    let server = TestServer::start("torrent").await;
+    let release = server.root.join("public/release");
+    std::fs::create_dir_all(release.join("extras")).unwrap();
+    std::fs::write(release.join("movie.mkv"), b"movie bytes").unwrap();
+    std::fs::write(release.join("extras/notes.txt"), b"notes").unwrap();
+    let admin = server.bootstrap_admin().await;
+
+    let mut torrent = server
+        .get("/api/torrent?path=public/release", Some(&admin))
+        .await;
+    for _ in 0..100 {
+        if torrent.status != 202 {
+            break;
+        }
+        assert_eq!(torrent.json()["status"], "hashing");
+        assert_eq!(torrent.json()["totalBytes"], 16);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        torrent = server
+            .get("/api/torrent?path=public/release", Some(&admin))
+            .await;
+    }
+    assert_eq!(torrent.status, 200);
+    assert_eq!(
+        torrent.header("content-type"),
+        Some("application/x-bittorrent")
+    );
+    let body = String::from_utf8_lossy(&torrent.body).to_string();
+    assert!(body.contains("4:name7:release"));
+    assert!(body.contains("4:pathl6:extras9:notes.txte"));
+
+    // The web seed is a bencoded `length:url` string.
+    let (len, rest) = body
+        .split_once("8:url-list")
+        .unwrap()
+        .1
+        .split_once(':')
+        .unwrap();
+    let web_seed = &rest[..len.parse::<usize>().unwrap()];
+    let prefix = format!("http://{}/seed/", server.addr);
+    assert!(web_seed.starts_with(&prefix), "{web_seed}");
+    assert!(web_seed.ends_with("/public/"), "{web_seed}");
+
+    let seed = web_seed.trim_start_matches(&format!("http://{}", server.addr));
+    let piece = server
+        .get(&format!("{seed}release/extras/notes.txt"), None)
+        .await;
+    assert_eq!(piece.status, 200);
+    assert_eq!(piece.body, b"notes");
+    let outside = server.get(&format!("{seed}readme.txt"), None).await;
+    assert_eq!(outside.status, 401);
+
+    let single = server
+        .get("/api/torrent?path=public/readme.txt", Some(&admin))
+        .await;
+    assert!(matches!(single.status, 200 | 202));
+
+    let secret = server.create_user(&admin, "mallory").await;
+    let user = server.login_token("mallory", &secret).await;
+    let hidden = server
+        .get("/api/torrent?path=private/nested", Some(&user))
+        .await;
+    assert_eq!(hidden.status, 404);
+    let anonymous = server.get("/api/torrent?path=public/release", None).await;
+    assert_eq!(anonymous.status, 401);
+}
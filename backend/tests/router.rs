@@ -0,0 +1,120 @@
+use std::path::PathBuf;
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Request, StatusCode};
+use mlist::AppConfig;
+use serde_json::Value;
+use tower::ServiceExt;
+
+fn temp_config(name: &str) -> (AppConfig, PathBuf) {
+    let base = std::env::temp_dir().join(format!("mlist-{name}-{}", uuid::Uuid::new_v4().simple()));
+    let root_dir = base.join("files");
+    std::fs::create_dir_all(&root_dir).unwrap();
+    let config = AppConfig {
+        root_dir,
+        database_path: base.join("mlist.sqlite3"),
+        ..AppConfig::default()
+    };
+    (config, base)
+}
+
+async fn get_json(app: &Router, uri: &str) -> (StatusCode, Value) {
+    let response = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn me_reports_bootstrap_on_empty_database() {
+    let (config, base) = temp_config("router-me");
+    let app = mlist::build_app(config).await.unwrap();
+
+    let (status, body) = get_json(&app, "/api/me").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["authenticated"], false);
+    assert_eq!(body["needsBootstrap"], true);
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[tokio::test]
+async fn list_requires_authentication() {
+    let (config, base) = temp_config("router-list");
+    let app = mlist::build_app(config).await.unwrap();
+
+    let (status, body) = get_json(&app, "/api/list?path=").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "AUTH_REQUIRED");
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[tokio::test]
+async fn unknown_api_route_returns_json_not_found() {
+    let (config, base) = temp_config("router-404");
+    let app = mlist::build_app(config).await.unwrap();
+
+    let (status, body) = get_json(&app, "/api/does-not-exist").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "NOT_FOUND");
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[tokio::test]
+async fn build_app_rejects_relative_root() {
+    let (mut config, base) = temp_config("router-relative");
+    config.root_dir = PathBuf::from("relative/files");
+
+    assert!(mlist::build_app(config).await.is_err());
+
+    let _ = std::fs::remove_dir_all(base);
+}
+
+#[tokio::test]
+async fn frontend_is_served_under_the_base_path() {
+    let (mut config, base) = temp_config("router-base-path");
+    config.base_path = "/files".to_string();
+    let dist = base.join("dist");
+    std::fs::create_dir_all(dist.join("assets")).unwrap();
+    std::fs::write(
+        dist.join("index.html"),
+        r#"<html><head><script src="./assets/app.js"></script></head></html>"#,
+    )
+    .unwrap();
+    std::fs::write(dist.join("assets/app.js"), "console.log(1)").unwrap();
+    let app = mlist::with_frontend(mlist::build_app(config).await.unwrap(), &dist, "/files");
+
+    let get = |uri: &str| {
+        app.clone()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+    };
+    let asset = get("/files/assets/app.js").await.unwrap();
+    assert_eq!(asset.status(), StatusCode::OK);
+
+    let index = get("/files/some/dir").await.unwrap();
+    assert_eq!(index.status(), StatusCode::OK);
+    let html = to_bytes(index.into_body(), usize::MAX).await.unwrap();
+    let html = String::from_utf8(html.to_vec()).unwrap();
+    assert!(html.contains(r#"src="/files/assets/app.js""#));
+    assert!(html.contains(r#"<meta name="mlist-base-path" content="/files" />"#));
+
+    let (status, _) = get_json(&app, "/files/api/me").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        get("/assets/app.js").await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        get("/filesx").await.unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let _ = std::fs::remove_dir_all(base);
+}
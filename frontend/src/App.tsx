@@ -27,6 +27,7 @@ import {
     fetchMe,
     refreshAccessToken,
     setAccessToken,
+    watchDirectory,
 } from "@/api";
 import {
     browserPath,
@@ -37,7 +38,9 @@ import {
     pathFromLocation,
     syncBrowserState,
     toAbsoluteUrl,
+    withBasePath,
 } from "@/lib/path";
+import { answerLoginChallenge } from "@/lib/pow";
 import type {
     FavoritePath,
     FavoritesResponse,
@@ -46,6 +49,7 @@ import type {
     ListEntry,
     ListResponse,
     LoadPathOptions,
+    LoginChallenge,
     LoginResponse,
     MeResponse,
     SortField,
@@ -57,6 +61,7 @@ function App() {
     const [authLoading, setAuthLoading] = useState(true);
     const [user, setUser] = useState<UserView | null>(null);
     const [needsBootstrap, setNeedsBootstrap] = useState(false);
+    const [oidcEnabled, setOidcEnabled] = useState(false);
     const [adminRoute, setAdminRoute] = useState(() =>
         isAdminPath(window.location.pathname),
     );
@@ -83,6 +88,8 @@ function App() {
     const [pagination, setPagination] = useState({ offset: 0, limit: 50 });
     const [search, setSearch] = useState("");
     const searchDebounceRef = useRef<ReturnType<typeof setTimeout> | null>(null);
+    const reloadListingRef = useRef<() => void>(() => {});
+    const loginChallengeRef = useRef<LoginChallenge | null>(null);
     const [total, setTotal] = useState(0);
     const [hasMore, setHasMore] = useState(false);
 
@@ -131,6 +138,34 @@ function App() {
         // eslint-disable-next-line react-hooks/exhaustive-deps
     }, [user]);
 
+    useEffect(() => {
+        reloadListingRef.current = () => {
+            void loadPath(currentPath, {
+                updateUrl: false,
+                previewPath: previewEntry?.path ?? null,
+            });
+        };
+    });
+
+    // 目录内容变化时自动刷新列表；短时间内的多次变化合并为一次请求
+    useEffect(() => {
+        if (!user || adminRoute || pathNotFound) return;
+        const controller = new AbortController();
+        let timer: ReturnType<typeof setTimeout> | null = null;
+        watchDirectory(
+            currentPath,
+            () => {
+                if (timer) clearTimeout(timer);
+                timer = setTimeout(() => reloadListingRef.current(), 500);
+            },
+            controller.signal,
+        ).catch(() => undefined);
+        return () => {
+            controller.abort();
+            if (timer) clearTimeout(timer);
+        };
+    }, [user, adminRoute, currentPath, pathNotFound]);
+
     async function bootstrapApp() {
         setAuthLoading(true);
         try {
@@ -162,6 +197,7 @@ function App() {
     function applyMe(me: MeResponse) {
         setUser(me.user);
         setNeedsBootstrap(me.needsBootstrap);
+        setOidcEnabled(me.oidcEnabled);
     }
 
     function clearSearchDebounce() {
@@ -215,6 +251,8 @@ function App() {
             }
             const payload = await apiJson<ListResponse>(
                 `/api/list?${params.toString()}`,
+                {},
+                { revalidate: true },
             );
             const safeEntries = payload.entries.filter(
                 (item) => item.name !== ".private",
@@ -275,11 +313,36 @@ function App() {
         }
     }
 
+    async function requestLogin(
+        username: string,
+        code: string,
+    ): Promise<LoginResponse> {
+        // 服务器要求挑战时会随错误下发，本次缺少答案则求解后自动重试一次
+        for (let attempt = 0; ; attempt += 1) {
+            const pending = loginChallengeRef.current;
+            loginChallengeRef.current = null;
+            const challenge = pending
+                ? await answerLoginChallenge(pending)
+                : undefined;
+            try {
+                return await apiJson<LoginResponse>("/api/auth/login", {
+                    method: "POST",
+                    body: JSON.stringify({ username, code, challenge }),
+                });
+            } catch (err) {
+                if (!(err instanceof ApiRequestError) || !err.challenge) {
+                    throw err;
+                }
+                loginChallengeRef.current = err.challenge;
+                if (err.code !== "CHALLENGE_REQUIRED" || attempt > 0) {
+                    throw err;
+                }
+            }
+        }
+    }
+
     async function handleLogin(username: string, code: string) {
-        const payload = await apiJson<LoginResponse>("/api/auth/login", {
-            method: "POST",
-            body: JSON.stringify({ username, code }),
-        });
+        const payload = await requestLogin(username, code);
         setAccessToken(payload.accessToken);
         setUser(payload.user);
         setNeedsBootstrap(false);
@@ -340,7 +403,7 @@ function App() {
     function openAdmin() {
         setPreviewEntry(null);
         setAdminRoute(true);
-        window.history.pushState(null, "", "/_mlist/admin");
+        window.history.pushState(null, "", withBasePath("/_mlist/admin"));
     }
 
     function closeAdmin() {
@@ -589,7 +652,7 @@ function App() {
     if (!user) {
         return (
             <Shell>
-                <LoginView onLogin={handleLogin} />
+                <LoginView onLogin={handleLogin} oidcEnabled={oidcEnabled} />
             </Shell>
         );
     }
@@ -1,13 +1,19 @@
 import type {
     ApiError,
+    LoginChallenge,
     MeResponse,
     RefreshResponse,
     SignedFileLinkResponse,
 } from "@/types";
+import { withBasePath } from "@/lib/path";
 
 let accessToken: string | null = null;
 let refreshPromise: Promise<RefreshResponse> | null = null;
 
+// 按 URL 记住最近的 ETag 与结果，服务端返回 304 时直接复用
+const MAX_REVALIDATED = 50;
+const revalidated = new Map<string, { etag: string; payload: unknown }>();
+
 export function setAccessToken(token: string | null) {
     accessToken = token;
 }
@@ -15,12 +21,19 @@ export function setAccessToken(token: string | null) {
 export class ApiRequestError extends Error {
     status: number;
     code?: string;
+    challenge?: LoginChallenge;
 
-    constructor(status: number, message: string, code?: string) {
+    constructor(
+        status: number,
+        message: string,
+        code?: string,
+        challenge?: LoginChallenge,
+    ) {
         super(message);
         this.name = "ApiRequestError";
         this.status = status;
         this.code = code;
+        this.challenge = challenge;
     }
 }
 
@@ -45,7 +58,7 @@ export async function refreshAccessToken(): Promise<RefreshResponse> {
 export async function apiJson<T>(
     url: string,
     init: RequestInit = {},
-    options: { skipRefresh?: boolean } = {},
+    options: { skipRefresh?: boolean; revalidate?: boolean } = {},
 ): Promise<T> {
     return apiJsonRequest<T>(url, init, options);
 }
@@ -53,9 +66,13 @@ export async function apiJson<T>(
 async function apiJsonRequest<T>(
     url: string,
     init: RequestInit,
-    options: { skipRefresh?: boolean },
+    options: { skipRefresh?: boolean; revalidate?: boolean },
 ): Promise<T> {
     const headers = new Headers(init.headers);
+    const cached = options.revalidate ? revalidated.get(url) : undefined;
+    if (cached) {
+        headers.set("If-None-Match", cached.etag);
+    }
     if (init.body && !headers.has("Content-Type")) {
         headers.set("Content-Type", "application/json");
     }
@@ -63,18 +80,22 @@ async function apiJsonRequest<T>(
         headers.set("Authorization", `Bearer ${accessToken}`);
     }
 
-    const response = await fetch(url, {
+    const response = await fetch(withBasePath(url), {
         ...init,
         headers,
         credentials: "include",
     });
 
+    if (response.status === 304 && cached) {
+        return cached.payload as T;
+    }
     if (!response.ok) {
         const payload = (await response.json().catch(() => ({}))) as ApiError;
         const error = new ApiRequestError(
             response.status,
             payload.message ?? `请求失败（${response.status}）`,
             payload.code,
+            payload.challenge,
         );
         if (
             !options.skipRefresh &&
@@ -83,7 +104,10 @@ async function apiJsonRequest<T>(
         ) {
             try {
                 await refreshAccessToken();
-                return apiJsonRequest<T>(url, init, { skipRefresh: true });
+                return apiJsonRequest<T>(url, init, {
+                    ...options,
+                    skipRefresh: true,
+                });
             } catch {
                 setAccessToken(null);
             }
@@ -91,7 +115,19 @@ async function apiJsonRequest<T>(
         throw error;
     }
 
-    return (await response.json()) as T;
+    const payload = (await response.json()) as T;
+    const etag = response.headers.get("ETag");
+    if (options.revalidate && etag) {
+        revalidated.delete(url);
+        if (revalidated.size >= MAX_REVALIDATED) {
+            const oldest = revalidated.keys().next().value;
+            if (oldest !== undefined) {
+                revalidated.delete(oldest);
+            }
+        }
+        revalidated.set(url, { etag, payload });
+    }
+    return payload;
 }
 
 export async function fetchMe(): Promise<MeResponse> {
@@ -106,3 +142,42 @@ export async function createSignedFileLink(
         body: JSON.stringify({ path }),
     });
 }
+
+/**
+ * 订阅 `/api/events` 的目录变化，每收到一个事件调用一次 `onChange`。
+ * `EventSource` 无法携带 Bearer 头，因此改用 `fetch` 读取 SSE 流；
+ * 流结束或 `signal` 中止时返回。
+ */
+export async function watchDirectory(
+    path: string,
+    onChange: () => void,
+    signal: AbortSignal,
+): Promise<void> {
+    const headers = new Headers();
+    if (accessToken) {
+        headers.set("Authorization", `Bearer ${accessToken}`);
+    }
+    const params = new URLSearchParams({ path });
+    const response = await fetch(withBasePath(`/api/events?${params.toString()}`), {
+        headers,
+        credentials: "include",
+        signal,
+    });
+    if (!response.ok || !response.body) return;
+
+    const reader = response.body
+        .pipeThrough(new TextDecoderStream())
+        .getReader();
+    let buffer = "";
+    for (;;) {
+        const { done, value } = await reader.read();
+        if (done) return;
+        buffer += value;
+        const events = buffer.split("\n\n");
+        buffer = events.pop() ?? "";
+        // 心跳注释行没有 `event:` 字段，直接忽略
+        if (events.some((event) => /^event: /m.test(event))) {
+            onChange();
+        }
+    }
+}
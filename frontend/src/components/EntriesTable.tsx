@@ -5,6 +5,7 @@ import {
     DownloadIcon,
     EyeIcon,
     LockIcon,
+    PinIcon,
     StarIcon,
 } from "lucide-react";
 import {
@@ -277,6 +278,12 @@ function NameCell({
         >
             <EntryIcon entry={entry} />
             <span className="min-w-0 truncate">{entry.name}</span>
+            {entry.pinned ? (
+                <PinIcon
+                    className="text-muted-foreground size-3.5 shrink-0"
+                    aria-label="置顶"
+                />
+            ) : null}
             {entry.favorite ? (
                 <StarIcon
                     className="size-3.5 shrink-0 fill-yellow-400 text-yellow-400"
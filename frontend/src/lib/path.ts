@@ -1,5 +1,27 @@
+/**
+ * 服务端挂载的子路径（如 `/files`），由后端注入 index.html 的
+ * `<meta name="mlist-base-path">` 提供；挂载在根路径时为空字符串。
+ */
+export const basePath: string =
+    typeof document === "undefined"
+        ? ""
+        : (document
+              .querySelector<HTMLMetaElement>('meta[name="mlist-base-path"]')
+              ?.content.replace(/\/+$/, "") ?? "");
+
+/** 为以 `/` 开头的站内地址加上挂载前缀。 */
+export function withBasePath(url: string): string {
+    return url.startsWith("/") ? `${basePath}${url}` : url;
+}
+
+function stripBasePath(pathname: string): string {
+    if (!basePath) return pathname;
+    if (pathname === basePath) return "/";
+    return pathname.startsWith(`${basePath}/`) ? pathname.slice(basePath.length) : pathname;
+}
+
 export function isAdminPath(pathname: string): boolean {
-    return normalizePath(pathname) === "_mlist/admin";
+    return normalizePath(stripBasePath(pathname)) === "_mlist/admin";
 }
 
 export function normalizePath(path: string): string {
@@ -27,7 +49,7 @@ export function parentPathOf(path: string): string {
 }
 
 export function pathFromLocation(pathname: string): string {
-    const normalized = normalizePath(pathname);
+    const normalized = normalizePath(stripBasePath(pathname));
     if (!normalized) return "";
     return normalized
         .split("/")
@@ -42,8 +64,8 @@ export function pathFromLocation(pathname: string): string {
 }
 
 export function browserPath(relativePath: string): string {
-    if (!relativePath) return "/";
-    return `/${relativePath
+    if (!relativePath) return `${basePath}/`;
+    return `${basePath}/${relativePath
         .split("/")
         .map((segment) => encodeURIComponent(segment))
         .join("/")}`;
@@ -0,0 +1,49 @@
+import type { LoginChallenge, LoginChallengeAnswer } from "@/types";
+
+function leadingZeroBits(digest: Uint8Array) {
+    let bits = 0;
+    for (const byte of digest) {
+        if (byte !== 0) {
+            return bits + Math.clz32(byte) - 24;
+        }
+        bits += 8;
+    }
+    return bits;
+}
+
+/**
+ * 求解登录工作量证明：寻找 `solution`，使 `nonce:solution` 的 SHA-256
+ * 以 `difficulty` 个零位开头。每批计算后让出主线程，避免页面卡死。
+ */
+export async function solveProofOfWork(
+    nonce: string,
+    difficulty: number,
+): Promise<string> {
+    const encoder = new TextEncoder();
+    for (let candidate = 0; ; candidate += 1) {
+        const solution = candidate.toString(36);
+        const digest = await crypto.subtle.digest(
+            "SHA-256",
+            encoder.encode(`${nonce}:${solution}`),
+        );
+        if (leadingZeroBits(new Uint8Array(digest)) >= difficulty) {
+            return solution;
+        }
+        if (candidate % 1000 === 999) {
+            await new Promise((resolve) => setTimeout(resolve, 0));
+        }
+    }
+}
+
+/** 内置登录页只能自动完成工作量证明，人机验证需自定义登录页。 */
+export async function answerLoginChallenge(
+    challenge: LoginChallenge,
+): Promise<LoginChallengeAnswer> {
+    if (challenge.kind !== "pow") {
+        throw new Error("需要完成人机验证，当前登录页不支持。");
+    }
+    return {
+        nonce: challenge.nonce,
+        solution: await solveProofOfWork(challenge.nonce, challenge.difficulty),
+    };
+}
@@ -10,6 +10,7 @@ export type ListEntry = {
     requiresAuth: boolean;
     authorized: boolean;
     favorite: boolean;
+    pinned?: boolean;
 };
 
 export type ListResponse = {
@@ -49,6 +50,7 @@ export type MeResponse = {
     user: UserView | null;
     accessExpiresAt: string | null;
     needsBootstrap: boolean;
+    oidcEnabled: boolean;
 };
 
 export type LoginResponse = {
@@ -143,6 +145,17 @@ export type SignedFileLinkResponse = {
 export type ApiError = {
     code?: string;
     message?: string;
+    challenge?: LoginChallenge;
+};
+
+export type LoginChallenge =
+    | { kind: "pow"; nonce: string; difficulty: number }
+    | { kind: "captcha"; siteKey?: string | null };
+
+export type LoginChallengeAnswer = {
+    nonce?: string;
+    solution?: string;
+    token?: string;
 };
 
 export type LoadPathOptions = {
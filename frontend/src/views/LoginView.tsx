@@ -13,13 +13,16 @@ import {
 } from "@/components/ui/card";
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
+import { withBasePath } from "@/lib/path";
 import { isCompleteTotpCode } from "@/lib/totp";
 import { TotpCodeInput } from "@/components/TotpCodeInput";
 
 export function LoginView({
     onLogin,
+    oidcEnabled,
 }: {
     onLogin: (username: string, code: string) => Promise<void>;
+    oidcEnabled: boolean;
 }) {
     const [username, setUsername] = useState("");
     const [code, setCode] = useState("");
@@ -94,6 +97,17 @@ export function LoginView({
                         >
                             {submitting ? "登录中..." : "登录"}
                         </Button>
+                        {oidcEnabled ? (
+                            <Button
+                                asChild
+                                variant="outline"
+                                className="w-full"
+                            >
+                                <a href={withBasePath("/api/auth/oidc/login")}>
+                                    使用单点登录
+                                </a>
+                            </Button>
+                        ) : null}
                     </form>
                 </CardContent>
             </Card>
@@ -53,6 +53,8 @@ export default defineConfig({
       "@": path.resolve(__dirname, "./src"),
     },
   },
+  // 相对路径构建，后端按 MLIST_BASE_PATH 改写 index.html 中的资源地址
+  base: "./",
   build: {
     sourcemap: false,
   },